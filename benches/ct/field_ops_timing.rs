@@ -0,0 +1,139 @@
+//! A dudect-style timing leakage test for field arithmetic.
+//!
+//! Based on "Dude, is my code constant time?" by Oscar Reparaz, Josep Balasch and Ingrid Verbauwhede
+//! <https://eprint.iacr.org/2016/1123.pdf>
+//!
+//! For every operation, inputs are drawn from two classes: a fixed class, where one operand is
+//! always zero, and a random class. Both classes are measured in random order and Welch's t-test
+//! is used to check whether the timing distributions differ. A `|t|` value that exceeds
+//! [`T_THRESHOLD`] is a strong indication that the operation is not constant time.
+//!
+//! ```bash
+//! cargo bench -F enable-benches --bench dudect_field_ops
+//! ```
+use ipa::ff::{Field, Fp31, Fp32BitPrime, Fp61BitPrime, Gf2, Gf32Bit, Gf40Bit, Gf64Bit, Gf8Bit};
+use rand::{thread_rng, Rng};
+use std::{hint::black_box, time::Instant};
+
+/// Number of measurements per operation.
+const SAMPLES: usize = 200_000;
+/// Each measurement runs the operation this many times to get above the timer resolution.
+const BATCH: usize = 64;
+/// Threshold used by dudect to flag a leak.
+const T_THRESHOLD: f64 = 4.5;
+/// Fraction of the slowest measurements discarded to reduce the effect of interrupts.
+const CROP: f64 = 0.95;
+
+/// Online mean/variance computation (Welford's algorithm).
+#[derive(Default)]
+struct Stats {
+    n: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Stats {
+    fn push(&mut self, x: f64) {
+        self.n += 1.0;
+        let delta = x - self.mean;
+        self.mean += delta / self.n;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn variance(&self) -> f64 {
+        self.m2 / (self.n - 1.0)
+    }
+}
+
+/// Welch's t statistic for the two classes.
+fn welch_t(a: &Stats, b: &Stats) -> f64 {
+    (a.mean - b.mean) / (a.variance() / a.n + b.variance() / b.n).sqrt()
+}
+
+/// Measures `op` on inputs from both classes and returns the t statistic.
+fn measure<F: Field, O: Fn(F, F) -> F>(op: O) -> f64 {
+    let mut rng = thread_rng();
+    let mut timings = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        let fixed_class = rng.gen::<bool>();
+        let inputs = (0..BATCH)
+            .map(|_| {
+                let a = F::truncate_from(rng.gen::<u128>());
+                let b = if fixed_class {
+                    F::ZERO
+                } else {
+                    F::truncate_from(rng.gen::<u128>())
+                };
+                (a, b)
+            })
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        for &(a, b) in &inputs {
+            black_box(op(black_box(a), black_box(b)));
+        }
+        #[allow(clippy::cast_precision_loss)]
+        timings.push((fixed_class, start.elapsed().as_nanos() as f64));
+    }
+
+    let mut sorted = timings.iter().map(|(_, t)| *t).collect::<Vec<_>>();
+    sorted.sort_by(f64::total_cmp);
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let cutoff = sorted[(sorted.len() as f64 * CROP) as usize];
+
+    let (mut fixed, mut random) = (Stats::default(), Stats::default());
+    for (fixed_class, t) in timings.into_iter().filter(|(_, t)| *t <= cutoff) {
+        if fixed_class {
+            fixed.push(t);
+        } else {
+            random.push(t);
+        }
+    }
+
+    welch_t(&fixed, &random)
+}
+
+fn report(name: &str, t: f64) -> bool {
+    let leaks = t.abs() > T_THRESHOLD;
+    println!(
+        "{name:<20} t = {t:>8.2} {}",
+        if leaks { "<- possible leak" } else { "" }
+    );
+    leaks
+}
+
+fn check_field<F: Field>(name: &str) -> bool {
+    let results = [
+        report(&format!("{name} add"), measure::<F, _>(|a, b| a + b)),
+        report(&format!("{name} sub"), measure::<F, _>(|a, b| a - b)),
+        report(&format!("{name} mul"), measure::<F, _>(|a, b| a * b)),
+        report(&format!("{name} neg"), measure::<F, _>(|_, b| -b)),
+        report(
+            &format!("{name} try_from"),
+            measure::<F, _>(|_, b| F::try_from(b.as_u128()).unwrap()),
+        ),
+    ];
+    results.into_iter().any(|leaks| leaks)
+}
+
+fn main() {
+    let leaks = [
+        check_field::<Fp31>("Fp31"),
+        check_field::<Fp32BitPrime>("Fp32BitPrime"),
+        check_field::<Fp61BitPrime>("Fp61BitPrime"),
+        check_field::<Gf2>("Gf2"),
+        check_field::<Gf8Bit>("Gf8Bit"),
+        check_field::<Gf32Bit>("Gf32Bit"),
+        check_field::<Gf40Bit>("Gf40Bit"),
+        check_field::<Gf64Bit>("Gf64Bit"),
+    ];
+
+    if leaks.into_iter().any(|leaks| leaks) {
+        println!("timing differences detected, re-run on an idle machine to confirm");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,309 @@
+use clap::{Parser, ValueEnum};
+use futures::FutureExt;
+use ipa::{
+    error::Error,
+    ff::{Field, Fp32BitPrime, PrimeField},
+    helpers::{query::IpaQueryConfig, GatewayConfig, LinkConfig},
+    protocol::{
+        attribution::{credit_capping::credit_capping, input::MCCreditCappingInputRow},
+        boolean::RandomBits,
+        context::{Context, UpgradableContext, UpgradedContext, Validator},
+        sort::multi_bit_permutation::multi_bit_permutation,
+        BasicProtocols,
+    },
+    secret_sharing::{
+        replicated::malicious::ExtendableField, BitDecomposed, Linear as LinearSecretSharing,
+    },
+    telemetry::StepStatsCsvExporter,
+    test_fixture::{
+        cost::{CostReport, CostSample},
+        ipa::{ipa_in_the_clear, test_ipa, IpaSecurityModel},
+        EventGenerator, EventGeneratorConfig, Runner, TestWorldConfig,
+    },
+};
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+use std::{
+    fs::File,
+    io::{stdout, Write},
+    iter::zip,
+    num::NonZeroU32,
+    path::PathBuf,
+    time::Duration,
+};
+use tokio::runtime::Builder;
+
+/// Simulated latency between helpers. The clock is paused, so this does not take any time.
+const LATENCY: Duration = Duration::from_secs(1);
+
+type BenchField = Fp32BitPrime;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Protocol {
+    Ipa,
+    CreditCapping,
+    MultiBitPermutation,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+/// Measures the communication cost of every step of a protocol at a few small input sizes, and
+/// fits a linear model of the cost as a function of the input size.
+///
+/// Helpers talk over links with latency, to count the communication rounds. The clock is paused,
+/// so it only advances while all the helpers wait for data: the latency separates the rounds
+/// without slowing down the runs.
+#[derive(Parser)]
+#[command(about, long_about = None)]
+struct Args {
+    /// The protocol to measure. Credit capping and multi-bit permutation run on random inputs.
+    #[arg(short = 'p', long, value_enum, default_value_t = Protocol::Ipa)]
+    protocol: Protocol,
+    /// The input sizes to run the protocol at.
+    #[arg(short = 'n', long, value_delimiter = ',', default_value = "10,20,40")]
+    sizes: Vec<usize>,
+    /// The maximum number of records for each person.
+    #[arg(short = 'u', long, default_value = "10")]
+    records_per_user: u32,
+    /// The contribution cap for each person. Also the cap of credit capping.
+    #[arg(short = 'c', long, default_value = "3")]
+    per_user_cap: u32,
+    /// The number of breakdown keys.
+    #[arg(short = 'b', long, default_value = "16")]
+    breakdown_keys: u32,
+    /// The size of the attribution window, in seconds. Pass 0 for an infinite window.
+    #[arg(short = 'w', long, default_value = "86400")]
+    attribution_window: u32,
+    /// The number of sequential bits of breakdown key and match key to process in parallel
+    /// while doing modulus conversion and attribution. Also the number of bits that multi-bit
+    /// permutation sorts on.
+    #[arg(long, default_value = "3")]
+    num_multi_bits: u32,
+    /// Desired security model for the protocol
+    #[arg(short = 'm', long, value_enum, default_value_t=IpaSecurityModel::Malicious)]
+    mode: IpaSecurityModel,
+    /// If set, the cost of the steps deeper than this in the gate tree is added to their ancestor.
+    #[arg(short = 'd', long)]
+    depth: Option<usize>,
+    /// The random seed to use.
+    #[arg(short = 's', long)]
+    random_seed: Option<u64>,
+    #[arg(short = 'f', long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+    /// The destination file for the report. Printed to stdout if not set.
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
+    /// Needed for benches.
+    #[arg(long, hide = true)]
+    bench: bool,
+}
+
+impl Args {
+    fn attribution_window(&self) -> Option<NonZeroU32> {
+        NonZeroU32::new(self.attribution_window)
+    }
+
+    fn config(&self) -> IpaQueryConfig {
+        IpaQueryConfig {
+            per_user_credit_cap: self.per_user_cap,
+            max_breakdown_key: self.breakdown_keys,
+            attribution_window_seconds: self.attribution_window(),
+            num_multi_bits: self.num_multi_bits,
+            plaintext_match_keys: true,
+        }
+    }
+}
+
+async fn measure(args: &Args, size: usize, seed: u64) -> CostSample {
+    let config = TestWorldConfig {
+        gateway_config: GatewayConfig::new(size.clamp(16, 1024)),
+        ..TestWorldConfig::default()
+    }
+    .with_link(LinkConfig::default().with_latency(LATENCY));
+    let rng = StdRng::seed_from_u64(seed);
+    let sample = match args.protocol {
+        Protocol::Ipa => ipa(args, config, size, rng).await,
+        Protocol::CreditCapping => user_capping(args, config, size, rng).await,
+        Protocol::MultiBitPermutation => permutation(args, config, size, rng).await,
+    };
+
+    match args.depth {
+        Some(depth) => sample.truncate(depth),
+        None => sample,
+    }
+}
+
+async fn ipa(args: &Args, config: TestWorldConfig, size: usize, rng: StdRng) -> CostSample {
+    let raw_data = EventGenerator::with_config(
+        rng,
+        EventGeneratorConfig {
+            max_breakdown_key: NonZeroU32::try_from(args.breakdown_keys).unwrap(),
+            max_events_per_user: NonZeroU32::try_from(args.records_per_user).unwrap(),
+            ..Default::default()
+        },
+    )
+    .take(size)
+    .collect::<Vec<_>>();
+    let expected_results =
+        ipa_in_the_clear(&raw_data, args.per_user_cap, args.attribution_window());
+    let (query_config, mode) = (args.config(), args.mode);
+
+    CostSample::measure(config, size, |world| {
+        async move {
+            test_ipa::<BenchField>(world, &raw_data, &expected_results, query_config, mode).await;
+        }
+        .boxed_local()
+    })
+    .await
+}
+
+async fn user_capping(
+    args: &Args,
+    config: TestWorldConfig,
+    size: usize,
+    mut rng: StdRng,
+) -> CostSample {
+    let (cap, mode) = (args.per_user_cap, args.mode);
+    let mut column = |value: &mut dyn FnMut(&mut StdRng) -> u32| {
+        (0..size)
+            .map(|_| BenchField::truncate_from(value(&mut rng)))
+            .collect::<Vec<_>>()
+    };
+    let is_trigger_report = column(&mut |rng| u32::from(rng.gen::<bool>()));
+    let helper_bit = column(&mut |rng| u32::from(rng.gen::<bool>()));
+    let credit = column(&mut |rng| rng.gen_range(0..=cap));
+    let input = (
+        is_trigger_report.into_iter(),
+        (helper_bit.into_iter(), credit.into_iter()),
+    );
+
+    CostSample::measure(config, size, |world| {
+        async move {
+            match mode {
+                IpaSecurityModel::SemiHonest => {
+                    world
+                        .semi_honest(input, |ctx, input| cap_credits(ctx, input, cap))
+                        .await;
+                }
+                IpaSecurityModel::Malicious => {
+                    world
+                        .upgraded_malicious(input, |ctx, input| cap_credits(ctx, input, cap))
+                        .await;
+                }
+            }
+        }
+        .boxed_local()
+    })
+    .await
+}
+
+async fn cap_credits<C, S>(
+    ctx: C,
+    (is_trigger_report, (helper_bit, credit)): (Vec<S>, (Vec<S>, Vec<S>)),
+    cap: u32,
+) -> Vec<S>
+where
+    C: Context + RandomBits<BenchField, Share = S>,
+    S: LinearSecretSharing<BenchField> + BasicProtocols<C, BenchField>,
+{
+    let rows = zip(is_trigger_report, zip(helper_bit, credit))
+        .map(|(is_trigger_report, (helper_bit, credit))| {
+            MCCreditCappingInputRow::new(
+                is_trigger_report,
+                helper_bit,
+                BitDecomposed::new(Vec::new()),
+                credit,
+            )
+        })
+        .collect::<Vec<_>>();
+    credit_capping(ctx, &rows, cap)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.credit)
+        .collect()
+}
+
+async fn permutation(
+    args: &Args,
+    config: TestWorldConfig,
+    size: usize,
+    mut rng: StdRng,
+) -> CostSample {
+    let input = (0..size)
+        .map(|_| {
+            BitDecomposed::new(
+                (0..args.num_multi_bits)
+                    .map(|_| BenchField::truncate_from(rng.gen::<bool>()))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mode = args.mode;
+
+    CostSample::measure(config, size, |world| {
+        async move {
+            match mode {
+                IpaSecurityModel::SemiHonest => {
+                    world
+                        .semi_honest(input.into_iter(), |ctx, input| async move {
+                            permute(ctx.validator::<BenchField>().context(), input).await
+                        })
+                        .await;
+                }
+                IpaSecurityModel::Malicious => {
+                    world.upgraded_malicious(input.into_iter(), permute).await;
+                }
+            }
+        }
+        .boxed_local()
+    })
+    .await
+}
+
+async fn permute<F, C, S>(ctx: C, input: Vec<BitDecomposed<S>>) -> Vec<S>
+where
+    F: PrimeField + ExtendableField,
+    C: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F> + 'static,
+{
+    multi_bit_permutation(ctx, &input).await.unwrap()
+}
+
+async fn run(args: Args) -> Result<(), Error> {
+    let seed = args.random_seed.unwrap_or_else(|| thread_rng().gen());
+    let mut samples = Vec::with_capacity(args.sizes.len());
+    for &size in &args.sizes {
+        tracing::info!(
+            "measuring {m:?} {p:?} for {size} records",
+            m = args.mode,
+            p = args.protocol
+        );
+        samples.push(measure(&args, size, seed).await);
+    }
+    let report = CostReport::new(samples);
+
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout()),
+    };
+    match args.format {
+        Format::Csv => report.export(&mut output)?,
+        Format::Json => report.export_json(&mut output)?,
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    let args = Args::parse();
+    // Pausing the clock requires a single-threaded runtime.
+    let rt = Builder::new_current_thread()
+        .enable_all()
+        .start_paused(true)
+        .build()
+        .unwrap();
+    rt.block_on(run(args))
+}
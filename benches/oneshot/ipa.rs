@@ -2,7 +2,8 @@ use clap::Parser;
 use ipa::{
     error::Error,
     ff::Fp32BitPrime,
-    helpers::{query::IpaQueryConfig, GatewayConfig},
+    helpers::{query::IpaQueryConfig, GatewayConfig, LinkConfig},
+    telemetry::StepStatsCsvExporter,
     test_fixture::{
         ipa::{ipa_in_the_clear, test_ipa, IpaSecurityModel},
         EventGenerator, EventGeneratorConfig, TestWorld, TestWorldConfig,
@@ -10,8 +11,9 @@ use ipa::{
 };
 use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
 use std::{
+    io::stdout,
     num::{NonZeroU32, NonZeroUsize},
-    time::Instant,
+    time::{Duration, Instant},
 };
 use tokio::runtime::Builder;
 
@@ -66,6 +68,13 @@ struct Args {
     /// Desired security model for IPA protocol
     #[arg(short = 'm', long, value_enum, default_value_t=IpaSecurityModel::Malicious)]
     mode: IpaSecurityModel,
+    /// Simulated latency between helpers, in milliseconds. If set, the number of communication
+    /// rounds per step is printed after the run.
+    #[arg(long, default_value = "0")]
+    latency_ms: u64,
+    /// Simulated bandwidth of the links between helpers, in bytes per second.
+    #[arg(long)]
+    bandwidth: Option<NonZeroU32>,
     /// Needed for benches.
     #[arg(long, hide = true)]
     bench: bool,
@@ -91,6 +100,14 @@ impl Args {
             plaintext_match_keys: true,
         }
     }
+
+    fn link(&self) -> LinkConfig {
+        let link = LinkConfig::default().with_latency(Duration::from_millis(self.latency_ms));
+        match self.bandwidth {
+            Some(bandwidth) => link.with_bandwidth(bandwidth),
+            None => link,
+        }
+    }
 }
 
 async fn run(args: Args) -> Result<(), Error> {
@@ -99,6 +116,7 @@ async fn run(args: Args) -> Result<(), Error> {
     let _prep_time = Instant::now();
     let config = TestWorldConfig {
         gateway_config: GatewayConfig::new(args.active()),
+        link: args.link(),
         ..TestWorldConfig::default()
     };
 
@@ -141,6 +159,11 @@ async fn run(args: Args) -> Result<(), Error> {
         q = args.query_size,
         t = _protocol_time.elapsed()
     );
+    if args.latency_ms > 0 {
+        let rounds = world.rounds();
+        rounds.export(&mut stdout())?;
+        println!("Total rounds: {}", rounds.total());
+    }
     Ok(())
 }
 
@@ -6,11 +6,12 @@ use crate::{
     hpke::{KeyPair, KeyRegistry},
     protocol::QueryId,
     query::{
-        NewQueryError, QueryCompletionError, QueryInputError, QueryProcessor, QueryStatus,
-        QueryStatusError,
+        NewQueryError, QueryCompletionError, QueryInputError, QueryProcessor, QueryProgress,
+        QueryStatus, QueryStatusError,
     },
     sync::Arc,
 };
+use std::num::NonZeroU32;
 
 pub struct Setup {
     query_processor: Arc<QueryProcessor>,
@@ -33,7 +34,25 @@ impl Setup {
     pub fn with_key_registry(
         key_registry: KeyRegistry<KeyPair>,
     ) -> (Self, TransportCallbacks<TransportImpl>) {
-        let query_processor = Arc::new(QueryProcessor::new(key_registry));
+        Self::with_query_processor(QueryProcessor::new(key_registry))
+    }
+
+    /// Sets up a helper that sends the data of every query to each peer no faster than
+    /// `bytes_per_second`.
+    #[must_use]
+    pub fn with_bandwidth_limit(
+        key_registry: KeyRegistry<KeyPair>,
+        bytes_per_second: NonZeroU32,
+    ) -> (Self, TransportCallbacks<TransportImpl>) {
+        Self::with_query_processor(
+            QueryProcessor::new(key_registry).with_bandwidth_limit(bytes_per_second),
+        )
+    }
+
+    fn with_query_processor(
+        query_processor: QueryProcessor,
+    ) -> (Self, TransportCallbacks<TransportImpl>) {
+        let query_processor = Arc::new(query_processor);
         let this = Self {
             query_processor: Arc::clone(&query_processor),
         };
@@ -53,6 +72,7 @@ impl Setup {
         let pqp = Arc::clone(query_processor);
         let iqp = Arc::clone(query_processor);
         let sqp = Arc::clone(query_processor);
+        let gqp = Arc::clone(query_processor);
         let cqp = Arc::clone(query_processor);
 
         TransportCallbacks {
@@ -76,6 +96,10 @@ impl Setup {
                 let processor = Arc::clone(&sqp);
                 Box::pin(async move { processor.query_status(query_id) })
             }),
+            query_progress: Box::new(move |_transport: TransportImpl, query_id| {
+                let processor = Arc::clone(&gqp);
+                Box::pin(async move { processor.query_progress(query_id) })
+            }),
             complete_query: Box::new(move |_transport: TransportImpl, query_id| {
                 let processor = Arc::clone(&cqp);
                 Box::pin(async move { processor.complete(query_id).await })
@@ -123,6 +147,14 @@ impl HelperApp {
         Ok(self.query_processor.query_status(query_id)?)
     }
 
+    /// Retrieves the progress of a running query.
+    ///
+    /// ## Errors
+    /// Propagates errors from the helper.
+    pub fn query_progress(&self, query_id: QueryId) -> Result<Option<QueryProgress>, Error> {
+        Ok(self.query_processor.query_progress(query_id)?)
+    }
+
     /// Waits for a query to complete and returns the result.
     ///
     /// ## Errors
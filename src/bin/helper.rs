@@ -2,20 +2,29 @@ use clap::{self, Parser, Subcommand};
 use hyper::http::uri::Scheme;
 use ipa::{
     cli::{
-        client_config_setup, keygen, test_setup, ConfGenArgs, KeygenArgs, TestSetupArgs, Verbosity,
+        client_config_setup, discovery_keygen, keygen, sign_network, test_setup, ConfGenArgs,
+        DiscoveryKeygenArgs, KeygenArgs, SignNetworkArgs, TestSetupArgs, Verbosity,
+    },
+    config::{
+        hpke_registry, HpkeServerConfig, NetworkConfig, ReportCollectorsConfig, ServerConfig,
+        TlsConfig, TlsReloadConfig,
     },
-    config::{hpke_registry, HpkeServerConfig, NetworkConfig, ServerConfig, TlsConfig},
     error::BoxError,
     helpers::HelperIdentity,
-    net::{ClientIdentity, HttpTransport, MpcHelperClient},
+    net::{
+        discovery::{DocumentSource, Signed},
+        ClientIdentity, HttpTransport, MpcHelperClient,
+    },
     AppSetup,
 };
 use std::{
     fs,
     net::TcpListener,
+    num::{NonZeroU32, NonZeroU64},
     os::fd::{FromRawFd, RawFd},
     path::{Path, PathBuf},
     process,
+    time::Duration,
 };
 use tracing::{error, info};
 
@@ -64,9 +73,27 @@ struct ServerArgs {
     disable_https: bool,
 
     /// File containing helper network configuration
-    #[arg(long, required = true)]
+    #[arg(
+        long,
+        required_unless_present = "discovery",
+        conflicts_with = "discovery"
+    )]
     network: Option<PathBuf>,
 
+    /// Signed discovery document to read the helper network configuration from, instead of
+    /// `--network`. Either a file or an `http://` URL.
+    #[arg(long, requires = "discovery_root_key")]
+    discovery: Option<DocumentSource>,
+
+    /// File containing the hex-encoded root key that discovery documents must be signed with
+    #[arg(long, requires = "discovery")]
+    discovery_root_key: Option<PathBuf>,
+
+    /// How often to check the discovery document for changes, in seconds. Changes are applied
+    /// between queries.
+    #[arg(long, default_value = "60")]
+    discovery_interval: u64,
+
     /// TLS certificate for helper-to-helper communication
     #[arg(
         long,
@@ -87,12 +114,32 @@ struct ServerArgs {
     /// Private key for decrypting match keys
     #[arg(long, requires = "mk_public_key")]
     mk_private_key: Option<PathBuf>,
+
+    /// How often to check the TLS certificates and the network configuration for changes, in
+    /// seconds. Changed certificates are used without restarting the helper.
+    #[arg(long, default_value = "60")]
+    tls_reload_interval: NonZeroU64,
+
+    /// Warn when a TLS certificate expires within this many days
+    #[arg(long, default_value = "30")]
+    cert_expiry_warning_days: u64,
+
+    /// File listing the report collectors that are allowed to run queries. If not specified, the
+    /// query APIs do not require authentication.
+    #[arg(long)]
+    report_collectors: Option<PathBuf>,
+
+    /// Maximum number of bytes per second sent to each of the other helpers, by every query
+    #[arg(long)]
+    bandwidth_limit: Option<NonZeroU32>,
 }
 
 #[derive(Debug, Subcommand)]
 enum HelperCommand {
     Confgen(ConfGenArgs),
+    DiscoveryKeygen(DiscoveryKeygenArgs),
     Keygen(KeygenArgs),
+    SignNetwork(SignNetworkArgs),
     TestSetup(TestSetupArgs),
 }
 
@@ -130,13 +177,24 @@ async fn server(args: ServerArgs) -> Result<(), BoxError> {
         });
 
     let key_registry = hpke_registry(mk_encryption.as_ref()).await?;
-    let (setup, callbacks) = AppSetup::with_key_registry(key_registry);
+    let (setup, callbacks) = match args.bandwidth_limit {
+        Some(limit) => AppSetup::with_bandwidth_limit(key_registry, limit),
+        None => AppSetup::with_key_registry(key_registry),
+    };
+
+    let report_collectors = args
+        .report_collectors
+        .as_deref()
+        .map(|path| ReportCollectorsConfig::from_toml_str(&fs::read_to_string(path)?))
+        .transpose()?
+        .unwrap_or_default();
 
     let server_config = ServerConfig {
         port: args.port,
         disable_https: args.disable_https,
         tls: server_tls,
         hpke_config: mk_encryption,
+        report_collectors: report_collectors.collectors,
     };
 
     let scheme = if args.disable_https {
@@ -144,10 +202,19 @@ async fn server(args: ServerArgs) -> Result<(), BoxError> {
     } else {
         Scheme::HTTPS
     };
-    let network_config_path = args.network.as_deref().unwrap();
-    let network_config = NetworkConfig::from_toml_str(&fs::read_to_string(network_config_path)?)?
-        .override_scheme(&scheme);
-    let clients = MpcHelperClient::from_conf(&network_config, identity);
+    let discovery = match (args.discovery, args.discovery_root_key.as_deref()) {
+        (Some(source), Some(root_key)) => {
+            Some(Signed::new(source, fs::read_to_string(root_key)?.parse()?).await?)
+        }
+        _ => None,
+    };
+    let network_config = match (args.network.as_deref(), &discovery) {
+        (Some(path), _) => NetworkConfig::from_toml_str(&fs::read_to_string(path)?)?,
+        (None, Some(discovery)) => discovery.network_config(),
+        (None, None) => panic!("should have been rejected by clap"),
+    }
+    .override_scheme(&scheme);
+    let clients = MpcHelperClient::from_conf(&network_config, identity)?;
 
     let (transport, server) = HttpTransport::new(
         my_identity,
@@ -182,6 +249,15 @@ async fn server(args: ServerArgs) -> Result<(), BoxError> {
             None as Option<()>,
         )
         .await;
+    let _streams = server.start_streams_on(None).await?;
+    let _tls_watch = server.watch_tls(TlsReloadConfig {
+        interval: Duration::from_secs(args.tls_reload_interval.get()),
+        network_config_path: args.network,
+        expiry_warning: Duration::from_secs(args.cert_expiry_warning_days * 24 * 60 * 60),
+    });
+    let _discovery_watch = discovery.map(|discovery| {
+        server.watch_discovery(discovery, Duration::from_secs(args.discovery_interval))
+    });
 
     server_handle.await?;
 
@@ -198,6 +274,8 @@ pub async fn main() {
         Some(HelperCommand::Keygen(args)) => keygen(&args),
         Some(HelperCommand::TestSetup(args)) => test_setup(args),
         Some(HelperCommand::Confgen(args)) => client_config_setup(args),
+        Some(HelperCommand::DiscoveryKeygen(args)) => discovery_keygen(&args),
+        Some(HelperCommand::SignNetwork(args)) => sign_network(&args),
     };
 
     if let Err(e) = res {
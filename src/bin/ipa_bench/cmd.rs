@@ -1,7 +1,21 @@
-use crate::{gen_events::generate_events, sample::Sample};
+#[cfg(not(all(feature = "test-fixture", feature = "web-app")))]
+use crate::gen_events::generate_events;
+use crate::sample::Sample;
+#[cfg(all(feature = "test-fixture", feature = "web-app"))]
+use crate::{ipa_input::IpaInputArgs, run::run};
 use clap::Parser;
+#[cfg(all(feature = "test-fixture", feature = "web-app"))]
+use hyper::http::uri::Scheme;
 use ipa::cli::Verbosity;
+#[cfg(all(feature = "test-fixture", feature = "web-app"))]
+use ipa::{
+    cli::playbook::InputSource,
+    helpers::query::IpaQueryConfig,
+    test_fixture::ipa::{IpaSecurityModel, TestRawDataRecord},
+};
 use rand::{rngs::StdRng, SeedableRng};
+#[cfg(all(feature = "test-fixture", feature = "web-app"))]
+use std::io::Write;
 use std::{
     fs::File,
     io,
@@ -89,6 +103,42 @@ pub enum Command {
             help = "Configuration file containing distributions data."
         )]
         config_file: PathBuf,
+
+        #[cfg(all(feature = "test-fixture", feature = "web-app"))]
+        #[clap(flatten)]
+        ipa_input: IpaInputArgs,
+    },
+
+    #[cfg(all(feature = "test-fixture", feature = "web-app"))]
+    #[command(about = "Run IPA on generated events and measure latency and memory.")]
+    Run {
+        #[arg(
+            short,
+            long,
+            help = "IPA inputs, as written by gen-events --format csv. Read from stdin if not set."
+        )]
+        input_file: Option<PathBuf>,
+
+        #[arg(
+            long,
+            help = "Helper network configuration file. If not set, IPA runs on helpers in memory, which needs the in-memory-infra feature."
+        )]
+        network: Option<PathBuf>,
+
+        #[arg(long, help = "Use insecure HTTP to talk to the helpers.")]
+        disable_https: bool,
+
+        #[arg(
+            short,
+            long,
+            value_enum,
+            default_value_t = IpaSecurityModel::Malicious,
+            help = "Security model of the IPA protocol."
+        )]
+        mode: IpaSecurityModel,
+
+        #[clap(flatten)]
+        config: IpaQueryConfig,
     },
 }
 
@@ -102,8 +152,35 @@ impl Command {
                 random_seed,
                 epoch,
                 config_file,
+                #[cfg(all(feature = "test-fixture", feature = "web-app"))]
+                ipa_input,
+            } => {
+                Command::gen_events(
+                    common,
+                    *scale_factor,
+                    random_seed,
+                    *epoch,
+                    config_file,
+                    #[cfg(all(feature = "test-fixture", feature = "web-app"))]
+                    ipa_input,
+                );
+            }
+            #[cfg(all(feature = "test-fixture", feature = "web-app"))]
+            Self::Run {
+                input_file,
+                network,
+                disable_https,
+                mode,
+                config,
             } => {
-                Command::gen_events(common, *scale_factor, random_seed, *epoch, config_file);
+                Command::run_ipa(
+                    common,
+                    input_file,
+                    network.as_deref(),
+                    *disable_https,
+                    *mode,
+                    *config,
+                );
             }
         }
     }
@@ -114,6 +191,7 @@ impl Command {
         random_seed: &Option<u64>,
         epoch: u8,
         config_file: &Path,
+        #[cfg(all(feature = "test-fixture", feature = "web-app"))] ipa_input: &IpaInputArgs,
     ) {
         let mut input = Command::get_input(&Some(config_file.to_path_buf())).unwrap_or_else(|e| {
             error!("Failed to open the input file. {}", e);
@@ -139,13 +217,16 @@ impl Command {
 
         let mut rng = random_seed.map_or(StdRng::from_entropy(), StdRng::seed_from_u64);
 
-        let (s_count, t_count) = generate_events(
-            &sample,
-            DEFAULT_EVENT_GEN_COUNT * scale_factor,
-            epoch,
-            &mut rng,
-            &mut out,
-        );
+        let total_count = DEFAULT_EVENT_GEN_COUNT * scale_factor;
+        #[cfg(all(feature = "test-fixture", feature = "web-app"))]
+        let (s_count, t_count) = ipa_input
+            .generate(&sample, total_count, epoch, &mut rng, &mut out)
+            .unwrap_or_else(|e| {
+                error!("Failed to write IPA inputs. {}", e);
+                process::exit(1);
+            });
+        #[cfg(not(all(feature = "test-fixture", feature = "web-app")))]
+        let (s_count, t_count) = generate_events(&sample, total_count, epoch, &mut rng, &mut out);
 
         info!("{} source events generated", s_count);
         info!("{} trigger events generated", t_count);
@@ -155,6 +236,48 @@ impl Command {
         );
     }
 
+    #[cfg(all(feature = "test-fixture", feature = "web-app"))]
+    fn run_ipa(
+        common: &CommonArgs,
+        input_file: &Option<PathBuf>,
+        network: Option<&Path>,
+        disable_https: bool,
+        mode: IpaSecurityModel,
+        config: IpaQueryConfig,
+    ) {
+        let input = match input_file {
+            Some(path) => InputSource::from_file(path),
+            None => InputSource::from_stdin(),
+        };
+        let records = input.iter::<TestRawDataRecord>().collect::<Vec<_>>();
+        info!("{} IPA inputs read", records.len());
+
+        let mut out = common.get_output().unwrap_or_else(|e| {
+            error!("Failed to open the output file. {}", e);
+            process::exit(1);
+        });
+
+        let scheme = if disable_https {
+            Scheme::HTTP
+        } else {
+            Scheme::HTTPS
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let result = runtime
+            .block_on(run(&records, network, scheme, mode, config))
+            .unwrap_or_else(|e| {
+                error!("Failed to run IPA. {}", e);
+                process::exit(1);
+            });
+
+        info!(
+            "IPA on {} inputs took {:.3}s",
+            result.input_size, result.latency_secs
+        );
+        serde_json::to_writer_pretty(&mut out, &result).unwrap();
+        writeln!(out).unwrap();
+    }
+
     fn get_input(path: &Option<PathBuf>) -> Result<Box<dyn io::Read>, io::Error> {
         match path {
             Some(ref path) => File::open(path).map(|f| Box::new(f) as Box<dyn io::Read>),
@@ -16,12 +16,30 @@ const RECORD_SEPARATOR: u8 = 30;
 // TODO: Currently, users are mutually exclusive in each ad loop (i.e. User A in ad X will never appear in other ads).
 // We need to generate events from same users across ads (but how often should a user appear in different ads?)
 
+/// Writes events as a JSON text sequence.
 pub fn generate_events<R: RngCore + CryptoRng, W: io::Write>(
     sample: &Sample,
     total_count: u32,
     epoch: Epoch,
     rng: &mut R,
     out: &mut W,
+) -> (u32, u32) {
+    generate_reports(sample, total_count, epoch, rng, |e| {
+        out.write_all(RECORD_SEPARATOR.as_raw_slice()).unwrap();
+        out.write_all(serde_json::to_string(&e).unwrap().as_bytes())
+            .unwrap();
+        writeln!(out).unwrap();
+    })
+}
+
+/// Generates `total_count` events and passes them to `emit`, grouped by user. Returns the
+/// number of impressions and conversions of the users that events were generated for.
+pub fn generate_reports<R: RngCore + CryptoRng, F: FnMut(GenericReport)>(
+    sample: &Sample,
+    total_count: u32,
+    epoch: Epoch,
+    rng: &mut R,
+    mut emit: F,
 ) -> (u32, u32) {
     let mut ad_count = 0;
     let mut event_count = 0;
@@ -64,10 +82,7 @@ pub fn generate_events<R: RngCore + CryptoRng, W: io::Write>(
             total_conversions += u32::from(conversions);
 
             for e in events {
-                out.write_all(RECORD_SEPARATOR.as_raw_slice()).unwrap();
-                out.write_all(serde_json::to_string(&e).unwrap().as_bytes())
-                    .unwrap();
-                writeln!(out).unwrap();
+                emit(e);
 
                 event_count += 1;
                 if event_count % 10000 == 0 {
@@ -1,7 +1,11 @@
 mod cmd;
 mod config;
 mod gen_events;
+#[cfg(all(feature = "test-fixture", feature = "web-app"))]
+mod ipa_input;
 mod models;
+#[cfg(all(feature = "test-fixture", feature = "web-app"))]
+mod run;
 mod sample;
 
 use clap::Parser;
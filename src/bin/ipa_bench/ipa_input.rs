@@ -0,0 +1,272 @@
+use crate::{
+    gen_events::{generate_events, generate_reports},
+    models::GenericReport,
+    sample::Sample,
+};
+use ipa::{
+    cli::CsvSerializer,
+    config::{NetworkConfig, PeerConfig},
+    ff::Fp32BitPrime,
+    hpke::{KeyRegistry, PublicKeyOnly},
+    protocol::{BreakdownKey, MatchKey},
+    report::{Epoch, Report, DEFAULT_KEY_ID},
+    secret_sharing::{IntoShares, SharedValue},
+    test_fixture::ipa::TestRawDataRecord,
+};
+use rand::{CryptoRng, RngCore};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    io::{self, Write},
+    num::NonZeroU32,
+    path::PathBuf,
+};
+
+/// Formats that `gen-events` writes events in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum EventFormat {
+    /// JSON text sequence of the events
+    JsonSeq,
+    /// Plaintext IPA inputs, as CSV that `report_collector` reads
+    Csv,
+    /// Reports encrypted for the helpers, the three reports of every event one after another
+    Encrypted,
+}
+
+/// How `gen-events` writes events as IPA inputs.
+#[derive(Debug, clap::Args)]
+pub struct IpaInputArgs {
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = EventFormat::JsonSeq,
+        help = "Format of the generated events. IPA inputs are either plaintext, for report_collector, or encrypted reports for the helpers."
+    )]
+    format: EventFormat,
+
+    #[arg(
+        long,
+        default_value = "20",
+        help = "Number of breakdown keys that ads are mapped to in IPA inputs."
+    )]
+    max_breakdown_key: NonZeroU32,
+
+    #[arg(
+        long,
+        required_if_eq("format", "encrypted"),
+        help = "Helper network configuration file with the public keys that reports are encrypted with."
+    )]
+    network: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value = "www.example.com",
+        help = "Site domain of the encrypted reports."
+    )]
+    site_domain: String,
+}
+
+impl IpaInputArgs {
+    /// Generates `total_count` events and writes them to `out` in the chosen format. Returns the
+    /// number of impressions and conversions, like [`generate_events`].
+    ///
+    /// # Errors
+    /// If the network configuration can't be read, or encryption or writing to `out` fails.
+    pub fn generate<R: RngCore + CryptoRng, W: Write>(
+        &self,
+        sample: &Sample,
+        total_count: u32,
+        epoch: u8,
+        rng: &mut R,
+        out: &mut W,
+    ) -> Result<(u32, u32), Box<dyn Error>> {
+        if self.format == EventFormat::JsonSeq {
+            return Ok(generate_events(sample, total_count, epoch, rng, out));
+        }
+
+        let mut reports = Vec::new();
+        let counts = generate_reports(sample, total_count, epoch, rng, |e| reports.push(e));
+        let events = to_ipa_events(&reports, self.max_breakdown_key);
+        match self.format {
+            EventFormat::JsonSeq => unreachable!("events are written as they are"),
+            EventFormat::Csv => write_csv(&events, out)?,
+            EventFormat::Encrypted => {
+                // clap makes sure that there is a network for encrypted reports
+                let network = self.network.as_ref().unwrap();
+                let network = NetworkConfig::from_toml_str(&fs::read_to_string(network)?)?;
+                let registries = key_registries(&network)?;
+                write_encrypted(events, &registries, &self.site_domain, rng, out)?;
+            }
+        }
+
+        Ok(counts)
+    }
+}
+
+/// An IPA input record, with the epoch that its event happened in.
+pub struct IpaEvent {
+    pub record: TestRawDataRecord,
+    pub epoch: Epoch,
+}
+
+/// Turns events into IPA inputs, ordered by timestamp as IPA expects them. Every ad gets a
+/// breakdown key of its own, until there are more ads than breakdown keys; then keys are
+/// reused. Match keys are cut down to the bits that IPA match keys have.
+pub fn to_ipa_events(reports: &[GenericReport], max_breakdown_key: NonZeroU32) -> Vec<IpaEvent> {
+    const MATCH_KEY_MASK: u64 = (1 << MatchKey::BITS) - 1;
+
+    let mut ads = HashMap::new();
+    let mut events = reports
+        .iter()
+        .map(|report| {
+            let (event, is_trigger_report, breakdown_key, trigger_value) = match *report {
+                GenericReport::Source {
+                    event,
+                    breakdown_key: ad_id,
+                } => {
+                    let next = u32::try_from(ads.len()).unwrap();
+                    let breakdown_key = *ads.entry(ad_id).or_insert(next) % max_breakdown_key;
+                    (event, false, breakdown_key, 0)
+                }
+                GenericReport::Trigger { event, value } => (event, true, 0, value),
+            };
+            IpaEvent {
+                record: TestRawDataRecord {
+                    timestamp: u64::from(u32::from(event.timestamp)),
+                    user_id: event.matchkey & MATCH_KEY_MASK,
+                    is_trigger_report,
+                    breakdown_key,
+                    trigger_value,
+                },
+                epoch: Epoch::from(event.timestamp.epoch()),
+            }
+        })
+        .collect::<Vec<_>>();
+    events.sort_by_key(|event| event.record.timestamp);
+
+    events
+}
+
+/// # Errors
+/// If writing to `out` fails.
+pub fn write_csv<W: Write>(events: &[IpaEvent], out: &mut W) -> io::Result<()> {
+    for event in events {
+        event.record.to_csv(out)?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Public key registries of the helpers in `network`, in the order of the helpers.
+///
+/// # Errors
+/// If one of the helpers has no public key.
+pub fn key_registries(network: &NetworkConfig) -> Result<[KeyRegistry<PublicKeyOnly>; 3], String> {
+    let [h1, h2, h3] = network.peers();
+    let registry = |peer: &PeerConfig| {
+        peer.hpke_config
+            .as_ref()
+            .map(|hpke| KeyRegistry::from_keys([PublicKeyOnly(hpke.public_key.clone())]))
+            .ok_or_else(|| format!("helper {} has no public key", peer.url))
+    };
+
+    Ok([registry(h1)?, registry(h2)?, registry(h3)?])
+}
+
+/// Encrypts every event for the helpers, as reports from `site_domain`, and writes them
+/// length-delimited, the three reports of every event one after another.
+///
+/// # Errors
+/// If encryption or writing to `out` fails.
+pub fn write_encrypted<R: RngCore + CryptoRng, W: Write>(
+    events: Vec<IpaEvent>,
+    key_registries: &[KeyRegistry<PublicKeyOnly>; 3],
+    site_domain: &str,
+    rng: &mut R,
+    out: &mut W,
+) -> Result<(), Box<dyn Error>> {
+    let mut buf = Vec::new();
+    for IpaEvent { record, epoch } in events {
+        let shares: [Report<Fp32BitPrime, MatchKey, BreakdownKey>; 3] = record.share_with(rng);
+        for (mut share, key_registry) in shares.into_iter().zip(key_registries) {
+            share.epoch = epoch;
+            share.site_domain = site_domain.to_string();
+            share.delimited_encrypt_to(DEFAULT_KEY_ID, key_registry, rng, &mut buf)?;
+        }
+        out.write_all(&buf)?;
+        buf.clear();
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::models::{Event, EventTimestamp};
+
+    fn event(matchkey: u64, offset: u32) -> Event {
+        Event {
+            matchkey,
+            attribution_constraint_id: None,
+            timestamp: EventTimestamp::new(1, offset),
+        }
+    }
+
+    #[test]
+    fn converts_to_ipa_inputs() {
+        let reports = [
+            GenericReport::Source {
+                event: event(1, 20),
+                breakdown_key: 1234,
+            },
+            GenericReport::Trigger {
+                event: event(1, 30),
+                value: 5,
+            },
+            GenericReport::Source {
+                event: event(2, 10),
+                breakdown_key: 99,
+            },
+            GenericReport::Source {
+                event: event(3, 40),
+                breakdown_key: 7,
+            },
+        ];
+        let events = to_ipa_events(&reports, NonZeroU32::new(2).unwrap());
+
+        let base = u64::from(EventTimestamp::SECONDS_IN_EPOCH);
+        let records = events
+            .iter()
+            .map(|event| {
+                let r = &event.record;
+                (
+                    r.timestamp - base,
+                    r.user_id,
+                    r.is_trigger_report,
+                    r.breakdown_key,
+                    r.trigger_value,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (10, 2, false, 1, 0),
+                (20, 1, false, 0, 0),
+                (30, 1, true, 0, 5),
+                (40, 3, false, 0, 0),
+            ],
+            records
+        );
+        assert!(events.iter().all(|event| event.epoch == 1));
+
+        let mut csv = Vec::new();
+        write_csv(&events[..1], &mut csv).unwrap();
+        assert_eq!(
+            format!("{},2,0,1,0\n", base + 10),
+            String::from_utf8(csv).unwrap()
+        );
+    }
+}
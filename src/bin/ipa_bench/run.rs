@@ -0,0 +1,150 @@
+use crate::ipa_input::key_registries;
+use hyper::http::uri::Scheme;
+#[cfg(feature = "in-memory-infra")]
+use ipa::test_fixture::{ipa::test_ipa, TestWorld};
+use ipa::{
+    cli::playbook::{make_clients, playbook_ipa, validate},
+    ff::{FieldType, Fp32BitPrime},
+    helpers::query::{IpaQueryConfig, QueryConfig, QuerySize, QueryType, StepStreamConfig},
+    net::ClientIdentity,
+    protocol::{BreakdownKey, MatchKey},
+    report::DEFAULT_KEY_ID,
+    test_fixture::ipa::{ipa_oracle, IpaSecurityModel, TestRawDataRecord},
+};
+use serde::Serialize;
+use std::{error::Error, fs, path::Path, time::Instant};
+
+/// Helper network that IPA runs on.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NetworkKind {
+    InMemory,
+    Http,
+}
+
+/// Measurements of one IPA run.
+#[derive(Debug, Serialize)]
+pub struct RunResult {
+    pub mode: IpaSecurityModel,
+    pub network: NetworkKind,
+    pub input_size: usize,
+    pub config: IpaQueryConfig,
+    pub latency_secs: f64,
+    /// Peak resident memory of this process, if the platform reports it. With an HTTP network,
+    /// that is the memory of the report collector only.
+    pub peak_memory_bytes: Option<u64>,
+    pub breakdowns: Vec<u32>,
+}
+
+/// Runs IPA on `records` and checks the results against IPA in the clear. Without `network`,
+/// helpers run in memory, inside this process.
+///
+/// # Errors
+/// If the network configuration can't be read, or helpers have no public keys while match keys
+/// are to be encrypted, or helpers are to run in memory without the `in-memory-infra` feature.
+pub async fn run(
+    records: &[TestRawDataRecord],
+    network: Option<&Path>,
+    scheme: Scheme,
+    mode: IpaSecurityModel,
+    config: IpaQueryConfig,
+) -> Result<RunResult, Box<dyn Error>> {
+    let expected = ipa_oracle(records, &config);
+
+    let start = Instant::now();
+    let (network_kind, breakdowns) = if let Some(network) = network {
+        let breakdowns = run_http(records, network, scheme, mode, config).await?;
+        validate(&expected, &breakdowns)?;
+        (NetworkKind::Http, breakdowns)
+    } else {
+        let breakdowns = run_in_memory(records, expected, mode, config).await?;
+        (NetworkKind::InMemory, breakdowns)
+    };
+    let latency = start.elapsed();
+
+    Ok(RunResult {
+        mode,
+        network: network_kind,
+        input_size: records.len(),
+        config,
+        latency_secs: latency.as_secs_f64(),
+        peak_memory_bytes: peak_memory(),
+        breakdowns,
+    })
+}
+
+#[cfg(feature = "in-memory-infra")]
+async fn run_in_memory(
+    records: &[TestRawDataRecord],
+    expected: Vec<u32>,
+    mode: IpaSecurityModel,
+    config: IpaQueryConfig,
+) -> Result<Vec<u32>, Box<dyn Error>> {
+    // `test_ipa` fails if the results don't match the expected ones.
+    test_ipa::<Fp32BitPrime>(&TestWorld::default(), records, &expected, config, mode).await;
+
+    Ok(expected)
+}
+
+#[cfg(not(feature = "in-memory-infra"))]
+async fn run_in_memory(
+    _records: &[TestRawDataRecord],
+    _expected: Vec<u32>,
+    _mode: IpaSecurityModel,
+    _config: IpaQueryConfig,
+) -> Result<Vec<u32>, Box<dyn Error>> {
+    Err("helpers can only run in memory with the in-memory-infra feature, set --network to run them over HTTP".into())
+}
+
+async fn run_http(
+    records: &[TestRawDataRecord],
+    network: &Path,
+    scheme: Scheme,
+    mode: IpaSecurityModel,
+    config: IpaQueryConfig,
+) -> Result<Vec<u32>, Box<dyn Error>> {
+    let (clients, network) = make_clients(Some(network), scheme, 0, ClientIdentity::None).await;
+    let query_type = match mode {
+        IpaSecurityModel::SemiHonest => QueryType::SemiHonestIpa(config),
+        IpaSecurityModel::Malicious => QueryType::MaliciousIpa(config),
+    };
+    let query_id = clients[0]
+        .create_query(QueryConfig {
+            size: QuerySize::try_from(records.len())?,
+            field_type: FieldType::Fp32BitPrime,
+            query_type,
+            step_stream: StepStreamConfig::default(),
+        })
+        .await?;
+
+    let registries = if config.plaintext_match_keys {
+        None
+    } else {
+        Some(key_registries(&network)?)
+    };
+    let encryption = registries
+        .as_ref()
+        .map(|[r1, r2, r3]| (DEFAULT_KEY_ID, [r1, r2, r3]));
+    let result = playbook_ipa::<Fp32BitPrime, MatchKey, BreakdownKey, _>(
+        records, &clients, query_id, config, encryption,
+    )
+    .await
+    .map_err(|e| e as Box<dyn Error>)?;
+
+    Ok(result.breakdowns)
+}
+
+/// Reads the peak resident set size of this process from `/proc`, which only Linux has.
+fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kb * 1024)
+}
@@ -2,26 +2,32 @@ use clap::{Parser, Subcommand};
 use hyper::http::uri::Scheme;
 use ipa::{
     cli::{
-        playbook::{make_clients, playbook_ipa, validate, InputSource},
+        playbook::{
+            make_clients, playbook_encrypted_ipa, playbook_ipa, validate, EncryptedInputs,
+            InputSource, ReportFiles, ReportFilter,
+        },
         Verbosity,
     },
-    config::NetworkConfig,
+    config::{NetworkConfig, TokenKey},
     ff::{FieldType, Fp32BitPrime},
-    helpers::query::{IpaQueryConfig, QueryConfig, QueryType},
+    helpers::query::{IpaQueryConfig, QueryConfig, QueryType, StepStreamConfig},
     hpke::{KeyRegistry, PublicKeyOnly},
-    net::MpcHelperClient,
+    net::{sign_bearer_token, ClientIdentity, MpcHelperClient},
     protocol::{BreakdownKey, MatchKey},
     report::{KeyIdentifier, DEFAULT_KEY_ID},
     test_fixture::{
-        ipa::{ipa_in_the_clear, IpaSecurityModel, TestRawDataRecord},
-        EventGenerator, EventGeneratorConfig,
+        ipa::{ipa_oracle, IpaSecurityModel, TestRawDataRecord},
+        EventGenerator, EventGeneratorConfig, SimulationConfig,
     },
 };
 
 use comfy_table::{Cell, Table};
 use ipa::{
     cli::{
+        dictionary::{BreakdownDictionary, BREAKDOWN_KEY_LIMIT, NO_CAMPAIGN},
+        job::{JobSpec, QuerySpec},
         noise::{apply, ApplyDpArgs},
+        output::{histogram, write_noisy, write_result, OutputArgs},
         CsvSerializer, IpaQueryResult,
     },
     helpers::query::QuerySize,
@@ -34,9 +40,11 @@ use std::{
     fmt::Debug,
     fs::{File, OpenOptions},
     io,
-    io::{stdout, Write},
+    io::{stdout, BufRead, Write},
+    num::NonZeroU32,
     ops::Deref,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
 #[derive(Debug, Parser)]
@@ -58,17 +66,73 @@ struct Args {
     #[arg(short, long, default_value_t = 0)]
     wait: usize,
 
+    #[clap(flatten)]
+    auth: CollectorAuth,
+
     #[clap(flatten)]
     input: CommandInput,
 
+    #[clap(flatten)]
+    step_stream: StepStreamConfig,
+
     /// The destination file for output.
     #[arg(long, value_name = "FILE")]
     output_file: Option<PathBuf>,
 
+    #[clap(flatten)]
+    output: OutputArgs,
+
     #[command(subcommand)]
     action: ReportCollectorCommand,
 }
 
+/// How this report collector authenticates to the helpers. Helpers that don't restrict the query
+/// APIs to known report collectors accept requests without authentication.
+#[derive(Debug, Parser)]
+struct CollectorAuth {
+    /// Client certificate to authenticate with, in PEM format
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// Private key of the client certificate, in PKCS8 PEM format
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Name of this report collector in the helper configuration, to authenticate with a bearer
+    /// token
+    #[arg(long, requires = "token_key_file")]
+    collector_name: Option<String>,
+
+    /// File containing the hex-encoded key that signs bearer tokens
+    #[arg(long, requires = "collector_name")]
+    token_key_file: Option<PathBuf>,
+
+    /// Seconds that bearer tokens are valid for
+    #[arg(long, default_value_t = 3600)]
+    token_lifetime: u64,
+}
+
+impl CollectorAuth {
+    fn identity(&self) -> Result<ClientIdentity, Box<dyn Error>> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Ok(ClientIdentity::from_pks8(
+                &std::fs::read(cert)?,
+                &std::fs::read(key)?,
+            )?),
+            _ => Ok(ClientIdentity::None),
+        }
+    }
+
+    fn bearer_token(&self) -> Result<Option<String>, Box<dyn Error>> {
+        let (Some(name), Some(key_file)) = (&self.collector_name, &self.token_key_file) else {
+            return Ok(None);
+        };
+        let key = TokenKey::try_from(std::fs::read_to_string(key_file)?)?;
+        let expires = SystemTime::now() + Duration::from_secs(self.token_lifetime);
+        Ok(Some(sign_bearer_token(name, &key, expires)))
+    }
+}
+
 #[derive(Debug, Parser)]
 pub struct CommandInput {
     #[arg(
@@ -104,11 +168,102 @@ enum ReportCollectorCommand {
         #[clap(long, short = 's')]
         seed: Option<u64>,
 
+        /// Generate events for the campaigns of this breakdown dictionary, instead of
+        /// `--max-breakdown-key` breakdowns
+        #[clap(long, value_name = "FILE")]
+        dictionary: Option<PathBuf>,
+
         #[clap(flatten)]
         gen_args: EventGeneratorConfig,
+
+        #[command(subcommand)]
+        model: Option<EventModel>,
     },
     /// Apply differential privacy noise to IPA inputs
     ApplyDpNoise(ApplyDpArgs),
+    /// Execute IPA on reports that were encrypted by user agents
+    EncryptedIpa(EncryptedIpaArgs),
+    /// Maintain the dictionary that maps campaigns to breakdown keys
+    Dictionary(DictionaryArgs),
+    /// Run the queries of a job spec file and write the manifest of their outcomes
+    RunJobs {
+        /// Job spec file, in toml format
+        #[arg(long, value_name = "FILE")]
+        spec: PathBuf,
+    },
+}
+
+/// How events are generated. Without one, events are sampled uniformly.
+#[derive(Debug, Subcommand)]
+enum EventModel {
+    /// Simulate users that see impressions and convert over several epochs. Records have the
+    /// epoch, site domain and device of their event as extra columns
+    Simulate(SimulationConfig),
+}
+
+#[derive(Debug, clap::Args)]
+struct EncryptedIpaArgs {
+    /// Files with the reports encrypted for each helper, in the order of the helpers
+    #[arg(
+        long,
+        num_args = 3,
+        value_names = ["H1_FILE", "H2_FILE", "H3_FILE"],
+        required_unless_present = "combined_file",
+        conflicts_with = "combined_file"
+    )]
+    helper_files: Vec<PathBuf>,
+
+    /// File with the reports of every event for each helper, one event after another
+    #[arg(long, value_name = "FILE")]
+    combined_file: Option<PathBuf>,
+
+    /// Desired security model for IPA protocol
+    #[arg(short = 'm', long, value_enum, default_value_t = IpaSecurityModel::Malicious)]
+    mode: IpaSecurityModel,
+
+    #[clap(flatten)]
+    filter: ReportFilter,
+
+    #[clap(flatten)]
+    config: IpaQueryConfig,
+}
+
+impl EncryptedIpaArgs {
+    fn files(&self) -> ReportFiles {
+        match &self.combined_file {
+            Some(path) => ReportFiles::Combined(path.clone()),
+            None => ReportFiles::PerHelper(self.helper_files.clone().try_into().unwrap()),
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct DictionaryArgs {
+    /// Breakdown dictionary file. It is created if it does not exist
+    #[arg(long, value_name = "FILE")]
+    file: PathBuf,
+
+    /// Campaigns get breakdown keys below this value
+    #[arg(long, default_value_t = BREAKDOWN_KEY_LIMIT)]
+    max_breakdown_key: u32,
+
+    #[command(subcommand)]
+    action: DictionaryCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum DictionaryCommand {
+    /// Assign breakdown keys to campaigns and print them
+    Assign {
+        #[arg(required = true)]
+        campaigns: Vec<String>,
+    },
+    /// Print every campaign and its breakdown key
+    List,
+    /// Replace the campaigns of input records with their breakdown keys. Campaigns that are not in
+    /// the dictionary yet get new keys. Records with no campaign get breakdown key 0, which is never
+    /// assigned to a campaign
+    Ingest,
 }
 
 #[derive(Debug, clap::Args)]
@@ -131,7 +286,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
         Scheme::HTTPS
     };
 
-    let (clients, network) = make_clients(args.network.as_deref(), scheme, args.wait).await;
+    let (clients, network) = make_clients(
+        args.network.as_deref(),
+        scheme,
+        args.wait,
+        args.auth.identity()?,
+    )
+    .await;
+    let clients = match args.auth.bearer_token()? {
+        Some(token) => clients.map(|client| client.with_bearer_token(&token)),
+        None => clients,
+    };
     match args.action {
         ReportCollectorCommand::SemiHonestIpa(config) => {
             ipa(
@@ -156,9 +321,25 @@ async fn main() -> Result<(), Box<dyn Error>> {
         ReportCollectorCommand::GenIpaInputs {
             count,
             seed,
-            gen_args,
-        } => gen_inputs(count, seed, args.output_file, gen_args)?,
+            dictionary,
+            mut gen_args,
+            model,
+        } => {
+            if let Some(path) = dictionary {
+                gen_args.max_breakdown_key = campaign_count(&path)?;
+            }
+            gen_inputs(count, seed, args.output_file, gen_args, model)?
+        }
         ReportCollectorCommand::ApplyDpNoise(ref dp_args) => apply_dp_noise(&args, dp_args)?,
+        ReportCollectorCommand::EncryptedIpa(ref ipa_args) => {
+            encrypted_ipa(&args, ipa_args, &clients).await?
+        }
+        ReportCollectorCommand::Dictionary(ref dictionary_args) => {
+            maintain_dictionary(&args, dictionary_args)?
+        }
+        ReportCollectorCommand::RunJobs { ref spec } => {
+            run_jobs(&args, spec, &network, &clients).await?
+        }
     };
 
     Ok(())
@@ -169,25 +350,99 @@ fn gen_inputs(
     seed: Option<u64>,
     output_file: Option<PathBuf>,
     args: EventGeneratorConfig,
+    model: Option<EventModel>,
 ) -> io::Result<()> {
     let rng = seed
         .map(StdRng::seed_from_u64)
         .unwrap_or_else(|| StdRng::from_entropy());
-    let event_gen = EventGenerator::with_config(rng, args).take(count as usize);
     let mut writer: Box<dyn Write> = if let Some(path) = output_file {
         Box::new(OpenOptions::new().write(true).create_new(true).open(path)?)
     } else {
         Box::new(stdout().lock())
     };
 
-    for event in event_gen {
-        event.to_csv(&mut writer)?;
-        writer.write(&[b'\n'])?;
+    match model {
+        None => write_events(
+            EventGenerator::with_config(rng, args).take(count as usize),
+            &mut writer,
+        ),
+        Some(EventModel::Simulate(simulation)) => write_events(
+            EventGenerator::with_simulation(rng, args, simulation)
+                .events()
+                .take(count as usize),
+            &mut writer,
+        ),
+    }
+}
+
+fn write_events<E: CsvSerializer, W: Write>(
+    events: impl Iterator<Item = E>,
+    writer: &mut W,
+) -> io::Result<()> {
+    for event in events {
+        event.to_csv(writer)?;
+        writer.write_all(&[b'\n'])?;
     }
 
     Ok(())
 }
 
+/// Returns the max breakdown key that covers every campaign of the breakdown dictionary at `path`.
+fn campaign_count(path: &Path) -> Result<NonZeroU32, Box<dyn Error>> {
+    let dictionary = BreakdownDictionary::read(path, BREAKDOWN_KEY_LIMIT)?;
+    NonZeroU32::new(dictionary.key_bound())
+        .ok_or_else(|| format!("breakdown dictionary {} has no campaigns", path.display()).into())
+}
+
+fn maintain_dictionary(
+    args: &Args,
+    dictionary_args: &DictionaryArgs,
+) -> Result<(), Box<dyn Error>> {
+    let mut dictionary =
+        BreakdownDictionary::read(&dictionary_args.file, dictionary_args.max_breakdown_key)?;
+    match &dictionary_args.action {
+        DictionaryCommand::Assign { campaigns } => {
+            for campaign in campaigns {
+                println!("{},{campaign}", dictionary.assign(campaign)?);
+            }
+        }
+        DictionaryCommand::List => {
+            dictionary.to_csv(&mut stdout().lock())?;
+            return Ok(());
+        }
+        DictionaryCommand::Ingest => {
+            let mut writer: Box<dyn Write> = if let Some(path) = &args.output_file {
+                Box::new(OpenOptions::new().write(true).create_new(true).open(path)?)
+            } else {
+                Box::new(stdout().lock())
+            };
+            for line in InputSource::from(&args.input).lines() {
+                let line = line?;
+                let [ts, user_id, is_trigger, campaign, trigger_value] =
+                    line.splitn(5, ',').collect::<Vec<_>>()[..]
+                else {
+                    return Err(format!("{line} is not a valid input record").into());
+                };
+                let key = if campaign.is_empty() {
+                    NO_CAMPAIGN
+                } else {
+                    dictionary.assign(campaign)?
+                };
+                writeln!(writer, "{ts},{user_id},{is_trigger},{key},{trigger_value}")?;
+            }
+        }
+    }
+
+    dictionary.write(&dictionary_args.file)?;
+    tracing::info!(
+        "breakdown dictionary {} has {} campaigns",
+        dictionary_args.file.display(),
+        dictionary.len()
+    );
+
+    Ok(())
+}
+
 #[derive(Default)]
 struct KeyRegistries(Vec<KeyRegistry<PublicKeyOnly>>);
 
@@ -197,18 +452,14 @@ impl KeyRegistries {
         network: &NetworkConfig,
     ) -> Option<(KeyIdentifier, [&KeyRegistry<PublicKeyOnly>; 3])> {
         // Get the configs, if all three peers have one
-        let Some(configs) = network
-            .peers()
-            .iter()
-            .fold(Some(vec![]), |acc, peer| {
-                if let (Some(mut vec), Some(hpke_config)) = (acc, peer.hpke_config.as_ref()) {
-                    vec.push(hpke_config);
-                    Some(vec)
-                } else {
-                    None
-                }
-            })
-        else {
+        let Some(configs) = network.peers().iter().fold(Some(vec![]), |acc, peer| {
+            if let (Some(mut vec), Some(hpke_config)) = (acc, peer.hpke_config.as_ref()) {
+                vec.push(hpke_config);
+                Some(vec)
+            } else {
+                None
+            }
+        }) else {
             return None;
         };
 
@@ -232,7 +483,31 @@ async fn ipa(
     ipa_query_config: IpaQueryConfig,
     helper_clients: &[MpcHelperClient; 3],
 ) -> Result<(), Box<dyn Error>> {
-    let input = InputSource::from(&args.input);
+    let input_rows = InputSource::from(&args.input)
+        .iter::<TestRawDataRecord>()
+        .collect::<Vec<_>>();
+    let actual = run_ipa(
+        network,
+        security_model,
+        ipa_query_config,
+        helper_clients,
+        &input_rows,
+        args.step_stream,
+    )
+    .await?;
+
+    write_output(&args.output, args.output_file.as_deref(), &actual)
+}
+
+/// Runs IPA on `input_rows` and checks the results against IPA in the clear.
+async fn run_ipa(
+    network: &NetworkConfig,
+    security_model: IpaSecurityModel,
+    ipa_query_config: IpaQueryConfig,
+    helper_clients: &[MpcHelperClient; 3],
+    input_rows: &[TestRawDataRecord],
+    step_stream: StepStreamConfig,
+) -> Result<IpaQueryResult, Box<dyn Error>> {
     let query_type: QueryType;
     match security_model {
         IpaSecurityModel::SemiHonest => {
@@ -243,45 +518,142 @@ async fn ipa(
         }
     };
 
-    let input_rows = input.iter::<TestRawDataRecord>().collect::<Vec<_>>();
     let query_config = QueryConfig {
         size: QuerySize::try_from(input_rows.len()).unwrap(),
         field_type: FieldType::Fp32BitPrime,
         query_type,
+        step_stream,
     };
-    let query_id = helper_clients[0].create_query(query_config).await.unwrap();
+    let query_id = helper_clients[0].create_query(query_config).await?;
 
-    let expected = {
-        let mut r = ipa_in_the_clear(
-            &input_rows,
-            ipa_query_config.per_user_credit_cap,
-            ipa_query_config.attribution_window_seconds,
-        );
-
-        // pad the output vector to the max breakdown key, to make sure it is aligned with the MPC results
-        // truncate shouldn't happen unless in_the_clear is badly broken
-        r.resize(
-            usize::try_from(ipa_query_config.max_breakdown_key).unwrap(),
-            0,
-        );
-        r
-    };
+    let expected = ipa_oracle(input_rows, &ipa_query_config);
 
     let mut key_registries = KeyRegistries::default();
     let actual = playbook_ipa::<Fp32BitPrime, MatchKey, BreakdownKey, _>(
-        &input_rows,
+        input_rows,
         &helper_clients,
         query_id,
         ipa_query_config,
         key_registries.init_from(network),
     )
-    .await;
+    .await
+    .map_err(|e| e as Box<dyn Error>)?;
 
     tracing::info!("{m:?}", m = ipa_query_config);
 
-    validate(&expected, &actual.breakdowns);
+    validate(&expected, &actual.breakdowns)?;
+
+    Ok(actual)
+}
 
-    if let Some(ref path) = args.output_file {
+async fn encrypted_ipa(
+    args: &Args,
+    ipa_args: &EncryptedIpaArgs,
+    helper_clients: &[MpcHelperClient; 3],
+) -> Result<(), Box<dyn Error>> {
+    let reports = EncryptedInputs::read::<Fp32BitPrime>(&ipa_args.files(), &ipa_args.filter)?;
+    tracing::info!(
+        "submitting {} reports, {} reports did not pass the filter",
+        reports.count,
+        reports.skipped
+    );
+    if reports.count == 0 {
+        return Err("no reports to submit".into());
+    }
+
+    if ipa_args.config.plaintext_match_keys {
+        tracing::warn!(
+            "ignoring --plaintext-match-keys, the match keys of the reports are encrypted"
+        );
+    }
+    let ipa_query_config = IpaQueryConfig {
+        plaintext_match_keys: false,
+        ..ipa_args.config
+    };
+    let query_type = match ipa_args.mode {
+        IpaSecurityModel::SemiHonest => QueryType::SemiHonestIpa(ipa_query_config),
+        IpaSecurityModel::Malicious => QueryType::MaliciousIpa(ipa_query_config),
+    };
+    let query_config = QueryConfig {
+        size: QuerySize::try_from(reports.count).unwrap(),
+        field_type: FieldType::Fp32BitPrime,
+        query_type,
+        step_stream: args.step_stream,
+    };
+    let query_id = helper_clients[0].create_query(query_config).await?;
+
+    let actual =
+        playbook_encrypted_ipa::<Fp32BitPrime>(reports, helper_clients, query_id, ipa_query_config)
+            .await
+            .map_err(|e| e as Box<dyn Error>)?;
+    tracing::info!("{m:?}", m = ipa_query_config);
+
+    write_output(&args.output, args.output_file.as_deref(), &actual)
+}
+
+async fn run_jobs(
+    args: &Args,
+    spec: &Path,
+    network: &NetworkConfig,
+    helper_clients: &[MpcHelperClient; 3],
+) -> Result<(), Box<dyn Error>> {
+    let spec = JobSpec::from_toml_str(&std::fs::read_to_string(spec)?)?;
+    let run_query = |query: &QuerySpec| {
+        // the future can't borrow from the query, it outlives the reference `run` gets
+        let (input, mode, config) = (query.input.clone(), query.mode, query.config);
+        async move {
+            let input_rows = InputSource::from_file(&input)
+                .iter::<TestRawDataRecord>()
+                .collect::<Vec<_>>();
+            run_ipa(
+                network,
+                mode,
+                config,
+                helper_clients,
+                &input_rows,
+                args.step_stream,
+            )
+            .await
+            .map_err(|e| e.to_string())
+        }
+    };
+    let mut manifest = spec.run(run_query).await;
+
+    // outputs are written once the queries are done, so a failure to write one does not run its
+    // query again.
+    for query in &mut manifest.queries {
+        if let Some(result) = &query.result {
+            if let Err(e) = write_output(&args.output, query.output.as_deref(), result) {
+                tracing::error!("failed to write the results of query {}: {e}", query.name);
+                query.error = Some(format!("failed to write the results: {e}"));
+            }
+        }
+    }
+
+    let manifest_json = serde_json::to_string_pretty(&manifest)?;
+    match &args.output_file {
+        Some(path) => std::fs::write(path, manifest_json)?,
+        None => println!("{manifest_json}"),
+    }
+
+    if manifest.is_success() {
+        Ok(())
+    } else {
+        Err("some of the queries failed, see the manifest for details".into())
+    }
+}
+
+fn write_output(
+    output_args: &OutputArgs,
+    output_file: Option<&Path>,
+    actual: &IpaQueryResult,
+) -> Result<(), Box<dyn Error>> {
+    let labels = output_args.labels()?;
+    if output_args.histogram {
+        println!("{}", histogram(&labels.rows(&actual.breakdowns), 40));
+    }
+
+    if let Some(path) = output_file {
         // it will be sad to lose the results if file already exists.
         let path = if Path::is_file(&path) {
             let mut new_file_name = thread_rng()
@@ -313,7 +685,7 @@ async fn ipa(
             .open(path.deref())
             .map_err(|e| format!("Failed to create output file {}: {e}", path.display()))?;
 
-        write!(file, "{}", serde_json::to_string_pretty(&actual)?)?;
+        write_result(&mut file, output_args.output_format, &labels, actual)?;
     }
 
     Ok(())
@@ -323,19 +695,22 @@ fn apply_dp_noise(args: &Args, dp_args: &ApplyDpArgs) -> Result<(), Box<dyn Erro
     let IpaQueryResult { breakdowns, .. } =
         serde_json::from_slice(&InputSource::from(&args.input).to_vec()?)?;
 
+    let labels = args.output.labels()?;
+    let confidence = args.output.confidence;
     let output = apply(&breakdowns, &dp_args);
     let mut table = Table::new();
     let header = std::iter::once("Epsilon".to_string())
         .chain(std::iter::once("Variance".to_string()))
         .chain(std::iter::once("Mean".to_string()))
-        .chain((0..breakdowns.len()).map(|i| format!("{}", i + 1)))
+        .chain(std::iter::once(format!("{:.0}% CI", confidence * 100.0)))
+        .chain((0..).take(breakdowns.len()).map(|key| labels.label(key)))
         .collect::<Vec<_>>();
     table.set_header(header);
 
     // original values
     table.add_row(
         std::iter::repeat("-".to_string())
-            .take(3)
+            .take(4)
             .chain(breakdowns.iter().map(ToString::to_string)),
     );
 
@@ -347,6 +722,10 @@ fn apply_dp_noise(args: &Args, dp_args: &ApplyDpArgs) -> Result<(), Box<dyn Erro
             Cell::new(format!("{:.3}", epsilon)),
             Cell::new(format!("{:.3}", noised_values.std)),
             Cell::new(format!("{:.3}", noised_values.mean)),
+            Cell::new({
+                let interval = noised_values.confidence_interval(0, confidence);
+                format!("±{:.3}", (interval.upper - interval.lower) / 2.0)
+            }),
         ];
 
         for agg in noised_values.breakdowns.iter() {
@@ -358,9 +737,25 @@ fn apply_dp_noise(args: &Args, dp_args: &ApplyDpArgs) -> Result<(), Box<dyn Erro
 
     println!("{}", table);
 
+    if args.output.histogram {
+        for (epsilon, noised_values) in output.iter().rev() {
+            println!("epsilon = {epsilon}");
+            println!(
+                "{}",
+                histogram(&labels.noisy_rows(noised_values, confidence), 40)
+            );
+        }
+    }
+
     if let Some(file) = &args.output_file {
         let mut file = File::create(file)?;
-        serde_json::to_writer_pretty(&mut file, &output)?;
+        write_noisy(
+            &mut file,
+            args.output.output_format,
+            &labels,
+            &output,
+            confidence,
+        )?;
     }
 
     Ok(())
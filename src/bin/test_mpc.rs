@@ -6,9 +6,9 @@ use ipa::{
         playbook::{make_clients, secure_mul, validate, InputSource},
         Verbosity,
     },
-    ff::{Field, FieldType, Fp31, Fp32BitPrime, Serializable},
+    ff::{Field, FieldType, Fp31, Fp32BitPrime, Fp61BitPrime, Serializable},
     helpers::query::{QueryConfig, QueryType::TestMultiply},
-    net::MpcHelperClient,
+    net::{ClientIdentity, MpcHelperClient},
     secret_sharing::{replicated::semi_honest::AdditiveShare, IntoShares},
 };
 use std::{error::Error, fmt::Debug, ops::Add, path::PathBuf};
@@ -90,7 +90,13 @@ async fn main() -> Result<(), Box<dyn Error>> {
         Scheme::HTTPS
     };
 
-    let (clients, _) = make_clients(args.network.as_deref(), scheme, args.wait).await;
+    let (clients, _) = make_clients(
+        args.network.as_deref(),
+        scheme,
+        args.wait,
+        ClientIdentity::None,
+    )
+    .await;
     match args.action {
         TestAction::Multiply => multiply(&args, &clients).await,
     };
@@ -112,12 +118,13 @@ where
     let expected = input_rows.iter().map(|(a, b)| *a * *b).collect::<Vec<_>>();
     let actual = secure_mul(input_rows, &helper_clients, query_id).await;
 
-    validate(&expected, &actual);
+    validate(&expected, &actual).unwrap();
 }
 
 async fn multiply(args: &Args, helper_clients: &[MpcHelperClient; 3]) {
     match args.input.field {
         FieldType::Fp31 => multiply_in_field::<Fp31>(&args, helper_clients).await,
         FieldType::Fp32BitPrime => multiply_in_field::<Fp32BitPrime>(&args, helper_clients).await,
+        FieldType::Fp61BitPrime => multiply_in_field::<Fp61BitPrime>(&args, helper_clients).await,
     };
 }
@@ -20,3 +20,14 @@ impl Serializer for crate::test_fixture::ipa::TestRawDataRecord {
         Ok(())
     }
 }
+
+#[cfg(any(test, feature = "test-fixture"))]
+impl Serializer for crate::test_fixture::GeneratedEvent {
+    /// Writes the columns of the record, followed by the epoch, site domain and device.
+    fn to_csv<W: Write>(&self, buf: &mut W) -> io::Result<()> {
+        self.record.to_csv(buf)?;
+        write!(buf, ",{},{},{}", self.epoch, self.site_domain, self.device)?;
+
+        Ok(())
+    }
+}
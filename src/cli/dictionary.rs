@@ -0,0 +1,279 @@
+//! Breakdown dictionary of the report collector. Reports carry small integer breakdown keys, which
+//! the report collector assigns to its campaigns. The dictionary keeps that mapping in a file, in
+//! the same `key,campaign` format as breakdown labels, so the results of IPA can be labelled
+//! with it. Breakdown key [`NO_CAMPAIGN`] is never assigned, it is reserved for records that are
+//! not attributed to any campaign.
+
+use crate::{
+    cli::output::{BreakdownLabels, BreakdownLabelsError},
+    ff::Field,
+    protocol::BreakdownKey,
+    secret_sharing::SharedValue,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+/// The number of distinct breakdown keys that reports can carry.
+pub const BREAKDOWN_KEY_LIMIT: u32 = 1 << BreakdownKey::BITS;
+
+/// The breakdown key of records with no campaign.
+pub const NO_CAMPAIGN: u32 = 0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DictionaryError {
+    #[error(transparent)]
+    Malformed(#[from] BreakdownLabelsError),
+    #[error("failed to write breakdown dictionary: {0}")]
+    Io(#[from] io::Error),
+    #[error("max breakdown key {0} is more than the {BREAKDOWN_KEY_LIMIT} keys reports can carry")]
+    MaxBreakdownKey(u32),
+    #[error("campaign {0} has more than one breakdown key")]
+    DuplicateCampaign(String),
+    #[error("campaign {0} has breakdown key {NO_CAMPAIGN}, which is reserved for records with no campaign")]
+    ReservedKey(String),
+    #[error("breakdown key {key} of campaign {campaign} is not below max breakdown key {max_breakdown_key}")]
+    KeyOutOfRange {
+        key: u32,
+        campaign: String,
+        max_breakdown_key: u32,
+    },
+    #[error("no breakdown key left for campaign {campaign}, all keys below {max_breakdown_key} are assigned")]
+    Full {
+        campaign: String,
+        max_breakdown_key: u32,
+    },
+}
+
+/// Bidirectional mapping between campaigns and breakdown keys below `max_breakdown_key`.
+#[derive(Debug)]
+pub struct BreakdownDictionary {
+    campaigns: BTreeMap<u32, String>,
+    keys: HashMap<String, u32>,
+    max_breakdown_key: u32,
+}
+
+impl BreakdownDictionary {
+    /// Creates an empty dictionary.
+    ///
+    /// # Errors
+    /// If `max_breakdown_key` is more than [`BREAKDOWN_KEY_LIMIT`].
+    pub fn new(max_breakdown_key: u32) -> Result<Self, DictionaryError> {
+        if max_breakdown_key > BREAKDOWN_KEY_LIMIT {
+            return Err(DictionaryError::MaxBreakdownKey(max_breakdown_key));
+        }
+
+        Ok(Self {
+            campaigns: BTreeMap::new(),
+            keys: HashMap::new(),
+            max_breakdown_key,
+        })
+    }
+
+    /// Reads the dictionary from `path`. If there is no such file, the dictionary is empty.
+    ///
+    /// # Errors
+    /// If the file can't be read or is malformed, or has keys that are not below
+    /// `max_breakdown_key`.
+    pub fn read(path: &Path, max_breakdown_key: u32) -> Result<Self, DictionaryError> {
+        if path.exists() {
+            Self::from_csv_str(&fs::read_to_string(path)?, max_breakdown_key)
+        } else {
+            Self::new(max_breakdown_key)
+        }
+    }
+
+    /// Parses the dictionary from one `key,campaign` pair per line.
+    ///
+    /// # Errors
+    /// If `input` is malformed, maps a key or a campaign more than once, assigns [`NO_CAMPAIGN`],
+    /// or has keys that are not below `max_breakdown_key`.
+    pub fn from_csv_str(input: &str, max_breakdown_key: u32) -> Result<Self, DictionaryError> {
+        let mut dictionary = Self::new(max_breakdown_key)?;
+        for (key, campaign) in BreakdownLabels::from_csv_str(input)?.iter() {
+            if key == NO_CAMPAIGN {
+                return Err(DictionaryError::ReservedKey(campaign.to_string()));
+            }
+            if key >= max_breakdown_key {
+                return Err(DictionaryError::KeyOutOfRange {
+                    key,
+                    campaign: campaign.to_string(),
+                    max_breakdown_key,
+                });
+            }
+            dictionary.insert(key, campaign)?;
+        }
+
+        Ok(dictionary)
+    }
+
+    fn insert(&mut self, key: u32, campaign: &str) -> Result<(), DictionaryError> {
+        if self.keys.insert(campaign.to_string(), key).is_some() {
+            return Err(DictionaryError::DuplicateCampaign(campaign.to_string()));
+        }
+        self.campaigns.insert(key, campaign.to_string());
+
+        Ok(())
+    }
+
+    /// Writes the dictionary to `path`. The file is replaced at once, so it is never left half
+    /// written.
+    ///
+    /// # Errors
+    /// If the file can't be written.
+    pub fn write(&self, path: &Path) -> Result<(), DictionaryError> {
+        let tmp = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        self.to_csv(&mut file)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Writes one `key,campaign` pair per line, ordered by key.
+    ///
+    /// # Errors
+    /// If writing to `w` fails.
+    pub fn to_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for (key, campaign) in &self.campaigns {
+            writeln!(w, "{key},{campaign}")?;
+        }
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn key(&self, campaign: &str) -> Option<u32> {
+        self.keys.get(campaign).copied()
+    }
+
+    #[must_use]
+    pub fn campaign(&self, key: u32) -> Option<&str> {
+        self.campaigns.get(&key).map(String::as_str)
+    }
+
+    /// Returns the breakdown key of `campaign`. Campaigns that are not in the dictionary yet
+    /// get the lowest key that is free, other than [`NO_CAMPAIGN`].
+    ///
+    /// # Errors
+    /// If `campaign` is new and every key below `max_breakdown_key` is assigned.
+    pub fn assign(&mut self, campaign: &str) -> Result<u32, DictionaryError> {
+        if let Some(key) = self.key(campaign) {
+            return Ok(key);
+        }
+        let key = (NO_CAMPAIGN + 1..self.max_breakdown_key)
+            .find(|key| !self.campaigns.contains_key(key))
+            .ok_or_else(|| DictionaryError::Full {
+                campaign: campaign.to_string(),
+                max_breakdown_key: self.max_breakdown_key,
+            })?;
+        self.insert(key, campaign)?;
+
+        Ok(key)
+    }
+
+    /// Same as [`Self::assign`], as the breakdown key that reports carry.
+    ///
+    /// # Errors
+    /// If `campaign` is new and every key below `max_breakdown_key` is assigned.
+    pub fn assign_breakdown_key(
+        &mut self,
+        campaign: &str,
+    ) -> Result<BreakdownKey, DictionaryError> {
+        // keys are below `BREAKDOWN_KEY_LIMIT`, so they are never truncated.
+        Ok(BreakdownKey::truncate_from(self.assign(campaign)?))
+    }
+
+    /// Returns the campaigns and their breakdown keys, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.campaigns
+            .iter()
+            .map(|(key, campaign)| (*key, campaign.as_str()))
+    }
+
+    /// Returns the smallest max breakdown key that covers every campaign in the dictionary.
+    #[must_use]
+    pub fn key_bound(&self) -> u32 {
+        self.campaigns.keys().next_back().map_or(0, |key| key + 1)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.campaigns.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.campaigns.is_empty()
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assigns_free_keys() {
+        let mut dictionary = BreakdownDictionary::from_csv_str("2,campaign-b\n", 4).unwrap();
+        assert_eq!(1, dictionary.assign("campaign-a").unwrap());
+        assert_eq!(2, dictionary.assign("campaign-b").unwrap());
+        assert_eq!(
+            BreakdownKey::truncate_from(3_u128),
+            dictionary.assign_breakdown_key("campaign-c").unwrap()
+        );
+        assert_eq!(Some("campaign-c"), dictionary.campaign(3));
+        assert_eq!(Some(3), dictionary.key("campaign-c"));
+        assert_eq!(None, dictionary.campaign(NO_CAMPAIGN));
+        assert_eq!(4, dictionary.key_bound());
+
+        assert!(matches!(
+            dictionary.assign("campaign-d"),
+            Err(DictionaryError::Full {
+                max_breakdown_key: 4,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut dictionary = BreakdownDictionary::new(BREAKDOWN_KEY_LIMIT).unwrap();
+        for campaign in ["a", "b", "c"] {
+            dictionary.assign(campaign).unwrap();
+        }
+        let mut buf = Vec::new();
+        dictionary.to_csv(&mut buf).unwrap();
+        let csv = String::from_utf8(buf).unwrap();
+        assert_eq!("1,a\n2,b\n3,c\n", csv);
+
+        let read_back = BreakdownDictionary::from_csv_str(&csv, 4).unwrap();
+        assert_eq!(
+            dictionary.iter().collect::<Vec<_>>(),
+            read_back.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_dictionaries() {
+        assert!(matches!(
+            BreakdownDictionary::from_csv_str("1,a\n2,a\n", 3),
+            Err(DictionaryError::DuplicateCampaign(campaign)) if campaign == "a"
+        ));
+        assert!(matches!(
+            BreakdownDictionary::from_csv_str("1,a\n3,b\n", 3),
+            Err(DictionaryError::KeyOutOfRange { key: 3, .. })
+        ));
+        assert!(matches!(
+            BreakdownDictionary::from_csv_str("0,a\n1,b\n", 3),
+            Err(DictionaryError::ReservedKey(campaign)) if campaign == "a"
+        ));
+        assert!(matches!(
+            BreakdownDictionary::new(BREAKDOWN_KEY_LIMIT + 1),
+            Err(DictionaryError::MaxBreakdownKey(_))
+        ));
+    }
+}
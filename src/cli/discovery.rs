@@ -0,0 +1,102 @@
+use crate::{
+    error::BoxError,
+    net::discovery::{RootKey, SignedDocument},
+};
+use clap::Args;
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Args)]
+#[clap(
+    name = "discovery-keygen",
+    about = "Generate the key pair that signs peer discovery documents",
+    next_help_heading = "Discovery Key Generation Options"
+)]
+pub struct DiscoveryKeygenArgs {
+    /// Writes the generated signing key to the file
+    #[arg(long)]
+    signing_key: PathBuf,
+
+    /// Writes the generated root key, which helpers verify discovery documents with, to the file
+    #[arg(long)]
+    root_key: PathBuf,
+}
+
+#[derive(Debug, Args)]
+#[clap(
+    name = "sign-network",
+    about = "Sign a network configuration, to publish it for peer discovery",
+    next_help_heading = "Signing Options"
+)]
+pub struct SignNetworkArgs {
+    /// Network configuration to sign, in the same format as `network.toml`
+    #[arg(long)]
+    network: PathBuf,
+
+    /// File containing the signing key
+    #[arg(long)]
+    signing_key: PathBuf,
+
+    /// Version of the document. Helpers only accept documents that are newer than the one they
+    /// use, so it must be higher than the version of the previously published document
+    #[arg(long)]
+    document_version: u64,
+
+    /// Number of seconds, from now, for which helpers accept the document
+    #[arg(long)]
+    valid_for_secs: Option<u64>,
+
+    /// Writes the signed discovery document to the file
+    #[arg(long)]
+    output: PathBuf,
+}
+
+/// Generate the key pair that signs peer discovery documents. Both keys are written hex-encoded.
+///
+/// # Errors
+/// If a problem is encountered during key generation, or either file exists already.
+pub fn discovery_keygen(args: &DiscoveryKeygenArgs) -> Result<(), BoxError> {
+    let (signing_key, root_key) = RootKey::generate()?;
+
+    File::options()
+        .write(true)
+        .create_new(true)
+        .open(&args.signing_key)?
+        .write_all(hex::encode(signing_key).as_bytes())?;
+    File::options()
+        .write(true)
+        .create_new(true)
+        .open(&args.root_key)?
+        .write_all(root_key.to_string().as_bytes())?;
+
+    Ok(())
+}
+
+/// Sign a network configuration, and write it as a discovery document in TOML format.
+///
+/// # Errors
+/// If the network configuration or the signing key is not valid, or a file can't be accessed.
+pub fn sign_network(args: &SignNetworkArgs) -> Result<(), BoxError> {
+    let signing_key = hex::decode(fs::read_to_string(&args.signing_key)?.trim())?;
+    let not_after = args
+        .valid_for_secs
+        .map(|secs| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs() + secs)
+        })
+        .transpose()?;
+    let document = SignedDocument::sign(
+        fs::read_to_string(&args.network)?,
+        args.document_version,
+        not_after,
+        &signing_key,
+    )?;
+    fs::write(&args.output, toml::to_string(&document)?)?;
+
+    Ok(())
+}
@@ -0,0 +1,359 @@
+//! Batch jobs of the report collector. A job spec lists several IPA queries, which the report
+//! collector runs one after another or a few at a time, retrying the ones that fail. The outcome
+//! of every query ends up in one manifest.
+//!
+//! ```toml
+//! concurrency = 1
+//! retries = 2
+//!
+//! [[query]]
+//! name = "campaign-a"
+//! input = "campaign-a.csv"
+//! mode = "malicious"
+//! output = "campaign-a.json"
+//!
+//! [query.config]
+//! per_user_credit_cap = 3
+//! max_breakdown_key = 16
+//!
+//! [query.dp]
+//! epsilon = [1.0, 5.0]
+//! cap = 3
+//! ```
+
+use crate::{
+    cli::{
+        ipa_output::duration_to_secs,
+        noise::{apply, ApplyDpArgs, EpsilonBits, NoisyOutput},
+        IpaQueryResult,
+    },
+    helpers::query::IpaQueryConfig,
+    test_fixture::ipa::IpaSecurityModel,
+};
+use futures::{stream, Future, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+
+#[derive(Debug, thiserror::Error)]
+pub enum JobSpecError {
+    #[error(transparent)]
+    ParseError(#[from] config::ConfigError),
+    #[error("job spec has no queries")]
+    NoQueries,
+    #[error("query name {0} is used more than once")]
+    DuplicateName(String),
+    #[error("retry delay must be a non-negative number of seconds, got {0}")]
+    InvalidRetryDelay(f64),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobSpec {
+    /// Number of queries that run at the same time. Helpers run one query at a time, so queries
+    /// that are submitted while another one is running are rejected, and retried.
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+
+    /// How many times a query that failed is run again.
+    #[serde(default)]
+    pub retries: usize,
+
+    /// Seconds to wait before running a failed query again. It doubles after every attempt.
+    #[serde(default = "default_retry_delay_secs")]
+    pub retry_delay_secs: f64,
+
+    #[serde(rename = "query")]
+    pub queries: Vec<QuerySpec>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuerySpec {
+    /// Identifies the query in the manifest.
+    pub name: String,
+
+    /// CSV file with the input records.
+    pub input: PathBuf,
+
+    #[serde(default = "default_mode")]
+    pub mode: IpaSecurityModel,
+
+    #[serde(default)]
+    pub config: IpaQueryConfig,
+
+    /// If set, noise is added to the results of the query.
+    pub dp: Option<ApplyDpArgs>,
+
+    /// The destination file for the results of the query.
+    pub output: Option<PathBuf>,
+}
+
+fn default_concurrency() -> usize {
+    1
+}
+
+fn default_retry_delay_secs() -> f64 {
+    1.0
+}
+
+fn default_mode() -> IpaSecurityModel {
+    IpaSecurityModel::Malicious
+}
+
+impl JobSpec {
+    /// Reads the job spec from string. Expects it to be in toml format.
+    ///
+    /// # Errors
+    /// If `input` is in an invalid format, has no queries, several queries with the same name, or
+    /// a retry delay that is negative or not a number.
+    pub fn from_toml_str(input: &str) -> Result<Self, JobSpecError> {
+        use config::{Config, File, FileFormat};
+
+        let spec: Self = Config::builder()
+            .add_source(File::from_str(input, FileFormat::Toml))
+            .build()?
+            .try_deserialize()?;
+
+        if spec.queries.is_empty() {
+            return Err(JobSpecError::NoQueries);
+        }
+        // `Duration::from_secs_f64` panics on anything else.
+        if !(spec.retry_delay_secs.is_finite() && spec.retry_delay_secs >= 0.0) {
+            return Err(JobSpecError::InvalidRetryDelay(spec.retry_delay_secs));
+        }
+        let mut names = HashSet::new();
+        for query in &spec.queries {
+            if !names.insert(query.name.as_str()) {
+                return Err(JobSpecError::DuplicateName(query.name.clone()));
+            }
+        }
+
+        Ok(spec)
+    }
+
+    /// Runs every query of this job with `run`, at most `concurrency` of them at the same time,
+    /// and retries the ones that fail. The queries are listed in the manifest in the order of
+    /// this spec.
+    pub async fn run<F, Fut>(&self, run: F) -> Manifest
+    where
+        F: Fn(&QuerySpec) -> Fut,
+        Fut: Future<Output = Result<IpaQueryResult, String>>,
+    {
+        let start = Instant::now();
+        let queries = stream::iter(&self.queries)
+            .map(|query| self.run_query(query, &run))
+            .buffered(self.concurrency.max(1))
+            .collect()
+            .await;
+
+        Manifest {
+            queries,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    async fn run_query<F, Fut>(&self, query: &QuerySpec, run: &F) -> QueryOutcome
+    where
+        F: Fn(&QuerySpec) -> Fut,
+        Fut: Future<Output = Result<IpaQueryResult, String>>,
+    {
+        let start = Instant::now();
+        let mut delay = Duration::from_secs_f64(self.retry_delay_secs);
+        let mut attempts = 0;
+        let result = loop {
+            attempts += 1;
+            match run(query).await {
+                Ok(result) => break Ok(result),
+                Err(e) if attempts > self.retries => break Err(e),
+                Err(e) => {
+                    tracing::warn!(
+                        "query {} failed: {e}, retrying (attempt {attempts} of {})",
+                        query.name,
+                        self.retries
+                    );
+                    sleep(delay).await;
+                    delay = delay.saturating_mul(2);
+                }
+            }
+        };
+
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(e) => {
+                tracing::error!("query {} failed: {e}", query.name);
+                (None, Some(e))
+            }
+        };
+        QueryOutcome {
+            name: query.name.clone(),
+            mode: query.mode,
+            attempts,
+            elapsed: start.elapsed(),
+            output: query.output.clone(),
+            noisy: query
+                .dp
+                .as_ref()
+                .zip(result.as_ref())
+                .map(|(dp, result)| apply(&result.breakdowns, dp)),
+            result,
+            error,
+        }
+    }
+}
+
+/// The outcome of all the queries of a job.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub queries: Vec<QueryOutcome>,
+    /// Time it took to run the whole job.
+    #[serde(serialize_with = "duration_to_secs")]
+    pub elapsed: Duration,
+}
+
+impl Manifest {
+    /// Returns `true` if every query of the job succeeded.
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.queries.iter().all(|query| query.error.is_none())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueryOutcome {
+    pub name: String,
+    pub mode: IpaSecurityModel,
+    /// Number of times the query was run.
+    pub attempts: usize,
+    /// Time from the first attempt until the query succeeded or gave up, including retries.
+    #[serde(serialize_with = "duration_to_secs")]
+    pub elapsed: Duration,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    /// Results of the last attempt, if it succeeded. Its latency only counts that attempt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<IpaQueryResult>,
+    /// Results with noise added, for every epsilon.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noisy: Option<BTreeMap<EpsilonBits, NoisyOutput>>,
+    /// Error of the last attempt, if every attempt failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::helpers::query::QuerySize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const SPEC: &str = r#"
+        concurrency = 2
+        retries = 1
+        retry_delay_secs = 0.0
+
+        [[query]]
+        name = "a"
+        input = "a.csv"
+        output = "a.json"
+
+        [query.config]
+        max_breakdown_key = 4
+
+        [query.dp]
+        epsilon = [1.0]
+        cap = 3
+
+        [[query]]
+        name = "b"
+        input = "b.csv"
+        mode = "semi-honest"
+    "#;
+
+    fn result(config: IpaQueryConfig) -> IpaQueryResult {
+        IpaQueryResult {
+            input_size: QuerySize::try_from(10).unwrap(),
+            config,
+            latency: Duration::from_secs(1),
+            breakdowns: vec![1, 2, 3, 4],
+        }
+    }
+
+    #[test]
+    fn parses_spec() {
+        let spec = JobSpec::from_toml_str(SPEC).unwrap();
+        assert_eq!(2, spec.concurrency);
+        assert_eq!(2, spec.queries.len());
+
+        let [a, b] = &spec.queries[..] else {
+            panic!("expected two queries");
+        };
+        assert!(matches!(a.mode, IpaSecurityModel::Malicious));
+        assert_eq!(4, a.config.max_breakdown_key);
+        assert_eq!(
+            IpaQueryConfig::default().per_user_credit_cap,
+            a.config.per_user_credit_cap
+        );
+        assert!(a.dp.is_some());
+        assert!(matches!(b.mode, IpaSecurityModel::SemiHonest));
+        assert_eq!(None, b.output);
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let spec = SPEC.replace(r#"name = "b""#, r#"name = "a""#);
+        assert!(matches!(
+            JobSpec::from_toml_str(&spec),
+            Err(JobSpecError::DuplicateName(name)) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_retry_delay() {
+        for delay in ["-1.0", "nan", "inf"] {
+            let spec = SPEC.replace(
+                "retry_delay_secs = 0.0",
+                &format!("retry_delay_secs = {delay}"),
+            );
+            assert!(
+                matches!(
+                    JobSpec::from_toml_str(&spec),
+                    Err(JobSpecError::InvalidRetryDelay(_))
+                ),
+                "{delay}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_failed_queries() {
+        let spec = JobSpec::from_toml_str(SPEC).unwrap();
+        let a_attempts = AtomicUsize::new(0);
+        let manifest = spec
+            .run(|query| {
+                // query a fails once, query b fails every time.
+                let outcome = match query.name.as_str() {
+                    "a" if a_attempts.fetch_add(1, Ordering::Relaxed) > 0 => {
+                        Ok(result(query.config))
+                    }
+                    _ => Err(format!("{} failed", query.name)),
+                };
+                async move { outcome }
+            })
+            .await;
+
+        assert!(!manifest.is_success());
+        let [a, b] = &manifest.queries[..] else {
+            panic!("expected two queries");
+        };
+        assert_eq!(("a", 2), (a.name.as_str(), a.attempts));
+        assert_eq!(vec![1, 2, 3, 4], a.result.as_ref().unwrap().breakdowns);
+        assert_eq!(1, a.noisy.as_ref().unwrap().len());
+        assert_eq!(None, a.error);
+        assert_eq!(("b", 2), (b.name.as_str(), b.attempts));
+        assert!(b.result.is_none());
+        assert_eq!(Some("b failed"), b.error.as_deref());
+    }
+}
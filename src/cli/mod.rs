@@ -1,12 +1,20 @@
 #[cfg(feature = "web-app")]
 mod clientconf;
 mod csv;
+#[cfg(all(feature = "cli", feature = "enable-serde"))]
+pub mod dictionary;
+#[cfg(feature = "web-app")]
+mod discovery;
 mod ipa_output;
+#[cfg(all(feature = "cli", feature = "test-fixture"))]
+pub mod job;
 #[cfg(feature = "web-app")]
 mod keygen;
 mod metric_collector;
 #[cfg(feature = "cli")]
 pub mod noise;
+#[cfg(all(feature = "cli", feature = "enable-serde"))]
+pub mod output;
 mod paths;
 #[cfg(all(feature = "test-fixture", feature = "web-app", feature = "cli"))]
 pub mod playbook;
@@ -17,6 +25,8 @@ mod verbosity;
 #[cfg(feature = "web-app")]
 pub use clientconf::{setup as client_config_setup, ConfGenArgs};
 pub use csv::Serializer as CsvSerializer;
+#[cfg(feature = "web-app")]
+pub use discovery::{discovery_keygen, sign_network, DiscoveryKeygenArgs, SignNetworkArgs};
 pub use ipa_output::QueryResult as IpaQueryResult;
 #[cfg(feature = "web-app")]
 pub use keygen::{keygen, KeygenArgs};
@@ -9,6 +9,7 @@ use crate::protocol::dp::InsecureDiscreteDp;
 use rand_core::SeedableRng;
 
 #[derive(Debug, Args)]
+#[cfg_attr(feature = "enable-serde", derive(serde::Deserialize))]
 #[clap(about = "Apply differential privacy noise to the given input")]
 pub struct ApplyDpArgs {
     /// Various epsilon values to use inside the DP.
@@ -17,6 +18,7 @@ pub struct ApplyDpArgs {
 
     /// Delta parameter for (\epsilon, \delta) DP.
     #[arg(long, short = 'd', default_value = "1e-7")]
+    #[cfg_attr(feature = "enable-serde", serde(default = "default_delta"))]
     delta: f64,
 
     /// Seed for the random number generator.
@@ -28,6 +30,11 @@ pub struct ApplyDpArgs {
     cap: u32,
 }
 
+#[cfg(feature = "enable-serde")]
+fn default_delta() -> f64 {
+    1e-7
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "enable-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NoisyOutput {
@@ -38,6 +45,89 @@ pub struct NoisyOutput {
     pub std: f64,
 }
 
+/// Range that holds a breakdown before noise was added to it, with some probability.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "enable-serde", derive(serde::Serialize))]
+pub struct ConfidenceInterval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl NoisyOutput {
+    /// Returns the interval around `value`, one of the noisy breakdowns, that holds the breakdown
+    /// before noise was added with probability `confidence`.
+    ///
+    /// ## Panics
+    /// If `confidence` is not within (0, 1).
+    #[must_use]
+    pub fn confidence_interval(&self, value: i64, confidence: f64) -> ConfidenceInterval {
+        #[allow(clippy::cast_precision_loss)]
+        let center = value as f64 - self.mean;
+        let margin = z_score(confidence) * self.std;
+
+        ConfidenceInterval {
+            lower: center - margin,
+            upper: center + margin,
+        }
+    }
+}
+
+/// Returns `z` such that a standard normal sample is within `[-z, z]` with probability
+/// `confidence`. Uses the [`Acklam`] approximation of the normal quantile function, which is
+/// accurate to about 1e-9.
+///
+/// [`Acklam`]: https://web.archive.org/web/20151030215612/http://home.online.no/~pjacklam/notes/invnorm/
+#[allow(clippy::unreadable_literal)]
+fn z_score(confidence: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01,
+        2.209460984245205e+02,
+        -2.759285104469687e+02,
+        1.38357751867269e+02,
+        -3.066479806614716e+01,
+        2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01,
+        1.615858368580409e+02,
+        -1.556989798598866e+02,
+        6.680131188771972e+01,
+        -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03,
+        -3.223964580411365e-01,
+        -2.400758277161838e+00,
+        -2.549732539343734e+00,
+        4.374664141464968e+00,
+        2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03,
+        3.224671290700398e-01,
+        2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const P_HIGH: f64 = 1.0 - 0.02425;
+
+    assert!(
+        confidence > 0.0 && confidence < 1.0,
+        "confidence must be within (0, 1), got {confidence}"
+    );
+    // the quantile of the upper end of the interval. It is never in the lower tail.
+    let p = (1.0 + confidence) / 2.0;
+    let poly = |coefficients: &[f64], x: f64| coefficients.iter().fold(0.0, |acc, c| acc * x + c);
+
+    if p <= P_HIGH {
+        let q = p - 0.5;
+        let r = q * q;
+        q * poly(&A, r) / (poly(&B, r) * r + 1.0)
+    } else {
+        let q = f64::sqrt(-2.0 * f64::ln(1.0 - p));
+        -poly(&C, q) / (poly(&D, q) * q + 1.0)
+    }
+}
+
 /// This exists to be able to use f64 as key inside a map. We don't have to deal with infinities or
 /// NaN values for epsilons, so we can treat them as raw bytes for this purpose.
 #[derive(Debug, Copy, Clone, PartialOrd)]
@@ -82,6 +172,10 @@ impl Display for EpsilonBits {
     }
 }
 
+/// Adds discrete DP noise to `input` once for every epsilon in `args`.
+///
+/// ## Panics
+/// If an epsilon or the delta in `args` is out of the range the DP mechanism accepts.
 pub fn apply<I: AsRef<[u32]>>(input: I, args: &ApplyDpArgs) -> BTreeMap<EpsilonBits, NoisyOutput> {
     let mut rng = args
         .seed
@@ -110,3 +204,35 @@ pub fn apply<I: AsRef<[u32]>>(input: I, args: &ApplyDpArgs) -> BTreeMap<EpsilonB
 
     result
 }
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn z_scores() {
+        for (confidence, expected) in [
+            (0.5, 0.674_489_75),
+            (0.95, 1.959_963_98),
+            (0.999, 3.290_526_73),
+        ] {
+            let z = z_score(confidence);
+            assert!(
+                (z - expected).abs() < 1e-6,
+                "z({confidence}) = {z}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn confidence_interval() {
+        let noisy = NoisyOutput {
+            breakdowns: Box::new([10]),
+            mean: 1.0,
+            std: 2.0,
+        };
+        let interval = noisy.confidence_interval(10, 0.95);
+        assert!((interval.lower - (9.0 - 2.0 * 1.959_964)).abs() < 1e-5);
+        assert!((interval.upper - (9.0 + 2.0 * 1.959_964)).abs() < 1e-5);
+    }
+}
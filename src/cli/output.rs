@@ -0,0 +1,383 @@
+//! Formats that report collectors get IPA results in. Breakdowns can be given labels from a
+//! dictionary, so they read as campaigns instead of breakdown keys.
+
+use crate::cli::{
+    noise::{ConfidenceInterval, EpsilonBits, NoisyOutput},
+    IpaQueryResult,
+};
+use comfy_table::Table;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// The results with every breakdown labeled, as JSON
+    Json,
+    /// One row per breakdown, as CSV
+    Csv,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct OutputArgs {
+    /// Format of the output file
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    pub output_format: OutputFormat,
+
+    /// CSV file that maps breakdown keys to labels, one `key,label` pair per line. A breakdown
+    /// dictionary can be used as is
+    #[arg(long, value_name = "FILE")]
+    pub breakdown_labels: Option<PathBuf>,
+
+    /// Print a histogram of the breakdowns
+    #[arg(long)]
+    pub histogram: bool,
+
+    /// Probability that the confidence intervals of noisy breakdowns hold the breakdowns before
+    /// noise was added
+    #[arg(long, default_value_t = 0.95, value_parser = parse_confidence)]
+    pub confidence: f64,
+}
+
+impl OutputArgs {
+    /// Reads the breakdown labels, if there are any.
+    ///
+    /// # Errors
+    /// If the breakdown labels file can't be read or is malformed.
+    pub fn labels(&self) -> Result<BreakdownLabels, BreakdownLabelsError> {
+        match &self.breakdown_labels {
+            Some(path) => BreakdownLabels::read(path),
+            None => Ok(BreakdownLabels::default()),
+        }
+    }
+}
+
+fn parse_confidence(value: &str) -> Result<f64, String> {
+    let confidence = value.parse::<f64>().map_err(|e| e.to_string())?;
+    if confidence > 0.0 && confidence < 1.0 {
+        Ok(confidence)
+    } else {
+        Err(format!(
+            "confidence must be within (0, 1), got {confidence}"
+        ))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BreakdownLabelsError {
+    #[error("failed to read breakdown labels: {0}")]
+    Io(#[from] io::Error),
+    #[error("line {line}: expected a breakdown key and a label, got {content:?}")]
+    Malformed { line: usize, content: String },
+    #[error("breakdown key {0} has more than one label")]
+    DuplicateKey(u32),
+}
+
+/// Labels of breakdown keys. Keys without a label are shown as the key itself.
+#[derive(Debug, Default)]
+pub struct BreakdownLabels(BTreeMap<u32, String>);
+
+impl BreakdownLabels {
+    /// Reads the labels from a CSV file with one `key,label` pair per line.
+    ///
+    /// # Errors
+    /// If the file can't be read or is malformed.
+    pub fn read(path: &Path) -> Result<Self, BreakdownLabelsError> {
+        Self::from_csv_str(&fs::read_to_string(path)?)
+    }
+
+    /// Parses labels from one `key,label` pair per line. Empty lines are skipped.
+    ///
+    /// # Errors
+    /// If a line is not a breakdown key followed by a label, or a key has more than one label.
+    pub fn from_csv_str(input: &str) -> Result<Self, BreakdownLabelsError> {
+        let mut labels = BTreeMap::new();
+        for (i, line) in input.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (key, label) = line
+                .split_once(',')
+                .and_then(|(key, label)| Some((key.trim().parse::<u32>().ok()?, label.trim())))
+                .ok_or_else(|| BreakdownLabelsError::Malformed {
+                    line: i + 1,
+                    content: line.to_string(),
+                })?;
+            if labels.insert(key, label.to_string()).is_some() {
+                return Err(BreakdownLabelsError::DuplicateKey(key));
+            }
+        }
+
+        Ok(Self(labels))
+    }
+
+    /// Returns the labelled breakdown keys and their labels, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.0.iter().map(|(key, label)| (*key, label.as_str()))
+    }
+
+    #[must_use]
+    pub fn label(&self, key: u32) -> String {
+        self.0.get(&key).cloned().unwrap_or_else(|| key.to_string())
+    }
+
+    /// Labels `breakdowns`, which are indexed by breakdown key.
+    #[must_use]
+    pub fn rows(&self, breakdowns: &[u32]) -> Vec<BreakdownRow> {
+        (0_u32..)
+            .zip(breakdowns)
+            .map(|(key, &value)| BreakdownRow {
+                key,
+                label: self.label(key),
+                value: i64::from(value),
+                interval: None,
+            })
+            .collect()
+    }
+
+    /// Labels the noisy breakdowns, and gives each one an interval that holds the breakdown
+    /// before noise was added with probability `confidence`.
+    #[must_use]
+    pub fn noisy_rows(&self, noisy: &NoisyOutput, confidence: f64) -> Vec<BreakdownRow> {
+        (0_u32..)
+            .zip(noisy.breakdowns.iter())
+            .map(|(key, &value)| BreakdownRow {
+                key,
+                label: self.label(key),
+                value,
+                interval: Some(noisy.confidence_interval(value, confidence)),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BreakdownRow {
+    pub key: u32,
+    pub label: String,
+    pub value: i64,
+    #[serde(flatten)]
+    pub interval: Option<ConfidenceInterval>,
+}
+
+#[derive(Serialize)]
+struct LabeledResult<'a> {
+    #[serde(flatten)]
+    result: &'a IpaQueryResult,
+    labeled_breakdowns: Vec<BreakdownRow>,
+}
+
+#[derive(Serialize)]
+struct LabeledNoisyOutput {
+    mean: f64,
+    std: f64,
+    confidence: f64,
+    breakdowns: Vec<BreakdownRow>,
+}
+
+/// Writes the results of a query in the given format. JSON output is a superset of
+/// [`IpaQueryResult`], so it can be read back as one.
+///
+/// # Errors
+/// If writing to `w` fails.
+pub fn write_result<W: Write>(
+    w: &mut W,
+    format: OutputFormat,
+    labels: &BreakdownLabels,
+    result: &IpaQueryResult,
+) -> io::Result<()> {
+    let rows = labels.rows(&result.breakdowns);
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(
+                &mut *w,
+                &LabeledResult {
+                    result,
+                    labeled_breakdowns: rows,
+                },
+            )?;
+            writeln!(w)
+        }
+        OutputFormat::Csv => {
+            writeln!(w, "breakdown_key,label,value")?;
+            for row in rows {
+                writeln!(w, "{},{},{}", row.key, csv_field(&row.label), row.value)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Writes breakdowns with noise added in the given format, for every epsilon.
+///
+/// # Errors
+/// If writing to `w` fails.
+///
+/// # Panics
+/// If `confidence` is not within (0, 1).
+pub fn write_noisy<W: Write>(
+    w: &mut W,
+    format: OutputFormat,
+    labels: &BreakdownLabels,
+    noisy: &BTreeMap<EpsilonBits, NoisyOutput>,
+    confidence: f64,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            let output = noisy
+                .iter()
+                .map(|(epsilon, output)| {
+                    (
+                        *epsilon,
+                        LabeledNoisyOutput {
+                            mean: output.mean,
+                            std: output.std,
+                            confidence,
+                            breakdowns: labels.noisy_rows(output, confidence),
+                        },
+                    )
+                })
+                .collect::<BTreeMap<_, _>>();
+            serde_json::to_writer_pretty(&mut *w, &output)?;
+            writeln!(w)
+        }
+        OutputFormat::Csv => {
+            writeln!(w, "epsilon,breakdown_key,label,value,lower,upper")?;
+            for (epsilon, output) in noisy {
+                for row in labels.noisy_rows(output, confidence) {
+                    let interval = row.interval.unwrap();
+                    writeln!(
+                        w,
+                        "{epsilon},{},{},{},{:.3},{:.3}",
+                        row.key,
+                        csv_field(&row.label),
+                        row.value,
+                        interval.lower,
+                        interval.upper
+                    )?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Quotes `value` if it has characters that CSV treats specially.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Draws breakdowns as a histogram, with the largest one `width` characters wide. Negative
+/// values, which only noisy breakdowns have, get an empty bar.
+#[must_use]
+pub fn histogram(rows: &[BreakdownRow], width: usize) -> Table {
+    let max = rows.iter().map(|row| row.value).max().unwrap_or(0).max(1);
+    let mut table = Table::new();
+    table.set_header(["Breakdown", "Value", ""]);
+    for row in rows {
+        // values are rounded down, so bars are never wider than `width`
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let bar = (row.value.max(0) as f64 / max as f64 * width as f64) as usize;
+        table.add_row([row.label.clone(), row.value.to_string(), "#".repeat(bar)]);
+    }
+
+    table
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::helpers::query::{IpaQueryConfig, QuerySize};
+    use std::time::Duration;
+
+    #[test]
+    fn parses_labels() {
+        let labels =
+            BreakdownLabels::from_csv_str("0,campaign-a\n\n2, campaign, with comma \n").unwrap();
+        assert_eq!("campaign-a", labels.label(0));
+        assert_eq!("1", labels.label(1));
+        assert_eq!("campaign, with comma", labels.label(2));
+
+        assert!(matches!(
+            BreakdownLabels::from_csv_str("0,a\nb,c"),
+            Err(BreakdownLabelsError::Malformed { line: 2, .. })
+        ));
+        assert!(matches!(
+            BreakdownLabels::from_csv_str("0,a\n0,b"),
+            Err(BreakdownLabelsError::DuplicateKey(0))
+        ));
+    }
+
+    #[test]
+    fn json_result_reads_back() {
+        let result = IpaQueryResult {
+            input_size: QuerySize::try_from(4).unwrap(),
+            config: IpaQueryConfig::default(),
+            latency: Duration::from_secs(1),
+            breakdowns: vec![3, 1],
+        };
+        let labels = BreakdownLabels::from_csv_str("1,campaign-b").unwrap();
+        let mut buf = Vec::new();
+        write_result(&mut buf, OutputFormat::Json, &labels, &result).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!("campaign-b", json["labeled_breakdowns"][1]["label"]);
+        let read_back: IpaQueryResult = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(result.breakdowns, read_back.breakdowns);
+
+        buf.clear();
+        write_result(&mut buf, OutputFormat::Csv, &labels, &result).unwrap();
+        assert_eq!(
+            "breakdown_key,label,value\n0,0,3\n1,campaign-b,1\n",
+            String::from_utf8(buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn noisy_csv() {
+        let noisy = BTreeMap::from([(
+            EpsilonBits::from(1.0),
+            NoisyOutput {
+                breakdowns: Box::new([5, -1]),
+                mean: 0.0,
+                std: 1.0,
+            },
+        )]);
+        let mut buf = Vec::new();
+        write_noisy(
+            &mut buf,
+            OutputFormat::Csv,
+            &BreakdownLabels::from_csv_str("0,\"a\"").unwrap(),
+            &noisy,
+            0.95,
+        )
+        .unwrap();
+        assert_eq!(
+            "epsilon,breakdown_key,label,value,lower,upper\n\
+             1,0,\"\"\"a\"\"\",5,3.040,6.960\n\
+             1,1,1,-1,-2.960,0.960\n",
+            String::from_utf8(buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn histogram_bars() {
+        let rows = BreakdownLabels::default().rows(&[4, 2, 0]);
+        let table = histogram(&rows, 8).to_string();
+        assert!(table.contains("########"));
+        assert!(!table.contains("#########"));
+        assert_eq!(8 + 4, table.matches('#').count());
+    }
+}
@@ -0,0 +1,398 @@
+use crate::{
+    ff::{Gf40Bit, Gf8Bit, PrimeField, Serializable},
+    helpers::Role,
+    report::{EncryptedReport, Epoch, InvalidReportError},
+    secret_sharing::replicated::semi_honest::AdditiveShare,
+};
+use std::{array, io, path::PathBuf};
+
+/// Files of reports that user agents encrypted for the helpers. Reports are length-delimited:
+/// each one is preceded by its length, as two little-endian bytes.
+#[derive(Clone, Debug)]
+pub enum ReportFiles {
+    /// One file per helper, in the order of the helpers, with the reports encrypted for it. The
+    /// n-th reports of all the files are shares of the same event.
+    PerHelper([PathBuf; 3]),
+    /// One file with the three reports of every event, in the order of the helpers, one event
+    /// after another.
+    Combined(PathBuf),
+}
+
+/// Selects the reports to submit to the helpers, by their public fields.
+#[derive(Clone, Debug, Default, clap::Args)]
+pub struct ReportFilter {
+    /// Only submit the reports of this epoch
+    #[arg(long)]
+    pub epoch: Option<Epoch>,
+
+    /// Only submit the reports of this site
+    #[arg(long)]
+    pub site_domain: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReportFileError {
+    #[error("failed to read {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("report {index} for {helper:?} is truncated")]
+    Truncated { helper: Role, index: usize },
+    #[error("report {index} for {helper:?} is invalid: {source}")]
+    Invalid {
+        helper: Role,
+        index: usize,
+        source: InvalidReportError,
+    },
+    #[error("helpers have different numbers of reports: {0:?}")]
+    CountMismatch([usize; 3]),
+    #[error("reports {index} of the helpers are not shares of the same event")]
+    EventMismatch { index: usize },
+}
+
+/// Encrypted reports, ready to be submitted to the helpers. The report collector never sees
+/// what is inside of them: it only checks that they are well-formed and selects the ones to
+/// submit by their public fields.
+#[derive(Debug)]
+pub struct EncryptedInputs {
+    /// Length-delimited reports for each helper.
+    pub inputs: [Vec<u8>; 3],
+    /// Number of reports submitted to each helper.
+    pub count: usize,
+    /// Number of reports that did not pass the filter.
+    pub skipped: usize,
+}
+
+impl EncryptedInputs {
+    /// Reads the reports in `files` and keeps the ones that pass `filter`.
+    ///
+    /// ## Errors
+    /// If the files can't be read, or the reports in them are malformed or don't line up.
+    pub fn read<F>(files: &ReportFiles, filter: &ReportFilter) -> Result<Self, ReportFileError>
+    where
+        F: PrimeField,
+        AdditiveShare<F>: Serializable,
+    {
+        let read = |path: &PathBuf| {
+            std::fs::read(path).map_err(|source| ReportFileError::Io {
+                path: path.clone(),
+                source,
+            })
+        };
+        match files {
+            ReportFiles::PerHelper(paths) => {
+                let [h1, h2, h3] = paths;
+                Self::from_helper_bytes::<F>(&[read(h1)?, read(h2)?, read(h3)?], filter)
+            }
+            ReportFiles::Combined(path) => Self::from_combined_bytes::<F>(&read(path)?, filter),
+        }
+    }
+
+    /// Parses the reports for each of the helpers, in the order of the helpers.
+    ///
+    /// ## Errors
+    /// If the reports are malformed or don't line up.
+    pub fn from_helper_bytes<F>(
+        bytes: &[Vec<u8>; 3],
+        filter: &ReportFilter,
+    ) -> Result<Self, ReportFileError>
+    where
+        F: PrimeField,
+        AdditiveShare<F>: Serializable,
+    {
+        let mut reports = [Vec::new(), Vec::new(), Vec::new()];
+        for (role, bytes) in Role::all().iter().zip(bytes) {
+            reports[*role] = split(bytes, |index| ReportFileError::Truncated {
+                helper: *role,
+                index,
+            })?;
+        }
+
+        Self::from_reports::<F>(&reports, filter)
+    }
+
+    /// Parses the reports of a combined file, where the reports of every event follow each other
+    /// in the order of the helpers.
+    ///
+    /// ## Errors
+    /// If the reports are malformed, or the number of them is not a multiple of three.
+    pub fn from_combined_bytes<F>(
+        bytes: &[u8],
+        filter: &ReportFilter,
+    ) -> Result<Self, ReportFileError>
+    where
+        F: PrimeField,
+        AdditiveShare<F>: Serializable,
+    {
+        let combined = split(bytes, |index| ReportFileError::Truncated {
+            helper: Role::all()[index % 3],
+            index: index / 3,
+        })?;
+        let mut reports = [Vec::new(), Vec::new(), Vec::new()];
+        for (i, report) in combined.into_iter().enumerate() {
+            reports[i % 3].push(report);
+        }
+
+        Self::from_reports::<F>(&reports, filter)
+    }
+
+    fn from_reports<F>(
+        reports: &[Vec<&[u8]>; 3],
+        filter: &ReportFilter,
+    ) -> Result<Self, ReportFileError>
+    where
+        F: PrimeField,
+        AdditiveShare<F>: Serializable,
+    {
+        let counts = [reports[0].len(), reports[1].len(), reports[2].len()];
+        if counts.iter().any(|&count| count != counts[0]) {
+            return Err(ReportFileError::CountMismatch(counts));
+        }
+
+        let mut this = Self {
+            inputs: array::from_fn(|_| Vec::new()),
+            count: 0,
+            skipped: 0,
+        };
+        let [h1_reports, h2_reports, h3_reports] = reports;
+        let per_event = h1_reports.iter().zip(h2_reports).zip(h3_reports);
+        for (index, ((&h1_bytes, &h2_bytes), &h3_bytes)) in per_event.enumerate() {
+            let parse = |role: Role, bytes| {
+                EncryptedReport::<F, Gf40Bit, Gf8Bit, _>::from_bytes(bytes).map_err(|source| {
+                    ReportFileError::Invalid {
+                        helper: role,
+                        index,
+                        source,
+                    }
+                })
+            };
+            let (h1, h2, h3) = (
+                parse(Role::H1, h1_bytes)?,
+                parse(Role::H2, h2_bytes)?,
+                parse(Role::H3, h3_bytes)?,
+            );
+
+            // the public fields of an event are the same in the reports for all helpers.
+            if !same_event(&h1, &h2) || !same_event(&h1, &h3) {
+                return Err(ReportFileError::EventMismatch { index });
+            }
+            if !filter.accepts(&h1) {
+                this.skipped += 1;
+                continue;
+            }
+
+            for (input, report) in this.inputs.iter_mut().zip([h1_bytes, h2_bytes, h3_bytes]) {
+                // `split` only yields reports whose length fits in two bytes.
+                input.extend_from_slice(&u16::try_from(report.len()).unwrap().to_le_bytes());
+                input.extend_from_slice(report);
+            }
+            this.count += 1;
+        }
+
+        Ok(this)
+    }
+}
+
+impl ReportFilter {
+    fn accepts<F, B>(&self, report: &EncryptedReport<F, Gf40Bit, Gf8Bit, B>) -> bool
+    where
+        F: PrimeField,
+        AdditiveShare<F>: Serializable,
+        B: std::ops::Deref<Target = [u8]>,
+    {
+        self.epoch.map_or(true, |epoch| report.epoch() == epoch)
+            && self
+                .site_domain
+                .as_ref()
+                .map_or(true, |site_domain| report.site_domain() == site_domain)
+    }
+}
+
+fn same_event<F, B>(
+    a: &EncryptedReport<F, Gf40Bit, Gf8Bit, B>,
+    b: &EncryptedReport<F, Gf40Bit, Gf8Bit, B>,
+) -> bool
+where
+    F: PrimeField,
+    AdditiveShare<F>: Serializable,
+    B: std::ops::Deref<Target = [u8]>,
+{
+    a.timestamp() == b.timestamp()
+        && a.event_type() == b.event_type()
+        && a.epoch() == b.epoch()
+        && a.site_domain() == b.site_domain()
+}
+
+/// Splits length-delimited reports. `truncated` makes the error for the report at the given
+/// index that is cut short.
+fn split(
+    mut bytes: &[u8],
+    truncated: impl Fn(usize) -> ReportFileError,
+) -> Result<Vec<&[u8]>, ReportFileError> {
+    let mut reports = Vec::new();
+    while !bytes.is_empty() {
+        let index = reports.len();
+        if bytes.len() < 2 {
+            return Err(truncated(index));
+        }
+        let (len, rest) = bytes.split_at(2);
+        let len = usize::from(u16::from_le_bytes([len[0], len[1]]));
+        if rest.len() < len {
+            return Err(truncated(index));
+        }
+        let (report, rest) = rest.split_at(len);
+        reports.push(report);
+        bytes = rest;
+    }
+
+    Ok(reports)
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::{
+        ff::Fp32BitPrime,
+        hpke::{KeyPair, KeyRegistry},
+        report::{EventType, Report},
+    };
+    use rand::{rngs::StdRng, Rng};
+    use rand_core::SeedableRng;
+
+    struct Events {
+        rng: StdRng,
+        key_registries: [KeyRegistry<KeyPair>; 3],
+    }
+
+    impl Events {
+        fn new() -> Self {
+            let mut rng = StdRng::seed_from_u64(42);
+            let key_registries = array::from_fn(|_| KeyRegistry::random(1, &mut rng));
+            Self {
+                rng,
+                key_registries,
+            }
+        }
+
+        /// Encrypts the reports of an event for each helper.
+        fn encrypt(&mut self, epoch: Epoch, site_domain: &str) -> [Vec<u8>; 3] {
+            let timestamp = self.rng.gen();
+            array::from_fn(|i| {
+                let report = Report::<Fp32BitPrime, Gf40Bit, Gf8Bit> {
+                    timestamp,
+                    mk_shares: (self.rng.gen(), self.rng.gen()).into(),
+                    event_type: EventType::Source,
+                    breakdown_key: self.rng.gen(),
+                    trigger_value: (self.rng.gen(), self.rng.gen()).into(),
+                    epoch,
+                    site_domain: site_domain.to_string(),
+                };
+                let mut out = Vec::new();
+                report
+                    .delimited_encrypt_to(0, &self.key_registries[i], &mut self.rng, &mut out)
+                    .unwrap();
+                out
+            })
+        }
+    }
+
+    fn per_helper(events: &[[Vec<u8>; 3]]) -> [Vec<u8>; 3] {
+        array::from_fn(|i| events.iter().flat_map(|event| event[i].clone()).collect())
+    }
+
+    #[test]
+    fn reads_per_helper_files() {
+        let mut events = Events::new();
+        let reports = [
+            events.encrypt(1, "a.example"),
+            events.encrypt(2, "a.example"),
+            events.encrypt(1, "b.example"),
+        ];
+        let files = per_helper(&reports);
+
+        let all =
+            EncryptedInputs::from_helper_bytes::<Fp32BitPrime>(&files, &ReportFilter::default())
+                .unwrap();
+        assert_eq!((3, 0), (all.count, all.skipped));
+        assert_eq!(files, all.inputs);
+
+        let filter = ReportFilter {
+            epoch: Some(1),
+            site_domain: Some("b.example".into()),
+        };
+        let filtered = EncryptedInputs::from_helper_bytes::<Fp32BitPrime>(&files, &filter).unwrap();
+        assert_eq!((1, 2), (filtered.count, filtered.skipped));
+        assert_eq!(reports[2], filtered.inputs);
+    }
+
+    #[test]
+    fn reads_combined_file() {
+        let mut events = Events::new();
+        let reports = [
+            events.encrypt(1, "a.example"),
+            events.encrypt(1, "a.example"),
+        ];
+        let combined = reports
+            .iter()
+            .flatten()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+
+        let inputs = EncryptedInputs::from_combined_bytes::<Fp32BitPrime>(
+            &combined,
+            &ReportFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(2, inputs.count);
+        assert_eq!(per_helper(&reports), inputs.inputs);
+
+        // the last event is missing the report for the third helper.
+        let [h1, h2, _] = events.encrypt(1, "a.example");
+        let combined = [combined, h1, h2].concat();
+        assert!(matches!(
+            EncryptedInputs::from_combined_bytes::<Fp32BitPrime>(
+                &combined,
+                &ReportFilter::default()
+            ),
+            Err(ReportFileError::CountMismatch([3, 3, 2]))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_reports() {
+        let mut events = Events::new();
+        let mut files = per_helper(&[events.encrypt(1, "a.example")]);
+        files[1].pop();
+        assert!(matches!(
+            EncryptedInputs::from_helper_bytes::<Fp32BitPrime>(&files, &ReportFilter::default()),
+            Err(ReportFileError::Truncated {
+                helper: Role::H2,
+                index: 0
+            })
+        ));
+
+        // reports that are too short to be parsed
+        let files = [vec![1, 0, 0], vec![1, 0, 0], vec![1, 0, 0]];
+        assert!(matches!(
+            EncryptedInputs::from_helper_bytes::<Fp32BitPrime>(&files, &ReportFilter::default()),
+            Err(ReportFileError::Invalid {
+                helper: Role::H1,
+                index: 0,
+                source: InvalidReportError::Length(1, _),
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_reports_of_different_events() {
+        let mut events = Events::new();
+        let [h1, ..] = events.encrypt(1, "a.example");
+        let [_, h2, h3] = events.encrypt(1, "a.example");
+        assert!(matches!(
+            EncryptedInputs::from_helper_bytes::<Fp32BitPrime>(
+                &[h1, h2, h3],
+                &ReportFilter::default()
+            ),
+            Err(ReportFileError::EventMismatch { index: 0 })
+        ));
+    }
+}
@@ -63,8 +63,9 @@ impl<I: InputItem> InputItem for (I, I) {
 
 impl InputItem for TestRawDataRecord {
     fn from_str(s: &str) -> Self {
-        if let [ts, match_key, is_trigger_bit, breakdown_key, trigger_value] =
-            s.splitn(5, ',').collect::<Vec<_>>()[..]
+        // simulated events have more columns after these, IPA does not need them
+        if let [ts, match_key, is_trigger_bit, breakdown_key, trigger_value, ..] =
+            s.split(',').collect::<Vec<_>>()[..]
         {
             TestRawDataRecord {
                 user_id: match_key.parse().unwrap(),
@@ -142,10 +143,10 @@ impl BufRead for InputSource {
 #[cfg(all(test, unit_test))]
 mod tests {
     use crate::{
-        cli::playbook::input::InputItem,
+        cli::{playbook::input::InputItem, CsvSerializer},
         ff::{Fp31, Fp32BitPrime},
         secret_sharing::IntoShares,
-        test_fixture::Reconstruct,
+        test_fixture::{ipa::TestRawDataRecord, Reconstruct},
     };
 
     #[test]
@@ -190,6 +191,15 @@ mod tests {
         <(Fp31, Fp31)>::from_str("20,");
     }
 
+    #[test]
+    fn raw_data_record() {
+        for input in ["10,3,1,0,5", "10,3,1,0,5,0,advertiser.example,1"] {
+            let mut buf = Vec::new();
+            TestRawDataRecord::from_str(input).to_csv(&mut buf).unwrap();
+            assert_eq!(b"10,3,1,0,5", &buf[..]);
+        }
+    }
+
     mod input_source {
         use super::*;
         use crate::{cli::playbook::input::InputSource, ff::Field};
@@ -1,6 +1,7 @@
 #![cfg(all(feature = "web-app", feature = "cli"))]
 use crate::{
-    cli::IpaQueryResult,
+    cli::{playbook::EncryptedInputs, IpaQueryResult},
+    error::BoxError,
     ff::{Field, PrimeField, Serializable},
     helpers::{
         query::{IpaQueryConfig, QueryInput, QuerySize},
@@ -30,16 +31,22 @@ use std::{
 use tokio::time::sleep;
 use typenum::Unsigned;
 
+/// The query is reported as stalled if none of the helpers made progress for this long.
+const STALL_WARNING: Duration = Duration::from_secs(30);
+
 /// Semi-honest IPA protocol.
 /// Returns aggregated values per breakdown key represented as index in the returned vector
-#[allow(clippy::missing_panics_doc)]
+///
+/// # Errors
+/// If match keys are to be encrypted without a public key for every helper, the reports can't be
+/// encrypted, or any of the helpers fails the query.
 pub async fn playbook_ipa<F, MK, BK, KR>(
     records: &[TestRawDataRecord],
     clients: &[MpcHelperClient; 3],
     query_id: QueryId,
     query_config: IpaQueryConfig,
     encryption: Option<(KeyIdentifier, [&KR; 3])>,
-) -> IpaQueryResult
+) -> Result<IpaQueryResult, BoxError>
 where
     F: PrimeField + IntoShares<AdditiveShare<F>>,
     Standard: Distribution<F>,
@@ -60,17 +67,13 @@ where
 
             let mut rng = StdRng::from_entropy();
             let shares: [Vec<Report<_, _, _>>; 3] = records.iter().cloned().share();
-            zip(&mut buffers, shares).zip(key_registries).for_each(
-                |((buf, shares), key_registry)| {
-                    for share in shares {
-                        share
-                            .delimited_encrypt_to(key_id, key_registry, &mut rng, buf)
-                            .unwrap();
-                    }
-                },
-            );
+            for ((buf, shares), key_registry) in zip(&mut buffers, shares).zip(key_registries) {
+                for share in shares {
+                    share.delimited_encrypt_to(key_id, key_registry, &mut rng, buf)?;
+                }
+            }
         } else {
-            panic!("match key encryption was requested, but one or more helpers is missing a public key")
+            return Err("match key encryption was requested, but one or more helpers is missing a public key".into());
         }
     } else {
         let sz = <IPAInputRow<F, MatchKey, BreakdownKey> as Serializable>::Size::USIZE;
@@ -98,8 +101,55 @@ where
         });
     }
 
-    let inputs = buffers.map(BodyStream::from);
     tracing::info!("Starting query after finishing encryption");
+    run_query::<F>(buffers, clients, query_id, query_config, query_size).await
+}
+
+/// IPA protocol on reports that were encrypted by user agents. The reports are submitted to the
+/// helpers as they are.
+///
+/// # Errors
+/// If any of the helpers fails the query.
+///
+/// # Panics
+/// If `query_config` asks for plaintext match keys.
+pub async fn playbook_encrypted_ipa<F>(
+    reports: EncryptedInputs,
+    clients: &[MpcHelperClient; 3],
+    query_id: QueryId,
+    query_config: IpaQueryConfig,
+) -> Result<IpaQueryResult, BoxError>
+where
+    F: PrimeField,
+    AdditiveShare<F>: Serializable,
+{
+    assert!(
+        !query_config.plaintext_match_keys,
+        "encrypted reports can't be submitted with plaintext match keys"
+    );
+    run_query::<F>(
+        reports.inputs,
+        clients,
+        query_id,
+        query_config,
+        reports.count,
+    )
+    .await
+}
+
+/// Submits the inputs to the helpers, waits for the query to complete and collects the results.
+async fn run_query<F>(
+    buffers: [Vec<u8>; 3],
+    clients: &[MpcHelperClient; 3],
+    query_id: QueryId,
+    query_config: IpaQueryConfig,
+    query_size: usize,
+) -> Result<IpaQueryResult, BoxError>
+where
+    F: PrimeField,
+    AdditiveShare<F>: Serializable,
+{
+    let inputs = buffers.map(BodyStream::from);
     let mpc_time = Instant::now();
     try_join_all(
         inputs
@@ -112,30 +162,50 @@ where
                 })
             }),
     )
-    .await
-    .unwrap();
+    .await?;
 
     let mut delay = Duration::from_millis(125);
+    let mut last_progress = None;
+    let mut progress_at = Instant::now();
     loop {
-        if try_join_all(clients.iter().map(|client| client.query_status(query_id)))
-            .await
-            .unwrap()
-            .into_iter()
-            .all(|status| status == QueryStatus::Completed)
+        let responses =
+            try_join_all(clients.iter().map(|client| client.query_status(query_id))).await?;
+        if responses
+            .iter()
+            .all(|response| response.status == QueryStatus::Completed)
         {
             break;
         }
 
+        let progress = responses
+            .into_iter()
+            .map(|response| response.progress)
+            .collect::<Vec<_>>();
+        for (i, progress) in progress.iter().enumerate() {
+            if let Some(progress) = progress {
+                tracing::info!("H{}: {progress}", i + 1);
+            }
+        }
+        if last_progress.as_ref() == Some(&progress) {
+            if progress_at.elapsed() >= STALL_WARNING {
+                tracing::warn!(
+                    "query has not made progress for {:?}",
+                    progress_at.elapsed()
+                );
+            }
+        } else {
+            last_progress = Some(progress);
+            progress_at = Instant::now();
+        }
+
         sleep(delay).await;
         delay = min(Duration::from_secs(5), delay * 2);
-        // TODO: Add a timeout of some sort. Possibly, add some sort of progress indicator to
-        // the status API so we can check whether the query is making progress.
+        // TODO: Add a timeout of some sort.
     }
 
     // wait until helpers have processed the query and get the results from them
     let results: [_; 3] = try_join_all(clients.iter().map(|client| client.query_results(query_id)))
-        .await
-        .unwrap()
+        .await?
         .try_into()
         .unwrap();
 
@@ -150,20 +220,20 @@ where
     tracing::info!("Running IPA for {query_size:?} records took {t:?}", t = lat);
     let mut breakdowns = Vec::new();
     for row in results {
-        let breakdown_key = usize::try_from(row.breakdown_key.as_u128()).unwrap();
+        let breakdown_key = usize::try_from(row.breakdown_key.as_u128())?;
         // TODO: make the data type used consistent with `ipa_in_the_clear`
         // I think using u32 is wrong, we should move to u128
-        let trigger_value = u32::try_from(row.trigger_value.as_u128()).unwrap();
+        let trigger_value = u32::try_from(row.trigger_value.as_u128())?;
         if breakdown_key >= breakdowns.len() {
             breakdowns.resize(breakdown_key + 1, 0);
             breakdowns[breakdown_key] += trigger_value
         }
     }
 
-    IpaQueryResult {
-        input_size: QuerySize::try_from(query_size).unwrap(),
+    Ok(IpaQueryResult {
+        input_size: QuerySize::try_from(query_size)?,
         config: query_config,
         latency: lat,
         breakdowns,
-    }
+    })
 }
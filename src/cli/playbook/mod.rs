@@ -1,21 +1,31 @@
+mod encrypted;
 mod input;
 mod ipa;
 mod multiply;
 
-pub use self::ipa::playbook_ipa;
+pub use self::ipa::{playbook_encrypted_ipa, playbook_ipa};
 use crate::{
     config::{ClientConfig, NetworkConfig, PeerConfig},
     net::{ClientIdentity, MpcHelperClient},
 };
 use comfy_table::{Cell, Color, Table};
 use core::fmt::Debug;
+pub use encrypted::{EncryptedInputs, ReportFileError, ReportFiles, ReportFilter};
 use hyper::http::uri::Scheme;
 pub use input::InputSource;
 pub use multiply::secure_mul;
 use std::{fs, path::Path, time::Duration};
 use tokio::time::sleep;
 
-pub fn validate<'a, I, S>(expected: I, actual: I)
+#[derive(Debug, thiserror::Error)]
+#[error("expected and actual results don't match: {0}")]
+pub struct ValidationError(String);
+
+/// Prints the expected and actual rows side by side.
+///
+/// # Errors
+/// If any of the rows differ.
+pub fn validate<'a, I, S>(expected: I, actual: I) -> Result<(), ValidationError>
 where
     I: IntoIterator<Item = &'a S>,
     I::IntoIter: ExactSizeIterator,
@@ -55,17 +65,23 @@ where
 
     tracing::info!("\n{table}\n");
 
-    assert!(
-        mismatch.is_empty(),
-        "Expected and actual results don't match: {:?}",
-        mismatch
-    );
+    if mismatch.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError(format!("{mismatch:?}")))
+    }
 }
 
+/// Creates clients for the helpers in the network configuration at `network_path`, which
+/// authenticate with `identity`.
+///
+/// ## Panics
+/// If the network configuration can't be read, or clients can't be created from it.
 pub async fn make_clients(
     network_path: Option<&Path>,
     scheme: Scheme,
     wait: usize,
+    identity: ClientIdentity,
 ) -> ([MpcHelperClient; 3], NetworkConfig) {
     let mut wait = wait;
     let network = if let Some(path) = network_path {
@@ -84,7 +100,7 @@ pub async fn make_clients(
 
     // Note: This closure is only called when the selected action uses clients.
 
-    let clients = MpcHelperClient::from_conf(&network, ClientIdentity::None);
+    let clients = MpcHelperClient::from_conf(&network, identity).unwrap();
     while wait > 0 && !clients_ready(&clients).await {
         tracing::debug!("waiting for servers to come up");
         sleep(Duration::from_secs(1)).await;
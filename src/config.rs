@@ -10,7 +10,7 @@ use hyper::{client::Builder, http::uri::Scheme, Uri};
 use rustls_pemfile::Item;
 use serde::{Deserialize, Deserializer, Serialize};
 use tokio::fs;
-use tokio_rustls::rustls::Certificate;
+use tokio_rustls::rustls::{Certificate, ServerName};
 
 use std::{
     array,
@@ -19,6 +19,7 @@ use std::{
     iter::Zip,
     path::PathBuf,
     slice,
+    time::Duration,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -29,6 +30,17 @@ pub enum Error {
     InvalidUri(#[from] hyper::http::uri::InvalidUri),
     #[error(transparent)]
     IOError(#[from] std::io::Error),
+    #[error("invalid configuration of peer {url}: {reason}")]
+    InvalidPeer { url: Uri, reason: String },
+}
+
+impl Error {
+    fn invalid_peer<R: Into<String>>(peer: &PeerConfig, reason: R) -> Self {
+        Self::InvalidPeer {
+            url: peer.url.clone(),
+            reason: reason.into(),
+        }
+    }
 }
 
 /// Configuration information describing a helper network.
@@ -52,7 +64,8 @@ impl NetworkConfig {
     /// To read file, use `fs::read_to_string`
     ///
     /// # Errors
-    /// if `input` is in an invalid format
+    /// if `input` is in an invalid format, or describes peers that can't be connected to
+    /// (see [`Self::validate`]).
     pub fn from_toml_str(input: &str) -> Result<Self, Error> {
         use config::{Config, File, FileFormat};
 
@@ -60,10 +73,23 @@ impl NetworkConfig {
             .add_source(File::from_str(input, FileFormat::Toml))
             .build()?
             .try_deserialize()?;
+        conf.validate()?;
 
         Ok(conf)
     }
 
+    /// Checks that clients can be created for every peer: peer URLs must have a host that is a
+    /// valid TLS server name, and peers must have a stream port if the client configuration
+    /// selects [`StepTransport::Stream`].
+    ///
+    /// # Errors
+    /// If any of the peers does not meet these requirements.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.peers
+            .iter()
+            .try_for_each(|peer| peer.validate(&self.client))
+    }
+
     pub fn new(peers: [PeerConfig; 3], client: ClientConfig) -> Self {
         Self { peers, client }
     }
@@ -122,6 +148,11 @@ pub struct PeerConfig {
     /// Match key encryption configuration.
     #[serde(default, rename = "hpke")]
     pub hpke_config: Option<HpkeClientConfig>,
+
+    /// Port the peer accepts multiplexed step connections on. Must be set for every peer if the
+    /// client configuration selects [`StepTransport::Stream`]. The host is taken from `url`.
+    #[serde(default)]
+    pub stream_port: Option<u16>,
 }
 
 impl PeerConfig {
@@ -130,8 +161,42 @@ impl PeerConfig {
             url,
             certificate,
             hpke_config: None,
+            stream_port: None,
         }
     }
+
+    /// Returns the host of the peer URL, which is also the name its TLS certificate is
+    /// verified against.
+    ///
+    /// # Errors
+    /// If the URL has no host, or the host is not a valid TLS server name.
+    pub fn server_name(&self) -> Result<(&str, ServerName), Error> {
+        let host = self
+            .url
+            .host()
+            .ok_or_else(|| Error::invalid_peer(self, "URL has no host"))?;
+        let server_name = ServerName::try_from(host)
+            .map_err(|e| Error::invalid_peer(self, format!("invalid server name {host}: {e}")))?;
+        Ok((host, server_name))
+    }
+
+    /// Returns the port the peer accepts multiplexed step connections on.
+    ///
+    /// # Errors
+    /// If the peer does not configure one.
+    pub fn required_stream_port(&self) -> Result<u16, Error> {
+        self.stream_port.ok_or_else(|| {
+            Error::invalid_peer(self, "stream_port is required by the stream step transport")
+        })
+    }
+
+    fn validate(&self, client: &ClientConfig) -> Result<(), Error> {
+        self.server_name()?;
+        if client.step_transport == StepTransport::Stream {
+            self.required_stream_port()?;
+        }
+        Ok(())
+    }
 }
 
 /// Match key encryption client configuration. To encrypt match keys towards a helper node, clients
@@ -256,6 +321,20 @@ pub async fn hpke_registry(
     ))]))
 }
 
+/// Configuration for reloading TLS certificates while the helper is running.
+#[derive(Clone, Debug)]
+pub struct TlsReloadConfig {
+    /// How often the certificate files and the network configuration are checked for changes.
+    pub interval: Duration,
+
+    /// Network configuration file to re-read peer certificates from. If not set, the peer
+    /// certificates the helper was started with remain in use.
+    pub network_config_path: Option<PathBuf>,
+
+    /// A warning is logged when any configured certificate expires within this period.
+    pub expiry_warning: Duration,
+}
+
 /// Configuration information for launching an instance of the helper party web service.
 #[derive(Clone, Debug)]
 pub struct ServerConfig {
@@ -270,6 +349,90 @@ pub struct ServerConfig {
 
     /// Configuration needed for encrypting and decrypting match keys
     pub hpke_config: Option<HpkeServerConfig>,
+
+    /// Report collectors that are allowed to use the query APIs. If empty, the query APIs do not
+    /// require authentication.
+    pub report_collectors: Vec<ReportCollectorConfig>,
+}
+
+/// Report collectors that are allowed to run queries on a helper.
+///
+/// In the configuration file, each report collector is listed in a `[[collector]]` table.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ReportCollectorsConfig {
+    #[serde(default, rename = "collector")]
+    pub collectors: Vec<ReportCollectorConfig>,
+}
+
+impl ReportCollectorsConfig {
+    /// Reads config from string. Expects config to be toml format.
+    ///
+    /// # Errors
+    /// if `input` is in an invalid format
+    pub fn from_toml_str(input: &str) -> Result<Self, Error> {
+        use config::{Config, File, FileFormat};
+
+        let conf: Self = Config::builder()
+            .add_source(File::from_str(input, FileFormat::Toml))
+            .build()?
+            .try_deserialize()?;
+
+        Ok(conf)
+    }
+}
+
+/// A report collector that is allowed to run queries on a helper. The report collector
+/// authenticates either with a TLS client certificate or with a bearer token, and must have at
+/// least one of them configured.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ReportCollectorConfig {
+    /// Name of the report collector. Queries are bound to the report collector that created them.
+    pub name: String,
+
+    /// Report collector's TLS client certificate
+    ///
+    /// In the configuration file, the certificate must be in PEM format. It is converted to DER
+    /// when the config is loaded.
+    #[serde(default, deserialize_with = "certificate_from_pem")]
+    pub certificate: Option<Certificate>,
+
+    /// Secret that the report collector's bearer tokens are signed with, hex-encoded in the
+    /// configuration file.
+    #[serde(default)]
+    pub token_key: Option<TokenKey>,
+}
+
+/// Secret shared between a report collector and the helpers, that signs the report collector's
+/// bearer tokens.
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TokenKey(Vec<u8>);
+
+impl TokenKey {
+    #[must_use]
+    pub fn new(key: Vec<u8>) -> Self {
+        Self(key)
+    }
+
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for TokenKey {
+    type Error = hex::FromHexError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        hex::decode(value.trim()).map(Self)
+    }
+}
+
+impl Debug for TokenKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the secret into logs.
+        f.write_str("TokenKey(..)")
+    }
 }
 
 pub trait HyperClientConfigurator {
@@ -278,7 +441,12 @@ pub trait HyperClientConfigurator {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
+    #[serde(default = "HttpClientConfigurator::http2")]
     pub http_config: HttpClientConfigurator,
+
+    /// How MPC step data is sent to the other helpers.
+    #[serde(default)]
+    pub step_transport: StepTransport,
 }
 
 impl Default for ClientConfig {
@@ -292,6 +460,7 @@ impl ClientConfig {
     pub fn use_http2() -> Self {
         Self {
             http_config: HttpClientConfigurator::http2(),
+            step_transport: StepTransport::default(),
         }
     }
 
@@ -299,8 +468,27 @@ impl ClientConfig {
     pub fn use_http1() -> Self {
         Self {
             http_config: HttpClientConfigurator::http1(),
+            step_transport: StepTransport::default(),
         }
     }
+
+    #[must_use]
+    pub fn with_step_transport(mut self, step_transport: StepTransport) -> Self {
+        self.step_transport = step_transport;
+        self
+    }
+}
+
+/// Selects how helpers send MPC step data to each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepTransport {
+    /// Every channel is sent as the body of its own HTTP request.
+    #[default]
+    Http,
+    /// All channels are multiplexed over one persistent connection per pair of helpers, made to
+    /// the peer's [`PeerConfig::stream_port`]. It uses TLS unless HTTPS is disabled.
+    Stream,
 }
 
 impl<B: Borrow<ClientConfig>> HyperClientConfigurator for B {
@@ -379,7 +567,13 @@ impl Debug for Http2Configurator {
 
 #[cfg(all(test, unit_test))]
 mod tests {
-    use crate::{config::HpkeClientConfig, helpers::HelperIdentity, net::test::TestConfigBuilder};
+    use crate::{
+        config::{
+            Error, HpkeClientConfig, NetworkConfig, ReportCollectorsConfig, StepTransport, TokenKey,
+        },
+        helpers::HelperIdentity,
+        net::test::{TestConfigBuilder, TEST_CERTS, TEST_CERTS_DER},
+    };
     use hpke::{kem::X25519HkdfSha256, Kem};
     use hyper::Uri;
     use rand::rngs::StdRng;
@@ -409,6 +603,116 @@ mod tests {
         assert_eq!(value3.url, uri3);
     }
 
+    #[test]
+    fn parse_step_transport() {
+        let conf = NetworkConfig::from_toml_str(
+            r#"
+            [[peers]]
+            url = "helper1.test:443"
+            stream_port = 4431
+
+            [[peers]]
+            url = "helper2.test:443"
+            stream_port = 4432
+
+            [[peers]]
+            url = "helper3.test:443"
+            stream_port = 4433
+
+            [client]
+            step_transport = "stream"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(StepTransport::Stream, conf.client.step_transport);
+        assert_eq!(
+            [Some(4431), Some(4432), Some(4433)],
+            conf.peers().clone().map(|peer| peer.stream_port)
+        );
+
+        let conf = TestConfigBuilder::with_http_and_default_test_ports().build();
+        assert_eq!(StepTransport::Http, conf.network.client.step_transport);
+    }
+
+    #[test]
+    fn stream_transport_requires_stream_port() {
+        let err = NetworkConfig::from_toml_str(
+            r#"
+            [[peers]]
+            url = "helper1.test:443"
+            stream_port = 4431
+
+            [[peers]]
+            url = "helper2.test:443"
+
+            [[peers]]
+            url = "helper3.test:443"
+            stream_port = 4433
+
+            [client]
+            step_transport = "stream"
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, Error::InvalidPeer { ref url, .. } if url == "helper2.test:443"),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_peer_without_host() {
+        let err = NetworkConfig::from_toml_str(
+            r#"
+            [[peers]]
+            url = "/helper1"
+
+            [[peers]]
+            url = "helper2.test:443"
+
+            [[peers]]
+            url = "helper3.test:443"
+            "#,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::InvalidPeer { .. }), "{err:?}");
+    }
+
+    #[test]
+    fn parse_report_collectors() {
+        let conf = ReportCollectorsConfig::from_toml_str(&format!(
+            r#"
+            [[collector]]
+            name = "collector1"
+            certificate = """
+{}"""
+
+            [[collector]]
+            name = "collector2"
+            token_key = "00112233445566778899aabbccddeeff"
+            "#,
+            std::str::from_utf8(TEST_CERTS[0]).unwrap(),
+        ))
+        .unwrap();
+        let [collector1, collector2] = <[_; 2]>::try_from(conf.collectors).unwrap();
+        assert_eq!("collector1", collector1.name);
+        assert_eq!(
+            Some(TEST_CERTS_DER[0].as_slice()),
+            collector1
+                .certificate
+                .as_ref()
+                .map(|cert| cert.0.as_slice())
+        );
+        assert!(collector1.token_key.is_none());
+        assert_eq!("collector2", collector2.name);
+        assert!(collector2.certificate.is_none());
+        assert_eq!(
+            Some(&hex::decode("00112233445566778899aabbccddeeff").unwrap()[..]),
+            collector2.token_key.as_ref().map(TokenKey::as_bytes)
+        );
+        assert_eq!("Some(TokenKey(..))", format!("{:?}", collector2.token_key));
+    }
+
     #[test]
     fn debug_hpke_client_config() {
         let mut rng = StdRng::seed_from_u64(1);
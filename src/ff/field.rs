@@ -3,7 +3,7 @@ use crate::{
     secret_sharing::{Block, SharedValue},
 };
 use std::fmt::Debug;
-use typenum::{U1, U4};
+use typenum::{U1, U4, U8};
 
 impl Block for u8 {
     type Size = U1;
@@ -13,6 +13,10 @@ impl Block for u32 {
     type Size = U4;
 }
 
+impl Block for u64 {
+    type Size = U8;
+}
+
 pub trait Field: SharedValue + TryFrom<u128, Error = error::Error> + Into<Self::Storage> {
     /// Multiplicative identity element
     const ONE: Self;
@@ -34,4 +38,5 @@ pub enum FieldType {
     #[cfg(any(test, feature = "weak-field"))]
     Fp31,
     Fp32BitPrime,
+    Fp61BitPrime,
 }
@@ -8,7 +8,7 @@ use std::{
     fmt::{Debug, Formatter},
     ops::Index,
 };
-use typenum::{Unsigned, U1, U4, U5};
+use typenum::{Unsigned, U1, U4, U5, U8};
 
 /// Trait for data types storing arbitrary number of bits.
 pub trait GaloisField:
@@ -25,6 +25,7 @@ pub trait GaloisField:
 type U8_1 = BitArr!(for 8, in u8, Lsb0);
 type U8_4 = BitArr!(for 32, in u8, Lsb0);
 type U8_5 = BitArr!(for 40, in u8, Lsb0);
+type U8_8 = BitArr!(for 64, in u8, Lsb0);
 
 impl Block for U8_1 {
     type Size = U1;
@@ -38,6 +39,10 @@ impl Block for U8_5 {
     type Size = U5;
 }
 
+impl Block for U8_8 {
+    type Size = U8;
+}
+
 /// The implementation below cannot be constrained without breaking Rust's
 /// macro processor.  This noop ensures that the instance of `GenericArray` used
 /// is `Copy`.  It should be - it's the same size as the `BitArray` instance.
@@ -282,12 +287,15 @@ macro_rules! bit_array_impl {
                 fn mul(self, rhs: Self) -> Self::Output {
                     let mut product = clmul(self, rhs);
                     let poly = <Self as GaloisField>::POLYNOMIAL;
-                    while (u128::BITS - product.leading_zeros()) > Self::BITS {
-                        let bits_to_shift = poly.leading_zeros() - product.leading_zeros();
-                        product ^= (poly << bits_to_shift);
+                    // Reduce the product one bit at a time, starting from the highest one.
+                    // The polynomial is XORed in using a mask rather than a branch, so
+                    // the number of operations does not depend on the values multiplied.
+                    for i in (Self::BITS..2 * Self::BITS - 1).rev() {
+                        let mask = 0_u128.wrapping_sub((product >> i) & 1);
+                        product ^= (poly << (i - Self::BITS)) & mask;
                     }
 
-                    Self::try_from(product).unwrap()
+                    Self::truncate_from(product)
                 }
             }
 
@@ -310,9 +318,11 @@ macro_rules! bit_array_impl {
                 /// Fallible conversion from `u128` to this data type. The input value must
                 /// be at most `Self::BITS` long. That is, the integer value must be less than
                 /// or equal to `2^Self::BITS`, or it will return an error.
+                /// The conversion itself is constant time, only the validity check branches.
                 fn try_from(v: u128) -> Result<Self, Self::Error> {
-                    if u128::BITS - v.leading_zeros() <= Self::BITS {
-                        Ok(Self::truncate_from(v))
+                    let truncated = Self::truncate_from(v);
+                    if v >> Self::BITS == 0 {
+                        Ok(truncated)
                     } else {
                         Err(crate::error::Error::FieldValueTruncation(format!(
                             "Bit array size {} is too small to hold the value {}.",
@@ -528,6 +538,16 @@ macro_rules! bit_array_impl {
     };
 }
 
+bit_array_impl!(
+    bit_array_64,
+    Gf64Bit,
+    U8_8,
+    64,
+    bitarr!(const u8, Lsb0; 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0),
+    // x^64 + x^4 + x^3 + x + 1
+    0b1_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0001_1011_u128
+);
+
 bit_array_impl!(
     bit_array_40,
     Gf40Bit,
@@ -7,10 +7,10 @@ mod galois_field;
 mod prime_field;
 
 pub use field::{Field, FieldType};
-pub use galois_field::{GaloisField, Gf2, Gf32Bit, Gf40Bit, Gf8Bit};
+pub use galois_field::{GaloisField, Gf2, Gf32Bit, Gf40Bit, Gf64Bit, Gf8Bit};
 #[cfg(any(test, feature = "weak-field"))]
 pub use prime_field::Fp31;
-pub use prime_field::{Fp32BitPrime, PrimeField};
+pub use prime_field::{Fp32BitPrime, Fp61BitPrime, PrimeField};
 
 use crate::secret_sharing::SharedValue;
 use generic_array::{ArrayLength, GenericArray};
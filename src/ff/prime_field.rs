@@ -27,8 +27,109 @@ impl<F: PrimeField> Serializable for F {
     }
 }
 
+/// Constant-time arithmetic helpers for prime fields.
+///
+/// Helpers operate on secret shares, so the time it takes to add, multiply or reduce a field
+/// value must not depend on the value itself. Integer division (`%`) does not provide
+/// this guarantee on most platforms, so reductions are done using Barrett reduction and
+/// branch-free conditional subtraction instead. Barrett reduction requires the prime to fit into
+/// 32 bits, so that the product of two reduced values fits into `u64`. Larger fields use
+/// Mersenne primes, which are reduced by folding the high bits onto the low ones.
+mod ct {
+    /// Returns `x - p` if `x >= p` and `x` otherwise, without branching on `x`.
+    /// `x` must be less than `2p`.
+    #[inline]
+    pub const fn cond_sub(x: u64, p: u64) -> u64 {
+        let r = x.wrapping_sub(p);
+        // All ones if `x - p` underflowed, zero otherwise. This works because `x < 2p < 2^63`.
+        let mask = 0_u64.wrapping_sub(r >> 63);
+        r.wrapping_add(p & mask)
+    }
+
+    /// Barrett reduction factor `floor(2^64 / p)`.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_lossless)]
+    pub const fn barrett_factor(p: u64) -> u64 {
+        ((1_u128 << 64) / p as u128) as u64
+    }
+
+    /// Reduces `x` modulo `p`, where `m` is the Barrett factor for `p`.
+    /// The quotient estimate is off by at most one for any `x < 2^64`, so a single
+    /// conditional subtraction is sufficient.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_lossless)]
+    #[inline]
+    pub const fn reduce(x: u64, p: u64, m: u64) -> u64 {
+        let q = ((x as u128 * m as u128) >> 64) as u64;
+        cond_sub(x - q * p, p)
+    }
+
+    /// Reduces a 128 bit value modulo `p`, processing it 32 bits at a time.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_lossless)]
+    #[inline]
+    pub const fn reduce_u128(v: u128, p: u64, m: u64) -> u64 {
+        let mut acc = 0;
+        let mut limbs = 4;
+        while limbs > 0 {
+            limbs -= 1;
+            let limb = (v >> (32 * limbs)) as u32 as u64;
+            acc = reduce(acc << 32 | limb, p, m);
+        }
+        acc
+    }
+
+    /// Reduces a 128 bit value modulo the Mersenne prime `p = 2^bits - 1`, using
+    /// `2^bits ≡ 1 (mod p)`. For `43 <= bits <= 62`, two folds of the high bits onto the
+    /// low ones leave any `v` below `2p`, so a single conditional subtraction is sufficient.
+    #[allow(clippy::cast_possible_truncation)]
+    #[inline]
+    pub const fn reduce_mersenne(v: u128, bits: u32) -> u64 {
+        let p = (1_u128 << bits) - 1;
+        let v = (v & p) + (v >> bits);
+        let v = (v & p) + (v >> bits);
+        cond_sub(v as u64, p as u64)
+    }
+}
+
 macro_rules! field_impl {
+    (@reduction $field:ident, barrett) => {
+        impl $field {
+            /// Barrett factor for this field's prime.
+            const BARRETT: u64 = ct::barrett_factor(Self::P64);
+
+            #[inline]
+            fn reduce_u128(v: u128) -> u64 {
+                ct::reduce_u128(v, Self::P64, Self::BARRETT)
+            }
+
+            #[inline]
+            fn reduce_product(a: u64, b: u64) -> u64 {
+                ct::reduce(a * b, Self::P64, Self::BARRETT)
+            }
+        }
+
+        // Barrett reduction requires the prime to fit into 32 bits.
+        const _: () = assert!($field::P64 <= 0xFFFF_FFFF);
+    };
+    (@reduction $field:ident, mersenne) => {
+        impl $field {
+            #[inline]
+            fn reduce_u128(v: u128) -> u64 {
+                ct::reduce_mersenne(v, Self::BITS)
+            }
+
+            #[inline]
+            fn reduce_product(a: u64, b: u64) -> u64 {
+                ct::reduce_mersenne(u128::from(a) * u128::from(b), Self::BITS)
+            }
+        }
+
+        const _: () = assert!(
+            $field::P64 == (1 << $field::BITS) - 1 && 43 <= $field::BITS && $field::BITS <= 62
+        );
+    };
     ( $field:ident, $store:ty, $bits:expr, $prime:expr ) => {
+        field_impl! { $field, $store, $bits, $prime, barrett }
+    };
+    ( $field:ident, $store:ty, $bits:expr, $prime:expr, $reduction:ident ) => {
         use super::*;
         use crate::ff::FieldType;
 
@@ -56,8 +157,7 @@ macro_rules! field_impl {
             ///
             /// This method is simpler than rejection sampling for these small prime fields.
             fn truncate_from<T: Into<u128>>(v: T) -> Self {
-                #[allow(clippy::cast_possible_truncation)]
-                Self((v.into() % u128::from(Self::PRIME)) as <Self as SharedValue>::Storage)
+                Self::from_reduced(Self::reduce_u128(v.into()))
             }
         }
 
@@ -66,13 +166,27 @@ macro_rules! field_impl {
             const PRIME: Self::PrimeInteger = $prime;
         }
 
+        impl $field {
+            /// Prime as `u64`, which is what constant time helpers operate on.
+            #[allow(clippy::cast_lossless)]
+            const P64: u64 = Self::PRIME as u64;
+
+            /// Wraps a value that has already been reduced.
+            #[allow(clippy::cast_possible_truncation)]
+            #[inline]
+            fn from_reduced(v: u64) -> Self {
+                Self(v as <Self as SharedValue>::Storage)
+            }
+        }
+
+        field_impl! { @reduction $field, $reduction }
+
         impl std::ops::Add for $field {
             type Output = Self;
 
             fn add(self, rhs: Self) -> Self::Output {
                 let c = u64::from;
-                debug_assert!(c(Self::PRIME) < (u64::MAX >> 1));
-                Self(((c(self.0) + c(rhs.0)) % c(Self::PRIME)) as <Self as SharedValue>::Storage)
+                Self::from_reduced(ct::cond_sub(c(self.0) + c(rhs.0), Self::P64))
             }
         }
 
@@ -87,7 +201,7 @@ macro_rules! field_impl {
             type Output = Self;
 
             fn neg(self) -> Self::Output {
-                Self((Self::PRIME - self.0) % Self::PRIME)
+                Self::from_reduced(ct::cond_sub(Self::P64 - u64::from(self.0), Self::P64))
             }
         }
 
@@ -96,12 +210,7 @@ macro_rules! field_impl {
 
             fn sub(self, rhs: Self) -> Self::Output {
                 let c = u64::from;
-                debug_assert!(c(Self::PRIME) < (u64::MAX >> 1));
-                // TODO(mt) - constant time?
-                Self(
-                    ((c(Self::PRIME) + c(self.0) - c(rhs.0)) % c(Self::PRIME))
-                        as <Self as SharedValue>::Storage,
-                )
+                Self::from_reduced(ct::cond_sub(Self::P64 + c(self.0) - c(rhs.0), Self::P64))
             }
         }
 
@@ -116,11 +225,8 @@ macro_rules! field_impl {
             type Output = Self;
 
             fn mul(self, rhs: Self) -> Self::Output {
-                debug_assert!(u32::try_from(Self::PRIME).is_ok());
                 let c = u64::from;
-                // TODO(mt) - constant time?
-                #[allow(clippy::cast_possible_truncation)]
-                Self(((c(self.0) * c(rhs.0)) % c(Self::PRIME)) as <Self as SharedValue>::Storage)
+                Self::from_reduced(Self::reduce_product(c(self.0), c(rhs.0)))
             }
         }
 
@@ -134,9 +240,12 @@ macro_rules! field_impl {
         impl TryFrom<u128> for $field {
             type Error = crate::error::Error;
 
+            /// The reduction is always computed, so the time it takes only depends on whether
+            /// `v` fits into `Self::BITS`, not on the value itself.
             fn try_from(v: u128) -> Result<Self, Self::Error> {
-                if u128::BITS - v.leading_zeros() <= Self::BITS {
-                    Ok(Self::truncate_from(v))
+                let reduced = Self::truncate_from(v);
+                if v >> Self::BITS == 0 {
+                    Ok(reduced)
                 } else {
                     Err(crate::error::Error::FieldValueTruncation(format!(
                         "Storage size {} is too small to hold the value {}.",
@@ -205,6 +314,19 @@ macro_rules! field_impl {
 
             proptest! {
 
+                #[test]
+                fn matches_reference(a in 0..$field::PRIME, b in 0..$field::PRIME, v: u128) {
+                    let p = u128::from($field::PRIME);
+                    let (x, y) = ($field(a), $field(b));
+                    let (a, b) = (u128::from(a), u128::from(b));
+
+                    assert_eq!((a + b) % p, (x + y).as_u128());
+                    assert_eq!((p + a - b) % p, (x - y).as_u128());
+                    assert_eq!((a * b) % p, (x * y).as_u128());
+                    assert_eq!((p - a) % p, (-x).as_u128());
+                    assert_eq!(v % p, $field::truncate_from(v).as_u128());
+                }
+
                 #[test]
                 fn serde(v in 0..$field::PRIME) {
                     let field_v = $field(v);
@@ -294,6 +416,38 @@ mod fp32bit {
     }
 }
 
+mod fp61bit {
+    field_impl! { Fp61BitPrime, u64, 61, 2_305_843_009_213_693_951, mersenne }
+
+    #[cfg(all(test, unit_test))]
+    mod specialized_tests {
+        use super::*;
+
+        #[test]
+        fn sixty_one_bit_prime() {
+            let x = Fp61BitPrime::truncate_from(Fp61BitPrime::PRIME - 1);
+            let y = Fp61BitPrime::truncate_from(Fp61BitPrime::PRIME - 2);
+
+            assert_eq!(x - y, Fp61BitPrime::ONE);
+            assert_eq!(y - x, Fp61BitPrime::truncate_from(Fp61BitPrime::PRIME - 1));
+            assert_eq!(y + x, Fp61BitPrime::truncate_from(Fp61BitPrime::PRIME - 3));
+            assert_eq!(x * y, Fp61BitPrime::truncate_from(2_u32));
+            assert_eq!(x * x, Fp61BitPrime::ONE);
+
+            // 2^128 = 2^(2*61 + 6) ≡ 2^6
+            assert_eq!(
+                Fp61BitPrime::truncate_from(63_u32),
+                Fp61BitPrime::truncate_from(u128::MAX)
+            );
+            assert_eq!(
+                Fp61BitPrime::ZERO,
+                Fp61BitPrime::truncate_from(u128::from(Fp61BitPrime::PRIME) << 66)
+            );
+        }
+    }
+}
+
 #[cfg(any(test, feature = "weak-field"))]
 pub use fp31::Fp31;
 pub use fp32bit::Fp32BitPrime;
+pub use fp61bit::Fp61BitPrime;
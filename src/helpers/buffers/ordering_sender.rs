@@ -197,6 +197,44 @@ impl Waiting {
     fn wake(&self, i: usize) {
         self.shard(i).wake(i);
     }
+
+    /// Indices of all the saved wakers, in ascending order.
+    fn indices(&self) -> Vec<usize> {
+        let mut indices = self
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                let shard = shard.lock().unwrap();
+                shard.wakers.iter().map(|wi| wi.i).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices
+    }
+}
+
+/// A snapshot of the [`OrderingSender`] state, used to diagnose stalled channels.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderingSenderState {
+    /// Index of the next message to be written. It counts [`close`] as well.
+    ///
+    /// [`close`]: OrderingSender::close
+    pub next: usize,
+    /// Number of bytes written, but not taken by the stream yet.
+    pub buffered: usize,
+    /// Set if the message at `next` is waiting for the stream to free up the buffer.
+    pub blocked: bool,
+    pub closed: bool,
+    /// Indices of messages waiting for their turn to be written.
+    pub waiting: Vec<usize>,
+}
+
+impl OrderingSenderState {
+    /// Returns `true` if some messages were not taken by the stream yet.
+    #[must_use]
+    pub fn is_open(&self) -> bool {
+        !self.closed || self.buffered > 0
+    }
 }
 
 /// An `OrderingSender` accepts messages for sending in any order, but
@@ -317,6 +355,43 @@ impl OrderingSender {
         }
     }
 
+    /// Returns `true` if all the data written to this sender was taken out, and it is either
+    /// closed or has seen all the messages up to `end`.
+    ///
+    /// ## Panics
+    /// If the internal mutex is poisoned.
+    pub(crate) fn is_drained(&self, end: Option<usize>) -> bool {
+        let b = self.state.lock().unwrap();
+        b.written == 0 && (b.closed || end.map_or(false, |end| self.next.load(Acquire) >= end))
+    }
+
+    /// Returns a snapshot of this sender's state.
+    ///
+    /// ## Panics
+    /// If the internal mutex is poisoned.
+    pub fn state(&self) -> OrderingSenderState {
+        let next = self.next.load(Acquire);
+        let (buffered, blocked, closed) = {
+            let state = self.state.lock().unwrap();
+            (state.written, state.write_ready.is_some(), state.closed)
+        };
+        // wakers are not cleaned up if the futures were polled without being woken up.
+        let waiting = self
+            .waiting
+            .indices()
+            .into_iter()
+            .filter(|&i| i > next)
+            .collect();
+
+        OrderingSenderState {
+            next,
+            buffered,
+            blocked,
+            closed,
+            waiting,
+        }
+    }
+
     /// The stream interface requires a mutable reference to the stream itself.
     /// That's not possible here as we create a ton of immutable references to this.
     /// This wrapper takes a trivial reference so that we can implement `Stream`.
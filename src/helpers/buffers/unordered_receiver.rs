@@ -17,7 +17,7 @@ use typenum::Unsigned;
 /// A future for receiving item `i` from an `UnorderedReceiver`.
 pub struct Receiver<S, C, M>
 where
-    S: Stream<Item = C> + Send,
+    S: Stream<Item = Result<C, Error>> + Send,
     C: AsRef<[u8]>,
     M: Message,
 {
@@ -28,7 +28,7 @@ where
 
 impl<S, C, M> Future for Receiver<S, C, M>
 where
-    S: Stream<Item = C> + Send,
+    S: Stream<Item = Result<C, Error>> + Send,
     C: AsRef<[u8]>,
     M: Message,
 {
@@ -37,7 +37,9 @@ where
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.as_ref();
         let mut recv = this.receiver.lock().unwrap();
-        if recv.is_next(this.i) {
+        if let Some(error) = recv.failure.as_ref() {
+            Poll::Ready(Err(error()))
+        } else if recv.is_next(this.i) {
             recv.poll_next(cx)
         } else {
             recv.add_waker(this.i, cx.waker().clone());
@@ -107,7 +109,7 @@ impl Spare {
 
 pub struct OperatingState<S, C>
 where
-    S: Stream<Item = C>,
+    S: Stream<Item = Result<C, Error>>,
     C: AsRef<[u8]>,
 {
     /// The stream we're reading from.
@@ -142,12 +144,16 @@ where
     /// that easing load on this mechanism.  There might also need to be some
     /// end-to-end back pressure for tasks that do not involve sending at all.
     overflow_wakers: Vec<Waker>,
+    /// The waker of the future for the next record, if it is waiting for the stream.
+    next_waker: Option<Waker>,
+    /// If set, all the pending and future reads fail with the error this function creates.
+    failure: Option<Box<dyn Fn() -> Error + Send>>,
     _marker: PhantomData<C>,
 }
 
 impl<S, C> OperatingState<S, C>
 where
-    S: Stream<Item = C> + Send,
+    S: Stream<Item = Result<C, Error>> + Send,
     C: AsRef<[u8]>,
 {
     /// Determine whether `i` is the next record that we expect to receive.
@@ -199,6 +205,16 @@ where
         }
     }
 
+    /// Makes all the pending and future reads fail with the error `error` creates. Returns the
+    /// wakers of the pending reads.
+    fn fail<F: Fn() -> Error + Send + 'static>(&mut self, error: F) -> Vec<Waker> {
+        self.failure = Some(Box::new(error));
+        let mut wakers = take(&mut self.overflow_wakers);
+        wakers.extend(self.wakers.iter_mut().filter_map(Option::take));
+        wakers.extend(self.next_waker.take());
+        wakers
+    }
+
     /// Poll for the next record.  This should only be invoked when
     /// the future for the next message is polled.
     fn poll_next<M: Message>(&mut self, cx: &mut Context<'_>) -> Poll<Result<M, Error>> {
@@ -210,15 +226,32 @@ where
         loop {
             match self.stream.as_mut().poll_next(cx) {
                 Poll::Pending => {
+                    self.next_waker = Some(cx.waker().clone());
                     return Poll::Pending;
                 }
-                Poll::Ready(Some(b)) => {
+                Poll::Ready(Some(Ok(b))) => {
                     if let Some(m) = self.spare.extend(b.as_ref()) {
+                        self.next_waker = None;
                         self.wake_next();
                         return Poll::Ready(Ok(m));
                     }
                 }
+                Poll::Ready(Some(Err(e))) => {
+                    // Nothing can be read past this point, so all the other reads fail too.
+                    self.next_waker = None;
+                    let reason = match &e {
+                        Error::StreamFailed { reason } => reason.clone(),
+                        e => e.to_string(),
+                    };
+                    for w in self.fail(move || Error::StreamFailed {
+                        reason: reason.clone(),
+                    }) {
+                        w.wake();
+                    }
+                    return Poll::Ready(Err(e));
+                }
                 Poll::Ready(None) => {
+                    self.next_waker = None;
                     return Poll::Ready(Err(Error::EndOfStream {
                         record_id: RecordId::from(self.next),
                     }));
@@ -232,7 +265,7 @@ where
 /// available in any order.
 pub struct UnorderedReceiver<S, C>
 where
-    S: Stream<Item = C>,
+    S: Stream<Item = Result<C, Error>>,
     C: AsRef<[u8]>,
 {
     inner: Arc<Mutex<OperatingState<S, C>>>,
@@ -241,7 +274,7 @@ where
 #[allow(dead_code)]
 impl<S, C> UnorderedReceiver<S, C>
 where
-    S: Stream<Item = C> + Send,
+    S: Stream<Item = Result<C, Error>> + Send,
     C: AsRef<[u8]>,
 {
     /// Wrap a stream for unordered reading.
@@ -263,6 +296,8 @@ where
                 spare: Spare::default(),
                 wakers,
                 overflow_wakers: Vec::new(),
+                next_waker: None,
+                failure: None,
                 _marker: PhantomData,
             })),
         }
@@ -282,11 +317,67 @@ where
             _marker: PhantomData,
         }
     }
+
+    /// Returns a snapshot of this receiver's state.
+    ///
+    /// # Panics
+    ///
+    /// If the internal mutex is poisoned.
+    pub fn state(&self) -> UnorderedReceiverState {
+        let state = self.inner.lock().unwrap();
+        let capacity = state.wakers.len();
+        let next = state.next;
+        let waiting = state
+            .next_waker
+            .as_ref()
+            .map(|_| next)
+            .into_iter()
+            .chain((next + 1..=next + capacity).filter(|i| state.wakers[i % capacity].is_some()))
+            .collect();
+
+        UnorderedReceiverState {
+            next,
+            waiting,
+            overflow: state.overflow_wakers.len(),
+        }
+    }
+
+    /// Fails all the pending and future reads from this receiver with the error `error` creates.
+    ///
+    /// # Panics
+    ///
+    /// If the internal mutex is poisoned.
+    pub fn fail<F: Fn() -> Error + Send + 'static>(&self, error: F) {
+        let wakers = self.inner.lock().unwrap().fail(error);
+        for w in wakers {
+            w.wake();
+        }
+    }
+}
+
+/// A snapshot of the [`UnorderedReceiver`] state, used to diagnose stalled channels.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnorderedReceiverState {
+    /// Index of the next record to be received, which is also the number of records
+    /// received so far.
+    pub next: usize,
+    /// Indices of the records that are being waited for.
+    pub waiting: Vec<usize>,
+    /// Number of reads waiting for records too far ahead of `next` to be tracked by index.
+    pub overflow: usize,
+}
+
+impl UnorderedReceiverState {
+    /// Returns `true` if some reads are waiting for records to arrive.
+    #[must_use]
+    pub fn is_waiting(&self) -> bool {
+        !self.waiting.is_empty() || self.overflow > 0
+    }
 }
 
 impl<S, C> Clone for UnorderedReceiver<S, C>
 where
-    S: Stream<Item = C> + Send,
+    S: Stream<Item = Result<C, Error>> + Send,
     C: AsRef<[u8]>,
 {
     fn clone(&self) -> Self {
@@ -300,7 +391,7 @@ where
 mod test {
     use crate::{
         ff::{Field, Fp31, Fp32BitPrime, Serializable},
-        helpers::buffers::unordered_receiver::UnorderedReceiver,
+        helpers::{buffers::unordered_receiver::UnorderedReceiver, Error},
     };
     use futures::{
         future::{try_join, try_join_all},
@@ -316,7 +407,7 @@ mod test {
     use tokio::spawn;
     use typenum::Unsigned;
 
-    fn receiver<I, T>(it: I) -> UnorderedReceiver<impl Stream<Item = T>, T>
+    fn receiver<I, T>(it: I) -> UnorderedReceiver<impl Stream<Item = Result<T, Error>>, T>
     where
         I: IntoIterator<Item = T> + 'static,
         I::IntoIter: Send,
@@ -324,7 +415,7 @@ mod test {
     {
         // Use a small capacity so that we can overflow it easily.
         let capacity = NonZeroUsize::new(3).unwrap();
-        UnorderedReceiver::new(Box::pin(iter(it)), capacity)
+        UnorderedReceiver::new(Box::pin(iter(it.into_iter().map(Ok))), capacity)
     }
 
     #[cfg(not(feature = "shuttle"))]
@@ -506,4 +597,39 @@ mod test {
             }
         });
     }
+
+    /// A stream error fails the read that hit it, and all the other reads, including those that
+    /// were waiting already.
+    #[test]
+    #[cfg(not(feature = "shuttle"))]
+    fn stream_error_fails_all_reads() {
+        use futures::FutureExt;
+
+        let recv = UnorderedReceiver::new(
+            Box::pin(iter(vec![
+                Ok(vec![1]),
+                Err(Error::StreamFailed {
+                    reason: "corrupt".into(),
+                }),
+            ])),
+            NonZeroUsize::new(3).unwrap(),
+        );
+        let mut waiting = recv.recv::<Fp31, _>(2_usize);
+        assert!((&mut waiting).now_or_never().is_none());
+
+        assert!(recv
+            .recv::<Fp31, _>(0_usize)
+            .now_or_never()
+            .unwrap()
+            .is_ok());
+        for result in [
+            recv.recv::<Fp31, _>(1_usize).now_or_never().unwrap(),
+            waiting.now_or_never().unwrap(),
+        ] {
+            assert!(
+                matches!(&result, Err(Error::StreamFailed { reason }) if reason == "corrupt"),
+                "{result:?}"
+            );
+        }
+    }
 }
@@ -1,6 +1,6 @@
 use crate::{
     error::BoxError,
-    helpers::{ChannelId, HelperIdentity, Message, Role, TotalRecords},
+    helpers::{ChannelId, HelperIdentity, Message, Role, StallReport, TotalRecords},
     protocol::{step::Gate, RecordId},
 };
 use thiserror::Error;
@@ -53,6 +53,10 @@ pub enum Error {
         channel_id: ChannelId,
         total_records: TotalRecords,
     },
+    #[error("{0}")]
+    Stalled(StallReport),
+    #[error("The stream of records failed: {reason}")]
+    StreamFailed { reason: String },
 }
 
 impl Error {
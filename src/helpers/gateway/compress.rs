@@ -0,0 +1,259 @@
+use crate::helpers::Error;
+use futures::{ready, Stream, StreamExt};
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Display, Formatter},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Compression of the data sent over step channels.
+///
+/// Compressed data is sent in frames, each holding one chunk taken from the sending buffer. A frame
+/// has a 5-byte header: the length of its body as a little-endian `u32`, and a byte that tells
+/// whether the body is compressed. Chunks that don't get smaller are sent as is.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "enable-serde", serde(rename_all = "lowercase"))]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+}
+
+const HEADER_LEN: usize = 5;
+const RAW: u8 = 0;
+const LZ4: u8 = 1;
+
+impl Compression {
+    pub const NONE_STR: &'static str = "none";
+    pub const LZ4_STR: &'static str = "lz4";
+
+    /// Converts a chunk of data taken from the sending buffer into the bytes sent to the peer.
+    pub(super) fn encode(self, chunk: Vec<u8>) -> Vec<u8> {
+        match self {
+            Self::None => chunk,
+            Self::Lz4 => {
+                let compressed = lz4_flex::compress_prepend_size(&chunk);
+                let (kind, body) = if compressed.len() < chunk.len() {
+                    (LZ4, compressed)
+                } else {
+                    (RAW, chunk)
+                };
+                let len = u32::try_from(body.len()).expect("chunks are smaller than 4 GiB");
+                let mut frame = Vec::with_capacity(HEADER_LEN + body.len());
+                frame.extend_from_slice(&len.to_le_bytes());
+                frame.push(kind);
+                frame.extend_from_slice(&body);
+                frame
+            }
+        }
+    }
+
+    /// Reverts [`Self::encode`] on the bytes received from a peer. The peer takes chunks of at
+    /// most `max_chunk` bytes out of its sending buffer, so larger frames are rejected rather
+    /// than allocated.
+    pub(super) fn decode<S>(self, stream: S, max_chunk: usize) -> Decompress<S> {
+        Decompress {
+            inner: stream,
+            compression: self,
+            max_chunk,
+            buf: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+impl AsRef<str> for Compression {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::None => Self::NONE_STR,
+            Self::Lz4 => Self::LZ4_STR,
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+/// Stream of the data received over a step channel, with the compression removed.
+///
+/// If the data is corrupt, the stream yields [`Error::StreamFailed`] and ends, which fails all the
+/// receive operations on the channel.
+pub struct Decompress<S> {
+    inner: S,
+    compression: Compression,
+    /// Size of the largest chunk the peer sends, before compression.
+    max_chunk: usize,
+    /// Received bytes that do not make a complete frame yet.
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<S> Decompress<S> {
+    /// Takes the first frame out of the buffer, if it was received completely.
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>, String> {
+        let Some(header) = self.buf.get(..HEADER_LEN) else {
+            return Ok(None);
+        };
+        let len = usize::try_from(u32::from_le_bytes(header[..4].try_into().unwrap()))
+            .expect("u32 fits into usize");
+        let kind = header[4];
+        if len > self.max_chunk {
+            return Err(format!(
+                "frame of {len} bytes exceeds the maximum of {}",
+                self.max_chunk
+            ));
+        }
+        if self.buf.len() < HEADER_LEN + len {
+            return Ok(None);
+        }
+
+        let frame = self.buf.drain(..HEADER_LEN + len).skip(HEADER_LEN);
+        match kind {
+            RAW => Ok(Some(frame.collect())),
+            LZ4 => {
+                let frame = frame.collect::<Vec<_>>();
+                // `decompress_size_prepended` allocates as much as the frame claims to hold.
+                let size = frame
+                    .get(..4)
+                    .map(|size| u32::from_le_bytes(size.try_into().unwrap()))
+                    .ok_or_else(|| "compressed frame is too short".to_string())?;
+                if usize::try_from(size).map_or(true, |size| size > self.max_chunk) {
+                    return Err(format!(
+                        "compressed frame of {size} bytes exceeds the maximum of {}",
+                        self.max_chunk
+                    ));
+                }
+                lz4_flex::decompress_size_prepended(&frame)
+                    .map(Some)
+                    .map_err(|e| e.to_string())
+            }
+            other => Err(format!("unknown frame type {other}")),
+        }
+    }
+}
+
+impl<S: Stream<Item = Vec<u8>> + Unpin> Stream for Decompress<S> {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.compression == Compression::None {
+            return this.inner.poll_next_unpin(cx).map(|chunk| chunk.map(Ok));
+        }
+
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            match this.next_frame() {
+                Ok(Some(chunk)) => return Poll::Ready(Some(Ok(chunk))),
+                Ok(None) => {}
+                Err(reason) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(Error::StreamFailed {
+                        reason: format!("failed to decompress step data: {reason}"),
+                    })));
+                }
+            }
+            if let Some(data) = ready!(this.inner.poll_next_unpin(cx)) {
+                this.buf.extend_from_slice(&data);
+            } else {
+                this.done = true;
+                if !this.buf.is_empty() {
+                    return Poll::Ready(Some(Err(Error::StreamFailed {
+                        reason: "step stream ended in the middle of a compressed frame".into(),
+                    })));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use futures::{stream, TryStreamExt};
+    use rand::{thread_rng, Rng};
+
+    const MAX_CHUNK: usize = 1024;
+
+    async fn roundtrip(compression: Compression, chunks: Vec<Vec<u8>>, split: usize) -> Vec<u8> {
+        let wire = chunks
+            .into_iter()
+            .flat_map(|chunk| compression.encode(chunk))
+            .collect::<Vec<_>>();
+        let received = wire.chunks(split).map(<[u8]>::to_vec).collect::<Vec<_>>();
+        compression
+            .decode(stream::iter(received), MAX_CHUNK)
+            .try_concat()
+            .await
+            .unwrap()
+    }
+
+    async fn decode_corrupt(wire: Vec<u8>) -> String {
+        let decoded = Compression::Lz4
+            .decode(stream::iter(vec![wire]), MAX_CHUNK)
+            .collect::<Vec<_>>()
+            .await;
+        match &decoded[..] {
+            [Err(Error::StreamFailed { reason })] => reason.clone(),
+            other => panic!("expected a single error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn lz4_roundtrip() {
+        let chunks = vec![vec![0_u8; 1024], (0..=255).collect(), vec![7; 3]];
+        let expected = chunks.concat();
+        for split in [1, 5, 100, 10_000] {
+            assert_eq!(
+                expected,
+                roundtrip(Compression::Lz4, chunks.clone(), split).await
+            );
+        }
+    }
+
+    #[test]
+    fn lz4_shrinks_repetitive_data() {
+        assert!(Compression::Lz4.encode(vec![0; 1024]).len() < 100);
+        // incompressible data only gets the frame header
+        let random = (0..=255).collect::<Vec<u8>>();
+        assert_eq!(HEADER_LEN + 256, Compression::Lz4.encode(random).len());
+    }
+
+    #[tokio::test]
+    async fn none_passes_through() {
+        let chunks = vec![vec![1, 2, 3], vec![4]];
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            roundtrip(Compression::None, chunks, 2).await
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_on_corrupt_data() {
+        let mut wire = Compression::Lz4.encode(vec![0; MAX_CHUNK]);
+        wire[4] = 42;
+        assert!(decode_corrupt(wire).await.contains("unknown frame type 42"));
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_frames() {
+        let mut rng = thread_rng();
+        let wire = Compression::Lz4.encode((0..=MAX_CHUNK).map(|_| rng.gen()).collect());
+        assert_eq!(RAW, wire[4]);
+        assert!(decode_corrupt(wire).await.contains("exceeds the maximum"));
+
+        // The size prepended to a compressed frame is not trusted either.
+        let mut wire = Compression::Lz4.encode(vec![0; MAX_CHUNK]);
+        wire[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode_corrupt(wire).await.contains("exceeds the maximum"));
+    }
+}
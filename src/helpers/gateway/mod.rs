@@ -1,23 +1,39 @@
+mod compress;
+mod progress;
 mod receive;
 mod send;
+mod stall;
+mod throttle;
 mod transport;
 
+pub use compress::Compression;
+pub use progress::{ChannelProgress, GatewayProgress};
+#[cfg(any(test, feature = "test-fixture"))]
+pub use send::MessageInterceptor;
 pub use send::SendingEnd;
+pub use stall::StallReport;
 
 use crate::{
     helpers::{
         gateway::{
             receive::{GatewayReceivers, ReceivingEnd as ReceivingEndBase},
-            send::GatewaySenders,
+            send::{max_chunk_size, GatewaySender, GatewaySenders},
+            throttle::BandwidthLimiter,
             transport::RoleResolvingTransport,
         },
-        ChannelId, Message, Role, RoleAssignment, TotalRecords, Transport,
+        ChannelId, Error, Message, Role, RoleAssignment, TotalRecords, Transport,
     },
     protocol::QueryId,
+    sync::Arc,
 };
 #[cfg(all(feature = "shuttle", test))]
 use shuttle::future as tokio;
-use std::{fmt::Debug, num::NonZeroUsize};
+use std::{
+    fmt::Debug,
+    num::{NonZeroU32, NonZeroUsize},
+    time::Duration,
+};
+use tracing::Instrument;
 
 /// Alias for the currently configured transport.
 ///
@@ -40,8 +56,11 @@ pub type ReceivingEnd<M> = ReceivingEndBase<TransportImpl, M>;
 pub struct Gateway<T: Transport = TransportImpl> {
     config: GatewayConfig,
     transport: RoleResolvingTransport<T>,
-    senders: GatewaySenders,
-    receivers: GatewayReceivers<T>,
+    senders: Arc<GatewaySenders>,
+    receivers: Arc<GatewayReceivers<T>>,
+    /// Alters every message sent through this gateway, if set.
+    #[cfg(any(test, feature = "test-fixture"))]
+    interceptor: Option<Arc<dyn MessageInterceptor>>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -49,6 +68,21 @@ pub struct GatewayConfig {
     /// The number of items that can be active at the one time.
     /// This is used to determine the size of sending and receiving buffers.
     active: NonZeroUsize,
+
+    /// How many times a channel attempts to reconnect to the peer before it fails.
+    send_retries: usize,
+
+    /// If set, the gateway reports its channels once none of them made progress for this long.
+    stall_timeout: Option<Duration>,
+
+    /// Whether a stall fails all the channels, instead of just being reported.
+    fail_on_stall: bool,
+
+    /// Compression of the data sent over the channels. Must be the same on all helpers.
+    compression: Compression,
+
+    /// If set, the maximum number of bytes per second sent to each peer.
+    bandwidth_limit: Option<NonZeroU32>,
 }
 
 impl<T: Transport> Gateway<T> {
@@ -59,17 +93,42 @@ impl<T: Transport> Gateway<T> {
         roles: RoleAssignment,
         transport: T,
     ) -> Self {
-        Self {
+        let this = Self {
             config,
             transport: RoleResolvingTransport {
                 query_id,
                 roles,
                 inner: transport,
                 config,
+                limiter: config
+                    .bandwidth_limit
+                    .map(|limit| Arc::new(BandwidthLimiter::new(limit))),
             },
-            senders: GatewaySenders::default(),
-            receivers: GatewayReceivers::default(),
+            senders: Arc::new(GatewaySenders::default()),
+            receivers: Arc::new(GatewayReceivers::default()),
+            #[cfg(any(test, feature = "test-fixture"))]
+            interceptor: None,
+        };
+        if let Some(timeout) = config.stall_timeout {
+            tokio::spawn(stall::watch(
+                this.role(),
+                Arc::downgrade(&this.senders),
+                Arc::downgrade(&this.receivers),
+                timeout,
+                config.fail_on_stall,
+            ));
         }
+
+        this
+    }
+
+    /// Makes this gateway pass every message it sends through `interceptor`, so that the helper
+    /// it belongs to can deviate from the protocol in tests.
+    #[cfg(any(test, feature = "test-fixture"))]
+    #[must_use]
+    pub fn with_interceptor(mut self, interceptor: Arc<dyn MessageInterceptor>) -> Self {
+        self.interceptor = Some(interceptor);
+        self
     }
 
     #[must_use]
@@ -82,42 +141,101 @@ impl<T: Transport> Gateway<T> {
         &self.config
     }
 
+    /// Returns a handle that tells how many records this gateway has sent, without keeping it
+    /// alive.
+    #[must_use]
+    pub fn progress(&self) -> GatewayProgress {
+        GatewayProgress {
+            senders: Arc::downgrade(&self.senders),
+        }
+    }
+
     #[must_use]
     pub fn get_sender<M: Message>(
         &self,
         channel_id: &ChannelId,
         total_records: TotalRecords,
     ) -> SendingEnd<M> {
-        let (tx, maybe_stream) =
-            self.senders
-                .get_or_create::<M>(channel_id, self.config.active_work(), total_records);
-        if let Some(stream) = maybe_stream {
-            tokio::spawn({
-                let channel_id = channel_id.clone();
-                let transport = self.transport.clone();
-                async move {
-                    // TODO(651): In the HTTP case we probably need more robust error handling here.
-                    transport
-                        .send(&channel_id, stream)
-                        .await
-                        .expect("{channel_id:?} receiving end should be accepted by transport");
-                }
-            });
+        let (tx, created) = self.senders.get_or_create::<M, _>(
+            channel_id,
+            self.config.active_work(),
+            total_records,
+            self.config.compression,
+            || self.transport.acknowledged(channel_id),
+        );
+        if created {
+            // Keep the span of the protocol, so that the metrics of the stream are attributed to it.
+            tokio::spawn(
+                send_stream(
+                    self.transport.clone(),
+                    channel_id.clone(),
+                    Arc::clone(&tx),
+                    self.config.send_retries,
+                )
+                .in_current_span(),
+            );
         }
 
-        SendingEnd::new(tx, self.role(), channel_id)
+        let sending_end = SendingEnd::new(tx, self.role(), channel_id);
+        #[cfg(any(test, feature = "test-fixture"))]
+        let sending_end = sending_end.with_interceptor(self.interceptor.clone());
+
+        sending_end
     }
 
     #[must_use]
     pub fn get_receiver<M: Message>(&self, channel_id: &ChannelId) -> ReceivingEndBase<T, M> {
         ReceivingEndBase::new(
             channel_id.clone(),
-            self.receivers
-                .get_or_create(channel_id, || self.transport.receive(channel_id)),
+            self.receivers.get_or_create(channel_id, || {
+                self.transport
+                    .receive(channel_id, max_chunk_size::<M>(self.config.active_work()))
+            }),
         )
     }
 }
 
+/// Streams the data sent over `channel_id` to the peer. If the connection is lost, it
+/// reconnects up to `retries` times, resuming the stream where the peer stopped receiving.
+/// If the channel cannot be delivered, it fails, so that protocols sending data over it
+/// receive an error instead of waiting forever.
+async fn send_stream<T: Transport>(
+    transport: RoleResolvingTransport<T>,
+    channel_id: ChannelId,
+    sender: Arc<GatewaySender>,
+    retries: usize,
+) {
+    let mut attempt = 0;
+    let error = loop {
+        let (stream, delivered) = sender.stream();
+        let offset = stream.offset();
+        let error = match transport.send(&channel_id, offset, stream).await {
+            Ok(()) => match delivered.await {
+                Ok(true) => return,
+                Ok(false) | Err(_) => Error::send_error(
+                    channel_id.clone(),
+                    format!("stream starting at byte {offset} was interrupted"),
+                ),
+            },
+            Err(e) => Error::send_error(channel_id.clone(), format!("{e:?}")),
+        };
+
+        if sender.is_failed() {
+            // the gateway is closed, nobody is waiting for this data anymore.
+            return;
+        }
+        attempt += 1;
+        if attempt > retries {
+            break error;
+        }
+        tracing::warn!("{error}, resuming the stream (attempt {attempt} of {retries})");
+    };
+
+    tracing::error!("{error}");
+    sender.fail(error.to_string());
+    sender.drain().await;
+}
+
 impl Default for GatewayConfig {
     fn default() -> Self {
         Self::new(1024)
@@ -133,6 +251,59 @@ impl GatewayConfig {
     pub fn new(active: usize) -> Self {
         Self {
             active: NonZeroUsize::new(active).unwrap(),
+            send_retries: 3,
+            stall_timeout: None,
+            fail_on_stall: false,
+            compression: Compression::None,
+            bandwidth_limit: None,
+        }
+    }
+
+    /// Sets how many times a channel attempts to reconnect to the peer before it fails.
+    #[must_use]
+    pub fn with_send_retries(self, send_retries: usize) -> Self {
+        Self {
+            send_retries,
+            ..self
+        }
+    }
+
+    /// Makes the gateway log the state of its channels if none of them made progress for
+    /// `timeout` while some are waiting for data.
+    #[must_use]
+    pub fn with_stall_timeout(self, timeout: Duration) -> Self {
+        Self {
+            stall_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Makes the gateway fail all its channels with [`Error::Stalled`] once a stall is detected,
+    /// so the query fails instead of hanging. Has no effect unless a stall timeout is set.
+    #[must_use]
+    pub fn fail_on_stall(self) -> Self {
+        Self {
+            fail_on_stall: true,
+            ..self
+        }
+    }
+
+    /// Compresses the data sent over the channels with `compression`. All helpers must use the
+    /// same compression.
+    #[must_use]
+    pub fn with_compression(self, compression: Compression) -> Self {
+        Self {
+            compression,
+            ..self
+        }
+    }
+
+    /// Limits the rate at which data is sent to each peer to `bytes_per_second`.
+    #[must_use]
+    pub fn with_bandwidth_limit(self, bytes_per_second: NonZeroU32) -> Self {
+        Self {
+            bandwidth_limit: Some(bytes_per_second),
+            ..self
         }
     }
 
@@ -148,11 +319,16 @@ mod tests {
     use super::*;
     use crate::{
         ff::{Field, Fp31, Fp32BitPrime, Gf2},
-        helpers::{Direction, GatewayConfig, SendingEnd},
+        helpers::{Direction, GatewayConfig, LinkConfig, SendingEnd},
         protocol::{context::Context, RecordId},
+        telemetry::{
+            labels,
+            metrics::{BYTES_SENT, WIRE_BYTES_SENT},
+        },
         test_fixture::{Runner, TestWorld, TestWorldConfig},
     };
-    use futures_util::future::{join, try_join};
+    use futures_util::future::{join, try_join, try_join_all};
+    use std::time::Duration;
 
     /// Verifies that [`Gateway`] send buffer capacity is adjusted to the message size.
     /// IPA protocol opens many channels to send values from different fields, while message size
@@ -232,4 +408,192 @@ mod tests {
         spawned.await.unwrap();
         let _world = unsafe { Box::from_raw(world_ptr) };
     }
+
+    /// The clock is paused, so the time spent waiting for the bandwidth limit is exact.
+    #[tokio::test(start_paused = true)]
+    pub async fn compresses_and_throttles() {
+        const COUNT: usize = 1000;
+        const LIMIT: u32 = 100;
+        let config = TestWorldConfig {
+            gateway_config: GatewayConfig::new(16)
+                .with_compression(Compression::Lz4)
+                .with_bandwidth_limit(NonZeroU32::new(LIMIT).unwrap()),
+            ..TestWorldConfig::default()
+        }
+        .enable_metrics();
+        let world = TestWorld::new_with(config);
+        let start = tokio::time::Instant::now();
+
+        let results = world
+            .semi_honest((), |ctx, _| async move {
+                let ctx = ctx.narrow("compressed").set_total_records(COUNT);
+                let role = ctx.role();
+                let send_channel = ctx.send_channel(role.peer(Direction::Right));
+                let recv_channel = ctx.recv_channel::<Fp32BitPrime>(role.peer(Direction::Left));
+                let (_, received) = try_join(
+                    try_join_all((0..COUNT).map(|i| {
+                        send_channel.send(
+                            RecordId::from(i),
+                            Fp32BitPrime::truncate_from(u128::try_from(i % 7).unwrap()),
+                        )
+                    })),
+                    try_join_all((0..COUNT).map(|i| recv_channel.receive(RecordId::from(i)))),
+                )
+                .await
+                .unwrap();
+                received
+            })
+            .await;
+
+        let elapsed = start.elapsed();
+
+        let expected = (0..COUNT)
+            .map(|i| Fp32BitPrime::truncate_from(u128::try_from(i % 7).unwrap()))
+            .collect::<Vec<_>>();
+        for received in results {
+            assert_eq!(expected, received);
+        }
+
+        let snapshot = world.metrics_snapshot();
+        let bytes_sent = snapshot.get_counter(BYTES_SENT);
+        assert_eq!(3 * COUNT * 4, usize::try_from(bytes_sent).unwrap());
+        let wire_bytes_sent = &snapshot.counters[WIRE_BYTES_SENT];
+        assert!(wire_bytes_sent.total_value < bytes_sent / 2);
+
+        // Every helper sends to one peer. The first second worth of bytes goes out at once, and
+        // the rest at the limit.
+        let most_sent = wire_bytes_sent.dimensions[labels::ROLE]
+            .values()
+            .copied()
+            .max()
+            .unwrap();
+        assert!(most_sent > u64::from(LIMIT));
+        #[allow(clippy::cast_precision_loss)]
+        let throttled =
+            Duration::from_secs_f64((most_sent - u64::from(LIMIT)) as f64 / f64::from(LIMIT));
+        assert!(
+            elapsed >= throttled && elapsed < throttled + Duration::from_secs(1),
+            "sending {most_sent} bytes took {elapsed:?}, expected about {throttled:?}"
+        );
+    }
+
+    /// The clock is paused, so computing takes no time and the rounds are counted exactly.
+    #[tokio::test(start_paused = true)]
+    pub async fn counts_rounds_over_slow_links() {
+        let config = TestWorldConfig {
+            gateway_config: GatewayConfig::new(2),
+            ..TestWorldConfig::default()
+        }
+        .with_link(LinkConfig::default().with_latency(Duration::from_millis(50)));
+        let world = TestWorld::new_with(config);
+
+        // every helper sends a record to its right peer, and waits for the record from its left
+        // peer before sending the next one.
+        world
+            .semi_honest((), |ctx, _| async move {
+                // records are sent one by one, without waiting for the buffer to fill up.
+                let ctx = ctx
+                    .narrow("ping")
+                    .set_total_records(TotalRecords::Indeterminate);
+                let role = ctx.role();
+                let send_channel = ctx.send_channel(role.peer(Direction::Right));
+                let recv_channel = ctx.recv_channel::<Fp31>(role.peer(Direction::Left));
+                for i in 0_usize..2 {
+                    send_channel
+                        .send(RecordId::from(i), Fp31::ONE)
+                        .await
+                        .unwrap();
+                    recv_channel.receive(RecordId::from(i)).await.unwrap();
+                }
+            })
+            .await;
+
+        let rounds = world.rounds();
+        assert_eq!(
+            vec![2],
+            rounds.iter().map(|(_, rounds)| rounds).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    pub async fn reports_progress() {
+        let world = TestWorld::default();
+        let progress = world.gateway(Role::H1).progress();
+        assert_eq!(None, progress.current());
+
+        {
+            let [h1, ..] = world.contexts();
+            let first_tx = h1
+                .narrow("first")
+                .set_total_records(2)
+                .send_channel::<Fp31>(Role::H2);
+            let channel = |gate: &str, records_sent| {
+                let progress = progress.current().unwrap();
+                assert_eq!(Role::H2, progress.channel_id.role);
+                assert!(progress.channel_id.gate.as_ref().ends_with(gate));
+                assert_eq!(records_sent, progress.records_sent);
+                assert_eq!(Some(2), progress.total_records);
+            };
+
+            first_tx.send(RecordId::FIRST, Fp31::ONE).await.unwrap();
+            channel("first", 1);
+
+            // the channel opened later is done, so it is not the current one.
+            let second_tx = h1
+                .narrow("second")
+                .set_total_records(2)
+                .send_channel::<Fp31>(Role::H2);
+            for i in 0_usize..2 {
+                second_tx.send(RecordId::from(i), Fp31::ONE).await.unwrap();
+            }
+            channel("first", 1);
+
+            first_tx.send(RecordId::from(1), Fp31::ONE).await.unwrap();
+            channel("second", 2);
+        }
+
+        drop(world);
+        assert_eq!(None, progress.current());
+    }
+
+    #[tokio::test]
+    pub async fn fails_on_stall() {
+        let config = TestWorldConfig {
+            gateway_config: GatewayConfig::new(2)
+                .with_stall_timeout(Duration::from_millis(100))
+                .fail_on_stall(),
+            ..TestWorldConfig::default()
+        };
+        let world = TestWorld::new_with(config);
+
+        // every helper sends the first of two records and waits for the first record from its
+        // left peer, which stays in the sender's buffer, because it is neither full nor closed.
+        let results = world
+            .semi_honest((), |ctx, _| async move {
+                let ctx = ctx.narrow("stall").set_total_records(2);
+                let role = ctx.role();
+                ctx.send_channel(role.peer(Direction::Right))
+                    .send(RecordId::FIRST, Fp31::ONE)
+                    .await?;
+                ctx.recv_channel::<Fp31>(role.peer(Direction::Left))
+                    .receive(RecordId::FIRST)
+                    .await
+            })
+            .await;
+
+        for result in results {
+            let Err(Error::ReceiveError { inner, .. }) = result else {
+                panic!("expected the receive to fail, got {result:?}");
+            };
+            let Some(Error::Stalled(report)) = inner.downcast_ref::<Error>() else {
+                panic!("expected a stall, got {inner:?}");
+            };
+            assert!(report.idle >= Duration::from_millis(100));
+            assert_eq!(1, report.senders.len());
+            assert_eq!(1, report.senders[0].1.next);
+            assert_eq!(1, report.senders[0].1.buffered);
+            assert_eq!(1, report.receivers.len());
+            assert_eq!(vec![0], report.receivers[0].1.waiting);
+        }
+    }
 }
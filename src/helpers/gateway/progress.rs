@@ -0,0 +1,32 @@
+use crate::{
+    helpers::{gateway::send::GatewaySenders, ChannelId},
+    sync::Weak,
+};
+
+/// Number of records sent over a channel so far.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelProgress {
+    pub channel_id: ChannelId,
+    pub records_sent: usize,
+    /// Number of records the channel sends in total, if it is known upfront.
+    pub total_records: Option<usize>,
+}
+
+/// Tells how far along the protocol running over a gateway is, from the records sent on its
+/// channels. It does not keep the gateway alive.
+#[derive(Clone)]
+pub struct GatewayProgress {
+    pub(super) senders: Weak<GatewaySenders>,
+}
+
+impl GatewayProgress {
+    /// Returns the progress of the channel that was opened last and still has records to send.
+    /// Protocols open channels as they move on to their next step, so that is the step they are
+    /// at. If all the channels are done, returns the one that was opened last.
+    ///
+    /// Returns `None` if nothing was sent yet, or the gateway is gone.
+    #[must_use]
+    pub fn current(&self) -> Option<ChannelProgress> {
+        self.senders.upgrade()?.progress()
+    }
+}
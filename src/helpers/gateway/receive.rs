@@ -1,5 +1,9 @@
 use crate::{
-    helpers::{buffers::UnorderedReceiver, ChannelId, Error, Message, Transport},
+    helpers::{
+        buffers::{UnorderedReceiver, UnorderedReceiverState},
+        gateway::compress::Decompress,
+        ChannelId, Error, Message, StallReport, Transport,
+    },
     protocol::RecordId,
 };
 use dashmap::DashMap;
@@ -19,7 +23,7 @@ pub(super) struct GatewayReceivers<T: Transport> {
 }
 
 pub(super) type UR<T> = UnorderedReceiver<
-    <T as Transport>::RecordsStream,
+    Decompress<<T as Transport>::RecordsStream>,
     <<T as Transport>::RecordsStream as Stream>::Item,
 >;
 
@@ -72,4 +76,20 @@ impl<T: Transport> GatewayReceivers<T> {
             stream
         }
     }
+
+    /// Returns the state of every channel.
+    pub(super) fn states(&self) -> Vec<(ChannelId, UnorderedReceiverState)> {
+        self.inner
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().state()))
+            .collect()
+    }
+
+    /// Fails all the pending and future reads with [`Error::Stalled`].
+    pub(super) fn fail_all(&self, report: &StallReport) {
+        for receiver in &self.inner {
+            let report = report.clone();
+            receiver.fail(move || Error::Stalled(report.clone()));
+        }
+    }
 }
@@ -1,16 +1,27 @@
-use crate::sync::Arc;
+use crate::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
 use dashmap::DashMap;
 use futures::Stream;
+#[cfg(any(test, feature = "test-fixture"))]
+use generic_array::GenericArray;
 use std::{
+    collections::VecDeque,
     marker::PhantomData,
     num::NonZeroUsize,
     pin::Pin,
     task::{Context, Poll},
 };
+use tokio::sync::oneshot;
 use typenum::Unsigned;
 
 use crate::{
-    helpers::{buffers::OrderingSender, ChannelId, Error, Message, Role, TotalRecords},
+    helpers::{
+        buffers::{OrderingSender, OrderingSenderState},
+        gateway::progress::ChannelProgress,
+        ChannelId, Compression, Error, Message, Role, TotalRecords,
+    },
     protocol::RecordId,
     telemetry::{
         labels::{ROLE, STEP},
@@ -18,40 +29,203 @@ use crate::{
     },
 };
 
+/// Spare capacity of the sending buffers, in bytes. Messages must be smaller than that.
+const SPARE: Option<NonZeroUsize> = NonZeroUsize::new(64);
+
+/// Returns the size of the largest chunk taken out of the sending buffer of a channel of `M`
+/// with `capacity`, which is what the receiving end has to accept.
+pub(super) fn max_chunk_size<M: Message>(capacity: NonZeroUsize) -> usize {
+    capacity.get() * M::Size::USIZE + SPARE.unwrap().get()
+}
+
 /// Sending end of the gateway channel.
 pub struct SendingEnd<M: Message> {
     sender_role: Role,
     channel_id: ChannelId,
     inner: Arc<GatewaySender>,
+    #[cfg(any(test, feature = "test-fixture"))]
+    interceptor: Option<Arc<dyn MessageInterceptor>>,
     _phantom: PhantomData<M>,
 }
 
+/// Alters the messages that a helper sends before they are written to the channel. Tests use it to
+/// make a helper deviate from the protocol.
+#[cfg(any(test, feature = "test-fixture"))]
+pub trait MessageInterceptor: Send + Sync {
+    /// Changes `data`, a serialized message that is about to be sent over `channel_id`, in place.
+    fn intercept(&self, channel_id: &ChannelId, data: &mut [u8]);
+}
+
 /// Sending channels, indexed by (role, step).
-#[derive(Default)]
 pub(super) struct GatewaySenders {
     inner: DashMap<ChannelId, Arc<GatewaySender>>,
+    /// Number of channels created so far.
+    created: AtomicUsize,
 }
 
 pub(super) struct GatewaySender {
     channel_id: ChannelId,
+    /// Order in which this channel was created, among all the channels of the gateway.
+    seq: usize,
     ordering_tx: OrderingSender,
     total_records: TotalRecords,
+    /// Compression applied to the chunks taken from `ordering_tx`.
+    compression: Compression,
+    /// Data taken from `ordering_tx` that is sent again if the connection to the peer is lost.
+    replay: Mutex<ReplayBuffer>,
+    /// Set once this channel cannot deliver data to the peer anymore.
+    failure: Mutex<Option<String>>,
 }
 
+/// Stream of bytes sent over a channel. Every stream starts at the oldest byte retained by the
+/// replay buffer of the channel, so a stream created after the connection was lost resumes the
+/// channel where the peer stopped receiving.
 pub(super) struct GatewaySendStream {
     inner: Arc<GatewaySender>,
+    /// Offset of the next byte yielded by this stream.
+    position: usize,
+    /// Reports whether all the data was taken out of this stream once it is finished or dropped.
+    delivered: Option<oneshot::Sender<bool>>,
+}
+
+/// Bytes sent over a channel that the peer has not acknowledged yet.
+///
+/// The peer acknowledges the bytes it has received as they arrive, see [`Transport::acknowledged`].
+/// When the sender reconnects, it replays everything that was not acknowledged and the peer
+/// skips the duplicates, as acknowledgements may lag behind the data it received.
+///
+/// [`Transport::acknowledged`]: crate::helpers::Transport::acknowledged
+struct ReplayBuffer {
+    /// Offset of the first retained byte.
+    start: usize,
+    chunks: VecDeque<Vec<u8>>,
+    len: usize,
+    /// Number of bytes the peer acknowledged.
+    acked: Arc<AtomicUsize>,
+    /// Set when the last chunk was taken from the ordering sender.
+    finished: bool,
+}
+
+impl ReplayBuffer {
+    fn new(acked: Arc<AtomicUsize>) -> Self {
+        Self {
+            start: 0,
+            chunks: VecDeque::new(),
+            len: 0,
+            acked,
+            finished: false,
+        }
+    }
+
+    fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    /// Appends a chunk taken from the ordering sender. All the retained data must have been
+    /// yielded by the stream calling this, so the acknowledged part of it can be released.
+    fn push(&mut self, chunk: Vec<u8>) {
+        self.len += chunk.len();
+        self.chunks.push_back(chunk);
+        self.release_acked();
+    }
+
+    /// Drops the chunks the peer has acknowledged.
+    fn release_acked(&mut self) {
+        let acked = self.acked.load(Ordering::Acquire);
+        while let Some(chunk) = self.chunks.front() {
+            if self.start + chunk.len() > acked {
+                break;
+            }
+            self.start += chunk.len();
+            self.len -= chunk.len();
+            self.chunks.pop_front();
+        }
+    }
+
+    /// Returns the retained chunk that starts at `offset`, if any.
+    fn chunk_at(&self, offset: usize) -> Option<Vec<u8>> {
+        let mut chunk_start = self.start;
+        for chunk in &self.chunks {
+            if chunk_start == offset {
+                return Some(chunk.clone());
+            }
+            chunk_start += chunk.len();
+        }
+
+        None
+    }
 }
 
 impl GatewaySender {
-    fn new(channel_id: ChannelId, tx: OrderingSender, total_records: TotalRecords) -> Self {
+    fn new(
+        channel_id: ChannelId,
+        seq: usize,
+        tx: OrderingSender,
+        total_records: TotalRecords,
+        compression: Compression,
+        acked: Arc<AtomicUsize>,
+    ) -> Self {
         Self {
             channel_id,
+            seq,
             ordering_tx: tx,
             total_records,
+            compression,
+            replay: Mutex::new(ReplayBuffer::new(acked)),
+            failure: Mutex::new(None),
         }
     }
 
+    /// Creates a new stream of the data sent over this channel. The returned receiver resolves
+    /// to `true` if the stream yielded all the data before it was dropped.
+    ///
+    /// There must be no other stream of this channel alive when this is called.
+    pub(super) fn stream(self: &Arc<Self>) -> (GatewaySendStream, oneshot::Receiver<bool>) {
+        let (tx, rx) = oneshot::channel();
+        let position = {
+            let mut replay = self.replay.lock().unwrap();
+            replay.release_acked();
+            replay.start
+        };
+        let stream = GatewaySendStream {
+            inner: Arc::clone(self),
+            position,
+            delivered: Some(tx),
+        };
+
+        (stream, rx)
+    }
+
+    /// Marks this channel as failed. All subsequent sends return an error with the given reason.
+    pub(super) fn fail(&self, reason: String) {
+        self.failure.lock().unwrap().get_or_insert(reason);
+    }
+
+    pub(super) fn is_failed(&self) -> bool {
+        self.failure.lock().unwrap().is_some()
+    }
+
+    /// Returns `true` if all the records sent over this channel were taken out of the ordering
+    /// sender. The stream may not have observed the end of the channel yet, if the last record
+    /// filled up the buffer before the channel was closed.
+    fn is_exhausted(&self) -> bool {
+        self.replay.lock().unwrap().finished
+            || self.ordering_tx.is_drained(self.total_records.count())
+    }
+
+    /// Takes and discards all the data sent over this channel after it failed, so that
+    /// senders waiting for buffer capacity can proceed and observe the failure.
+    pub(super) async fn drain(&self) {
+        while futures::future::poll_fn(|cx| self.ordering_tx.take_next(cx))
+            .await
+            .is_some()
+        {}
+    }
+
     pub async fn send<M: Message>(&self, record_id: RecordId, msg: M) -> Result<(), Error> {
+        if let Some(reason) = self.failure.lock().unwrap().as_ref() {
+            return Err(Error::send_error(self.channel_id.clone(), reason.clone()));
+        }
         debug_assert!(
             !self.total_records.is_unspecified(),
             "total_records cannot be unspecified when sending"
@@ -74,6 +248,12 @@ impl GatewaySender {
             self.ordering_tx.close(i + 1).await;
         }
 
+        // The channel may have failed while this message was waiting for buffer capacity, in
+        // which case it was discarded rather than sent.
+        if let Some(reason) = self.failure.lock().unwrap().as_ref() {
+            return Err(Error::send_error(self.channel_id.clone(), reason.clone()));
+        }
+
         Ok(())
     }
 }
@@ -84,10 +264,33 @@ impl<M: Message> SendingEnd<M> {
             sender_role: role,
             channel_id: channel_id.clone(),
             inner: sender,
+            #[cfg(any(test, feature = "test-fixture"))]
+            interceptor: None,
             _phantom: PhantomData,
         }
     }
 
+    #[cfg(any(test, feature = "test-fixture"))]
+    pub(super) fn with_interceptor(
+        mut self,
+        interceptor: Option<Arc<dyn MessageInterceptor>>,
+    ) -> Self {
+        self.interceptor = interceptor;
+        self
+    }
+
+    #[cfg(any(test, feature = "test-fixture"))]
+    fn intercept(&self, msg: M) -> M {
+        if let Some(interceptor) = &self.interceptor {
+            let mut buf = GenericArray::default();
+            msg.serialize(&mut buf);
+            interceptor.intercept(&self.channel_id, &mut buf);
+            M::deserialize(&buf)
+        } else {
+            msg
+        }
+    }
+
     /// Sends the given message to the recipient. This method will block if there is no enough
     /// capacity to hold the message and will return only after message has been confirmed
     /// for sending.
@@ -98,6 +301,8 @@ impl<M: Message> SendingEnd<M> {
     ///
     /// [`set_total_records`]: crate::protocol::context::Context::set_total_records
     pub async fn send(&self, record_id: RecordId, msg: M) -> Result<(), Error> {
+        #[cfg(any(test, feature = "test-fixture"))]
+        let msg = self.intercept(msg);
         let r = self.inner.send(record_id, msg).await;
         metrics::increment_counter!(RECORDS_SENT,
             STEP => self.channel_id.gate.as_ref().to_string(),
@@ -112,22 +317,35 @@ impl<M: Message> SendingEnd<M> {
     }
 }
 
+impl Default for GatewaySenders {
+    fn default() -> Self {
+        Self {
+            inner: DashMap::default(),
+            created: AtomicUsize::new(0),
+        }
+    }
+}
+
 impl GatewaySenders {
-    /// Returns or creates a new communication channel. In case if channel is newly created,
-    /// returns the receiving end of it as well. It must be send over to the receiver in order for
+    /// Returns or creates a new communication channel. The returned flag is set if the channel
+    /// is newly created, in which case its data must be streamed over to the receiver in order for
     /// messages to get through.
-    pub(crate) fn get_or_create<M: Message>(
+    ///
+    /// `acked` is called once the channel is created, to get the counter of bytes the peer
+    /// acknowledged.
+    pub(crate) fn get_or_create<M: Message, F: FnOnce() -> Arc<AtomicUsize>>(
         &self,
         channel_id: &ChannelId,
         capacity: NonZeroUsize,
         total_records: TotalRecords, // TODO track children for indeterminate senders
-    ) -> (Arc<GatewaySender>, Option<GatewaySendStream>) {
+        compression: Compression,
+        acked: F,
+    ) -> (Arc<GatewaySender>, bool) {
         assert!(!total_records.is_unspecified());
         let senders = &self.inner;
         if let Some(sender) = senders.get(channel_id) {
-            (Arc::clone(&sender), None)
+            (Arc::clone(&sender), false)
         } else {
-            const SPARE: Option<NonZeroUsize> = NonZeroUsize::new(64);
             // a little trick - if number of records is indeterminate, set the capacity to 1.
             // Any send will wake the stream reader then, effectively disabling buffering.
             // This mode is clearly inefficient, so avoid using this mode.
@@ -146,8 +364,11 @@ impl GatewaySenders {
 
             let sender = Arc::new(GatewaySender::new(
                 channel_id.clone(),
+                self.created.fetch_add(1, Ordering::Relaxed),
                 OrderingSender::new(write_size, SPARE.unwrap()),
                 total_records,
+                compression,
+                acked(),
             ));
             if senders
                 .insert(channel_id.clone(), Arc::clone(&sender))
@@ -155,18 +376,106 @@ impl GatewaySenders {
             {
                 panic!("TODO - make sender creation contention less dangerous");
             }
-            let stream = GatewaySendStream {
-                inner: Arc::clone(&sender),
-            };
-            (sender, Some(stream))
+            (sender, true)
+        }
+    }
+}
+
+impl GatewaySenders {
+    /// Returns the state of every channel.
+    pub(super) fn states(&self) -> Vec<(ChannelId, OrderingSenderState)> {
+        self.inner
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().ordering_tx.state()))
+            .collect()
+    }
+
+    /// Returns the progress of the latest channel that still has records to send, or the latest
+    /// one if all of them are closed.
+    pub(super) fn progress(&self) -> Option<ChannelProgress> {
+        self.inner
+            .iter()
+            .map(|entry| {
+                let sender = entry.value();
+                let state = sender.ordering_tx.state();
+                let total_records = sender.total_records.count();
+                // `next` counts the close of the channel, which is not a record.
+                let records_sent = total_records.map_or(state.next, |total| state.next.min(total));
+                let progress = ChannelProgress {
+                    channel_id: entry.key().clone(),
+                    records_sent,
+                    total_records,
+                };
+                ((!state.closed, sender.seq), progress)
+            })
+            .max_by_key(|(key, _)| *key)
+            .map(|(_, progress)| progress)
+    }
+
+    /// Fails all the channels with the given reason.
+    pub(super) fn fail_all(&self, reason: &str) {
+        for sender in &self.inner {
+            sender.fail(reason.to_string());
         }
     }
 }
 
+impl Drop for GatewaySenders {
+    fn drop(&mut self) {
+        // Stop the tasks that would otherwise keep resuming streams for a finished query.
+        self.fail_all("gateway is closed");
+    }
+}
+
+impl GatewaySendStream {
+    /// Offset of the first byte of this stream.
+    pub(super) fn offset(&self) -> usize {
+        self.position
+    }
+}
+
 impl Stream for GatewaySendStream {
     type Item = Vec<u8>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::get_mut(self).inner.ordering_tx.take_next(cx)
+        let this = Pin::get_mut(self);
+        let sender = &this.inner;
+
+        // replay the retained data first, then continue with the new data.
+        if let Some(chunk) = sender.replay.lock().unwrap().chunk_at(this.position) {
+            this.position += chunk.len();
+            return Poll::Ready(Some(chunk));
+        }
+
+        match sender.ordering_tx.take_next(cx) {
+            Poll::Ready(Some(chunk)) => {
+                // Chunks are compressed before they are retained, so that offsets count the
+                // bytes on the wire and a replayed chunk is identical to the original one.
+                let chunk = sender.compression.encode(chunk);
+                this.position += chunk.len();
+                sender.replay.lock().unwrap().push(chunk.clone());
+                Poll::Ready(Some(chunk))
+            }
+            Poll::Ready(None) => {
+                sender.replay.lock().unwrap().finished = true;
+                if let Some(delivered) = this.delivered.take() {
+                    // the channel task may be gone already if the gateway is closed.
+                    let _ = delivered.send(true);
+                }
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for GatewaySendStream {
+    fn drop(&mut self) {
+        if let Some(delivered) = self.delivered.take() {
+            // Receivers may stop reading once they got all the records they need, so a stream
+            // that has nothing left to send is delivered, even if it did not reach its end.
+            let end = self.inner.replay.lock().unwrap().end();
+            let _ = delivered.send(self.position == end && self.inner.is_exhausted());
+        }
     }
 }
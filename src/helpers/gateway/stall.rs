@@ -0,0 +1,142 @@
+use crate::{
+    helpers::{
+        buffers::{OrderingSenderState, UnorderedReceiverState},
+        gateway::{receive::GatewayReceivers, send::GatewaySenders},
+        ChannelId, Role, Transport,
+    },
+    sync::Weak,
+};
+use std::{
+    fmt::{Display, Formatter},
+    time::{Duration, Instant},
+};
+
+/// State of the gateway channels at the time it stopped making progress.
+#[derive(Clone, Debug)]
+pub struct StallReport {
+    pub role: Role,
+    /// How long the gateway has not been making progress for.
+    pub idle: Duration,
+    /// Send channels that still have data to send.
+    pub senders: Vec<(ChannelId, OrderingSenderState)>,
+    /// Receive channels that are waiting for data.
+    pub receivers: Vec<(ChannelId, UnorderedReceiverState)>,
+}
+
+impl StallReport {
+    fn collect<T: Transport>(
+        role: Role,
+        idle: Duration,
+        senders: &GatewaySenders,
+        receivers: &GatewayReceivers<T>,
+    ) -> Self {
+        Self {
+            role,
+            idle,
+            senders: senders.states(),
+            receivers: receivers.states(),
+        }
+    }
+
+    /// Total number of records sent and received, across all channels.
+    fn progress(&self) -> usize {
+        self.senders.iter().map(|(_, s)| s.next).sum::<usize>()
+            + self.receivers.iter().map(|(_, r)| r.next).sum::<usize>()
+    }
+
+    /// Returns `true` if some channels are waiting to send or receive data.
+    fn is_waiting(&self) -> bool {
+        !self.receivers.is_empty()
+            || self
+                .senders
+                .iter()
+                .any(|(_, s)| s.blocked || !s.waiting.is_empty())
+    }
+
+    /// Drops the channels that are done or idle.
+    fn retain_open(&mut self) {
+        self.senders.retain(|(_, s)| s.is_open());
+        self.receivers.retain(|(_, r)| r.is_waiting());
+    }
+}
+
+impl Display for StallReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} gateway has not made progress for {:?}",
+            self.role, self.idle
+        )?;
+        for (channel_id, state) in &self.senders {
+            write!(
+                f,
+                "\n    sending to {channel_id:?}: {} records sent, {} bytes buffered",
+                state.next, state.buffered
+            )?;
+            if state.blocked {
+                write!(f, ", blocked on a full buffer")?;
+            }
+            if !state.waiting.is_empty() {
+                write!(f, ", waiting to send {:?}", state.waiting)?;
+            }
+        }
+        for (channel_id, state) in &self.receivers {
+            write!(
+                f,
+                "\n    receiving from {channel_id:?}: {} records received, waiting for {:?}",
+                state.next, state.waiting
+            )?;
+            if state.overflow > 0 {
+                write!(f, " and {} more", state.overflow)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Periodically checks whether any channel of the gateway made progress. If none of them did
+/// for `timeout` while some of them are waiting for data, it logs their state and, if
+/// `fail` is set, fails all the channels with [`Error::Stalled`].
+///
+/// [`Error::Stalled`]: crate::helpers::Error::Stalled
+///
+/// Exits once the gateway is dropped.
+pub(super) async fn watch<T: Transport>(
+    role: Role,
+    senders: Weak<GatewaySenders>,
+    receivers: Weak<GatewayReceivers<T>>,
+    timeout: Duration,
+    fail: bool,
+) {
+    let mut last_progress = None;
+    let mut since = Instant::now();
+    loop {
+        tokio::time::sleep(timeout / 4).await;
+        let (Some(senders), Some(receivers)) = (senders.upgrade(), receivers.upgrade()) else {
+            return;
+        };
+
+        let mut report = StallReport::collect(role, since.elapsed(), &senders, &receivers);
+        let progress = report.progress();
+        if last_progress != Some(progress) {
+            last_progress = Some(progress);
+            since = Instant::now();
+            continue;
+        }
+
+        report.retain_open();
+        if report.idle < timeout || !report.is_waiting() {
+            continue;
+        }
+
+        tracing::warn!("{report}");
+        if fail {
+            senders.fail_all(&report.to_string());
+            receivers.fail_all(&report);
+            return;
+        }
+        // remind about the stall after another timeout
+        since = Instant::now();
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{helpers::Role, sync::Mutex};
+use std::{num::NonZeroU32, time::Duration};
+// Tokio's clock, so that tests with a paused clock see the limit.
+use tokio::time::Instant;
+
+/// Limits the rate at which a gateway sends data to each of its peers. All the channels to a peer
+/// share one token bucket.
+pub(super) struct BandwidthLimiter {
+    peers: [TokenBucket; 3],
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_second: NonZeroU32) -> Self {
+        Self {
+            peers: [(); 3].map(|()| TokenBucket::new(bytes_per_second, Instant::now())),
+        }
+    }
+
+    /// Waits until `bytes` can be sent to `peer`.
+    pub async fn acquire(&self, peer: Role, bytes: usize) {
+        let wait = self.peers[peer].take(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Token bucket that fills up at `rate` bytes per second, and holds up to one second worth of
+/// tokens.
+///
+/// Taking more tokens than the bucket holds puts it in debt, which the following sends wait out.
+/// That lets chunks larger than the bucket through, while keeping the average rate.
+struct TokenBucket {
+    rate: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: NonZeroU32, now: Instant) -> Self {
+        let rate = f64::from(rate.get());
+        Self {
+            rate,
+            state: Mutex::new(BucketState {
+                tokens: rate,
+                updated: now,
+            }),
+        }
+    }
+
+    /// Takes `bytes` tokens out of the bucket, and returns how long the caller must wait before
+    /// sending them.
+    #[allow(clippy::cast_precision_loss)] // chunks are much smaller than 2^52 bytes
+    fn take(&self, bytes: usize, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let elapsed = now.saturating_duration_since(state.updated).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.rate).min(self.rate) - bytes as f64;
+        state.updated = now;
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.rate)
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn allows_burst() {
+        let now = Instant::now();
+        let bucket = TokenBucket::new(NonZeroU32::new(1000).unwrap(), now);
+        assert_eq!(Duration::ZERO, bucket.take(600, now));
+        assert_eq!(Duration::ZERO, bucket.take(400, now));
+    }
+
+    #[test]
+    fn waits_out_debt() {
+        let now = Instant::now();
+        let bucket = TokenBucket::new(NonZeroU32::new(1000).unwrap(), now);
+        assert_eq!(SECOND, bucket.take(2000, now));
+        // half a second later, the bucket is still 500 bytes short
+        assert_eq!(SECOND, bucket.take(500, now + SECOND / 2));
+        // after the debt is paid off, the bucket refills up to its capacity
+        assert_eq!(Duration::ZERO, bucket.take(1000, now + 10 * SECOND));
+        assert_eq!(SECOND / 10, bucket.take(100, now + 10 * SECOND));
+    }
+}
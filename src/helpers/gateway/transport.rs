@@ -1,11 +1,17 @@
 use crate::{
     helpers::{
         buffers::UnorderedReceiver,
-        gateway::{receive::UR, send::GatewaySendStream},
+        gateway::{receive::UR, send::GatewaySendStream, throttle::BandwidthLimiter},
         ChannelId, GatewayConfig, Role, RoleAssignment, RouteId, Transport,
     },
     protocol::QueryId,
+    sync::{atomic::AtomicUsize, Arc},
+    telemetry::{
+        labels::{ROLE, STEP},
+        metrics::WIRE_BYTES_SENT,
+    },
 };
+use futures::StreamExt;
 
 /// Transport adapter that resolves [`Role`] -> [`HelperIdentity`] mapping. As gateways created
 /// per query, it is not ambiguous.
@@ -17,12 +23,16 @@ pub(super) struct RoleResolvingTransport<T> {
     pub roles: RoleAssignment,
     pub config: GatewayConfig,
     pub inner: T,
+    pub limiter: Option<Arc<BandwidthLimiter>>,
 }
 
 impl<T: Transport> RoleResolvingTransport<T> {
+    /// Sends `data` to the peer, starting at byte `offset` of the channel. The data is sent no
+    /// faster than the bandwidth limit allows.
     pub(crate) async fn send(
         &self,
         channel_id: &ChannelId,
+        offset: usize,
         data: GatewaySendStream,
     ) -> Result<(), T::Error> {
         let dest_identity = self.roles.identity(channel_id.role);
@@ -32,16 +42,41 @@ impl<T: Transport> RoleResolvingTransport<T> {
             "can't send message to itself"
         );
 
+        let limiter = self.limiter.clone();
+        let peer = channel_id.role;
+        let wire_bytes_sent = metrics::register_counter!(WIRE_BYTES_SENT,
+            STEP => channel_id.gate.as_ref().to_string(),
+            ROLE => self.role().as_static_str()
+        );
+        let data = data.then(move |chunk| {
+            let limiter = limiter.clone();
+            let wire_bytes_sent = wire_bytes_sent.clone();
+            async move {
+                if let Some(limiter) = limiter {
+                    limiter.acquire(peer, chunk.len()).await;
+                }
+                wire_bytes_sent.increment(chunk.len() as u64);
+                chunk
+            }
+        });
+
         self.inner
             .send(
                 dest_identity,
-                (RouteId::Records, self.query_id, channel_id.gate.clone()),
+                (
+                    RouteId::Records,
+                    self.query_id,
+                    channel_id.gate.clone(),
+                    offset,
+                ),
                 data,
             )
             .await
     }
 
-    pub(crate) fn receive(&self, channel_id: &ChannelId) -> UR<T> {
+    /// Returns the stream of data received over `channel_id`. The peer sends chunks of at most
+    /// `max_chunk` bytes.
+    pub(crate) fn receive(&self, channel_id: &ChannelId, max_chunk: usize) -> UR<T> {
         let peer = self.roles.identity(channel_id.role);
         assert_ne!(
             peer,
@@ -51,13 +86,24 @@ impl<T: Transport> RoleResolvingTransport<T> {
 
         UnorderedReceiver::new(
             Box::pin(
-                self.inner
-                    .receive(peer, (self.query_id, channel_id.gate.clone())),
+                self.config.compression.decode(
+                    self.inner
+                        .receive(peer, (self.query_id, channel_id.gate.clone())),
+                    max_chunk,
+                ),
             ),
             self.config.active_work(),
         )
     }
 
+    /// Returns the number of bytes sent over `channel_id` the peer acknowledged.
+    pub(crate) fn acknowledged(&self, channel_id: &ChannelId) -> Arc<AtomicUsize> {
+        self.inner.acknowledged(
+            self.roles.identity(channel_id.role),
+            (self.query_id, channel_id.gate.clone()),
+        )
+    }
+
     pub(crate) fn role(&self) -> Role {
         self.roles.role(self.inner.identity())
     }
@@ -9,8 +9,12 @@ mod gateway;
 pub(crate) mod prss_protocol;
 mod transport;
 
+pub use buffers::{OrderingSenderState, UnorderedReceiverState};
 pub use error::{Error, Result};
-pub use gateway::{GatewayConfig, ReceivingEnd, SendingEnd};
+pub use gateway::{
+    ChannelProgress, Compression, GatewayConfig, GatewayProgress, ReceivingEnd, SendingEnd,
+    StallReport,
+};
 
 // TODO: this type should only be available within infra. Right now several infra modules
 // are exposed at the root level. That makes it impossible to have a proper hierarchy here.
@@ -20,13 +24,18 @@ pub use prss_protocol::negotiate as negotiate_prss;
 #[cfg(feature = "web-app")]
 pub use transport::WrappedAxumBodyStream;
 pub use transport::{
-    callbacks::*, BodyStream, BytesStream, LengthDelimitedStream, LogErrors, NoResourceIdentifier,
-    QueryIdBinding, ReceiveRecords, RecordsStream, RouteId, RouteParams, StepBinding,
+    callbacks::*, records_offset, send_acknowledgements, Acknowledgements, BodyStream, BytesStream,
+    Interruptible, LengthDelimitedStream, LogErrors, NoResourceIdentifier, QueryIdBinding,
+    ReceiveRecords, RecordsStream, ResumeError, RouteId, RouteParams, StepBinding,
     StreamCollection, StreamKey, Transport, WrappedBoxBodyStream,
 };
 
+#[cfg(any(test, feature = "test-fixture"))]
+pub use gateway::MessageInterceptor;
 #[cfg(feature = "in-memory-infra")]
-pub use transport::{InMemoryNetwork, InMemoryTransport};
+pub use transport::{CommunicationRounds, InMemoryNetwork, InMemoryTransport, LinkConfig};
+#[cfg(any(test, feature = "test-fixture"))]
+pub use transport::{Fault, FaultSchedule, Faults, FaultyTransport};
 
 pub use transport::query;
 
@@ -224,7 +233,7 @@ pub struct RoleAssignment {
     helper_roles: [HelperIdentity; 3],
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Direction {
     Left,
     Right,
@@ -0,0 +1,80 @@
+use crate::{
+    helpers::transport::StreamKey,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use futures::{stream::FuturesUnordered, Future, StreamExt};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+/// Number of bytes of outgoing record streams that peers acknowledged. Streams are indexed by
+/// [`StreamKey`], where the helper identity is the peer receiving the stream.
+///
+/// Senders keep the data the peer has not acknowledged yet, so they can send it again if the
+/// connection is lost.
+#[derive(Clone, Default)]
+pub struct Acknowledgements {
+    inner: Arc<Mutex<HashMap<StreamKey, Arc<AtomicUsize>>>>,
+}
+
+impl Acknowledgements {
+    /// Returns the counter of bytes the peer acknowledged for the given stream. It is updated
+    /// every time a new acknowledgement arrives.
+    ///
+    /// ## Panics
+    /// If mutex is poisoned.
+    #[must_use]
+    pub fn get(&self, key: StreamKey) -> Arc<AtomicUsize> {
+        Arc::clone(self.inner.lock().unwrap().entry(key).or_default())
+    }
+
+    /// Records that the peer received the first `received` bytes of the given stream.
+    /// Acknowledgements may arrive out of order, older ones are ignored.
+    pub fn record(&self, key: StreamKey, received: usize) {
+        self.get(key).fetch_max(received, Ordering::Release);
+    }
+
+    /// Forgets all the acknowledgements received so far.
+    ///
+    /// ## Panics
+    /// If mutex is poisoned.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().clear();
+    }
+}
+
+/// Sends acknowledgements reported by the readers of record streams back to the senders.
+/// Acknowledgements that pile up while previous ones are being sent are merged, so there is
+/// at most one acknowledgement per stream in flight. Failures to deliver them are logged and
+/// otherwise ignored: a later acknowledgement supersedes the lost one.
+pub async fn send_acknowledgements<F, Fut, E>(
+    mut pending: mpsc::UnboundedReceiver<(StreamKey, usize)>,
+    send: F,
+) where
+    F: Fn(StreamKey, usize) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: std::fmt::Debug,
+{
+    while let Some((key, received)) = pending.recv().await {
+        let mut latest = HashMap::from([(key, received)]);
+        while let Ok((key, received)) = pending.try_recv() {
+            let entry = latest.entry(key).or_default();
+            *entry = std::cmp::max(*entry, received);
+        }
+
+        let mut acks = latest
+            .into_iter()
+            .map(|(key, received)| {
+                let ack = send(key.clone(), received);
+                async move { (key, received, ack.await) }
+            })
+            .collect::<FuturesUnordered<_>>();
+        while let Some((key, received, res)) = acks.next().await {
+            if let Err(e) = res {
+                tracing::debug!("failed to acknowledge {received} bytes of {key:?}: {e:?}");
+            }
+        }
+    }
+}
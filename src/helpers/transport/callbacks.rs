@@ -3,7 +3,7 @@ use crate::{
     protocol::QueryId,
     query::{
         NewQueryError, PrepareQueryError, ProtocolResult, QueryCompletionError, QueryInputError,
-        QueryStatus, QueryStatusError,
+        QueryProgress, QueryStatus, QueryStatusError,
     },
 };
 use std::{future::Future, pin::Pin};
@@ -59,6 +59,10 @@ callbacks! {
     (QueryStatusCallback, QueryStatusResult):
         async fn(T, QueryId) -> Result<QueryStatus, QueryStatusError>;
 
+    /// Called by clients to retrieve the progress of a running query.
+    (QueryProgressCallback, QueryProgressResult):
+        async fn(T, QueryId) -> Result<Option<QueryProgress>, QueryStatusError>;
+
     /// Called by clients to drive query to completion and retrieve results.
     (CompleteQueryCallback, CompleteQueryResult):
         async fn(T, QueryId) -> Result<Box<dyn ProtocolResult>, QueryCompletionError>;
@@ -69,6 +73,7 @@ pub struct TransportCallbacks<T> {
     pub prepare_query: Box<dyn PrepareQueryCallback<T>>,
     pub query_input: Box<dyn QueryInputCallback<T>>,
     pub query_status: Box<dyn QueryStatusCallback<T>>,
+    pub query_progress: Box<dyn QueryProgressCallback<T>>,
     pub complete_query: Box<dyn CompleteQueryCallback<T>>,
 }
 
@@ -92,6 +97,9 @@ impl<T> Default for TransportCallbacks<T> {
             query_status: Box::new(move |_, _| {
                 Box::pin(async { panic!("unexpected call to query_status") })
             }),
+            query_progress: Box::new(move |_, _| {
+                Box::pin(async { panic!("unexpected call to query_progress") })
+            }),
             complete_query: Box::new(move |_, _| {
                 Box::pin(async { panic!("unexpected call to complete_query") })
             }),
@@ -0,0 +1,590 @@
+//! Fault injection for chaos testing.
+//!
+//! [`FaultyTransport`] wraps another [`Transport`] and disrupts the requests sent through it
+//! according to a [`FaultSchedule`]. Step streams can be delayed, reordered, dropped, duplicated
+//! or truncated, and requests to specific [`RouteId`]s can be rejected outright. Tests use it to
+//! make sure that failures surface as errors rather than hangs.
+//!
+//! Decisions are derived from the schedule seed, the channel and the number of streams sent over
+//! it so far. This makes them independent of how tasks are interleaved, so a failing schedule can
+//! be replayed by running the test again with the same seed.
+use crate::{
+    helpers::{
+        records_offset, HelperIdentity, NoResourceIdentifier, QueryIdBinding, RouteId, RouteParams,
+        StepBinding, Transport,
+    },
+    protocol::{step::Gate, QueryId},
+    sync::{atomic::AtomicUsize, Arc, Mutex},
+    time,
+};
+use async_trait::async_trait;
+use futures::{future, stream, Stream, StreamExt};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    borrow::Borrow,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    mem,
+    time::Duration,
+};
+use tokio::sync::oneshot;
+
+/// How long a reordered stream waits for the next stream to the same peer before it is sent
+/// anyway.
+const REORDER_WINDOW: Duration = Duration::from_millis(50);
+
+/// A disruption applied to a step stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The stream is sent after the given delay.
+    Delay(Duration),
+    /// The stream is held back until the next stream to the same peer is sent, so the peer
+    /// receives them in the opposite order.
+    Reorder,
+    /// The stream is not delivered and the send request fails, as if the peer was unreachable.
+    Drop,
+    /// The stream is delivered twice. The second copy is sent once the first one is consumed.
+    Duplicate,
+    /// The stream ends after the given number of bytes.
+    Truncate(usize),
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    fault: Fault,
+    probability: f64,
+    /// If set, the rule only applies to gates that contain this string.
+    gate: Option<String>,
+}
+
+/// A seeded set of faults to inject into the streams sent through a [`FaultyTransport`].
+///
+/// Rules are evaluated in the order they were added, the first one that fires is applied to the
+/// stream. The default schedule does not inject any faults.
+///
+/// ```ignore
+/// let faults = FaultSchedule::new(seed)
+///     .inject_at("multiply", Fault::Truncate(4), 0.5)
+///     .inject(Fault::Delay(Duration::from_millis(10)), 0.1)
+///     .reject(RouteId::PrepareQuery);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FaultSchedule {
+    seed: u64,
+    rules: Vec<Rule>,
+    rejected: Vec<RouteId>,
+}
+
+impl FaultSchedule {
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+
+    /// Applies `fault` to every step stream with the given probability.
+    ///
+    /// ## Panics
+    /// If `probability` is not within `[0, 1]`.
+    #[must_use]
+    pub fn inject(self, fault: Fault, probability: f64) -> Self {
+        self.add_rule(fault, probability, None)
+    }
+
+    /// Applies `fault` with the given probability to the step streams whose gate contains `gate`.
+    ///
+    /// ## Panics
+    /// If `probability` is not within `[0, 1]`.
+    #[must_use]
+    pub fn inject_at(self, gate: &str, fault: Fault, probability: f64) -> Self {
+        self.add_rule(fault, probability, Some(gate.to_string()))
+    }
+
+    /// Rejects all requests sent to the given route.
+    #[must_use]
+    pub fn reject(mut self, route: RouteId) -> Self {
+        self.rejected.push(route);
+        self
+    }
+
+    fn add_rule(mut self, fault: Fault, probability: f64, gate: Option<String>) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "probability must be within [0, 1], got {probability}"
+        );
+        self.rules.push(Rule {
+            fault,
+            probability,
+            gate,
+        });
+        self
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error<E> {
+    #[error("{route:?} request to {dest:?} is rejected by the fault schedule")]
+    Rejected {
+        dest: HelperIdentity,
+        route: RouteId,
+    },
+    #[error("stream {gate:?} to {dest:?} is dropped by the fault schedule")]
+    Dropped { dest: HelperIdentity, gate: Gate },
+    #[error(transparent)]
+    Inner(E),
+}
+
+/// Fault injection state of a single helper.
+#[derive(Clone, Default)]
+pub struct Faults {
+    inner: Arc<FaultsInner>,
+}
+
+#[derive(Default)]
+struct FaultsInner {
+    schedule: FaultSchedule,
+    /// Number of streams sent over each channel so far.
+    attempts: Mutex<HashMap<u64, u64>>,
+    /// Reordered streams waiting for the next stream to the same peer.
+    held: Mutex<HashMap<HelperIdentity, Vec<oneshot::Sender<()>>>>,
+}
+
+impl Faults {
+    #[must_use]
+    pub fn new(schedule: FaultSchedule) -> Self {
+        Self {
+            inner: Arc::new(FaultsInner {
+                schedule,
+                ..FaultsInner::default()
+            }),
+        }
+    }
+
+    fn rejects(&self, route: RouteId) -> bool {
+        self.inner.schedule.rejected.contains(&route)
+    }
+
+    /// Decides which fault, if any, to apply to the next stream sent over the given channel.
+    fn next_fault(&self, from: HelperIdentity, dest: HelperIdentity, gate: &Gate) -> Option<Fault> {
+        let schedule = &self.inner.schedule;
+        if schedule.rules.is_empty() {
+            return None;
+        }
+
+        let channel = {
+            let mut hasher = DefaultHasher::new();
+            (from, dest, gate.as_ref()).hash(&mut hasher);
+            hasher.finish()
+        };
+        let attempt = {
+            let mut attempts = self.inner.attempts.lock().unwrap();
+            let attempt = attempts.entry(channel).or_default();
+            *attempt += 1;
+            *attempt
+        };
+
+        let mut hasher = DefaultHasher::new();
+        (schedule.seed, channel, attempt).hash(&mut hasher);
+        let mut rng = StdRng::seed_from_u64(hasher.finish());
+
+        schedule
+            .rules
+            .iter()
+            .filter(|rule| {
+                rule.gate
+                    .as_ref()
+                    .map_or(true, |g| gate.as_ref().contains(g.as_str()))
+            })
+            .find(|rule| rng.gen_bool(rule.probability))
+            .map(|rule| rule.fault.clone())
+    }
+
+    async fn hold(&self, dest: HelperIdentity) {
+        let (tx, rx) = oneshot::channel();
+        self.inner
+            .held
+            .lock()
+            .unwrap()
+            .entry(dest)
+            .or_default()
+            .push(tx);
+        let _ = time::timeout(REORDER_WINDOW, rx).await;
+    }
+
+    fn release(&self, dest: HelperIdentity) {
+        let held = self.inner.held.lock().unwrap().remove(&dest);
+        for tx in held.into_iter().flatten() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+/// [`Transport`] decorator that injects faults into the requests sent through it.
+pub struct FaultyTransport<T> {
+    inner: T,
+    faults: Faults,
+}
+
+impl<T> FaultyTransport<T> {
+    #[must_use]
+    pub fn new(inner: T, faults: Faults) -> Self {
+        Self { inner, faults }
+    }
+}
+
+impl<T: Transport> Clone for FaultyTransport<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Transport::clone_ref(&self.inner),
+            faults: self.faults.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Transport> Transport for FaultyTransport<T> {
+    type RecordsStream = T::RecordsStream;
+    type Error = Error<T::Error>;
+
+    fn identity(&self) -> HelperIdentity {
+        self.inner.identity()
+    }
+
+    async fn send<
+        D: Stream<Item = Vec<u8>> + Send + 'static,
+        Q: QueryIdBinding,
+        S: StepBinding,
+        R: RouteParams<RouteId, Q, S>,
+    >(
+        &self,
+        dest: HelperIdentity,
+        route: R,
+        data: D,
+    ) -> Result<(), Self::Error>
+    where
+        Option<QueryId>: From<Q>,
+        Option<Gate>: From<S>,
+    {
+        let route_id = route.resource_identifier();
+        if self.faults.rejects(route_id) {
+            return Err(Error::Rejected {
+                dest,
+                route: route_id,
+            });
+        }
+
+        let query_id: Option<QueryId> = route.query_id().into();
+        let gate: Option<Gate> = route.gate().into();
+        let (query_id, gate) = match (route_id, query_id, gate) {
+            (RouteId::Records, Some(query_id), Some(gate)) => (query_id, gate),
+            _ => {
+                return self
+                    .inner
+                    .send(dest, route, data)
+                    .await
+                    .map_err(Error::Inner)
+            }
+        };
+
+        let fault = self.faults.next_fault(self.identity(), dest, &gate);
+        if let Some(fault) = &fault {
+            tracing::info!("injecting {fault:?} into {gate:?} stream to {dest:?}");
+        }
+        let result = match fault {
+            None => self.inner.send(dest, route, data).await,
+            Some(Fault::Delay(delay)) => {
+                time::sleep(delay).await;
+                self.inner.send(dest, route, data).await
+            }
+            Some(Fault::Reorder) => {
+                self.faults.hold(dest).await;
+                // do not let the held stream release the streams behind it.
+                return self
+                    .inner
+                    .send(dest, route, data)
+                    .await
+                    .map_err(Error::Inner);
+            }
+            Some(Fault::Drop) => return Err(Error::Dropped { dest, gate }),
+            Some(Fault::Truncate(len)) => {
+                let truncated = data.scan(len, |remaining, mut chunk| {
+                    let chunk = (*remaining > 0).then(|| {
+                        chunk.truncate(*remaining);
+                        *remaining -= chunk.len();
+                        chunk
+                    });
+                    future::ready(chunk)
+                });
+                self.inner.send(dest, route, truncated).await
+            }
+            Some(Fault::Duplicate) => {
+                // the copy is sent with the same offset, once the original stream is consumed.
+                let offset = records_offset(route.extra().borrow()).unwrap_or_default();
+                let chunks = Arc::new(Mutex::new(Vec::new()));
+                let (done_tx, done_rx) = oneshot::channel();
+                let tee = data
+                    .inspect({
+                        let chunks = Arc::clone(&chunks);
+                        move |chunk| chunks.lock().unwrap().push(chunk.clone())
+                    })
+                    .chain(
+                        stream::once(async move {
+                            let _ = done_tx.send(());
+                        })
+                        .filter_map(|()| future::ready(None::<Vec<u8>>)),
+                    );
+
+                let result = self.inner.send(dest, route, tee).await;
+                if result.is_ok() && done_rx.await.is_ok() {
+                    let copy = mem::take(&mut *chunks.lock().unwrap());
+                    self.inner
+                        .send::<_, QueryId, Gate, _>(
+                            dest,
+                            (RouteId::Records, query_id, gate, offset),
+                            stream::iter(copy),
+                        )
+                        .await
+                } else {
+                    result
+                }
+            }
+        };
+        self.faults.release(dest);
+
+        result.map_err(Error::Inner)
+    }
+
+    fn receive<R: RouteParams<NoResourceIdentifier, QueryId, Gate>>(
+        &self,
+        from: HelperIdentity,
+        route: R,
+    ) -> Self::RecordsStream {
+        self.inner.receive(from, route)
+    }
+
+    fn acknowledged<R: RouteParams<NoResourceIdentifier, QueryId, Gate>>(
+        &self,
+        to: HelperIdentity,
+        route: R,
+    ) -> Arc<AtomicUsize> {
+        self.inner.acknowledged(to, route)
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::{Error as FaultError, Fault, FaultSchedule, Faults, FaultyTransport};
+    use crate::{
+        error::Error,
+        ff::Fp31,
+        helpers::{Error as InfraError, HelperIdentity, InMemoryNetwork, RouteId, Transport},
+        protocol::{
+            basics::SecureMul,
+            context::Context,
+            step::{Gate, StepNarrow},
+            QueryId, RecordId,
+        },
+        rand::{thread_rng, Rng},
+        secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
+        seq_join::SeqJoin,
+        test_fixture::{Reconstruct, Runner, TestWorld, TestWorldConfig},
+    };
+    use futures::{stream, StreamExt};
+    use std::{
+        iter::{repeat, zip},
+        time::Duration,
+    };
+
+    /// Enough records to fill the gateway buffers several times over.
+    const COUNT: usize = 100;
+
+    async fn multiply(
+        world: &TestWorld,
+        a: Vec<Fp31>,
+        b: Vec<Fp31>,
+    ) -> [Result<Vec<Replicated<Fp31>>, Error>; 3] {
+        world
+            .semi_honest(
+                (a.into_iter(), b.into_iter()),
+                |ctx, (a_shares, b_shares)| async move {
+                    ctx.try_join(
+                        zip(
+                            repeat(ctx.set_total_records(COUNT)),
+                            zip(a_shares, b_shares),
+                        )
+                        .enumerate()
+                        .map(|(i, (ctx, (a_share, b_share)))| async move {
+                            a_share.multiply(&b_share, ctx, RecordId::from(i)).await
+                        }),
+                    )
+                    .await
+                },
+            )
+            .await
+    }
+
+    fn inputs() -> (Vec<Fp31>, Vec<Fp31>) {
+        let mut rng = thread_rng();
+        (
+            (0..COUNT).map(|_| rng.gen::<Fp31>()).collect(),
+            (0..COUNT).map(|_| rng.gen::<Fp31>()).collect(),
+        )
+    }
+
+    /// Picks a random seed for the fault schedule. It is printed, so a failing schedule can be
+    /// replayed.
+    fn schedule() -> FaultSchedule {
+        let seed = thread_rng().gen();
+        println!("fault schedule seed: {seed}");
+        FaultSchedule::new(seed)
+    }
+
+    fn world(faults: FaultSchedule) -> TestWorld {
+        let mut config = TestWorldConfig::default().with_faults(faults);
+        config.gateway_config = config.gateway_config.with_send_retries(16);
+        TestWorld::new_with(config)
+    }
+
+    /// Peers of a helper that cannot deliver its data wait for it forever, unless the gateway
+    /// fails once it stops making progress.
+    fn failing_world(faults: FaultSchedule) -> TestWorld {
+        let mut config = TestWorldConfig::default().with_faults(faults);
+        config.gateway_config = config
+            .gateway_config
+            .with_send_retries(16)
+            .with_stall_timeout(Duration::from_millis(200))
+            .fail_on_stall();
+        TestWorld::new_with(config)
+    }
+
+    #[test]
+    fn schedule_is_deterministic() {
+        let schedule = FaultSchedule::new(42)
+            .inject(Fault::Drop, 0.3)
+            .inject(Fault::Duplicate, 0.3);
+        let gate = Gate::default().narrow("deterministic");
+        let decisions = |faults: Faults| {
+            (0..20)
+                .map(|_| faults.next_fault(HelperIdentity::ONE, HelperIdentity::TWO, &gate))
+                .collect::<Vec<_>>()
+        };
+
+        let first = decisions(Faults::new(schedule.clone()));
+        assert_eq!(first, decisions(Faults::new(schedule)));
+        assert!(first.contains(&Some(Fault::Drop)));
+        assert!(first.contains(&None));
+    }
+
+    #[test]
+    #[should_panic(expected = "probability must be within [0, 1]")]
+    fn rejects_invalid_probability() {
+        let _ = FaultSchedule::new(0).inject(Fault::Drop, 1.5);
+    }
+
+    #[tokio::test]
+    async fn tolerates_delayed_reordered_and_duplicated_streams() {
+        let world = world(
+            schedule()
+                .inject(Fault::Delay(Duration::from_millis(5)), 0.3)
+                .inject(Fault::Reorder, 0.3)
+                .inject(Fault::Duplicate, 0.3),
+        );
+        let (a, b) = inputs();
+
+        let results = multiply(&world, a.clone(), b.clone())
+            .await
+            .map(Result::unwrap);
+        let expected: Vec<_> = zip(a, b).map(|(a, b)| a * b).collect();
+        assert_eq!(expected, results.reconstruct());
+    }
+
+    #[tokio::test]
+    async fn resends_dropped_streams() {
+        let world = world(schedule().inject(Fault::Drop, 0.5));
+        let (a, b) = inputs();
+
+        let results = multiply(&world, a.clone(), b.clone())
+            .await
+            .map(Result::unwrap);
+        let expected: Vec<_> = zip(a, b).map(|(a, b)| a * b).collect();
+        assert_eq!(expected, results.reconstruct());
+    }
+
+    #[tokio::test]
+    async fn dropped_streams_fail_sends() {
+        let network = InMemoryNetwork::default();
+        let faults = Faults::new(schedule().inject(Fault::Drop, 1.0));
+        let transport = FaultyTransport::new(network.transport(HelperIdentity::ONE), faults);
+        let gate = Gate::default().narrow("drop");
+
+        let err = transport
+            .send(
+                HelperIdentity::TWO,
+                (RouteId::Records, QueryId, gate.clone()),
+                stream::iter(vec![vec![0, 1, 2]]),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, FaultError::Dropped { dest: HelperIdentity::TWO, gate: g } if *g == gate),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn truncated_streams_end_early() {
+        let network = InMemoryNetwork::default();
+        let faults = Faults::new(schedule().inject(Fault::Truncate(4), 1.0));
+        let transport = FaultyTransport::new(network.transport(HelperIdentity::ONE), faults);
+        let gate = Gate::default().narrow("truncate");
+
+        transport
+            .send(
+                HelperIdentity::TWO,
+                (RouteId::Records, QueryId, gate.clone()),
+                stream::iter(vec![vec![0, 1, 2], vec![3, 4, 5]]),
+            )
+            .await
+            .unwrap();
+        let received = network
+            .transport(HelperIdentity::TWO)
+            .receive(HelperIdentity::ONE, (QueryId, gate))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(vec![vec![0, 1, 2], vec![3]], received);
+    }
+
+    /// Asserts that every helper failed because it stopped receiving data from its peer after
+    /// the given number of records.
+    fn assert_stalled(results: [Result<Vec<Replicated<Fp31>>, Error>; 3], received: usize) {
+        for result in results {
+            let Err(Error::InfraError(InfraError::ReceiveError { inner, .. })) = &result else {
+                panic!("expected the receive to fail, got {result:?}");
+            };
+            let Some(InfraError::Stalled(report)) = inner.downcast_ref::<InfraError>() else {
+                panic!("expected a stall, got {inner:?}");
+            };
+            assert_eq!(1, report.receivers.len(), "{report}");
+            assert_eq!(received, report.receivers[0].1.next, "{report}");
+        }
+    }
+
+    #[tokio::test]
+    async fn dropped_streams_fail_queries() {
+        let world = failing_world(schedule().inject(Fault::Drop, 1.0));
+        let (a, b) = inputs();
+
+        assert_stalled(multiply(&world, a, b).await, 0);
+    }
+
+    #[tokio::test]
+    async fn truncated_streams_fail_queries() {
+        let world = failing_world(schedule().inject(Fault::Truncate(1), 1.0));
+        let (a, b) = inputs();
+
+        // one byte is enough for exactly one record.
+        assert_stalled(multiply(&world, a, b).await, 1);
+    }
+}
@@ -0,0 +1,312 @@
+use super::transport::InMemoryStream;
+use crate::{
+    protocol::step::Gate,
+    sync::{Arc, Mutex},
+    telemetry::StepStatsCsvExporter,
+    time::{sleep_until, Instant},
+};
+use ::tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use futures::{future::BoxFuture, ready, FutureExt, Stream, StreamExt};
+#[cfg(all(feature = "shuttle", test))]
+use shuttle::future as tokio;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    num::NonZeroU32,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Properties of the simulated network links between helpers. Every helper has a separate link to
+/// each of its peers, in each direction.
+///
+/// By default, links deliver data instantly. Setting a latency makes every communication round
+/// cost at least that much time, which makes benchmarks reflect the round complexity of the
+/// protocols, as it would show in a deployment where helpers are far apart.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkConfig {
+    /// Time it takes for a byte to travel from one helper to another.
+    pub latency: Duration,
+    /// If set, the number of bytes per second a link carries. Data sent faster than that is
+    /// queued up.
+    pub bandwidth: Option<NonZeroU32>,
+}
+
+impl LinkConfig {
+    #[must_use]
+    pub fn with_latency(self, latency: Duration) -> Self {
+        Self { latency, ..self }
+    }
+
+    #[must_use]
+    pub fn with_bandwidth(self, bytes_per_second: NonZeroU32) -> Self {
+        Self {
+            bandwidth: Some(bytes_per_second),
+            ..self
+        }
+    }
+
+    fn is_instant(&self) -> bool {
+        self.latency.is_zero() && self.bandwidth.is_none()
+    }
+
+    /// Time it takes to put `bytes` on the link.
+    #[allow(clippy::cast_precision_loss)] // chunks are much smaller than 2^52 bytes
+    fn transmission_time(&self, bytes: usize) -> Duration {
+        self.bandwidth.map_or(Duration::ZERO, |bandwidth| {
+            Duration::from_secs_f64(bytes as f64 / f64::from(bandwidth.get()))
+        })
+    }
+}
+
+/// One direction of a simulated link between two helpers. Record streams sent over the link share
+/// its bandwidth.
+pub(super) struct Link {
+    config: LinkConfig,
+    state: Mutex<LinkState>,
+}
+
+struct LinkState {
+    /// Time at which the link is done transmitting the data queued up on it.
+    idle_at: Instant,
+    rounds: HashMap<Gate, RoundCounter>,
+}
+
+/// Counts the communication rounds on a gate by grouping the data sent on it by the time it is
+/// sent. Data of one round is sent all at once, and the next round can't start before the data
+/// of the previous one arrived.
+///
+/// This relies on the link latency being much larger than the time helpers spend computing. Tests
+/// that check the number of rounds run with a paused clock, where computing takes no time at all
+/// and the count is exact.
+struct RoundCounter {
+    last_sent: Instant,
+    rounds: usize,
+}
+
+impl Link {
+    pub fn new(config: LinkConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(LinkState {
+                idle_at: Instant::now(),
+                rounds: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Sends `stream` over this link. The returned stream yields each chunk at the time it would
+    /// arrive at the other helper. Chunks are taken out of `stream` as soon as they are ready, like
+    /// a network socket would do it.
+    pub fn transmit(
+        self: &Arc<Self>,
+        gate: Option<Gate>,
+        mut stream: InMemoryStream,
+    ) -> InMemoryStream {
+        if self.config.is_instant() {
+            return stream;
+        }
+
+        let (tx, rx) = unbounded_channel();
+        let link = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Some(chunk) = stream.next().await {
+                let arrival = link.schedule(gate.as_ref(), chunk.len(), Instant::now());
+                if tx.send((arrival, chunk)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        InMemoryStream::wrap(Delayed {
+            rx,
+            next: None,
+            sleep: None,
+        })
+    }
+
+    /// Queues up `bytes` sent at `now` on this link, and returns the time they arrive.
+    fn schedule(&self, gate: Option<&Gate>, bytes: usize, now: Instant) -> Instant {
+        let mut state = self.state.lock().unwrap();
+        if let Some(gate) = gate {
+            let round_gap = self.config.latency / 2;
+            state
+                .rounds
+                .entry(gate.clone())
+                .and_modify(|counter| {
+                    if now.saturating_duration_since(counter.last_sent) >= round_gap {
+                        counter.rounds += 1;
+                    }
+                    counter.last_sent = now;
+                })
+                .or_insert(RoundCounter {
+                    last_sent: now,
+                    rounds: 1,
+                });
+        }
+
+        state.idle_at = state.idle_at.max(now) + self.config.transmission_time(bytes);
+        state.idle_at + self.config.latency
+    }
+
+    /// Adds the communication rounds observed on this link to `report`.
+    pub fn report_rounds(&self, report: &mut CommunicationRounds) {
+        for (gate, counter) in &self.state.lock().unwrap().rounds {
+            let rounds = report.0.entry(gate.as_ref().to_string()).or_default();
+            *rounds = (*rounds).max(counter.rounds);
+        }
+    }
+
+    /// Forgets the rounds observed so far.
+    pub fn reset(&self) {
+        self.state.lock().unwrap().rounds.clear();
+    }
+}
+
+/// Data that arrives at the other end of a [`Link`].
+struct Delayed {
+    rx: UnboundedReceiver<(Instant, Vec<u8>)>,
+    next: Option<Vec<u8>>,
+    sleep: Option<BoxFuture<'static, ()>>,
+}
+
+impl Stream for Delayed {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.next.is_none() {
+            match ready!(this.rx.poll_recv(cx)) {
+                Some((arrival, chunk)) => {
+                    this.next = Some(chunk);
+                    this.sleep = Some(sleep_until(arrival).boxed());
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+
+        if let Some(sleep) = this.sleep.as_mut() {
+            ready!(sleep.poll_unpin(cx));
+            this.sleep = None;
+        }
+        Poll::Ready(this.next.take())
+    }
+}
+
+/// Number of sequential communication rounds observed on each gate of a protocol, when run over
+/// links with latency. Helpers communicate on a gate in the same rounds, so each gate is reported
+/// once, with the largest number of rounds any helper needed on it.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CommunicationRounds(BTreeMap<String, usize>);
+
+impl CommunicationRounds {
+    /// Number of rounds observed on `gate`, or 0 if nothing was sent on it.
+    #[must_use]
+    pub fn get(&self, gate: &str) -> usize {
+        self.0.get(gate).copied().unwrap_or_default()
+    }
+
+    /// Total number of rounds on all the gates. This is an upper bound of the number of
+    /// sequential rounds of the protocol, because rounds on different gates may happen at the
+    /// same time.
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.0.values().sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.0.iter().map(|(gate, rounds)| (gate.as_str(), *rounds))
+    }
+}
+
+impl StepStatsCsvExporter for CommunicationRounds {
+    fn export<W: io::Write>(&self, w: &mut W) -> Result<(), io::Error> {
+        writeln!(w, "Step,Rounds")?;
+        for (gate, rounds) in self.iter() {
+            writeln!(w, "{gate},{rounds}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use futures::{future::join, stream};
+
+    const LATENCY: Duration = Duration::from_millis(100);
+
+    fn link(config: LinkConfig) -> Arc<Link> {
+        Arc::new(Link::new(config))
+    }
+
+    fn chunks() -> InMemoryStream {
+        InMemoryStream::wrap(stream::iter(vec![vec![1], vec![2]]))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn instant_by_default() {
+        let start = Instant::now();
+        let received = link(LinkConfig::default())
+            .transmit(None, chunks())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(vec![vec![1], vec![2]], received);
+        assert_eq!(Duration::ZERO, start.elapsed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delays_by_latency() {
+        let start = Instant::now();
+        let received = link(LinkConfig::default().with_latency(LATENCY))
+            .transmit(None, chunks())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(vec![vec![1], vec![2]], received);
+        // both chunks are in flight at the same time.
+        assert_eq!(LATENCY, start.elapsed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queues_up_beyond_bandwidth() {
+        let start = Instant::now();
+        let link = link(
+            LinkConfig::default()
+                .with_latency(LATENCY)
+                .with_bandwidth(NonZeroU32::new(10_000).unwrap()),
+        );
+        // the streams share the link, so the second one waits for the first.
+        let first = link.transmit(
+            None,
+            InMemoryStream::wrap(stream::iter(vec![vec![0; 1000]])),
+        );
+        let second = link.transmit(
+            None,
+            InMemoryStream::wrap(stream::iter(vec![vec![0; 1000]])),
+        );
+        join(first.count(), second.count()).await;
+        assert_eq!(Duration::from_millis(200) + LATENCY, start.elapsed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn counts_rounds() {
+        let link = link(LinkConfig::default().with_latency(LATENCY));
+        let gate = Gate::from("rounds");
+        for _ in 0..3 {
+            let received = link.transmit(Some(gate.clone()), chunks()).count().await;
+            assert_eq!(2, received);
+        }
+
+        let mut report = CommunicationRounds::default();
+        link.report_rounds(&mut report);
+        assert_eq!(3, report.get(gate.as_ref()));
+        assert_eq!(3, report.total());
+
+        link.reset();
+        let mut report = CommunicationRounds::default();
+        link.report_rounds(&mut report);
+        assert_eq!(CommunicationRounds::default(), report);
+    }
+}
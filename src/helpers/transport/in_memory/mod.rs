@@ -1,10 +1,14 @@
+mod link;
 mod transport;
 
+#[cfg(any(test, feature = "test-fixture"))]
+use crate::helpers::FaultSchedule;
 use crate::{
     helpers::{HelperIdentity, TransportCallbacks},
     sync::{Arc, Weak},
 };
 
+pub use link::{CommunicationRounds, LinkConfig};
 pub use transport::Setup;
 
 pub type InMemoryTransport = Weak<transport::InMemoryTransport>;
@@ -29,8 +33,54 @@ impl Default for InMemoryNetwork {
 impl InMemoryNetwork {
     #[must_use]
     pub fn new(callbacks: [TransportCallbacks<InMemoryTransport>; 3]) -> Self {
-        let [mut first, mut second, mut third]: [_; 3] =
-            HelperIdentity::make_three().map(Setup::new);
+        Self::start(HelperIdentity::make_three().map(Setup::new), callbacks)
+    }
+
+    /// Creates a network where every helper disrupts the requests it sends according to
+    /// the given schedule, see [`FaultyTransport`].
+    ///
+    /// [`FaultyTransport`]: crate::helpers::FaultyTransport
+    #[cfg(any(test, feature = "test-fixture"))]
+    #[must_use]
+    pub fn with_faults(
+        callbacks: [TransportCallbacks<InMemoryTransport>; 3],
+        schedule: &FaultSchedule,
+    ) -> Self {
+        Self::with_faults_and_links(callbacks, schedule, LinkConfig::default())
+    }
+
+    /// Creates a network where helpers send records to each other over simulated links with the
+    /// given latency and bandwidth.
+    #[must_use]
+    pub fn with_links(
+        callbacks: [TransportCallbacks<InMemoryTransport>; 3],
+        link: LinkConfig,
+    ) -> Self {
+        Self::start(
+            HelperIdentity::make_three().map(|id| Setup::new(id).with_link(link)),
+            callbacks,
+        )
+    }
+
+    /// Creates a network where helpers send records to each other over simulated links with the
+    /// given latency and bandwidth, and inject faults into the requests they send according to
+    /// the given schedule.
+    #[cfg(any(test, feature = "test-fixture"))]
+    #[must_use]
+    pub fn with_faults_and_links(
+        callbacks: [TransportCallbacks<InMemoryTransport>; 3],
+        schedule: &FaultSchedule,
+        link: LinkConfig,
+    ) -> Self {
+        Self::start(
+            HelperIdentity::make_three()
+                .map(|id| Setup::new(id).with_link(link).with_faults(schedule.clone())),
+            callbacks,
+        )
+    }
+
+    fn start(setups: [Setup; 3], callbacks: [TransportCallbacks<InMemoryTransport>; 3]) -> Self {
+        let [mut first, mut second, mut third] = setups;
 
         first.connect(&mut second);
         second.connect(&mut third);
@@ -80,6 +130,17 @@ impl InMemoryNetwork {
         transports
     }
 
+    /// Returns the number of communication rounds observed on each gate since the network was
+    /// created or reset. Rounds are only counted if the links have latency.
+    #[must_use]
+    pub fn rounds(&self) -> CommunicationRounds {
+        let mut report = CommunicationRounds::default();
+        for t in &self.transports {
+            t.report_rounds(&mut report);
+        }
+        report
+    }
+
     /// Reset all transports to the clear state.
     pub fn reset(&self) {
         for t in &self.transports {
@@ -1,19 +1,24 @@
+use super::link::{CommunicationRounds, Link, LinkConfig};
+#[cfg(any(test, feature = "test-fixture"))]
+use crate::helpers::{transport::faulty, FaultSchedule, Faults, FaultyTransport};
 use crate::{
     error::BoxError,
     helpers::{
         query::{PrepareQuery, QueryConfig},
-        HelperIdentity, NoResourceIdentifier, QueryIdBinding, ReceiveRecords, RouteId, RouteParams,
-        StepBinding, StreamCollection, Transport, TransportCallbacks,
+        records_offset, send_acknowledgements, Acknowledgements, HelperIdentity, Interruptible,
+        NoResourceIdentifier, QueryIdBinding, ReceiveRecords, RouteId, RouteParams, StepBinding,
+        StreamCollection, StreamKey, Transport, TransportCallbacks,
     },
     protocol::{step::Gate, QueryId},
+    sync::atomic::AtomicUsize,
 };
 use ::tokio::sync::{
-    mpsc::{channel, Receiver, Sender},
+    mpsc::{channel, Receiver, Sender, UnboundedReceiver},
     oneshot,
 };
 
 use async_trait::async_trait;
-use futures::{Stream, StreamExt};
+use futures::{stream, Stream, StreamExt};
 use serde::de::DeserializeOwned;
 #[cfg(all(feature = "shuttle", test))]
 use shuttle::future as tokio;
@@ -56,16 +61,35 @@ pub enum Error {
 pub struct InMemoryTransport {
     identity: HelperIdentity,
     connections: HashMap<HelperIdentity, ConnectionTx>,
+    /// Simulated links that record streams take to each peer.
+    links: HashMap<HelperIdentity, Arc<Link>>,
     record_streams: StreamCollection<InMemoryStream>,
+    acknowledgements: Acknowledgements,
+    /// Faults injected into the requests this transport sends.
+    #[cfg(any(test, feature = "test-fixture"))]
+    faults: Option<Faults>,
 }
 
 impl InMemoryTransport {
     #[must_use]
-    fn new(identity: HelperIdentity, connections: HashMap<HelperIdentity, ConnectionTx>) -> Self {
+    fn new(
+        identity: HelperIdentity,
+        connections: HashMap<HelperIdentity, ConnectionTx>,
+        record_streams: StreamCollection<InMemoryStream>,
+        link: LinkConfig,
+    ) -> Self {
+        let links = connections
+            .keys()
+            .map(|&peer| (peer, Arc::new(Link::new(link))))
+            .collect();
         Self {
             identity,
             connections,
-            record_streams: StreamCollection::default(),
+            links,
+            record_streams,
+            acknowledgements: Acknowledgements::default(),
+            #[cfg(any(test, feature = "test-fixture"))]
+            faults: None,
         }
     }
 
@@ -78,10 +102,34 @@ impl InMemoryTransport {
     /// out and processes it, the same way as query processor does. That will allow all tasks to be
     /// created in one place (driver). It does not affect the [`Transport`] interface,
     /// so I'll leave it as is for now.
-    fn listen(self: &Arc<Self>, callbacks: TransportCallbacks<Weak<Self>>, mut rx: ConnectionRx) {
+    fn listen(
+        self: &Arc<Self>,
+        callbacks: TransportCallbacks<Weak<Self>>,
+        mut rx: ConnectionRx,
+        acks: UnboundedReceiver<(StreamKey, usize)>,
+    ) {
+        tokio::spawn({
+            let this = Arc::downgrade(self);
+            send_acknowledgements(acks, move |(query_id, peer, gate), received| {
+                let this = Weak::clone(&this);
+                async move {
+                    // nothing to acknowledge if the transport is gone.
+                    if this.upgrade().is_none() {
+                        return Ok(());
+                    }
+                    this.send(
+                        peer,
+                        (RouteId::RecordsAck, query_id, gate, received),
+                        stream::empty(),
+                    )
+                    .await
+                }
+            })
+        });
         tokio::spawn(
             {
                 let streams = self.record_streams.clone();
+                let acknowledgements = self.acknowledgements.clone();
                 let this = Arc::downgrade(self);
                 let dest = this.identity();
                 async move {
@@ -109,8 +157,27 @@ impl InMemoryTransport {
                                 let query_id = addr.query_id.unwrap();
                                 let gate = addr.gate.unwrap();
                                 let from = addr.origin.unwrap();
-                                streams.add_stream((query_id, from, gate), stream);
-                                Ok(())
+                                records_offset(&addr.params)
+                                    .map_err(BoxError::from)
+                                    .and_then(|offset| {
+                                        streams
+                                            .add_stream((query_id, from, gate), offset, stream)
+                                            .map_err(BoxError::from)
+                                    })
+                                    .map_err(|inner| Error::Rejected { dest, inner })
+                            }
+                            RouteId::RecordsAck => {
+                                let query_id = addr.query_id.unwrap();
+                                let gate = addr.gate.unwrap();
+                                let from = addr.origin.unwrap();
+                                records_offset(&addr.params)
+                                    .map(|received| {
+                                        acknowledgements.record((query_id, from, gate), received);
+                                    })
+                                    .map_err(|e| Error::Rejected {
+                                        dest,
+                                        inner: Box::new(e),
+                                    })
                             }
                             RouteId::PrepareQuery => {
                                 let input = addr.into::<PrepareQuery>();
@@ -143,9 +210,55 @@ impl InMemoryTransport {
             .clone()
     }
 
+    /// Sends the request to `dest`, bypassing the fault schedule.
+    async fn send_direct<
+        D: Stream<Item = Vec<u8>> + Send + 'static,
+        Q: QueryIdBinding,
+        S: StepBinding,
+        R: RouteParams<RouteId, Q, S>,
+    >(
+        &self,
+        dest: HelperIdentity,
+        route: R,
+        data: D,
+    ) -> Result<(), Error>
+    where
+        Option<QueryId>: From<Q>,
+        Option<Gate>: From<S>,
+    {
+        let channel = self.get_channel(dest);
+        let addr = Addr::from_route(self.identity, route);
+        let (ack_tx, ack_rx) = oneshot::channel();
+        let data = self.links[&dest].transmit(addr.gate.clone(), InMemoryStream::wrap(data));
+
+        channel.send((addr, data, ack_tx)).await.map_err(|_e| {
+            io::Error::new::<String>(io::ErrorKind::ConnectionAborted, "channel closed".into())
+        })?;
+
+        ack_rx
+            .await
+            .map_err(|_recv_error| Error::Rejected {
+                dest,
+                inner: "channel closed".into(),
+            })
+            .and_then(convert::identity)
+    }
+
+    /// Adds the communication rounds observed on the links to the peers of this helper to
+    /// `report`.
+    pub fn report_rounds(&self, report: &mut CommunicationRounds) {
+        for link in self.links.values() {
+            link.report_rounds(report);
+        }
+    }
+
     /// Resets this transport, making it forget its state and be ready for processing another query.
     pub fn reset(&self) {
         self.record_streams.clear();
+        self.acknowledgements.clear();
+        for link in self.links.values() {
+            link.reset();
+        }
     }
 }
 
@@ -174,24 +287,21 @@ impl Transport for Weak<InMemoryTransport> {
         Option<Gate>: From<S>,
     {
         let this = self.upgrade().unwrap();
-        let channel = this.get_channel(dest);
-        let addr = Addr::from_route(this.identity, route);
-        let (ack_tx, ack_rx) = oneshot::channel();
-
-        channel
-            .send((addr, InMemoryStream::wrap(data), ack_tx))
-            .await
-            .map_err(|_e| {
-                io::Error::new::<String>(io::ErrorKind::ConnectionAborted, "channel closed".into())
-            })?;
+        #[cfg(any(test, feature = "test-fixture"))]
+        if let Some(faults) = &this.faults {
+            return FaultyTransport::new(Direct(Weak::clone(self)), faults.clone())
+                .send(dest, route, data)
+                .await
+                .map_err(|e| match e {
+                    faulty::Error::Inner(e) => e,
+                    e => Error::Rejected {
+                        dest,
+                        inner: Box::new(e),
+                    },
+                });
+        }
 
-        ack_rx
-            .await
-            .map_err(|_recv_error| Error::Rejected {
-                dest,
-                inner: "channel closed".into(),
-            })
-            .and_then(convert::identity)
+        this.send_direct(dest, route, data).await
     }
 
     fn receive<R: RouteParams<NoResourceIdentifier, QueryId, Gate>>(
@@ -204,6 +314,71 @@ impl Transport for Weak<InMemoryTransport> {
             self.upgrade().unwrap().record_streams.clone(),
         )
     }
+
+    fn acknowledged<R: RouteParams<NoResourceIdentifier, QueryId, Gate>>(
+        &self,
+        to: HelperIdentity,
+        route: R,
+    ) -> Arc<AtomicUsize> {
+        self.upgrade()
+            .unwrap()
+            .acknowledgements
+            .get((route.query_id(), to, route.gate()))
+    }
+}
+
+/// In-memory transport that ignores the fault schedule, so [`FaultyTransport`] can wrap it.
+#[cfg(any(test, feature = "test-fixture"))]
+#[derive(Clone)]
+struct Direct(Weak<InMemoryTransport>);
+
+#[cfg(any(test, feature = "test-fixture"))]
+#[async_trait]
+impl Transport for Direct {
+    type RecordsStream = ReceiveRecords<InMemoryStream>;
+    type Error = Error;
+
+    fn identity(&self) -> HelperIdentity {
+        self.0.identity()
+    }
+
+    async fn send<
+        D: Stream<Item = Vec<u8>> + Send + 'static,
+        Q: QueryIdBinding,
+        S: StepBinding,
+        R: RouteParams<RouteId, Q, S>,
+    >(
+        &self,
+        dest: HelperIdentity,
+        route: R,
+        data: D,
+    ) -> Result<(), Error>
+    where
+        Option<QueryId>: From<Q>,
+        Option<Gate>: From<S>,
+    {
+        self.0
+            .upgrade()
+            .unwrap()
+            .send_direct(dest, route, data)
+            .await
+    }
+
+    fn receive<R: RouteParams<NoResourceIdentifier, QueryId, Gate>>(
+        &self,
+        from: HelperIdentity,
+        route: R,
+    ) -> Self::RecordsStream {
+        self.0.receive(from, route)
+    }
+
+    fn acknowledged<R: RouteParams<NoResourceIdentifier, QueryId, Gate>>(
+        &self,
+        to: HelperIdentity,
+        route: R,
+    ) -> Arc<AtomicUsize> {
+        self.0.acknowledged(to, route)
+    }
 }
 
 /// Convenience struct to support heterogeneous in-memory streams
@@ -218,7 +393,7 @@ impl InMemoryStream {
         Self::from_iter(std::iter::empty())
     }
 
-    fn wrap<S: Stream<Item = StreamItem> + Send + 'static>(value: S) -> Self {
+    pub(super) fn wrap<S: Stream<Item = StreamItem> + Send + 'static>(value: S) -> Self {
         Self {
             inner: Box::pin(value),
         }
@@ -254,6 +429,13 @@ impl Stream for InMemoryStream {
     }
 }
 
+/// In-memory streams are never interrupted.
+impl Interruptible for InMemoryStream {
+    fn interrupted(&self) -> bool {
+        false
+    }
+}
+
 impl Debug for InMemoryStream {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "InMemoryStream")
@@ -318,6 +500,9 @@ pub struct Setup {
     tx: ConnectionTx,
     rx: ConnectionRx,
     connections: HashMap<HelperIdentity, ConnectionTx>,
+    #[cfg(any(test, feature = "test-fixture"))]
+    faults: Option<Faults>,
+    link: LinkConfig,
 }
 
 impl Setup {
@@ -329,9 +514,28 @@ impl Setup {
             tx,
             rx,
             connections: HashMap::default(),
+            #[cfg(any(test, feature = "test-fixture"))]
+            faults: None,
+            link: LinkConfig::default(),
         }
     }
 
+    /// Makes the transport inject faults into the requests it sends, according to `schedule`.
+    #[cfg(any(test, feature = "test-fixture"))]
+    #[must_use]
+    pub fn with_faults(self, schedule: FaultSchedule) -> Self {
+        Self {
+            faults: Some(Faults::new(schedule)),
+            ..self
+        }
+    }
+
+    /// Makes the record streams this helper sends go over links with the given properties.
+    #[must_use]
+    pub fn with_link(self, link: LinkConfig) -> Self {
+        Self { link, ..self }
+    }
+
     /// Establishes a link between this helper and another one
     ///
     /// ## Panics
@@ -351,8 +555,13 @@ impl Setup {
         self,
         callbacks: TransportCallbacks<Weak<InMemoryTransport>>,
     ) -> (ConnectionTx, Arc<InMemoryTransport>) {
-        let transport = Arc::new(InMemoryTransport::new(self.identity, self.connections));
-        transport.listen(callbacks, self.rx);
+        let (record_streams, acks) = StreamCollection::with_acks();
+        let transport = Arc::new(InMemoryTransport {
+            #[cfg(any(test, feature = "test-fixture"))]
+            faults: self.faults,
+            ..InMemoryTransport::new(self.identity, self.connections, record_streams, self.link)
+        });
+        transport.listen(callbacks, self.rx, acks);
 
         (self.tx, transport)
     }
@@ -1,26 +1,33 @@
 use crate::{
     helpers::HelperIdentity,
     protocol::{step::Gate, QueryId},
+    sync::{atomic::AtomicUsize, Arc},
 };
 use async_trait::async_trait;
 use futures::Stream;
-use std::borrow::Borrow;
+use std::{borrow::Borrow, num::ParseIntError};
 
+mod ack;
 pub mod callbacks;
+#[cfg(any(test, feature = "test-fixture"))]
+mod faulty;
 #[cfg(feature = "in-memory-infra")]
 mod in_memory;
 pub mod query;
 mod receive;
 mod stream;
 
+pub use ack::{send_acknowledgements, Acknowledgements};
+#[cfg(any(test, feature = "test-fixture"))]
+pub use faulty::{Fault, FaultSchedule, Faults, FaultyTransport};
 #[cfg(feature = "in-memory-infra")]
-pub use in_memory::{InMemoryNetwork, InMemoryTransport};
-pub use receive::{LogErrors, ReceiveRecords};
+pub use in_memory::{CommunicationRounds, InMemoryNetwork, InMemoryTransport, LinkConfig};
+pub use receive::{Interruptible, LogErrors, ReceiveRecords};
 #[cfg(feature = "web-app")]
 pub use stream::WrappedAxumBodyStream;
 pub use stream::{
-    BodyStream, BytesStream, LengthDelimitedStream, RecordsStream, StreamCollection, StreamKey,
-    WrappedBoxBodyStream,
+    BodyStream, BytesStream, LengthDelimitedStream, RecordsStream, ResumeError, StreamCollection,
+    StreamKey, WrappedBoxBodyStream,
 };
 
 pub trait ResourceIdentifier: Sized {}
@@ -39,9 +46,11 @@ pub struct NoResourceIdentifier;
 pub struct NoQueryId;
 pub struct NoStep;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum RouteId {
     Records,
+    /// Acknowledges the number of bytes received on a [`RouteId::Records`] stream.
+    RecordsAck,
     ReceiveQuery,
     PrepareQuery,
 }
@@ -121,11 +130,47 @@ impl RouteParams<RouteId, QueryId, Gate> for (RouteId, QueryId, Gate) {
     }
 }
 
+/// Route that carries a byte offset of the channel. For [`RouteId::Records`], it is the offset
+/// the stream starts at, senders use it to resume streams after the connection to the peer was
+/// lost. For [`RouteId::RecordsAck`], it is the number of bytes the receiver got.
+impl RouteParams<RouteId, QueryId, Gate> for (RouteId, QueryId, Gate, usize) {
+    type Params = String;
+
+    fn resource_identifier(&self) -> RouteId {
+        self.0
+    }
+
+    fn query_id(&self) -> QueryId {
+        self.1
+    }
+
+    fn gate(&self) -> Gate {
+        self.2.clone()
+    }
+
+    fn extra(&self) -> Self::Params {
+        self.3.to_string()
+    }
+}
+
+/// Parses the byte offset carried by [`RouteId::Records`] and [`RouteId::RecordsAck`] routes.
+/// Streams sent without an offset start at the beginning of the channel.
+///
+/// ## Errors
+/// If `params` is not a valid offset.
+pub fn records_offset(params: &str) -> Result<usize, ParseIntError> {
+    if params.is_empty() {
+        Ok(0)
+    } else {
+        params.parse()
+    }
+}
+
 /// Transport that supports per-query,per-step channels
 #[async_trait]
 pub trait Transport: Clone + Send + Sync + 'static {
     type RecordsStream: Stream<Item = Vec<u8>> + Send + Unpin;
-    type Error: std::fmt::Debug;
+    type Error: std::fmt::Debug + Send;
 
     fn identity(&self) -> HelperIdentity;
 
@@ -154,6 +199,15 @@ pub trait Transport: Clone + Send + Sync + 'static {
         route: R,
     ) -> Self::RecordsStream;
 
+    /// Returns the number of bytes of the records stream sent to `to` for the specific query and
+    /// step that the peer acknowledged. Acknowledged data does not need to be sent again if the
+    /// stream is resumed.
+    fn acknowledged<R: RouteParams<NoResourceIdentifier, QueryId, Gate>>(
+        &self,
+        to: HelperIdentity,
+        route: R,
+    ) -> Arc<AtomicUsize>;
+
     /// Alias for `Clone::clone`.
     ///
     /// `Transport` is implemented for `Weak<InMemoryTranport>` and `Arc<HttpTransport>`. Clippy won't
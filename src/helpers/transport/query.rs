@@ -2,7 +2,7 @@ use crate::{
     ff::FieldType,
     helpers::{
         transport::{BodyStream, NoQueryId, NoStep},
-        GatewayConfig, RoleAssignment, RouteId, RouteParams,
+        Compression, GatewayConfig, RoleAssignment, RouteId, RouteParams,
     },
     protocol::{step::Step, QueryId},
 };
@@ -87,6 +87,20 @@ pub struct QueryConfig {
     pub size: QuerySize,
     pub field_type: FieldType,
     pub query_type: QueryType,
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    pub step_stream: StepStreamConfig,
+}
+
+/// How the helpers send the data of a query to each other. Agreed on when the query is created,
+/// because all helpers must use the same compression. Limits on the bandwidth a helper uses are
+/// not part of it, they are set on each helper when it starts.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct StepStreamConfig {
+    /// Compression of the data sent between helpers.
+    #[cfg_attr(feature = "clap", arg(long, value_enum, default_value_t))]
+    pub compression: Compression,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -131,9 +145,9 @@ impl RouteParams<RouteId, NoQueryId, NoStep> for &QueryConfig {
 }
 
 impl From<&QueryConfig> for GatewayConfig {
-    fn from(_value: &QueryConfig) -> Self {
+    fn from(value: &QueryConfig) -> Self {
         // TODO: pick the correct value for active and test it
-        Self::default()
+        Self::default().with_compression(value.step_stream.compression)
     }
 }
 
@@ -154,6 +168,7 @@ impl QueryConfig {
             size: size.try_into()?,
             field_type,
             query_type,
+            step_stream: StepStreamConfig::default(),
         })
     }
 }
@@ -226,6 +241,7 @@ impl Step for QueryType {}
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "enable-serde", serde(default))]
 #[cfg_attr(feature = "clap", derive(clap::Args))]
 pub struct IpaQueryConfig {
     #[cfg_attr(feature = "clap", arg(long, default_value = "5"))]
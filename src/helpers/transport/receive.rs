@@ -1,13 +1,19 @@
 use crate::{
     error::BoxError,
     helpers::transport::stream::{StreamCollection, StreamKey},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 use futures::Stream;
 use futures_util::StreamExt;
 use std::{
+    future::Future,
     pin::Pin,
     task::{Context, Poll},
 };
+use tokio::time::Sleep;
 use tracing::error;
 
 /// Adapt a stream of `Result<T: Into<Vec<u8>>, Error>` to a stream of `Vec<u8>`.
@@ -20,6 +26,7 @@ where
     E: Into<BoxError>,
 {
     inner: S,
+    interrupted: bool,
 }
 
 impl<S, T, E> LogErrors<S, T, E>
@@ -29,7 +36,10 @@ where
     E: Into<BoxError>,
 {
     pub fn new(inner: S) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            interrupted: false,
+        }
     }
 }
 
@@ -42,7 +52,8 @@ where
     type Item = Vec<u8>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match Pin::get_mut(self).inner.poll_next_unpin(cx) {
+        let this = Pin::get_mut(self);
+        match this.inner.poll_next_unpin(cx) {
             Poll::Pending => Poll::Pending,
             Poll::Ready(Some(Ok(chunk))) => Poll::Ready(Some(chunk.into())),
             Poll::Ready(Some(Err(err))) => {
@@ -50,9 +61,11 @@ where
                 // by the helper party operators. It will not be informative for a report
                 // collector.
                 //
-                // Note that returning `Poll::Ready(None)` here will be turned back into
-                // an `EndOfStream` error by `UnorderedReceiver`.
+                // Note that returning `Poll::Ready(None)` here will either be turned back into
+                // an `EndOfStream` error by `UnorderedReceiver`, or [`ReceiveRecords`] will
+                // continue reading once the sender reconnects.
                 error!("error reading records: {}", err.into());
+                this.interrupted = true;
                 Poll::Ready(None)
             }
             Poll::Ready(None) => Poll::Ready(None),
@@ -60,6 +73,23 @@ where
     }
 }
 
+impl<S, T, E> Interruptible for LogErrors<S, T, E>
+where
+    S: Stream<Item = Result<T, E>> + Unpin,
+    T: Into<Vec<u8>>,
+    E: Into<BoxError>,
+{
+    fn interrupted(&self) -> bool {
+        self.interrupted
+    }
+}
+
+/// Streams of records that can tell a clean end of the stream apart from a lost connection.
+pub trait Interruptible {
+    /// Returns `true` if the stream ended because the connection it was read from failed.
+    fn interrupted(&self) -> bool;
+}
+
 /// Represents a stream of records.
 /// If stream is not received yet, each poll generates a waker that is used internally to wake up
 /// the task when stream is received.
@@ -76,7 +106,10 @@ impl<S> ReceiveRecords<S> {
     }
 }
 
-impl<S: Stream + Unpin> Stream for ReceiveRecords<S> {
+impl<S> Stream for ReceiveRecords<S>
+where
+    S: Stream<Item = Vec<u8>> + Interruptible + Unpin,
+{
     type Item = S::Item;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
@@ -87,10 +120,13 @@ impl<S: Stream + Unpin> Stream for ReceiveRecords<S> {
 /// Inner state for [`ReceiveRecords`] struct
 enum ReceiveRecordsInner<S> {
     Pending(StreamKey, StreamCollection<S>),
-    Ready(S),
+    Ready(ResumableStream<S>),
 }
 
-impl<S: Stream + Unpin> Stream for ReceiveRecordsInner<S> {
+impl<S> Stream for ReceiveRecordsInner<S>
+where
+    S: Stream<Item = Vec<u8>> + Interruptible + Unpin,
+{
     type Item = S::Item;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
@@ -98,8 +134,17 @@ impl<S: Stream + Unpin> Stream for ReceiveRecordsInner<S> {
         loop {
             match this {
                 Self::Pending(key, streams) => {
-                    if let Some(stream) = streams.add_waker(key, cx.waker()) {
-                        *this = Self::Ready(stream);
+                    if let Some((stream, received)) = streams.add_waker(key, cx.waker()) {
+                        *this = Self::Ready(ResumableStream {
+                            key: key.clone(),
+                            streams: streams.clone(),
+                            stream,
+                            received,
+                            skip: 0,
+                            interrupted: false,
+                            deadline: None,
+                            abandoned: false,
+                        });
                     } else {
                         return Poll::Pending;
                     }
@@ -109,3 +154,153 @@ impl<S: Stream + Unpin> Stream for ReceiveRecordsInner<S> {
         }
     }
 }
+
+/// Stream received from another helper. If the connection it was read from fails, it waits
+/// for the sender to reconnect and continues from the byte it stopped at. If the sender does not
+/// reconnect in time, the stream ends, so the channel fails instead of waiting forever.
+///
+/// The number of bytes received is reported to the [`StreamCollection`], so it can be
+/// acknowledged to the sender.
+struct ResumableStream<S> {
+    key: StreamKey,
+    streams: StreamCollection<S>,
+    stream: S,
+    /// Number of bytes received so far, shared with [`StreamCollection`] to validate offsets of
+    /// resumed streams.
+    received: Arc<AtomicUsize>,
+    /// Number of bytes at the beginning of the current stream that were received already.
+    skip: usize,
+    interrupted: bool,
+    /// Set while the stream is interrupted, the reader gives up once it expires.
+    deadline: Option<Pin<Box<Sleep>>>,
+    abandoned: bool,
+}
+
+impl<S> Stream for ResumableStream<S>
+where
+    S: Stream<Item = Vec<u8>> + Interruptible + Unpin,
+{
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = Pin::get_mut(self);
+        if this.abandoned {
+            return Poll::Ready(None);
+        }
+        loop {
+            if this.interrupted {
+                let Some((offset, stream)) = this.streams.take_resumed(&this.key, cx.waker())
+                else {
+                    let timeout = this.streams.resume_timeout();
+                    let deadline = this
+                        .deadline
+                        .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+                    if deadline.as_mut().poll(cx).is_ready() {
+                        error!("{:?} stream was not resumed within {timeout:?}", this.key);
+                        this.streams.abandon(&this.key);
+                        this.abandoned = true;
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Pending;
+                };
+                // `StreamCollection` does not accept streams that start past what was received.
+                this.skip = this.received.load(Ordering::Acquire) - offset;
+                this.stream = stream;
+                this.interrupted = false;
+                this.deadline = None;
+            }
+
+            match this.stream.poll_next_unpin(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(mut chunk)) => {
+                    if this.skip > 0 {
+                        let n = std::cmp::min(this.skip, chunk.len());
+                        this.skip -= n;
+                        chunk.drain(..n);
+                        if chunk.is_empty() {
+                            continue;
+                        }
+                    }
+                    let received =
+                        this.received.fetch_add(chunk.len(), Ordering::Release) + chunk.len();
+                    this.streams.acknowledge(&this.key, received);
+                    return Poll::Ready(Some(chunk));
+                }
+                Poll::Ready(None) if this.stream.interrupted() => {
+                    tracing::warn!(
+                        "{:?} stream interrupted, waiting for the sender to resume",
+                        this.key
+                    );
+                    this.interrupted = true;
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::{
+        helpers::{transport::stream::ResumeError, HelperIdentity},
+        protocol::{step::Gate, QueryId},
+    };
+    use futures_util::stream::poll_immediate;
+    use std::time::Duration;
+    use tokio::sync::mpsc::{channel, Sender};
+    use tokio_stream::wrappers::ReceiverStream;
+
+    type TestStream = LogErrors<ReceiverStream<Result<Vec<u8>, BoxError>>, Vec<u8>, BoxError>;
+
+    fn key() -> StreamKey {
+        (QueryId, HelperIdentity::TWO, Gate::from("receive"))
+    }
+
+    fn test_stream() -> (Sender<Result<Vec<u8>, BoxError>>, TestStream) {
+        let (tx, rx) = channel(1);
+        (tx, LogErrors::new(ReceiverStream::new(rx)))
+    }
+
+    #[tokio::test]
+    async fn acknowledges_received_data() {
+        let (streams, mut acks) = StreamCollection::with_acks();
+        let (tx, stream) = test_stream();
+        streams.add_stream(key(), 0, stream).unwrap();
+        let mut recv = ReceiveRecords::new(key(), streams);
+
+        tx.send(Ok(vec![0, 1, 2])).await.unwrap();
+        assert_eq!(Some(vec![0, 1, 2]), recv.next().await);
+        tx.send(Ok(vec![3, 4])).await.unwrap();
+        assert_eq!(Some(vec![3, 4]), recv.next().await);
+
+        assert_eq!(Some((key(), 3)), acks.recv().await);
+        assert_eq!(Some((key(), 5)), acks.recv().await);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_streams_not_resumed() {
+        let streams = StreamCollection::default().with_resume_timeout(Duration::from_millis(10));
+        let (tx, stream) = test_stream();
+        streams.add_stream(key(), 0, stream).unwrap();
+        let mut recv = ReceiveRecords::new(key(), streams.clone());
+
+        tx.send(Ok(vec![0, 1, 2])).await.unwrap();
+        assert_eq!(Some(vec![0, 1, 2]), recv.next().await);
+
+        // the connection fails and the sender never comes back.
+        tx.send(Err("connection reset".into())).await.unwrap();
+        assert!(matches!(
+            poll_immediate(&mut recv).next().await,
+            Some(Poll::Pending)
+        ));
+        assert_eq!(None, recv.next().await);
+
+        // it is too late to resume the stream now.
+        let (_tx, stream) = test_stream();
+        assert!(matches!(
+            streams.add_stream(key(), 3, stream),
+            Err(ResumeError::Abandoned { .. })
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::error::BoxError;
+use crate::{error::BoxError, helpers::transport::stream::BoxBytesStream};
 
 use axum::extract::{BodyStream, FromRequest, RequestParts};
 use futures::{Stream, TryStreamExt};
@@ -63,6 +63,12 @@ impl WrappedAxumBodyStream {
             .unwrap(),
         )
     }
+
+    /// Wrap a stream of bytes that does not come from an HTTP request.
+    #[must_use]
+    pub fn from_bytes_stream(inner: BoxBytesStream) -> Self {
+        Self::from_body(Body::wrap_stream(inner))
+    }
 }
 
 #[cfg(feature = "real-world-infra")]
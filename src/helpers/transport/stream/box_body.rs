@@ -15,6 +15,12 @@ impl WrappedBoxBodyStream {
     pub fn new(inner: axum::extract::BodyStream) -> Self {
         Self(Box::pin(super::WrappedAxumBodyStream::new_internal(inner)))
     }
+
+    /// Wrap a stream of bytes that does not come from an HTTP request.
+    #[must_use]
+    pub fn from_bytes_stream(inner: BoxBytesStream) -> Self {
+        Self(inner)
+    }
 }
 
 impl Stream for WrappedBoxBodyStream {
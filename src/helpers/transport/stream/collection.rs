@@ -1,14 +1,19 @@
 use crate::{
     helpers::HelperIdentity,
     protocol::{step::Gate, QueryId},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 use futures::Stream;
 use std::{
     collections::{hash_map::Entry, HashMap},
     fmt::{Debug, Formatter},
     task::Waker,
+    time::Duration,
 };
+use tokio::sync::mpsc;
 
 /// Each stream is indexed by query id, the identity of helper where stream is originated from
 /// and step.
@@ -18,16 +23,25 @@ pub type StreamKey = (QueryId, HelperIdentity, Gate);
 /// Streams are indexed by [`StreamKey`] and the lifecycle of each stream is described by the
 /// [`StreamState`] struct.
 ///
-/// Each stream can be inserted and taken away exactly once, any deviation from this behaviour will
-/// result in panic.
+/// Each stream can be taken away exactly once, any deviation from this behaviour will result in
+/// panic. Streams may be inserted again by senders that reconnect, see [`add_stream`].
+///
+/// [`add_stream`]: Self::add_stream
 pub struct StreamCollection<S> {
     inner: Arc<Mutex<HashMap<StreamKey, StreamState<S>>>>,
+    /// Receives the number of bytes read from each stream, so they can be acknowledged to the
+    /// sender.
+    acks: Option<mpsc::UnboundedSender<(StreamKey, usize)>>,
+    /// How long readers of interrupted streams wait for the sender to resume them.
+    resume_timeout: Duration,
 }
 
 impl<S> Default for StreamCollection<S> {
     fn default() -> Self {
         Self {
             inner: Arc::new(Mutex::new(HashMap::default())),
+            acks: None,
+            resume_timeout: Self::DEFAULT_RESUME_TIMEOUT,
         }
     }
 }
@@ -36,68 +50,155 @@ impl<S> Clone for StreamCollection<S> {
     fn clone(&self) -> Self {
         Self {
             inner: Arc::clone(&self.inner),
+            acks: self.acks.clone(),
+            resume_timeout: self.resume_timeout,
+        }
+    }
+}
+
+impl<S> StreamCollection<S> {
+    pub const DEFAULT_RESUME_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Creates a collection that reports the number of bytes read from its streams to the
+    /// returned receiver. Transports acknowledge them to the senders, see
+    /// [`send_acknowledgements`].
+    ///
+    /// [`send_acknowledgements`]: crate::helpers::send_acknowledgements
+    #[must_use]
+    pub fn with_acks() -> (Self, mpsc::UnboundedReceiver<(StreamKey, usize)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (
+            Self {
+                acks: Some(tx),
+                ..Self::default()
+            },
+            rx,
+        )
+    }
+
+    /// Sets how long readers of interrupted streams wait for the sender to resume them. Once
+    /// it expires, the stream ends and the channel fails.
+    #[must_use]
+    pub fn with_resume_timeout(self, resume_timeout: Duration) -> Self {
+        Self {
+            resume_timeout,
+            ..self
+        }
+    }
+
+    pub(in crate::helpers::transport) fn resume_timeout(&self) -> Duration {
+        self.resume_timeout
+    }
+
+    /// Reports that the first `received` bytes of the stream were read.
+    pub(in crate::helpers::transport) fn acknowledge(&self, key: &StreamKey, received: usize) {
+        if let Some(acks) = &self.acks {
+            // the transport may be shutting down, nobody needs acknowledgements then.
+            let _ = acks.send((key.clone(), received));
         }
     }
 }
 
 impl<S: Stream> StreamCollection<S> {
-    /// Adds a new stream associated with the given key.
+    /// Adds a new stream associated with the given key. The stream starts at byte `offset` of the
+    /// data sent on this channel.
+    ///
+    /// A stream that starts at a non-zero offset, or that arrives after an earlier stream with
+    /// the same key was taken away, resumes that earlier stream: its reader skips the bytes it
+    /// has seen already and continues reading from the new stream once the old one is
+    /// interrupted.
+    ///
+    /// ## Errors
+    /// If `offset` is past the number of bytes the reader has received, because the gap can
+    /// never be filled, or if the reader gave up waiting for the stream to be resumed.
     ///
     /// ## Panics
-    /// If there was another stream associated with the same key some time in the past.
-    pub fn add_stream(&self, key: StreamKey, stream: S) {
+    /// If mutex is poisoned.
+    pub fn add_stream(&self, key: StreamKey, offset: usize, stream: S) -> Result<(), ResumeError> {
         let mut streams = self.inner.lock().unwrap();
         match streams.entry(key) {
-            Entry::Occupied(mut entry) => match entry.get_mut() {
-                rs @ StreamState::Waiting(_) => {
-                    let StreamState::Waiting(waker) = std::mem::replace(rs, StreamState::Ready(stream)) else {
-                        unreachable!()
-                    };
-                    waker.wake();
+            Entry::Occupied(mut entry) => {
+                if matches!(entry.get(), StreamState::Abandoned) {
+                    return Err(ResumeError::Abandoned {
+                        key: entry.key().clone(),
+                    });
                 }
-                rs @ (StreamState::Ready(_) | StreamState::Completed) => {
-                    let state = format!("{rs:?}");
-                    let key = entry.key().clone();
-                    drop(streams);
-                    panic!("{key:?} entry state expected to be waiting, got {state:?}");
+                let received = entry.get().received();
+                if offset > received {
+                    return Err(ResumeError::Gap {
+                        key: entry.key().clone(),
+                        offset,
+                        received,
+                    });
                 }
-            },
+                let state = entry.get_mut();
+                let next = match state {
+                    StreamState::Abandoned => unreachable!(),
+                    // the reader has not seen any data yet, the new stream replaces the old one.
+                    StreamState::Waiting(_) | StreamState::Ready(_) => StreamState::Ready(stream),
+                    StreamState::Completed(received)
+                    | StreamState::Interrupted(received, _)
+                    | StreamState::Resumed(received, ..) => {
+                        StreamState::Resumed(Arc::clone(received), offset, stream)
+                    }
+                };
+                if let StreamState::Waiting(waker) | StreamState::Interrupted(_, waker) =
+                    std::mem::replace(state, next)
+                {
+                    waker.wake();
+                }
+            }
             Entry::Vacant(entry) => {
+                if offset > 0 {
+                    return Err(ResumeError::Gap {
+                        key: entry.into_key(),
+                        offset,
+                        received: 0,
+                    });
+                }
                 entry.insert(StreamState::Ready(stream));
             }
         }
+
+        Ok(())
     }
 
     /// Adds a new waker to notify when the stream is ready. If stream is ready, this method takes
-    /// it out, leaving a tombstone in its place, and returns it.
+    /// it out, leaving a tombstone in its place, and returns it along with the counter the reader
+    /// must update with the number of bytes it has received.
+    ///
+    /// The reader may be polled with a different waker every time, so the waker given last replaces
+    /// the one registered before.
     ///
     /// ## Panics
-    /// If [`Waker`] that exists already inside this collection will not wake the given one.
-    pub fn add_waker(&self, key: &StreamKey, waker: &Waker) -> Option<S> {
+    /// If the stream has been consumed already.
+    pub fn add_waker(&self, key: &StreamKey, waker: &Waker) -> Option<(S, Arc<AtomicUsize>)> {
         let mut streams = self.inner.lock().unwrap();
 
         match streams.entry(key.clone()) {
-            Entry::Occupied(mut entry) => {
-                match entry.get_mut() {
-                    StreamState::Waiting(old_waker) => {
-                        let will_wake = old_waker.will_wake(waker);
-                        drop(streams); // avoid mutex poisoning
-                        assert!(will_wake);
-                        None
-                    }
-                    rs @ StreamState::Ready(_) => {
-                        let StreamState::Ready(stream) = std::mem::replace(rs, StreamState::Completed) else {
-                            unreachable!();
-                        };
+            Entry::Occupied(mut entry) => match entry.get_mut() {
+                StreamState::Waiting(old_waker) => {
+                    old_waker.clone_from(waker);
+                    None
+                }
+                rs @ StreamState::Ready(_) => {
+                    let received = Arc::new(AtomicUsize::new(0));
+                    let StreamState::Ready(stream) =
+                        std::mem::replace(rs, StreamState::Completed(Arc::clone(&received)))
+                    else {
+                        unreachable!();
+                    };
 
-                        Some(stream)
-                    }
-                    StreamState::Completed => {
-                        drop(streams);
-                        panic!("{key:?} stream has been consumed already")
-                    }
+                    Some((stream, received))
                 }
-            }
+                StreamState::Completed(_)
+                | StreamState::Interrupted(..)
+                | StreamState::Resumed(..)
+                | StreamState::Abandoned => {
+                    drop(streams);
+                    panic!("{key:?} stream has been consumed already")
+                }
+            },
             Entry::Vacant(entry) => {
                 entry.insert(StreamState::Waiting(waker.clone()));
                 None
@@ -105,6 +206,53 @@ impl<S: Stream> StreamCollection<S> {
         }
     }
 
+    /// Called by the reader of a stream that was interrupted. Returns the stream the sender
+    /// reconnected with and the offset it starts at, or registers the waker to be notified
+    /// when that happens.
+    ///
+    /// ## Panics
+    /// If the stream has not been taken by a reader yet.
+    pub fn take_resumed(&self, key: &StreamKey, waker: &Waker) -> Option<(usize, S)> {
+        let mut streams = self.inner.lock().unwrap();
+        let Some(state) = streams.get_mut(key) else {
+            drop(streams);
+            panic!("{key:?} stream was cleared while being read");
+        };
+
+        match state {
+            StreamState::Completed(received) | StreamState::Interrupted(received, _) => {
+                *state = StreamState::Interrupted(Arc::clone(received), waker.clone());
+                None
+            }
+            StreamState::Resumed(received, ..) => {
+                let received = Arc::clone(received);
+                let StreamState::Resumed(_, offset, stream) =
+                    std::mem::replace(state, StreamState::Completed(received))
+                else {
+                    unreachable!();
+                };
+                Some((offset, stream))
+            }
+            StreamState::Waiting(_) | StreamState::Ready(_) | StreamState::Abandoned => {
+                let state = format!("{state:?}");
+                drop(streams);
+                panic!("{key:?} stream is not being read, its state is {state}");
+            }
+        }
+    }
+
+    /// Called by the reader of an interrupted stream that stopped waiting for the sender to
+    /// resume it. Senders that reconnect afterwards are rejected.
+    ///
+    /// ## Panics
+    /// If mutex is poisoned.
+    pub fn abandon(&self, key: &StreamKey) {
+        let mut streams = self.inner.lock().unwrap();
+        if let Some(state) = streams.get_mut(key) {
+            *state = StreamState::Abandoned;
+        }
+    }
+
     /// Clears up this collection, leaving no streams inside it.
     ///
     /// ## Panics
@@ -115,6 +263,19 @@ impl<S: Stream> StreamCollection<S> {
     }
 }
 
+/// Returned when a sender attempts to resume a stream that cannot be resumed.
+#[derive(Debug, thiserror::Error)]
+pub enum ResumeError {
+    #[error("{key:?} cannot be resumed at byte {offset}, only {received} bytes were received")]
+    Gap {
+        key: StreamKey,
+        offset: usize,
+        received: usize,
+    },
+    #[error("{key:?} reader stopped waiting for the stream to be resumed")]
+    Abandoned { key: StreamKey },
+}
+
 /// Describes the lifecycle of records stream inside [`StreamCollection`]
 enum StreamState<S> {
     /// There was a request to receive this stream, but it hasn't arrived yet
@@ -122,8 +283,26 @@ enum StreamState<S> {
     /// Stream is ready to be consumed
     Ready(S),
     /// Stream was successfully received and taken away from [`StreamCollection`].
-    /// It may not be requested or received again.
-    Completed,
+    /// It may not be requested again, but the sender may resume it. The reader keeps the
+    /// counter of bytes it has received up to date.
+    Completed(Arc<AtomicUsize>),
+    /// The reader lost its stream before the end and waits for the sender to resume it.
+    Interrupted(Arc<AtomicUsize>, Waker),
+    /// The sender reconnected with a stream that starts at the given byte offset.
+    Resumed(Arc<AtomicUsize>, usize, S),
+    /// The reader of an interrupted stream gave up waiting for it to be resumed.
+    Abandoned,
+}
+
+impl<S> StreamState<S> {
+    fn received(&self) -> usize {
+        match self {
+            Self::Waiting(_) | Self::Ready(_) | Self::Abandoned => 0,
+            Self::Completed(received)
+            | Self::Interrupted(received, _)
+            | Self::Resumed(received, ..) => received.load(Ordering::Acquire),
+        }
+    }
 }
 
 impl<S> Debug for StreamState<S> {
@@ -135,9 +314,18 @@ impl<S> Debug for StreamState<S> {
             StreamState::Ready(_) => {
                 write!(f, "Ready")
             }
-            StreamState::Completed => {
+            StreamState::Completed(_) => {
                 write!(f, "Completed")
             }
+            StreamState::Interrupted(..) => {
+                write!(f, "Interrupted")
+            }
+            StreamState::Resumed(_, offset, _) => {
+                write!(f, "Resumed({offset})")
+            }
+            StreamState::Abandoned => {
+                write!(f, "Abandoned")
+            }
         }
     }
 }
@@ -7,7 +7,7 @@ mod input;
 #[cfg(feature = "web-app")]
 pub use axum_body::WrappedAxumBodyStream;
 pub use box_body::WrappedBoxBodyStream;
-pub use collection::{StreamCollection, StreamKey};
+pub use collection::{ResumeError, StreamCollection, StreamKey};
 pub use input::{LengthDelimitedStream, RecordsStream};
 
 use crate::error::BoxError;
@@ -41,7 +41,7 @@ extern crate shuttle_crate as shuttle;
 
 #[cfg(all(feature = "shuttle", test))]
 pub(crate) mod sync {
-    pub use shuttle::sync::{Arc, Mutex, MutexGuard, Once, Weak};
+    pub use shuttle::sync::{Arc, Mutex, MutexGuard, Once, RwLock, Weak};
     pub mod atomic {
         pub use shuttle::sync::atomic::{AtomicUsize, Ordering};
     }
@@ -49,7 +49,7 @@ pub(crate) mod sync {
 
 #[cfg(not(all(feature = "shuttle", test)))]
 pub(crate) mod sync {
-    pub use std::sync::{Arc, Mutex, MutexGuard, Once, Weak};
+    pub use std::sync::{Arc, Mutex, MutexGuard, Once, RwLock, Weak};
     pub mod atomic {
         pub use std::sync::atomic::{AtomicUsize, Ordering};
     }
@@ -79,6 +79,42 @@ pub(crate) mod task {
     pub use tokio::task::{JoinError, JoinHandle};
 }
 
+/// Shuttle does not simulate time. Sleeping only lets other tasks run, and a timeout expires
+/// as soon as the other tasks got a chance to make the future ready.
+#[cfg(all(feature = "shuttle", test))]
+pub(crate) mod time {
+    use futures::future::{select, Either};
+    use std::{future::Future, time::Duration};
+
+    pub use std::time::Instant;
+
+    #[derive(Debug)]
+    pub struct Elapsed;
+
+    pub async fn sleep(_duration: Duration) {
+        shuttle::future::yield_now().await;
+    }
+
+    pub async fn sleep_until(_deadline: Instant) {
+        shuttle::future::yield_now().await;
+    }
+
+    pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+        futures::pin_mut!(future);
+        match select(future, Box::pin(sleep(duration))).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(Elapsed),
+        }
+    }
+}
+
+#[cfg(not(all(feature = "shuttle", test)))]
+pub(crate) mod time {
+    // only the test fixture and the in-memory links use the clock
+    #[allow(unused_imports)]
+    pub use tokio::time::{sleep, sleep_until, timeout, Instant};
+}
+
 #[cfg(all(feature = "shuttle", test))]
 pub(crate) mod test_executor {
     use std::future::Future;
@@ -0,0 +1,171 @@
+//! Bearer tokens that report collectors can authenticate with, instead of a client certificate.
+//!
+//! A token has the form `<collector name>.<expiry>.<signature>`. The expiry is in seconds since the
+//! Unix epoch, and the signature is the hex-encoded HMAC-SHA256 of the part before it, keyed with
+//! the [`TokenKey`] the report collector shares with the helpers.
+
+use crate::config::{ReportCollectorConfig, TokenKey};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum BearerTokenError {
+    #[error("malformed bearer token")]
+    Malformed,
+    #[error("bearer token of unknown report collector {0}")]
+    UnknownCollector(String),
+    #[error("invalid bearer token signature")]
+    InvalidSignature,
+    #[error("bearer token expired")]
+    Expired,
+}
+
+/// Creates a bearer token for the report collector `name`, which is valid until `expires`.
+#[must_use]
+pub fn sign_bearer_token(name: &str, key: &TokenKey, expires: SystemTime) -> String {
+    let expires = expires
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    let claims = format!("{name}.{expires}");
+    let signature = hex::encode(mac(key, &claims).finalize().into_bytes());
+    format!("{claims}.{signature}")
+}
+
+/// Returns the report collector in `collectors` that signed `token`.
+///
+/// ## Errors
+/// If the token is malformed, not signed by the report collector it names, or expired at `now`.
+pub(super) fn verify_bearer_token<'a>(
+    token: &str,
+    collectors: &'a [ReportCollectorConfig],
+    now: SystemTime,
+) -> Result<&'a ReportCollectorConfig, BearerTokenError> {
+    let (claims, signature) = token.rsplit_once('.').ok_or(BearerTokenError::Malformed)?;
+    let (name, expires) = claims.rsplit_once('.').ok_or(BearerTokenError::Malformed)?;
+    let expires = expires
+        .parse::<u64>()
+        .map_err(|_| BearerTokenError::Malformed)?;
+    let signature = hex::decode(signature).map_err(|_| BearerTokenError::Malformed)?;
+
+    let (collector, key) = collectors
+        .iter()
+        .find_map(|collector| {
+            (collector.name == name)
+                .then_some(collector.token_key.as_ref())
+                .flatten()
+                .map(|key| (collector, key))
+        })
+        .ok_or_else(|| BearerTokenError::UnknownCollector(name.to_owned()))?;
+    mac(key, claims)
+        .verify_slice(&signature)
+        .map_err(|_| BearerTokenError::InvalidSignature)?;
+    if UNIX_EPOCH + Duration::from_secs(expires) <= now {
+        return Err(BearerTokenError::Expired);
+    }
+
+    Ok(collector)
+}
+
+fn mac(key: &TokenKey, claims: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(claims.as_bytes());
+    mac
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn collectors() -> [ReportCollectorConfig; 2] {
+        [
+            ReportCollectorConfig {
+                name: String::from("collector.1"),
+                certificate: None,
+                token_key: Some(TokenKey::new(vec![1; 32])),
+            },
+            ReportCollectorConfig {
+                name: String::from("collector.2"),
+                certificate: None,
+                token_key: Some(TokenKey::new(vec![2; 32])),
+            },
+        ]
+    }
+
+    fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    }
+
+    #[test]
+    fn verifies_signed_token() {
+        let collectors = collectors();
+        for collector in &collectors {
+            let token = sign_bearer_token(
+                &collector.name,
+                collector.token_key.as_ref().unwrap(),
+                now() + HOUR,
+            );
+            let verified = verify_bearer_token(&token, &collectors, now()).unwrap();
+            assert_eq!(collector.name, verified.name);
+        }
+    }
+
+    #[test]
+    fn rejects_expired_token() {
+        let collectors = collectors();
+        let token = sign_bearer_token(
+            "collector.1",
+            collectors[0].token_key.as_ref().unwrap(),
+            now(),
+        );
+        assert_eq!(
+            Err(BearerTokenError::Expired),
+            verify_bearer_token(&token, &collectors, now()).map(|c| &c.name)
+        );
+    }
+
+    #[test]
+    fn rejects_token_signed_with_other_key() {
+        let collectors = collectors();
+        let token = sign_bearer_token(
+            "collector.1",
+            collectors[1].token_key.as_ref().unwrap(),
+            now() + HOUR,
+        );
+        assert_eq!(
+            Err(BearerTokenError::InvalidSignature),
+            verify_bearer_token(&token, &collectors, now()).map(|c| &c.name)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_collector() {
+        let token = sign_bearer_token("collector.3", &TokenKey::new(vec![3; 32]), now() + HOUR);
+        assert_eq!(
+            Err(BearerTokenError::UnknownCollector(String::from(
+                "collector.3"
+            ))),
+            verify_bearer_token(&token, &collectors(), now()).map(|c| &c.name)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_token() {
+        for token in [
+            "",
+            "collector.1",
+            "collector.1.soon.00",
+            "collector.1.0.xyz",
+        ] {
+            assert_eq!(
+                Err(BearerTokenError::Malformed),
+                verify_bearer_token(token, &collectors(), now()).map(|c| &c.name)
+            );
+        }
+    }
+}
@@ -1,18 +1,19 @@
 use crate::{
-    config::{ClientConfig, HyperClientConfigurator, NetworkConfig, PeerConfig},
+    config::{ClientConfig, HyperClientConfigurator, NetworkConfig, PeerConfig, StepTransport},
     helpers::{
         query::{PrepareQuery, QueryConfig, QueryInput},
         HelperIdentity,
     },
-    net::{http_serde, server::HTTP_CLIENT_ID_HEADER, Error},
+    net::{http_serde, multiplex::StepClient, server::HTTP_CLIENT_ID_HEADER, Error},
     protocol::{step::Gate, QueryId},
+    sync::{Arc, RwLock},
 };
 use axum::http::uri::{self, Parts, Scheme};
 use futures::{Stream, StreamExt};
 use hyper::{
     body,
     client::{HttpConnector, ResponseFuture},
-    header::HeaderName,
+    header::{HeaderName, AUTHORIZATION},
     http::HeaderValue,
     Body, Client, Request, Response, StatusCode, Uri,
 };
@@ -78,12 +79,20 @@ impl ClientIdentity {
 ///       separated from prepare/step data etc.
 /// TODO: It probably isn't necessary to always use `[MpcHelperClient; 3]`. Instead, a single
 ///       client can be configured to talk to all three helpers.
+/// The peer a client talks to, and the connection pool it uses for that.
 #[derive(Debug, Clone)]
-pub struct MpcHelperClient {
+struct Endpoint {
     client: Client<HttpsConnector<HttpConnector>, Body>,
     scheme: uri::Scheme,
     authority: uri::Authority,
+}
+
+#[derive(Debug, Clone)]
+pub struct MpcHelperClient {
+    // Shared by all clones of this client, so that reloading the configuration affects all of them.
+    endpoint: Arc<RwLock<Endpoint>>,
     auth_header: Option<(HeaderName, HeaderValue)>,
+    step_client: Option<StepClient>,
 }
 
 impl MpcHelperClient {
@@ -94,17 +103,24 @@ impl MpcHelperClient {
     ///
     /// `identity` configures whether and how the client will authenticate to the server. It is for
     /// the helper making the calls, so the same one is used for all three of the clients.
-    /// Authentication is not required when calling the report collector APIs.
-    #[must_use]
+    /// Report collectors authenticate to the query APIs with a certificate identity, or with a
+    /// bearer token (see [`Self::with_bearer_token`]), if the helpers require it.
+    ///
+    /// # Errors
+    /// If a client can't be created for one of the peers, see [`Self::new`].
     #[allow(clippy::missing_panics_doc)]
-    pub fn from_conf(conf: &NetworkConfig, identity: ClientIdentity) -> [MpcHelperClient; 3] {
-        conf.peers()
+    pub fn from_conf(
+        conf: &NetworkConfig,
+        identity: ClientIdentity,
+    ) -> Result<[MpcHelperClient; 3], Error> {
+        Ok(conf
+            .peers()
             .iter()
             .zip(repeat(identity))
             .map(|(peer_conf, identity)| Self::new(&conf.client, peer_conf.clone(), identity))
-            .collect::<Vec<_>>()
+            .collect::<Result<Vec<_>, _>>()?
             .try_into()
-            .unwrap()
+            .unwrap())
     }
 
     /// Create a new client with the given configuration
@@ -112,17 +128,23 @@ impl MpcHelperClient {
     /// `identity`, if present, configures whether and how the client will authenticate to the server
     /// (e.g. an X.509 certificate).
     ///
+    /// # Errors
+    /// If the peer URL has no scheme or host, the peer lacks a stream port required by
+    /// `client_config`, or the TLS configuration is not valid.
+    ///
     /// # Panics
-    /// If some aspect of the configuration is not valid.
-    #[must_use]
+    /// If a helper identity can't be sent in an HTTP header.
     pub fn new(
         client_config: &ClientConfig,
         peer_config: PeerConfig,
         identity: ClientIdentity,
-    ) -> Self {
+    ) -> Result<Self, Error> {
+        let step_client = (client_config.step_transport == StepTransport::Stream)
+            .then(|| StepClient::new(&peer_config, identity.clone()))
+            .transpose()?;
         let (connector, auth_header) = if peer_config.url.scheme() == Some(&Scheme::HTTP) {
             // This connector works for both http and https. A regular HttpConnector would suffice,
-            // but would make the type of `self.client` variable.
+            // but would make the type of `Endpoint::client` variable.
             let auth_header = match identity {
                 ClientIdentity::Certificate(_) => {
                     error!("certificate identity ignored for HTTP client");
@@ -143,28 +165,7 @@ impl MpcHelperClient {
                 auth_header,
             )
         } else {
-            let builder = rustls::ClientConfig::builder().with_safe_defaults();
-            let client_config = if let Some(certificate) = peer_config.certificate {
-                let cert_store = {
-                    let mut store = RootCertStore::empty();
-                    store.add(&certificate).unwrap();
-                    store
-                };
-
-                let builder = builder.with_root_certificates(cert_store);
-                match identity {
-                    ClientIdentity::Certificate((cert_chain, pk)) => builder
-                        .with_client_auth_cert(cert_chain, pk)
-                        .expect("Can setup client authentication with certificate"),
-                    ClientIdentity::Helper(_) => {
-                        error!("header-passed identity ignored for HTTPS client");
-                        builder.with_no_client_auth()
-                    }
-                    ClientIdentity::None => builder.with_no_client_auth(),
-                }
-            } else {
-                builder.with_native_roots().with_no_client_auth()
-            };
+            let client_config = rustls_client_config(&peer_config, identity)?;
             // `enforce_http` must be false to request HTTPS URLs. This is done automatically by
             // `HttpsConnector::new()`, but not by `HttpsConnector::from()`.
             let mut http = make_http_connector();
@@ -178,37 +179,92 @@ impl MpcHelperClient {
                 None,
             )
         };
-        Self::new_internal(peer_config.url, connector, auth_header, client_config)
+        Ok(Self {
+            step_client,
+            ..Self::new_internal(peer_config.url, connector, auth_header, client_config)?
+        })
     }
 
-    #[must_use]
     fn new_internal<C: HyperClientConfigurator>(
         addr: Uri,
         connector: HttpsConnector<HttpConnector>,
         auth_header: Option<(HeaderName, HeaderValue)>,
         conf: &C,
-    ) -> Self {
-        let client = conf.configure(&mut Client::builder()).build(connector);
+    ) -> Result<Self, Error> {
         let Parts {
             scheme: Some(scheme),
             authority: Some(authority),
             ..
-        } = addr.into_parts() else {
-            panic!("peer URL must have a scheme and authority");
+        } = addr.clone().into_parts()
+        else {
+            return Err(crate::config::Error::InvalidPeer {
+                url: addr,
+                reason: "URL must have a scheme and authority".into(),
+            }
+            .into());
         };
-        Self {
-            client,
-            scheme,
-            authority,
+        let client = conf.configure(&mut Client::builder()).build(connector);
+        Ok(Self {
+            endpoint: Arc::new(RwLock::new(Endpoint {
+                client,
+                scheme,
+                authority,
+            })),
             auth_header,
+            step_client: None,
+        })
+    }
+
+    /// Makes this client and all its clones use the configuration of `reloaded`, for example
+    /// after the certificates were renewed or the peer moved to another URL.
+    ///
+    /// Requests made from now on go to the peer URL of `reloaded`, and new connections use its
+    /// TLS configuration. Connections that are already established are kept.
+    ///
+    /// # Panics
+    /// If the lock on the endpoint of either client is poisoned.
+    pub fn reload(&self, reloaded: &MpcHelperClient) {
+        let endpoint = reloaded.endpoint.read().unwrap().clone();
+        *self.endpoint.write().unwrap() = endpoint;
+        if let (Some(step_client), Some(reloaded)) = (&self.step_client, &reloaded.step_client) {
+            step_client.reload(reloaded);
         }
     }
 
+    /// Authenticate requests of this client with the report collector bearer `token`, which is
+    /// created by [`crate::net::sign_bearer_token`].
+    ///
+    /// # Panics
+    /// If `token` can't be sent in an HTTP header.
+    #[must_use]
+    pub fn with_bearer_token(self, token: &str) -> Self {
+        let value = HeaderValue::try_from(format!("Bearer {token}"))
+            .expect("bearer token must be a valid header value");
+        Self {
+            auth_header: Some((AUTHORIZATION, value)),
+            ..self
+        }
+    }
+
+    /// Returns the client for the multiplexed step connection to this helper, if the client
+    /// configuration selects [`StepTransport::Stream`].
+    #[must_use]
+    pub fn step_client(&self) -> Option<&StepClient> {
+        self.step_client.as_ref()
+    }
+
+    /// Returns the scheme and authority of the peer this client talks to.
+    fn address(&self) -> (uri::Scheme, uri::Authority) {
+        let endpoint = self.endpoint.read().unwrap();
+        (endpoint.scheme.clone(), endpoint.authority.clone())
+    }
+
     pub fn request(&self, mut req: Request<Body>) -> ResponseFuture {
         if let Some((k, v)) = self.auth_header.clone() {
             req.headers_mut().insert(k, v);
         }
-        self.client.request(req)
+        let client = self.endpoint.read().unwrap().client.clone();
+        client.request(req)
     }
 
     /// Responds with whatever input is passed to it
@@ -219,7 +275,8 @@ impl MpcHelperClient {
 
         let req =
             http_serde::echo::Request::new(HashMap::from([(FOO.into(), s.into())]), HashMap::new());
-        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let (scheme, authority) = self.address();
+        let req = req.try_into_http_request(scheme, authority)?;
         let resp = self.request(req).await?;
         let status = resp.status();
         if status.is_success() {
@@ -256,7 +313,8 @@ impl MpcHelperClient {
     /// If the request has illegal arguments, or fails to deliver to helper
     pub async fn create_query(&self, data: QueryConfig) -> Result<QueryId, Error> {
         let req = http_serde::query::create::Request::new(data);
-        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let (scheme, authority) = self.address();
+        let req = req.try_into_http_request(scheme, authority)?;
         let resp = self.request(req).await?;
         if resp.status().is_success() {
             let body_bytes = body::to_bytes(resp.into_body()).await?;
@@ -270,12 +328,18 @@ impl MpcHelperClient {
 
     /// Used to communicate from one helper to another. Specifically, the helper that receives a
     /// "create query" from an external party must communicate the intent to start a query to the
-    /// other helpers, which this prepare query does.
+    /// other helpers, which this prepare query does. `owner` is the report collector that created
+    /// the query, if the helper requires report collectors to authenticate.
     /// # Errors
     /// If the request has illegal arguments, or fails to deliver to helper
-    pub async fn prepare_query(&self, data: PrepareQuery) -> Result<(), Error> {
-        let req = http_serde::query::prepare::Request::new(data);
-        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+    pub async fn prepare_query(
+        &self,
+        data: PrepareQuery,
+        owner: Option<String>,
+    ) -> Result<(), Error> {
+        let req = http_serde::query::prepare::Request::new(data).with_owner(owner);
+        let (scheme, authority) = self.address();
+        let req = req.try_into_http_request(scheme, authority)?;
         let resp = self.request(req).await?;
         Self::resp_ok(resp).await
     }
@@ -287,14 +351,15 @@ impl MpcHelperClient {
     /// If the request has illegal arguments, or fails to deliver to helper
     pub async fn query_input(&self, data: QueryInput) -> Result<(), Error> {
         let req = http_serde::query::input::Request::new(data);
-        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let (scheme, authority) = self.address();
+        let req = req.try_into_http_request(scheme, authority)?;
         let resp = self.request(req).await?;
         Self::resp_ok(resp).await
     }
 
     /// Sends a batch of messages associated with a query's step to another helper. Messages are a
-    /// contiguous block of records. Also includes [`crate::protocol::RecordId`] information and
-    /// [`crate::helpers::network::ChannelId`].
+    /// contiguous block of records, starting at byte `offset` of the channel. Also includes
+    /// [`crate::protocol::RecordId`] information and [`crate::helpers::network::ChannelId`].
     /// # Errors
     /// If the request has illegal arguments, or fails to deliver to helper
     /// # Panics
@@ -303,15 +368,29 @@ impl MpcHelperClient {
         &self,
         query_id: QueryId,
         gate: &Gate,
+        offset: usize,
         data: S,
     ) -> Result<ResponseFuture, Error> {
         let body = hyper::Body::wrap_stream::<_, _, Error>(data.map(Ok));
-        let req = http_serde::query::step::Request::new(query_id, gate.clone(), body);
-        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let req = http_serde::query::step::Request::new(query_id, gate.clone(), offset, body);
+        let (scheme, authority) = self.address();
+        let req = req.try_into_http_request(scheme, authority)?;
         Ok(self.request(req))
     }
 
-    /// Retrieve the status of a query.
+    /// Acknowledges that the first `received` bytes of the step stream sent by this helper's peer
+    /// were received, so the peer does not need to keep them for resending.
+    /// # Errors
+    /// If the request has illegal arguments, or fails to deliver to helper
+    pub async fn ack(&self, query_id: QueryId, gate: &Gate, received: usize) -> Result<(), Error> {
+        let req = http_serde::query::ack::Request::new(query_id, gate.clone(), received);
+        let (scheme, authority) = self.address();
+        let req = req.try_into_http_request(scheme, authority)?;
+        let resp = self.request(req).await?;
+        Self::resp_ok(resp).await
+    }
+
+    /// Retrieve the status of a query, and its progress if it is running.
     ///
     /// ## Errors
     /// If the request has illegal arguments, or fails to deliver to helper
@@ -319,16 +398,15 @@ impl MpcHelperClient {
     pub async fn query_status(
         &self,
         query_id: QueryId,
-    ) -> Result<crate::query::QueryStatus, Error> {
+    ) -> Result<http_serde::query::status::ResponseBody, Error> {
         let req = http_serde::query::status::Request::new(query_id);
-        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let (scheme, authority) = self.address();
+        let req = req.try_into_http_request(scheme, authority)?;
 
         let resp = self.request(req).await?;
         if resp.status().is_success() {
             let body_bytes = body::to_bytes(resp.into_body()).await?;
-            let http_serde::query::status::ResponseBody { status } =
-                serde_json::from_slice(&body_bytes)?;
-            Ok(status)
+            Ok(serde_json::from_slice(&body_bytes)?)
         } else {
             Err(Error::from_failed_resp(resp).await)
         }
@@ -342,7 +420,8 @@ impl MpcHelperClient {
     #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))]
     pub async fn query_results(&self, query_id: QueryId) -> Result<body::Bytes, Error> {
         let req = http_serde::query::results::Request::new(query_id);
-        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let (scheme, authority) = self.address();
+        let req = req.try_into_http_request(scheme, authority)?;
 
         let resp = self.request(req).await?;
         if resp.status().is_success() {
@@ -353,6 +432,38 @@ impl MpcHelperClient {
     }
 }
 
+/// Builds the TLS configuration used to talk to the helper described by `peer_config`.
+///
+/// # Errors
+/// If the peer certificate or the client certificate and key are not valid.
+pub(super) fn rustls_client_config(
+    peer_config: &PeerConfig,
+    identity: ClientIdentity,
+) -> Result<rustls::ClientConfig, Error> {
+    let builder = rustls::ClientConfig::builder().with_safe_defaults();
+    Ok(if let Some(certificate) = &peer_config.certificate {
+        let cert_store = {
+            let mut store = RootCertStore::empty();
+            store.add(certificate)?;
+            store
+        };
+
+        let builder = builder.with_root_certificates(cert_store);
+        match identity {
+            ClientIdentity::Certificate((cert_chain, pk)) => {
+                builder.with_client_auth_cert(cert_chain, pk)?
+            }
+            ClientIdentity::Helper(_) => {
+                error!("header-passed identity ignored for HTTPS client");
+                builder.with_no_client_auth()
+            }
+            ClientIdentity::None => builder.with_no_client_auth(),
+        }
+    } else {
+        builder.with_native_roots().with_no_client_auth()
+    })
+}
+
 fn make_http_connector() -> HttpConnector {
     let mut connector = HttpConnector::new();
     // IPA uses HTTP2 and it is sensitive to those delays especially in high-latency network
@@ -366,6 +477,7 @@ fn make_http_connector() -> HttpConnector {
 pub(crate) mod tests {
     use super::*;
     use crate::{
+        config::Error as ConfigError,
         ff::{FieldType, Fp31},
         helpers::{
             query::QueryType::TestMultiply, BytesStream, RoleAssignment, Transport,
@@ -399,12 +511,14 @@ pub(crate) mod tests {
             let pi = Arc::clone(inner);
             let qi = Arc::clone(inner);
             let si = Arc::clone(inner);
+            let gi = Arc::clone(inner);
             let ci = Arc::clone(inner);
             TransportCallbacks {
                 receive_query: Box::new(move |t, req| (ri.receive_query)(t, req)),
                 prepare_query: Box::new(move |t, req| (pi.prepare_query)(t, req)),
                 query_input: Box::new(move |t, req| (qi.query_input)(t, req)),
                 query_status: Box::new(move |t, req| (si.query_status)(t, req)),
+                query_progress: Box::new(move |t, req| (gi.query_progress)(t, req)),
                 complete_query: Box::new(move |t, req| (ci.complete_query)(t, req)),
             }
         }
@@ -419,15 +533,15 @@ pub(crate) mod tests {
 
         let TestServer { addr, .. } = TestServer::default().await;
 
-        let peer_config = PeerConfig {
-            url: format!("https://localhost:{}", addr.port())
+        let peer_config = PeerConfig::new(
+            format!("https://localhost:{}", addr.port())
                 .parse()
                 .unwrap(),
-            certificate: None,
-            hpke_config: None,
-        };
+            None,
+        );
         let client =
-            MpcHelperClient::new(&ClientConfig::default(), peer_config, ClientIdentity::None);
+            MpcHelperClient::new(&ClientConfig::default(), peer_config, ClientIdentity::None)
+                .unwrap();
 
         // The server's self-signed test cert is not in the system truststore, and we didn't supply
         // it in the client config, so the connection should fail with a certificate error.
@@ -435,6 +549,48 @@ pub(crate) mod tests {
         assert!(matches!(res, Err(Error::HyperPassthrough(e)) if e.is_connect()));
     }
 
+    #[tokio::test]
+    async fn reload_changes_peer_url() {
+        let TestServer { addr, .. } = TestServer::builder().disable_https().build().await;
+        let peer_config =
+            |port: u16| PeerConfig::new(format!("http://localhost:{port}").parse().unwrap(), None);
+
+        // Nothing listens on the port that was just released.
+        let unused_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let new_client = |port: u16| {
+            MpcHelperClient::new(
+                &ClientConfig::default(),
+                peer_config(port),
+                ClientIdentity::None,
+            )
+            .unwrap()
+        };
+        let client = new_client(unused_port);
+        let clone = client.clone();
+        assert!(client.echo("moved").await.is_err());
+
+        client.reload(&new_client(addr.port()));
+        assert_eq!("moved", clone.echo("moved").await.unwrap());
+    }
+
+    #[test]
+    fn stream_transport_requires_stream_port() {
+        let peer_config = PeerConfig::new("http://localhost:3000".parse().unwrap(), None);
+        let client_config = ClientConfig::default().with_step_transport(StepTransport::Stream);
+        let res = MpcHelperClient::new(&client_config, peer_config, ClientIdentity::None);
+        assert!(
+            matches!(
+                res,
+                Err(Error::InvalidConfig(ConfigError::InvalidPeer { .. }))
+            ),
+            "{res:?}"
+        );
+    }
+
     /// tests that a query command runs as expected. Since query commands require the server to
     /// actively respond to a client request, the test must handle both ends of the request
     /// simultaneously. That means taking the client behavior (`clientf`) and the server behavior
@@ -531,7 +687,7 @@ pub(crate) mod tests {
         test_query_command(
             |client| {
                 let req = input.clone();
-                async move { client.prepare_query(req).await.unwrap() }
+                async move { client.prepare_query(req, None).await.unwrap() }
             },
             cb,
         )
@@ -578,6 +734,7 @@ pub(crate) mod tests {
             .step(
                 expected_query_id,
                 &expected_step,
+                0,
                 once(ready(expected_payload.clone())),
             )
             .unwrap()
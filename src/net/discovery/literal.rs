@@ -1,11 +1,12 @@
-use crate::net::discovery::{peer, PeerDiscovery};
+use crate::{config::PeerConfig, net::discovery::PeerDiscovery};
 
 pub struct Literal {
-    peers: [peer::Config; 3],
+    peers: [PeerConfig; 3],
 }
 
 impl Literal {
-    pub fn new(h1: peer::Config, h2: peer::Config, h3: peer::Config) -> Self {
+    #[must_use]
+    pub fn new(h1: PeerConfig, h2: PeerConfig, h3: PeerConfig) -> Self {
         Self {
             peers: [h1, h2, h3],
         }
@@ -13,7 +14,7 @@ impl Literal {
 }
 
 impl PeerDiscovery for Literal {
-    fn peers(&self) -> &[peer::Config; 3] {
-        &self.peers
+    fn peers(&self) -> [PeerConfig; 3] {
+        self.peers.clone()
     }
 }
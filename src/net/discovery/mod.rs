@@ -0,0 +1,13 @@
+mod literal;
+mod signed;
+
+use crate::config::PeerConfig;
+
+pub use literal::Literal;
+pub use signed::{DiscoveryError, DocumentSource, RootKey, Signed, SignedDocument};
+
+/// Provides the configuration of the three helpers in the network. The order of the peers
+/// determines their helper identities.
+pub trait PeerDiscovery {
+    fn peers(&self) -> [PeerConfig; 3];
+}
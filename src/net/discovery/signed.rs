@@ -0,0 +1,503 @@
+use crate::{
+    config::{self, NetworkConfig, PeerConfig},
+    net::discovery::PeerDiscovery,
+    sync::RwLock,
+};
+use hyper::{body, http::uri::InvalidUri, Client, StatusCode, Uri};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    io,
+    path::PathBuf,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::fs;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiscoveryError {
+    #[error("failed to read discovery document: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to fetch discovery document: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("discovery endpoint responded with {0}")]
+    HttpStatus(StatusCode),
+    #[error("malformed discovery document: {0}")]
+    Malformed(String),
+    #[error("discovery document is not signed with the root key")]
+    InvalidSignature,
+    #[error("discovery document expired at {not_after} seconds since the epoch")]
+    Expired { not_after: u64 },
+    #[error(
+        "discovery document version {version} is not newer than the current version {current}"
+    )]
+    Stale { version: u64, current: u64 },
+    #[error("invalid network configuration in discovery document: {0}")]
+    InvalidNetwork(#[from] config::Error),
+    #[error("invalid discovery key: {0}")]
+    InvalidKey(String),
+}
+
+/// Ed25519 public key that discovery documents must be signed with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RootKey(Vec<u8>);
+
+impl RootKey {
+    /// Generates a key pair to sign discovery documents with.
+    ///
+    /// Returns the private key in PKCS#8 format, and the root key that verifies its signatures.
+    ///
+    /// ## Errors
+    /// If the system random number generator fails.
+    pub fn generate() -> Result<(Vec<u8>, Self), DiscoveryError> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|e| DiscoveryError::InvalidKey(e.to_string()))?;
+        let root_key = Self::from_signing_key(pkcs8.as_ref())?;
+        Ok((pkcs8.as_ref().to_vec(), root_key))
+    }
+
+    /// Returns the root key that verifies signatures made with `signing_key`, in PKCS#8 format.
+    ///
+    /// ## Errors
+    /// If `signing_key` is not an Ed25519 private key.
+    pub fn from_signing_key(signing_key: &[u8]) -> Result<Self, DiscoveryError> {
+        let key_pair = Ed25519KeyPair::from_pkcs8(signing_key)
+            .map_err(|e| DiscoveryError::InvalidKey(e.to_string()))?;
+        Ok(Self(key_pair.public_key().as_ref().to_vec()))
+    }
+}
+
+impl FromStr for RootKey {
+    type Err = DiscoveryError;
+
+    /// Parses a hex-encoded Ed25519 public key.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key = hex::decode(s.trim()).map_err(|e| DiscoveryError::InvalidKey(e.to_string()))?;
+        if key.len() != 32 {
+            return Err(DiscoveryError::InvalidKey(format!(
+                "expected 32 bytes, got {}",
+                key.len()
+            )));
+        }
+        Ok(Self(key))
+    }
+}
+
+impl Display for RootKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", hex::encode(&self.0))
+    }
+}
+
+impl Debug for RootKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RootKey({self})")
+    }
+}
+
+/// Helper network configuration that is signed by the operator of the network.
+///
+/// The document is encoded in JSON or TOML. `network` contains the network configuration in the
+/// same TOML format as `network.toml`, and `signature` the hex-encoded Ed25519 signature of it,
+/// together with `version` and `not_after`. Because the signature covers the text of `network`,
+/// it does not depend on how the document is encoded.
+///
+/// `version` must increase with every document that is published, so that an older document
+/// can't be replayed to move the helpers back to previous URLs or certificates. `not_after`, in
+/// seconds since the Unix epoch, optionally limits how long the document is accepted.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SignedDocument {
+    pub version: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<u64>,
+    pub network: String,
+    pub signature: String,
+}
+
+impl SignedDocument {
+    /// Signs the network configuration `network`, as document `version` that is valid until
+    /// `not_after`, with `signing_key`, which is an Ed25519 private key in PKCS#8 format.
+    ///
+    /// ## Errors
+    /// If `network` is not a valid network configuration, or `signing_key` is not valid.
+    pub fn sign(
+        network: String,
+        version: u64,
+        not_after: Option<u64>,
+        signing_key: &[u8],
+    ) -> Result<Self, DiscoveryError> {
+        NetworkConfig::from_toml_str(&network)?;
+        let key_pair = Ed25519KeyPair::from_pkcs8(signing_key)
+            .map_err(|e| DiscoveryError::InvalidKey(e.to_string()))?;
+        let mut document = Self {
+            version,
+            not_after,
+            network,
+            signature: String::new(),
+        };
+        document.signature = hex::encode(key_pair.sign(&document.signed_bytes()));
+        Ok(document)
+    }
+
+    /// The bytes that the signature covers: the version, the expiry time if there is one, and
+    /// the network configuration.
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(17 + self.network.len());
+        bytes.extend_from_slice(&self.version.to_be_bytes());
+        match self.not_after {
+            Some(not_after) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&not_after.to_be_bytes());
+            }
+            None => bytes.push(0),
+        }
+        bytes.extend_from_slice(self.network.as_bytes());
+        bytes
+    }
+
+    /// Parses a document in JSON or TOML format.
+    ///
+    /// ## Errors
+    /// If `input` is not a valid document.
+    pub fn parse(input: &str) -> Result<Self, DiscoveryError> {
+        if input.trim_start().starts_with('{') {
+            serde_json::from_str(input).map_err(|e| DiscoveryError::Malformed(e.to_string()))
+        } else {
+            toml::from_str(input).map_err(|e| DiscoveryError::Malformed(e.to_string()))
+        }
+    }
+
+    /// Returns the network configuration in this document, if it is signed with `root_key` and
+    /// has not expired.
+    ///
+    /// ## Errors
+    /// If the signature does not match `root_key`, the document expired, or the network
+    /// configuration is not valid.
+    pub fn verify(&self, root_key: &RootKey) -> Result<NetworkConfig, DiscoveryError> {
+        let signature = hex::decode(&self.signature)
+            .map_err(|e| DiscoveryError::Malformed(format!("invalid signature: {e}")))?;
+        UnparsedPublicKey::new(&ED25519, &root_key.0)
+            .verify(&self.signed_bytes(), &signature)
+            .map_err(|_| DiscoveryError::InvalidSignature)?;
+        if let Some(not_after) = self.not_after {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            if now > not_after {
+                return Err(DiscoveryError::Expired { not_after });
+            }
+        }
+        Ok(NetworkConfig::from_toml_str(&self.network)?)
+    }
+}
+
+/// Where a [`SignedDocument`] is read from.
+#[derive(Clone, Debug)]
+pub enum DocumentSource {
+    File(PathBuf),
+    /// An HTTP endpoint, typically on the local host. The document is signed, so it does not need
+    /// to be served over TLS.
+    Http(Uri),
+}
+
+impl DocumentSource {
+    async fn read(&self) -> Result<String, DiscoveryError> {
+        match self {
+            Self::File(path) => Ok(fs::read_to_string(path).await?),
+            Self::Http(uri) => {
+                let resp = Client::new().get(uri.clone()).await?;
+                if !resp.status().is_success() {
+                    return Err(DiscoveryError::HttpStatus(resp.status()));
+                }
+                let body = body::to_bytes(resp.into_body()).await?;
+                String::from_utf8(body.to_vec())
+                    .map_err(|e| DiscoveryError::Malformed(e.to_string()))
+            }
+        }
+    }
+}
+
+impl FromStr for DocumentSource {
+    type Err = InvalidUri;
+
+    /// Parses an `http://` URL, or a file path.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") {
+            Ok(Self::Http(s.parse()?))
+        } else {
+            Ok(Self::File(PathBuf::from(s)))
+        }
+    }
+}
+
+/// Discovers the peers from a [`SignedDocument`] that is read from a file or an HTTP endpoint.
+///
+/// [`Self::refresh`] reads the document again, to pick up changes to the URLs and certificates of
+/// the helpers. Documents that are not signed with the root key, have expired, or are not newer
+/// than the current one are rejected, and the configuration from the last valid document is kept.
+pub struct Signed {
+    source: DocumentSource,
+    root_key: RootKey,
+    /// The last valid document, and the network configuration in it.
+    current: RwLock<(SignedDocument, NetworkConfig)>,
+}
+
+impl Signed {
+    /// Reads the document from `source`.
+    ///
+    /// ## Errors
+    /// If the document can't be read, or is not valid.
+    pub async fn new(source: DocumentSource, root_key: RootKey) -> Result<Self, DiscoveryError> {
+        let current = Self::load(&source, &root_key).await?;
+        Ok(Self {
+            source,
+            root_key,
+            current: RwLock::new(current),
+        })
+    }
+
+    async fn load(
+        source: &DocumentSource,
+        root_key: &RootKey,
+    ) -> Result<(SignedDocument, NetworkConfig), DiscoveryError> {
+        let document = SignedDocument::parse(&source.read().await?)?;
+        let network_config = document.verify(root_key)?;
+        Ok((document, network_config))
+    }
+
+    /// Returns the network configuration of the last valid document.
+    ///
+    /// ## Panics
+    /// If the lock is poisoned.
+    #[must_use]
+    pub fn network_config(&self) -> NetworkConfig {
+        self.current.read().unwrap().1.clone()
+    }
+
+    /// Reads the document again. Returns the network configuration in it if it is a newer
+    /// version than the current one.
+    ///
+    /// ## Errors
+    /// If the document can't be read, is not valid, or replaces the current version with a
+    /// different document that is not newer. The current configuration is kept in that case.
+    ///
+    /// ## Panics
+    /// If the lock is poisoned.
+    pub async fn refresh(&self) -> Result<Option<NetworkConfig>, DiscoveryError> {
+        let (document, network_config) = Self::load(&self.source, &self.root_key).await?;
+        let mut current = self.current.write().unwrap();
+        let version = current.0.version;
+        if document.version == version && document.signature == current.0.signature {
+            return Ok(None);
+        }
+        if document.version <= version {
+            return Err(DiscoveryError::Stale {
+                version: document.version,
+                current: version,
+            });
+        }
+        *current = (document, network_config.clone());
+        Ok(Some(network_config))
+    }
+}
+
+impl PeerDiscovery for Signed {
+    fn peers(&self) -> [PeerConfig; 3] {
+        self.network_config().peers
+    }
+}
+
+impl Debug for Signed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Signed({:?})", self.source)
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn network(port: u16) -> String {
+        format!(
+            r#"
+            [[peers]]
+            url = "http://localhost:{port}"
+
+            [[peers]]
+            url = "http://localhost:3001"
+
+            [[peers]]
+            url = "http://localhost:3002"
+            "#
+        )
+    }
+
+    fn write(file: &NamedTempFile, document: &SignedDocument) {
+        std::fs::write(file.path(), toml::to_string(document).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn verifies_json_and_toml() {
+        let (signing_key, root_key) = RootKey::generate().unwrap();
+        let document = SignedDocument::sign(network(3000), 1, None, &signing_key).unwrap();
+
+        for encoded in [
+            serde_json::to_string(&document).unwrap(),
+            toml::to_string(&document).unwrap(),
+        ] {
+            let network_config = SignedDocument::parse(&encoded)
+                .unwrap()
+                .verify(&root_key)
+                .unwrap();
+            assert_eq!(
+                "http://localhost:3000".parse::<Uri>().unwrap(),
+                network_config.peers[0].url
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_other_root_key() {
+        let (signing_key, _) = RootKey::generate().unwrap();
+        let (_, other) = RootKey::generate().unwrap();
+        let document = SignedDocument::sign(network(3000), 1, None, &signing_key).unwrap();
+        assert!(matches!(
+            document.verify(&other),
+            Err(DiscoveryError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn rejects_tampered_document() {
+        let (signing_key, root_key) = RootKey::generate().unwrap();
+        let mut document = SignedDocument::sign(network(3000), 1, None, &signing_key).unwrap();
+        document.network = network(4000);
+        assert!(matches!(
+            document.verify(&root_key),
+            Err(DiscoveryError::InvalidSignature)
+        ));
+
+        let mut document = SignedDocument::sign(network(3000), 1, Some(1), &signing_key).unwrap();
+        document.not_after = None;
+        assert!(matches!(
+            document.verify(&root_key),
+            Err(DiscoveryError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn rejects_expired_document() {
+        let (signing_key, root_key) = RootKey::generate().unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let document =
+            SignedDocument::sign(network(3000), 1, Some(now + 3600), &signing_key).unwrap();
+        document.verify(&root_key).unwrap();
+
+        let document =
+            SignedDocument::sign(network(3000), 1, Some(now - 3600), &signing_key).unwrap();
+        assert!(matches!(
+            document.verify(&root_key),
+            Err(DiscoveryError::Expired { not_after }) if not_after == now - 3600
+        ));
+    }
+
+    #[test]
+    fn parses_root_key() {
+        let (_, root_key) = RootKey::generate().unwrap();
+        assert_eq!(root_key, root_key.to_string().parse::<RootKey>().unwrap());
+        assert!(matches!(
+            "0011".parse::<RootKey>(),
+            Err(DiscoveryError::InvalidKey(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn refreshes_from_file() {
+        let (signing_key, root_key) = RootKey::generate().unwrap();
+        let file = NamedTempFile::new().unwrap();
+        write(
+            &file,
+            &SignedDocument::sign(network(3000), 1, None, &signing_key).unwrap(),
+        );
+
+        let discovery = Signed::new(DocumentSource::File(file.path().to_owned()), root_key)
+            .await
+            .unwrap();
+        assert!(discovery.refresh().await.unwrap().is_none());
+
+        write(
+            &file,
+            &SignedDocument::sign(network(4000), 2, None, &signing_key).unwrap(),
+        );
+        let network_config = discovery.refresh().await.unwrap().unwrap();
+        assert_eq!(
+            "http://localhost:4000".parse::<Uri>().unwrap(),
+            network_config.peers[0].url
+        );
+
+        let (other_key, _) = RootKey::generate().unwrap();
+        write(
+            &file,
+            &SignedDocument::sign(network(5000), 3, None, &other_key).unwrap(),
+        );
+        assert!(matches!(
+            discovery.refresh().await,
+            Err(DiscoveryError::InvalidSignature)
+        ));
+        assert_eq!(
+            "http://localhost:4000".parse::<Uri>().unwrap(),
+            discovery.peers()[0].url
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_replayed_documents() {
+        let (signing_key, root_key) = RootKey::generate().unwrap();
+        let file = NamedTempFile::new().unwrap();
+        let first = SignedDocument::sign(network(3000), 1, None, &signing_key).unwrap();
+        write(&file, &first);
+
+        let discovery = Signed::new(DocumentSource::File(file.path().to_owned()), root_key)
+            .await
+            .unwrap();
+        write(
+            &file,
+            &SignedDocument::sign(network(4000), 2, None, &signing_key).unwrap(),
+        );
+        discovery.refresh().await.unwrap().unwrap();
+
+        // An older document, and a different document with the same version, are both rejected.
+        write(&file, &first);
+        assert!(matches!(
+            discovery.refresh().await,
+            Err(DiscoveryError::Stale {
+                version: 1,
+                current: 2
+            })
+        ));
+        write(
+            &file,
+            &SignedDocument::sign(network(5000), 2, None, &signing_key).unwrap(),
+        );
+        assert!(matches!(
+            discovery.refresh().await,
+            Err(DiscoveryError::Stale {
+                version: 2,
+                current: 2
+            })
+        ));
+        assert_eq!(
+            "http://localhost:4000".parse::<Uri>().unwrap(),
+            discovery.peers()[0].url
+        );
+    }
+}
@@ -26,6 +26,10 @@ pub enum Error {
     MissingExtension(#[from] axum::extract::rejection::ExtensionRejection),
     #[error("query id not found: {}", .0.as_ref())]
     QueryIdNotFound(QueryId),
+    #[error("report collector is not authenticated: {0}")]
+    CollectorUnauthenticated(String),
+    #[error("query {} was created by another report collector", .0.as_ref())]
+    QueryNotOwned(QueryId),
     #[error(transparent)]
     HyperPassthrough(#[from] hyper::Error),
     #[error(transparent)]
@@ -51,6 +55,14 @@ pub enum Error {
     },
     #[error("{error}")]
     Application { code: StatusCode, error: BoxError },
+    #[error("step connection failed: {0}")]
+    StepConnection(#[from] std::io::Error),
+    #[error("step stream rejected: {0}")]
+    StepRejected(String),
+    #[error(transparent)]
+    InvalidConfig(#[from] crate::config::Error),
+    #[error("invalid TLS configuration: {0}")]
+    InvalidTls(#[from] tokio_rustls::rustls::Error),
 }
 
 impl Error {
@@ -145,7 +157,16 @@ impl IntoResponse for Error {
             | Self::FailedHttpRequest { .. }
             | Self::InvalidUri(_)
             | Self::BodyAlreadyExtracted(_)
-            | Self::MissingExtension(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            | Self::MissingExtension(_)
+            | Self::StepConnection(_)
+            | Self::InvalidConfig(_)
+            | Self::InvalidTls(_) => StatusCode::INTERNAL_SERVER_ERROR,
+
+            Self::StepRejected(_) => StatusCode::CONFLICT,
+
+            Self::CollectorUnauthenticated(_) => StatusCode::UNAUTHORIZED,
+
+            Self::QueryNotOwned(_) => StatusCode::FORBIDDEN,
 
             Self::Application { code, .. } => code,
         };
@@ -80,7 +80,10 @@ pub mod echo {
 pub mod query {
     use crate::{
         ff::FieldType,
-        helpers::query::{IpaQueryConfig, QueryConfig, QuerySize, QueryType},
+        helpers::{
+            query::{IpaQueryConfig, QueryConfig, QuerySize, QueryType, StepStreamConfig},
+            Compression,
+        },
         net::Error,
     };
     use async_trait::async_trait;
@@ -112,11 +115,14 @@ pub mod query {
                 size: QuerySize,
                 field_type: FieldType,
                 query_type: String,
+                #[serde(default)]
+                compression: Compression,
             }
             let Query(QueryTypeParam {
                 size,
                 field_type,
                 query_type,
+                compression,
             }) = req.extract().await?;
 
             let query_type = match query_type.as_str() {
@@ -169,6 +175,7 @@ pub mod query {
                 size,
                 field_type,
                 query_type,
+                step_stream: StepStreamConfig { compression },
             }))
         }
     }
@@ -182,6 +189,9 @@ pub mod query {
                 f = self.field_type,
                 size = self.size
             )?;
+            if self.step_stream.compression != Compression::None {
+                write!(f, "&compression={}", self.step_stream.compression)?;
+            }
             match self.query_type {
                 #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
                 QueryType::TestMultiply => Ok(()),
@@ -272,6 +282,7 @@ pub mod query {
             helpers::{query::PrepareQuery, RoleAssignment},
             net::{
                 http_serde::query::{QueryConfigQueryParams, BASE_AXUM_PATH},
+                server::QUERY_OWNER_HEADER,
                 Error,
             },
         };
@@ -286,11 +297,19 @@ pub mod query {
         #[derive(Debug, Clone)]
         pub struct Request {
             pub data: PrepareQuery,
+            /// Report collector that created the query on the leader helper, if the leader
+            /// requires report collectors to authenticate.
+            pub owner: Option<String>,
         }
 
         impl Request {
             pub fn new(data: PrepareQuery) -> Self {
-                Self { data }
+                Self { data, owner: None }
+            }
+
+            #[must_use]
+            pub fn with_owner(self, owner: Option<String>) -> Self {
+                Self { owner, ..self }
             }
             pub fn try_into_http_request(
                 self,
@@ -311,9 +330,11 @@ pub mod query {
                     roles: self.data.roles,
                 };
                 let body = hyper::Body::from(serde_json::to_string(&body)?);
-                Ok(hyper::Request::post(uri)
-                    .header(CONTENT_TYPE, "application/json")
-                    .body(body)?)
+                let mut req = hyper::Request::post(uri).header(CONTENT_TYPE, "application/json");
+                if let Some(owner) = self.owner {
+                    req = req.header(&QUERY_OWNER_HEADER, owner);
+                }
+                Ok(req.body(body)?)
             }
         }
 
@@ -326,6 +347,11 @@ pub mod query {
             ) -> Result<Self, Self::Rejection> {
                 let Path(query_id) = req.extract().await?;
                 let QueryConfigQueryParams(config) = req.extract().await?;
+                let owner = req
+                    .headers()
+                    .get(&QUERY_OWNER_HEADER)
+                    .map(|owner| owner.to_str().map(str::to_owned))
+                    .transpose()?;
                 let Json(RequestBody { roles }) = req.extract().await?;
                 Ok(Request {
                     data: PrepareQuery {
@@ -333,6 +359,7 @@ pub mod query {
                         config,
                         roles,
                     },
+                    owner,
                 })
             }
         }
@@ -417,7 +444,7 @@ pub mod query {
         };
         use async_trait::async_trait;
         use axum::{
-            extract::{FromRequest, Path, RequestParts},
+            extract::{FromRequest, Path, Query, RequestParts},
             http::uri,
         };
 
@@ -427,14 +454,18 @@ pub mod query {
         pub struct Request<B> {
             pub query_id: QueryId,
             pub gate: Gate,
+            /// Byte offset of the channel `body` starts at. Non-zero when the sender resumes
+            /// a stream after the connection was lost.
+            pub offset: usize,
             pub body: B,
         }
 
         impl<B> Request<B> {
-            pub fn new(query_id: QueryId, gate: Gate, body: B) -> Self {
+            pub fn new(query_id: QueryId, gate: Gate, offset: usize, body: B) -> Self {
                 Self {
                     query_id,
                     gate,
+                    offset,
                     body,
                 }
             }
@@ -451,10 +482,11 @@ pub mod query {
                     .scheme(scheme)
                     .authority(authority)
                     .path_and_query(format!(
-                        "{}/{}/step/{}",
+                        "{}/{}/step/{}?offset={}",
                         BASE_AXUM_PATH,
                         self.query_id.as_ref(),
-                        self.gate.as_ref()
+                        self.gate.as_ref(),
+                        self.offset,
                     ))
                     .build()?;
                 Ok(hyper::Request::post(uri).body(self.body)?)
@@ -476,11 +508,19 @@ pub mod query {
             // the form of trait bounds on the impl) to see that PathRejection can be converted to
             // Error. Writing `Path` twice somehow avoids that.
             async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+                #[derive(serde::Deserialize)]
+                struct OffsetParam {
+                    #[serde(default)]
+                    offset: usize,
+                }
+
                 let Path((query_id, gate)) = req.extract::<Path<_>>().await?;
+                let Query(OffsetParam { offset }) = req.extract::<Query<_>>().await?;
                 let body = req.extract().await?;
                 Ok(Self {
                     query_id,
                     gate,
+                    offset,
                     body,
                 })
             }
@@ -489,8 +529,84 @@ pub mod query {
         pub const AXUM_PATH: &str = "/:query_id/step/*step";
     }
 
+    pub mod ack {
+        use crate::{
+            net::{http_serde::query::BASE_AXUM_PATH, Error},
+            protocol::{step::Gate, QueryId},
+        };
+        use async_trait::async_trait;
+        use axum::{
+            extract::{FromRequest, Path, Query, RequestParts},
+            http::uri,
+        };
+        use serde::Deserialize;
+
+        #[derive(Debug, Clone)]
+        pub struct Request {
+            pub query_id: QueryId,
+            pub gate: Gate,
+            /// Number of bytes of the step stream received so far.
+            pub received: usize,
+        }
+
+        impl Request {
+            pub fn new(query_id: QueryId, gate: Gate, received: usize) -> Self {
+                Self {
+                    query_id,
+                    gate,
+                    received,
+                }
+            }
+
+            pub fn try_into_http_request(
+                self,
+                scheme: uri::Scheme,
+                authority: uri::Authority,
+            ) -> Result<hyper::Request<hyper::Body>, Error> {
+                let uri = uri::Uri::builder()
+                    .scheme(scheme)
+                    .authority(authority)
+                    .path_and_query(format!(
+                        "{}/{}/ack/{}?received={}",
+                        BASE_AXUM_PATH,
+                        self.query_id.as_ref(),
+                        self.gate.as_ref(),
+                        self.received,
+                    ))
+                    .build()?;
+                Ok(hyper::Request::post(uri).body(hyper::Body::empty())?)
+            }
+        }
+
+        #[async_trait]
+        impl<B: Send> FromRequest<B> for Request {
+            type Rejection = Error;
+
+            async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+                #[derive(Deserialize)]
+                struct ReceivedParam {
+                    received: usize,
+                }
+
+                let Path((query_id, gate)) = req.extract::<Path<_>>().await?;
+                let Query(ReceivedParam { received }) = req.extract::<Query<_>>().await?;
+                Ok(Self {
+                    query_id,
+                    gate,
+                    received,
+                })
+            }
+        }
+
+        pub const AXUM_PATH: &str = "/:query_id/ack/*step";
+    }
+
     pub mod status {
-        use crate::{net::Error, protocol::QueryId, query::QueryStatus};
+        use crate::{
+            net::Error,
+            protocol::QueryId,
+            query::{QueryProgress, QueryStatus},
+        };
         use async_trait::async_trait;
         use axum::extract::{FromRequest, Path, RequestParts};
         use serde::{Deserialize, Serialize};
@@ -538,6 +654,9 @@ pub mod query {
         #[derive(Clone, Debug, Serialize, Deserialize)]
         pub struct ResponseBody {
             pub status: QueryStatus,
+            /// Set while the query is running, once it sent some records.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub progress: Option<QueryProgress>,
         }
 
         pub const AXUM_PATH: &str = "/:query_id";
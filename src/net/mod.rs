@@ -1,12 +1,17 @@
+mod auth;
 mod client;
+pub mod discovery;
 mod error;
 mod http_serde;
+mod multiplex;
 mod server;
 #[cfg(all(test, not(feature = "shuttle")))]
 pub mod test;
 mod transport;
 
+pub use auth::{sign_bearer_token, BearerTokenError};
 pub use client::{ClientIdentity, MpcHelperClient};
 pub use error::Error;
+pub use multiplex::StepClient;
 pub use server::{MpcHelperServer, TracingSpanMaker};
 pub use transport::HttpTransport;
@@ -0,0 +1,335 @@
+use crate::{
+    config::PeerConfig,
+    helpers::HelperIdentity,
+    net::{
+        client::{rustls_client_config, ClientIdentity},
+        multiplex::{
+            frame::MAX_PAYLOAD_LEN, write_frames, Frame, ALPN, STREAM_WINDOW, WRITE_QUEUE_LEN,
+        },
+        Error,
+    },
+    protocol::{step::Gate, QueryId},
+    sync::{Arc, Mutex, RwLock},
+};
+use axum::http::uri::Scheme;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Formatter},
+    io,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, BufWriter},
+    net::TcpStream,
+    sync::{mpsc, oneshot, Semaphore},
+};
+use tokio_rustls::{rustls::ServerName, TlsConnector};
+use tracing::error;
+
+/// Client side of the multiplexed step connection to one peer helper.
+///
+/// The connection is opened by the first send and re-opened by the next one after it fails.
+/// Streams that were in flight when the connection failed are dropped, which makes the gateway
+/// resume them.
+#[derive(Clone)]
+pub struct StepClient {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    target: RwLock<Target>,
+    identity: Option<HelperIdentity>,
+    connection: tokio::sync::Mutex<Option<Connection>>,
+}
+
+/// Where new connections go, and how they are secured.
+#[derive(Clone)]
+struct Target {
+    host: String,
+    port: u16,
+    tls: Option<(TlsConnector, ServerName)>,
+}
+
+#[derive(Clone)]
+struct Connection {
+    /// Address of the peer, to tell whether it moved since the connection was opened.
+    peer: String,
+    frames: mpsc::Sender<Frame>,
+    channels: Arc<Mutex<Channels>>,
+}
+
+/// Channels opened on a connection.
+#[derive(Default)]
+struct Channels {
+    next: u32,
+    /// Channels that wait for the peer to accept them.
+    opening: HashMap<u32, oneshot::Sender<Result<(), String>>>,
+    /// Number of data frames each channel can send before the peer returns credit for them.
+    windows: HashMap<u32, Arc<Semaphore>>,
+    closed: bool,
+}
+
+impl StepClient {
+    /// Create a client for the multiplexed step connection to the helper described by
+    /// `peer_config`.
+    ///
+    /// # Errors
+    /// If the peer does not configure a `stream_port`, its URL has no host that can be used as a
+    /// TLS server name, or the TLS configuration is not valid.
+    pub fn new(peer_config: &PeerConfig, identity: ClientIdentity) -> Result<Self, Error> {
+        let port = peer_config.required_stream_port()?;
+        let (host, server_name) = peer_config.server_name()?;
+        let host = host.to_owned();
+        let (tls, identity) = if peer_config.url.scheme() == Some(&Scheme::HTTP) {
+            let identity = match identity {
+                ClientIdentity::Helper(id) => Some(id),
+                ClientIdentity::Certificate(_) | ClientIdentity::None => None,
+            };
+            (None, identity)
+        } else {
+            let mut config = rustls_client_config(peer_config, identity)?;
+            config.alpn_protocols = vec![ALPN.to_vec()];
+            (
+                Some((TlsConnector::from(Arc::new(config)), server_name)),
+                None,
+            )
+        };
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                target: RwLock::new(Target { host, port, tls }),
+                identity,
+                connection: tokio::sync::Mutex::default(),
+            }),
+        })
+    }
+
+    /// Send the records of `gate` to the peer, starting at byte `offset` of the channel.
+    ///
+    /// Returns once the peer accepted the stream; the data is streamed in the background.
+    ///
+    /// ## Errors
+    /// If the connection can't be established, or the peer rejects the stream.
+    ///
+    /// ## Panics
+    /// If the lock on the channels of the connection is poisoned.
+    pub async fn send<S: Stream<Item = Vec<u8>> + Send + 'static>(
+        &self,
+        query_id: QueryId,
+        gate: Gate,
+        offset: usize,
+        data: S,
+    ) -> Result<(), Error> {
+        let connection = self.connection().await?;
+        let (channel, window, accepted) = connection.open();
+        connection
+            .frames
+            .send(Frame::Open {
+                channel,
+                query_id,
+                gate,
+                offset: offset as u64,
+            })
+            .await
+            .map_err(|_| connection_lost())?;
+        match accepted.await {
+            Ok(Ok(())) => {}
+            Ok(Err(reason)) => {
+                connection.channels.lock().unwrap().windows.remove(&channel);
+                return Err(Error::StepRejected(reason));
+            }
+            Err(_) => return Err(connection_lost().into()),
+        }
+
+        let Connection {
+            frames, channels, ..
+        } = connection;
+        tokio::spawn(async move {
+            let mut data = Box::pin(data);
+            while let Some(chunk) = data.next().await {
+                let mut chunk = Bytes::from(chunk);
+                while !chunk.is_empty() {
+                    // The window is closed when the connection fails.
+                    let Ok(permit) = window.acquire().await else {
+                        // Dropping `data` tells the sender that the stream was interrupted.
+                        return;
+                    };
+                    permit.forget();
+                    let data = chunk.split_to(chunk.len().min(MAX_PAYLOAD_LEN as usize));
+                    if frames.send(Frame::Data { channel, data }).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            channels.lock().unwrap().windows.remove(&channel);
+            // If the connection fails before this is sent, the receiver treats the stream as
+            // interrupted, which is no different from losing an HTTP request body.
+            let _ = frames.send(Frame::Close { channel }).await;
+        });
+
+        Ok(())
+    }
+
+    async fn connection(&self) -> Result<Connection, Error> {
+        let mut connection = self.inner.connection.lock().await;
+        let peer = self.inner.target.read().unwrap().peer();
+        match &*connection {
+            Some(open) if open.is_open() && open.peer == peer => Ok(open.clone()),
+            _ => {
+                let new = self.connect().await?;
+                *connection = Some(new.clone());
+                Ok(new)
+            }
+        }
+    }
+
+    async fn connect(&self) -> io::Result<Connection> {
+        let target = self.inner.target.read().unwrap().clone();
+        let stream = TcpStream::connect((target.host.as_str(), target.port)).await?;
+        stream.set_nodelay(true)?;
+        let peer = target.peer();
+        match target.tls {
+            Some((connector, server_name)) => {
+                Ok(self.start(peer, connector.connect(server_name, stream).await?))
+            }
+            None => Ok(self.start(peer, stream)),
+        }
+    }
+
+    /// Use the address and the TLS configuration of `other` for the connections this client
+    /// opens from now on. If the address changed, the next send opens a new connection; streams
+    /// on the old connection are not interrupted.
+    pub(in crate::net) fn reload(&self, other: &StepClient) {
+        let target = other.inner.target.read().unwrap().clone();
+        *self.inner.target.write().unwrap() = target;
+    }
+
+    fn start<S: AsyncRead + AsyncWrite + Send + 'static>(
+        &self,
+        peer: String,
+        stream: S,
+    ) -> Connection {
+        let (mut r, w) = tokio::io::split(stream);
+        let (frames, rx) = mpsc::channel(WRITE_QUEUE_LEN);
+        let channels = Arc::new(Mutex::new(Channels::default()));
+
+        let hello = Frame::Hello {
+            from: self.inner.identity,
+        };
+        tokio::spawn({
+            let peer = peer.clone();
+            async move {
+                let mut w = BufWriter::new(w);
+                let result = async {
+                    hello.write(&mut w).await?;
+                    write_frames(w, rx).await
+                }
+                .await;
+                if let Err(e) = result {
+                    error!("step connection to {peer} failed: {e}");
+                }
+            }
+        });
+        tokio::spawn({
+            let peer = peer.clone();
+            let channels = Arc::clone(&channels);
+            async move {
+                loop {
+                    let frame = match Frame::read(&mut r).await {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => break,
+                        Err(e) => {
+                            error!("step connection to {peer} failed: {e}");
+                            break;
+                        }
+                    };
+                    let mut channels = channels.lock().unwrap();
+                    match frame {
+                        Frame::Accept { channel } => channels.opened(channel, Ok(())),
+                        Frame::Reject { channel, reason } => channels.opened(channel, Err(reason)),
+                        Frame::Credit { channel, frames } => channels.credit(channel, frames),
+                        frame => {
+                            error!("unexpected frame on step connection to {peer}: {frame:?}");
+                            break;
+                        }
+                    }
+                }
+                channels.lock().unwrap().close();
+            }
+        });
+
+        Connection {
+            peer,
+            frames,
+            channels,
+        }
+    }
+}
+
+impl Debug for StepClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "StepClient({})",
+            self.inner.target.read().unwrap().peer()
+        )
+    }
+}
+
+impl Target {
+    fn peer(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+impl Connection {
+    fn is_open(&self) -> bool {
+        !self.frames.is_closed() && !self.channels.lock().unwrap().closed
+    }
+
+    /// Allocates a channel id and returns it, along with the flow control window of the channel
+    /// and a receiver that resolves once the peer accepted or rejected the stream.
+    fn open(&self) -> (u32, Arc<Semaphore>, oneshot::Receiver<Result<(), String>>) {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = channels.next;
+        channels.next = channels.next.wrapping_add(1);
+        let (tx, rx) = oneshot::channel();
+        let window = Arc::new(Semaphore::new(STREAM_WINDOW as usize));
+        if !channels.closed {
+            channels.opening.insert(channel, tx);
+            channels.windows.insert(channel, Arc::clone(&window));
+        }
+        (channel, window, rx)
+    }
+}
+
+impl Channels {
+    fn opened(&mut self, channel: u32, result: Result<(), String>) {
+        if let Some(tx) = self.opening.remove(&channel) {
+            let _ = tx.send(result);
+        }
+    }
+
+    /// Lets `channel` send `frames` more data frames.
+    fn credit(&mut self, channel: u32, frames: u32) {
+        // the channel may be closed already.
+        if let Some(window) = self.windows.get(&channel) {
+            window.add_permits(frames as usize);
+        }
+    }
+
+    /// Fails all channels that wait for the peer to accept them, and stops the ones that are
+    /// sending data.
+    fn close(&mut self) {
+        self.closed = true;
+        self.opening.clear();
+        for (_, window) in self.windows.drain() {
+            window.close();
+        }
+    }
+}
+
+fn connection_lost() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "step connection lost")
+}
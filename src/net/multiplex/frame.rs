@@ -0,0 +1,358 @@
+//! Wire format of the multiplexed step connection.
+//!
+//! Every frame starts with a fixed 9 byte header: the frame kind, the channel the frame belongs to
+//! and the length of the payload that follows, both big endian. A channel is opened once with its
+//! gate; all frames that follow refer to it by its compact channel id. Builds with the compact gate
+//! send the gate's 2 byte state id, builds with the descriptive gate send its full name. Both
+//! helpers must therefore be built with the same gate feature.
+
+use crate::{
+    error::BoxError,
+    helpers::HelperIdentity,
+    protocol::{step::Gate, QueryId},
+};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::{io, str};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+#[cfg(feature = "compact-gate")]
+use crate::protocol::step::Compact;
+
+const HEADER_LEN: usize = 9;
+
+/// Largest payload a peer is allowed to send in a single frame.
+pub const MAX_PAYLOAD_LEN: u32 = 1 << 20;
+
+const HELLO: u8 = 0;
+const OPEN: u8 = 1;
+const DATA: u8 = 2;
+const CLOSE: u8 = 3;
+const ACCEPT: u8 = 4;
+const REJECT: u8 = 5;
+const CREDIT: u8 = 6;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frame {
+    /// First frame on every connection. Over plain TCP it names the helper that connected; over
+    /// TLS the helper is identified by its client certificate instead.
+    Hello { from: Option<HelperIdentity> },
+    /// Opens `channel` for the records of `gate`, starting at byte `offset` of the channel.
+    Open {
+        channel: u32,
+        query_id: QueryId,
+        gate: Gate,
+        offset: u64,
+    },
+    /// Records data sent over an open channel.
+    Data { channel: u32, data: Bytes },
+    /// Clean end of the records sent over `channel`.
+    Close { channel: u32 },
+    /// The receiving helper accepted the records stream opened on `channel`.
+    Accept { channel: u32 },
+    /// The receiving helper refused the records stream opened on `channel`.
+    Reject { channel: u32, reason: String },
+    /// The receiving helper read `frames` data frames sent over `channel`, so the sender may send
+    /// that many more.
+    Credit { channel: u32, frames: u32 },
+}
+
+fn invalid_data<E: Into<BoxError>>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn utf8(bytes: &[u8]) -> io::Result<String> {
+    str::from_utf8(bytes)
+        .map(str::to_owned)
+        .map_err(invalid_data)
+}
+
+#[cfg(feature = "compact-gate")]
+fn encode_gate(gate: &Gate) -> [u8; 2] {
+    gate.0.to_be_bytes()
+}
+
+#[cfg(feature = "compact-gate")]
+fn decode_gate(bytes: &[u8]) -> io::Result<Gate> {
+    let id =
+        <[u8; 2]>::try_from(bytes).map_err(|_| invalid_data("gate id must be 2 bytes long"))?;
+    Ok(Compact(u16::from_be_bytes(id)))
+}
+
+#[cfg(feature = "descriptive-gate")]
+fn encode_gate(gate: &Gate) -> &[u8] {
+    gate.as_ref().as_bytes()
+}
+
+#[cfg(feature = "descriptive-gate")]
+fn decode_gate(bytes: &[u8]) -> io::Result<Gate> {
+    // Gates are parsed the same way as the wildcard segment of the HTTP step route, which keeps
+    // the leading slash.
+    Ok(Gate::from(format!("/{}", utf8(bytes)?).as_str()))
+}
+
+/// Payload of an open frame: the offset, the length-prefixed query id and the encoded gate.
+fn encode_open(offset: u64, query_id: &str, gate: impl AsRef<[u8]>) -> io::Result<Bytes> {
+    let gate = gate.as_ref();
+    let query_id_len = u8::try_from(query_id.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("query id of {} bytes is too long", query_id.len()),
+        )
+    })?;
+    let mut buf = BytesMut::with_capacity(9 + query_id.len() + gate.len());
+    buf.put_u64(offset);
+    buf.put_u8(query_id_len);
+    buf.put_slice(query_id.as_bytes());
+    buf.put_slice(gate);
+    Ok(buf.freeze())
+}
+
+fn decode_open(mut payload: Bytes) -> io::Result<(u64, String, Bytes)> {
+    if payload.len() < 9 {
+        return Err(invalid_data("open frame is too short"));
+    }
+    let offset = payload.get_u64();
+    let query_id_len = usize::from(payload.get_u8());
+    if payload.len() < query_id_len {
+        return Err(invalid_data("open frame is too short"));
+    }
+    let query_id = utf8(&payload.split_to(query_id_len))?;
+    Ok((offset, query_id, payload))
+}
+
+impl Frame {
+    fn kind(&self) -> u8 {
+        match self {
+            Frame::Hello { .. } => HELLO,
+            Frame::Open { .. } => OPEN,
+            Frame::Data { .. } => DATA,
+            Frame::Close { .. } => CLOSE,
+            Frame::Accept { .. } => ACCEPT,
+            Frame::Reject { .. } => REJECT,
+            Frame::Credit { .. } => CREDIT,
+        }
+    }
+
+    fn channel(&self) -> u32 {
+        match self {
+            Frame::Hello { .. } => 0,
+            Frame::Open { channel, .. }
+            | Frame::Data { channel, .. }
+            | Frame::Close { channel }
+            | Frame::Accept { channel }
+            | Frame::Reject { channel, .. }
+            | Frame::Credit { channel, .. } => *channel,
+        }
+    }
+
+    fn payload(&self) -> io::Result<Bytes> {
+        Ok(match self {
+            Frame::Hello { from } => Bytes::copy_from_slice(&[from.map_or(0, u8::from)]),
+            Frame::Open {
+                query_id,
+                gate,
+                offset,
+                ..
+            } => encode_open(*offset, query_id.as_ref(), encode_gate(gate))?,
+            Frame::Data { data, .. } => data.clone(),
+            Frame::Close { .. } | Frame::Accept { .. } => Bytes::new(),
+            Frame::Reject { reason, .. } => Bytes::copy_from_slice(reason.as_bytes()),
+            Frame::Credit { frames, .. } => Bytes::copy_from_slice(&frames.to_be_bytes()),
+        })
+    }
+
+    /// Writes this frame to `w`. The caller is responsible for flushing the writer.
+    ///
+    /// ## Errors
+    /// If the payload is larger than [`MAX_PAYLOAD_LEN`], the query id of an open frame is longer
+    /// than 255 bytes or writing fails.
+    pub async fn write<W: AsyncWrite + Unpin>(&self, w: &mut W) -> io::Result<()> {
+        let payload = self.payload()?;
+        let len = u32::try_from(payload.len())
+            .ok()
+            .filter(|len| *len <= MAX_PAYLOAD_LEN)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "frame payload is too large")
+            })?;
+
+        let mut header = [0; HEADER_LEN];
+        header[0] = self.kind();
+        header[1..5].copy_from_slice(&self.channel().to_be_bytes());
+        header[5..].copy_from_slice(&len.to_be_bytes());
+        w.write_all(&header).await?;
+        w.write_all(&payload).await
+    }
+
+    /// Reads the next frame from `r`. Returns `None` if the peer closed the connection between
+    /// two frames.
+    ///
+    /// ## Errors
+    /// If the connection fails, is closed in the middle of a frame, or the frame is malformed.
+    pub async fn read<R: AsyncRead + Unpin>(r: &mut R) -> io::Result<Option<Self>> {
+        let mut header = [0; HEADER_LEN];
+        // `read_exact` can't tell a connection closed between frames apart from a truncated one.
+        let n = r.read(&mut header).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        r.read_exact(&mut header[n..]).await?;
+
+        let mut fields = &header[1..];
+        let channel = fields.get_u32();
+        let len = fields.get_u32();
+        if len > MAX_PAYLOAD_LEN {
+            return Err(invalid_data(format!(
+                "frame payload of {len} bytes is too large"
+            )));
+        }
+        let mut payload = BytesMut::zeroed(usize::try_from(len).map_err(invalid_data)?);
+        r.read_exact(&mut payload).await?;
+
+        Self::decode(header[0], channel, payload.freeze()).map(Some)
+    }
+
+    fn decode(kind: u8, channel: u32, mut payload: Bytes) -> io::Result<Self> {
+        Ok(match kind {
+            HELLO => Frame::Hello {
+                from: match payload.first() {
+                    None | Some(0) => None,
+                    Some(&id) => {
+                        Some(HelperIdentity::try_from(usize::from(id)).map_err(invalid_data)?)
+                    }
+                },
+            },
+            OPEN => {
+                let (offset, query_id, gate) = decode_open(payload)?;
+                Frame::Open {
+                    channel,
+                    query_id: QueryId::try_from(query_id.as_str())
+                        .map_err(|e| invalid_data(e.to_string()))?,
+                    gate: decode_gate(&gate)?,
+                    offset,
+                }
+            }
+            DATA => Frame::Data {
+                channel,
+                data: payload,
+            },
+            CLOSE => Frame::Close { channel },
+            ACCEPT => Frame::Accept { channel },
+            REJECT => Frame::Reject {
+                channel,
+                reason: utf8(&payload)?,
+            },
+            CREDIT => {
+                if payload.len() != 4 {
+                    return Err(invalid_data("credit frame must carry 4 bytes"));
+                }
+                Frame::Credit {
+                    channel,
+                    frames: payload.get_u32(),
+                }
+            }
+            _ => return Err(invalid_data(format!("unknown frame kind {kind}"))),
+        })
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::protocol::step::StepNarrow;
+
+    async fn round_trip(frame: Frame) -> Frame {
+        let mut buf = Vec::new();
+        frame.write(&mut buf).await.unwrap();
+        Frame::read(&mut buf.as_slice()).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn frames_round_trip() {
+        let frames = [
+            Frame::Hello { from: None },
+            Frame::Hello {
+                from: Some(HelperIdentity::THREE),
+            },
+            Frame::Open {
+                channel: 7,
+                query_id: QueryId,
+                gate: Gate::default().narrow("multiply").narrow("bit3"),
+                offset: 1 << 40,
+            },
+            Frame::Data {
+                channel: 7,
+                data: Bytes::from_static(&[1, 2, 3]),
+            },
+            Frame::Close { channel: 7 },
+            Frame::Accept { channel: 7 },
+            Frame::Reject {
+                channel: 7,
+                reason: "resumed past the end".into(),
+            },
+            Frame::Credit {
+                channel: 7,
+                frames: 16,
+            },
+        ];
+        for frame in frames {
+            let expected = format!("{frame:?}");
+            assert_eq!(expected, format!("{:?}", round_trip(frame).await));
+        }
+    }
+
+    #[tokio::test]
+    async fn data_frames_use_compact_headers() {
+        let mut buf = Vec::new();
+        Frame::Data {
+            channel: 1,
+            data: Bytes::from_static(&[0xff; 4]),
+        }
+        .write(&mut buf)
+        .await
+        .unwrap();
+        assert_eq!(
+            vec![DATA, 0, 0, 0, 1, 0, 0, 0, 4, 0xff, 0xff, 0xff, 0xff],
+            buf
+        );
+    }
+
+    #[tokio::test]
+    async fn end_of_connection() {
+        assert_eq!(None, Frame::read(&mut [].as_slice()).await.unwrap());
+
+        let mut buf = Vec::new();
+        Frame::Close { channel: 1 }.write(&mut buf).await.unwrap();
+        let err = Frame::read(&mut &buf[..4]).await.unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn query_id_length_limit() {
+        let query_id = "q".repeat(usize::from(u8::MAX));
+        let payload = encode_open(42, &query_id, b"multiply").unwrap();
+        assert_eq!(
+            (42, query_id, Bytes::from_static(b"multiply")),
+            decode_open(payload).unwrap()
+        );
+
+        let err = encode_open(42, &"q".repeat(256), b"multiply").unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_frames() {
+        let mut header = vec![DATA, 0, 0, 0, 1];
+        header.extend((MAX_PAYLOAD_LEN + 1).to_be_bytes());
+        let err = Frame::read(&mut header.as_slice()).await.unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+
+        let err = Frame::Data {
+            channel: 1,
+            data: vec![0; 1 + MAX_PAYLOAD_LEN as usize].into(),
+        }
+        .write(&mut Vec::new())
+        .await
+        .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+}
@@ -0,0 +1,51 @@
+//! Step transport that multiplexes all records channels between two helpers over one persistent
+//! connection, instead of sending every channel as the body of its own HTTP request.
+//!
+//! The sending helper opens the connection and authenticates the same way it does for HTTP
+//! requests: with its client certificate over TLS, or by claiming its identity in the first frame
+//! when HTTPS is disabled. Each records stream is announced with an `Open` frame that names the
+//! gate and assigns it a channel id, which is all that subsequent frames carry. The receiving
+//! helper answers with `Accept` or `Reject`, mirroring the response to an HTTP step request.
+//!
+//! Each channel has its own flow control window, like HTTP/2 streams do. The sender may have at
+//! most [`STREAM_WINDOW`] data frames of a channel in flight; the receiver returns a `Credit` for
+//! every frame it read. This bounds the memory buffered for each channel without blocking the
+//! connection on one slow channel, which could deadlock a protocol that waits for data on another.
+
+mod client;
+mod frame;
+mod server;
+
+pub use client::StepClient;
+pub(super) use server::serve;
+
+use frame::Frame;
+use tokio::{
+    io::{self, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+/// ALPN protocol identifier negotiated on TLS step connections.
+pub(super) const ALPN: &[u8] = b"ipa-step/1";
+
+/// Number of frames that can be queued for a connection before senders have to wait.
+const WRITE_QUEUE_LEN: usize = 64;
+
+/// Number of data frames of one channel the sender can have in flight before it has to wait for
+/// the receiver to read them.
+const STREAM_WINDOW: u32 = 16;
+
+/// Writes the frames queued on `frames` to `w`, flushing whenever the queue runs empty.
+async fn write_frames<W: AsyncWrite + Unpin>(
+    mut w: W,
+    mut frames: mpsc::Receiver<Frame>,
+) -> io::Result<()> {
+    while let Some(frame) = frames.recv().await {
+        frame.write(&mut w).await?;
+        while let Ok(frame) = frames.try_recv() {
+            frame.write(&mut w).await?;
+        }
+        w.flush().await?;
+    }
+    w.shutdown().await
+}
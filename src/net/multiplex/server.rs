@@ -0,0 +1,387 @@
+use crate::{
+    error::BoxError,
+    helpers::{BodyStream, HelperIdentity},
+    net::{
+        multiplex::{write_frames, Frame, STREAM_WINDOW, WRITE_QUEUE_LEN},
+        server::{ClientCertRecognizingAcceptor, SharedNetworkConfig},
+        HttpTransport,
+    },
+    sync::{Arc, Mutex},
+};
+use axum_server::tls_rustls::RustlsConfig;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::{
+    collections::HashMap,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, BufWriter},
+    net::TcpListener,
+    sync::{mpsc, Notify},
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::error;
+
+type Records = Result<Bytes, BoxError>;
+type RecordsSender = mpsc::Sender<Records>;
+
+/// Data frames read from the channels of one connection that were not returned to the sender as
+/// credit yet.
+#[derive(Clone)]
+struct Credits {
+    inner: Arc<CreditsInner>,
+}
+
+struct CreditsInner {
+    /// Number of frames read from each channel, or `None` once the connection is closed.
+    pending: Mutex<Option<HashMap<u32, u32>>>,
+    notify: Notify,
+}
+
+impl Credits {
+    fn new() -> Self {
+        Self {
+            inner: Arc::new(CreditsInner {
+                pending: Mutex::new(Some(HashMap::new())),
+                notify: Notify::new(),
+            }),
+        }
+    }
+
+    /// Returns a credit for a frame read from `channel`.
+    fn grant(&self, channel: u32) {
+        if let Some(pending) = self.inner.pending.lock().unwrap().as_mut() {
+            *pending.entry(channel).or_default() += 1;
+            self.inner.notify.notify_one();
+        }
+    }
+
+    fn close(&self) {
+        self.inner.pending.lock().unwrap().take();
+        self.inner.notify.notify_one();
+    }
+
+    /// Waits for credits to be granted and takes them. Returns `None` once the connection is
+    /// closed.
+    async fn take(&self) -> Option<HashMap<u32, u32>> {
+        loop {
+            if let Some(pending) = self.inner.pending.lock().unwrap().as_mut() {
+                if !pending.is_empty() {
+                    return Some(std::mem::take(pending));
+                }
+            } else {
+                return None;
+            }
+            self.inner.notify.notified().await;
+        }
+    }
+}
+
+/// Records received over a channel. Every chunk read from it is returned to the sender as
+/// credit.
+struct ChannelStream {
+    channel: u32,
+    records: ReceiverStream<Records>,
+    credits: Credits,
+}
+
+impl Stream for ChannelStream {
+    type Item = Records;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = Pin::get_mut(self);
+        let next = this.records.poll_next_unpin(cx);
+        if let Poll::Ready(Some(Ok(_))) = next {
+            this.credits.grant(this.channel);
+        }
+        next
+    }
+}
+
+/// Accepts multiplexed step connections from peer helpers and hands the records streams opened on
+/// them to `transport`.
+///
+/// Connections are authenticated with client certificates if `tls` is set. Otherwise, the peer
+/// claims its identity in the first frame, like it does with a header for HTTP requests. Each
+/// connection uses the TLS configuration and the peer certificates that are current when it is
+/// accepted.
+pub async fn serve(
+    listener: TcpListener,
+    tls: Option<RustlsConfig>,
+    network_config: SharedNetworkConfig,
+    transport: Arc<HttpTransport>,
+) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("failed to accept step connection: {e}");
+                continue;
+            }
+        };
+        let tls = tls
+            .as_ref()
+            .map(|config| TlsAcceptor::from(config.get_inner()));
+        let network_config = Arc::clone(&network_config);
+        let transport = Arc::clone(&transport);
+        tokio::spawn(async move {
+            let result = async {
+                stream.set_nodelay(true)?;
+                if let Some(acceptor) = tls {
+                    let stream = acceptor.accept(stream).await?;
+                    let from = ClientCertRecognizingAcceptor::identify_client(
+                        &network_config.read().unwrap(),
+                        stream
+                            .get_ref()
+                            .1
+                            .peer_certificates()
+                            .and_then(<[_]>::first),
+                    )
+                    .map(|id| id.0);
+                    receive(stream, from, transport).await
+                } else {
+                    let mut stream = stream;
+                    let hello = Frame::read(&mut stream).await?;
+                    let from = if let Some(Frame::Hello { from }) = hello {
+                        from
+                    } else {
+                        None
+                    };
+                    receive(stream, from, transport).await
+                }
+            }
+            .await;
+            if let Err(e) = result {
+                error!("step connection from {addr} failed: {e}");
+            }
+        });
+    }
+}
+
+/// Reads the records streams the helper `from` sends over `stream`.
+async fn receive<S: AsyncRead + AsyncWrite + Send + 'static>(
+    stream: S,
+    from: Option<HelperIdentity>,
+    transport: Arc<HttpTransport>,
+) -> io::Result<()> {
+    let (mut r, w) = tokio::io::split(stream);
+    let Some(from) = from else {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "step connection was not opened by a known helper",
+        ));
+    };
+
+    let (replies, rx) = mpsc::channel(WRITE_QUEUE_LEN);
+    tokio::spawn(write_frames(BufWriter::new(w), rx));
+    let credits = Credits::new();
+    tokio::spawn({
+        let credits = credits.clone();
+        let replies = replies.clone();
+        async move {
+            while let Some(pending) = credits.take().await {
+                for (channel, frames) in pending {
+                    if replies
+                        .send(Frame::Credit { channel, frames })
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    let mut channels = HashMap::<u32, RecordsSender>::new();
+    let result = loop {
+        let reply = match Frame::read(&mut r).await {
+            Ok(Some(Frame::Hello { .. })) => continue,
+            Ok(Some(Frame::Open {
+                channel,
+                query_id,
+                gate,
+                offset,
+            })) => {
+                // The sender does not exceed the window, which leaves room for the error that
+                // interrupts the stream if the connection is lost.
+                let (tx, rx) = mpsc::channel(STREAM_WINDOW as usize + 1);
+                let body = BodyStream::from_bytes_stream(Box::pin(ChannelStream {
+                    channel,
+                    records: ReceiverStream::new(rx),
+                    credits: credits.clone(),
+                }));
+                let opened = usize::try_from(offset)
+                    .map_err(BoxError::from)
+                    .and_then(|offset| {
+                        Arc::clone(&transport)
+                            .receive_stream(query_id, gate, from, offset, body)
+                            .map_err(BoxError::from)
+                    });
+                match opened {
+                    Ok(()) => {
+                        channels.insert(channel, tx);
+                        Frame::Accept { channel }
+                    }
+                    Err(e) => Frame::Reject {
+                        channel,
+                        reason: e.to_string(),
+                    },
+                }
+            }
+            Ok(Some(Frame::Data { channel, data })) => {
+                if let Some(tx) = channels.get(&channel) {
+                    // The last slot is reserved for the error that interrupts the stream.
+                    if tx.capacity() <= 1 && !tx.is_closed() {
+                        break Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("channel {channel} exceeded its flow control window"),
+                        ));
+                    }
+                    // The receiving end may be gone already, e.g. because the query was aborted.
+                    // The data is discarded, but the sender must not stall on it.
+                    if tx.try_send(Ok(data)).is_err() {
+                        credits.grant(channel);
+                    }
+                }
+                continue;
+            }
+            Ok(Some(Frame::Close { channel })) => {
+                channels.remove(&channel);
+                continue;
+            }
+            Ok(Some(frame)) => {
+                break Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected frame on step connection: {frame:?}"),
+                ))
+            }
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        };
+        if replies.send(reply).await.is_err() {
+            break Err(io::ErrorKind::BrokenPipe.into());
+        }
+    };
+
+    // Streams that were not closed by the sender are interrupted. The sender resumes them over a
+    // new connection.
+    credits.close();
+    for tx in channels.into_values() {
+        let _ = tx.try_send(Err("step connection lost".into()));
+    }
+    result
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::{
+        helpers::Transport,
+        net::test::TestServer,
+        protocol::{
+            step::{Gate, StepNarrow},
+            QueryId,
+        },
+    };
+    use futures::StreamExt;
+    use tokio::io::{duplex, DuplexStream, ReadHalf, WriteHalf};
+
+    struct Peer {
+        r: ReadHalf<DuplexStream>,
+        w: WriteHalf<DuplexStream>,
+    }
+
+    impl Peer {
+        async fn send(&mut self, frame: Frame) {
+            frame.write(&mut self.w).await.unwrap();
+        }
+
+        async fn recv(&mut self) -> Frame {
+            Frame::read(&mut self.r).await.unwrap().unwrap()
+        }
+
+        async fn open(&mut self, channel: u32, gate: &Gate) {
+            self.send(Frame::Open {
+                channel,
+                query_id: QueryId,
+                gate: gate.clone(),
+                offset: 0,
+            })
+            .await;
+            assert_eq!(Frame::Accept { channel }, self.recv().await);
+        }
+
+        async fn send_data(&mut self, channel: u32, frames: u32) {
+            for i in 0..frames {
+                self.send(Frame::Data {
+                    channel,
+                    data: Bytes::from(vec![u8::try_from(i).unwrap()]),
+                })
+                .await;
+            }
+        }
+    }
+
+    fn connect(transport: &Arc<HttpTransport>) -> (Peer, tokio::task::JoinHandle<io::Result<()>>) {
+        let (client, server) = duplex(1 << 16);
+        let handle = tokio::spawn(receive(
+            server,
+            Some(HelperIdentity::TWO),
+            Arc::clone(transport),
+        ));
+        let (r, w) = tokio::io::split(client);
+        (Peer { r, w }, handle)
+    }
+
+    #[tokio::test]
+    async fn full_channel_does_not_block_others() {
+        let TestServer { transport, .. } = TestServer::builder().build().await;
+        let (mut peer, _handle) = connect(&transport);
+        let (slow, fast) = (
+            Gate::default().narrow("slow"),
+            Gate::default().narrow("fast"),
+        );
+        peer.open(0, &slow).await;
+        peer.open(1, &fast).await;
+
+        // nobody reads the first channel, but it stays within its window.
+        peer.send_data(0, STREAM_WINDOW).await;
+        peer.send_data(1, 1).await;
+        let mut fast = Transport::receive(&transport, HelperIdentity::TWO, (QueryId, fast));
+        assert_eq!(Some(vec![0]), fast.next().await);
+        assert_eq!(
+            Frame::Credit {
+                channel: 1,
+                frames: 1
+            },
+            peer.recv().await
+        );
+
+        // reading the first channel returns credit to the sender.
+        let mut slow = Transport::receive(&transport, HelperIdentity::TWO, (QueryId, slow));
+        assert_eq!(Some(vec![0]), slow.next().await);
+        assert_eq!(
+            Frame::Credit {
+                channel: 0,
+                frames: 1
+            },
+            peer.recv().await
+        );
+    }
+
+    #[tokio::test]
+    async fn exceeding_window_fails_connection() {
+        let TestServer { transport, .. } = TestServer::builder().build().await;
+        let (mut peer, handle) = connect(&transport);
+        peer.open(0, &Gate::default().narrow("overflow")).await;
+
+        peer.send_data(0, STREAM_WINDOW + 1).await;
+        let err = handle.await.unwrap().unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+}
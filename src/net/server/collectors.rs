@@ -0,0 +1,328 @@
+use crate::{
+    config::ReportCollectorConfig,
+    net::{auth::verify_bearer_token, Error},
+    protocol::QueryId,
+    sync::{Arc, Mutex},
+};
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequest, RequestParts},
+    Extension,
+};
+use futures::Future;
+use hyper::header::{HeaderName, HeaderValue, AUTHORIZATION};
+use std::{collections::HashMap, time::SystemTime};
+use tokio_rustls::rustls::Certificate;
+
+/// Name of the header that passes the report collector that created a query from the leader helper
+/// to the other helpers, when preparing the query.
+pub static QUERY_OWNER_HEADER: HeaderName = HeaderName::from_static("x-query-owner");
+
+tokio::task_local! {
+    /// Report collector on whose behalf the current task creates a query.
+    static CREATING_COLLECTOR: Option<CollectorIdentity>;
+}
+
+/// Returns the report collector on whose behalf the current task creates a query, if any.
+///
+/// This allows the transport to tell the other helpers who owns the query it prepares, without
+/// threading the owner through the query processor.
+pub(in crate::net) fn creating_collector() -> Option<String> {
+    CREATING_COLLECTOR
+        .try_with(|collector| {
+            collector
+                .as_ref()
+                .map(|CollectorIdentity(name)| name.clone())
+        })
+        .ok()
+        .flatten()
+}
+
+/// Axum `Extension` with the client certificate presented on a connection, if it does not belong to
+/// a peer helper. The query APIs check whether it belongs to a report collector.
+#[derive(Clone, Debug)]
+pub(super) struct ClientCertificate(pub Certificate);
+
+/// Name of an authenticated report collector.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct CollectorIdentity(pub String);
+
+/// Report collectors that are allowed to use the query APIs, and the queries they own.
+#[derive(Debug)]
+pub(super) struct ReportCollectors {
+    allowed: Vec<ReportCollectorConfig>,
+    // TODO(615): Queries are never removed from here. That is fine as long as the query id is
+    // always the same.
+    owners: Mutex<HashMap<QueryId, String>>,
+}
+
+impl ReportCollectors {
+    pub(super) fn new(allowed: Vec<ReportCollectorConfig>) -> Self {
+        Self {
+            allowed,
+            owners: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns true if the query APIs are open to everyone.
+    fn is_open(&self) -> bool {
+        self.allowed.is_empty()
+    }
+
+    /// Identifies the report collector that sent a request, either by the client `certificate` or
+    /// by the bearer token in the `authorization` header.
+    ///
+    /// Returns `None` if no report collectors are configured, in which case authentication is not
+    /// required.
+    ///
+    /// ## Errors
+    /// If the request is not authenticated as one of the allowed report collectors.
+    fn authenticate(
+        &self,
+        certificate: Option<&ClientCertificate>,
+        authorization: Option<&HeaderValue>,
+    ) -> Result<Option<CollectorIdentity>, Error> {
+        if self.is_open() {
+            return Ok(None);
+        }
+
+        if let Some(ClientCertificate(cert)) = certificate {
+            if let Some(collector) = self
+                .allowed
+                .iter()
+                .find(|collector| collector.certificate.as_ref() == Some(cert))
+            {
+                return Ok(Some(CollectorIdentity(collector.name.clone())));
+            }
+        }
+
+        let Some(authorization) = authorization else {
+            return Err(Error::CollectorUnauthenticated(
+                "this API requires a client certificate or a bearer token".into(),
+            ));
+        };
+        let token = authorization
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| {
+                Error::CollectorUnauthenticated("malformed authorization header".into())
+            })?;
+        let collector = verify_bearer_token(token, &self.allowed, SystemTime::now())
+            .map_err(|e| Error::CollectorUnauthenticated(e.to_string()))?;
+
+        Ok(Some(CollectorIdentity(collector.name.clone())))
+    }
+
+    /// Records that the report collector `owner` created the query `query_id`.
+    pub(super) fn bind(&self, query_id: QueryId, owner: Option<CollectorIdentity>) {
+        let mut owners = self.owners.lock().unwrap();
+        match owner {
+            Some(CollectorIdentity(name)) => owners.insert(query_id, name),
+            None => owners.remove(&query_id),
+        };
+    }
+
+    /// Checks that `collector` may use the query `query_id`. Queries that were created by a report
+    /// collector may only be used by it. Queries without an owner may be used by any report
+    /// collector that is allowed to use the query APIs.
+    ///
+    /// ## Errors
+    /// If the query was created by another report collector.
+    fn authorize(
+        &self,
+        query_id: QueryId,
+        collector: Option<&CollectorIdentity>,
+    ) -> Result<(), Error> {
+        if self.is_open() {
+            return Ok(());
+        }
+
+        match (self.owners.lock().unwrap().get(&query_id), collector) {
+            (None, _) => Ok(()),
+            (Some(owner), Some(CollectorIdentity(name))) if owner == name => Ok(()),
+            (Some(_), _) => Err(Error::QueryNotOwned(query_id)),
+        }
+    }
+}
+
+impl Default for ReportCollectors {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+/// Extractor for the report collector that sent a request to the query APIs.
+///
+/// Fails the request with HTTP 401 Unauthorized if report collectors are configured, but the
+/// request is not authenticated as one of them.
+#[derive(Clone, Default)]
+pub(super) struct Collector {
+    pub collectors: Arc<ReportCollectors>,
+    pub identity: Option<CollectorIdentity>,
+}
+
+impl Collector {
+    /// Runs `create` to create a query on behalf of this report collector, and binds the query
+    /// to it if that succeeds.
+    pub async fn create_query<F, E>(&self, create: F) -> Result<QueryId, E>
+    where
+        F: Future<Output = Result<QueryId, E>>,
+    {
+        let result = CREATING_COLLECTOR
+            .scope(self.identity.clone(), create)
+            .await;
+        if let Ok(query_id) = &result {
+            self.collectors.bind(*query_id, self.identity.clone());
+        }
+        result
+    }
+
+    /// ## Errors
+    /// If the query was created by another report collector.
+    pub fn authorize(&self, query_id: QueryId) -> Result<(), Error> {
+        self.collectors.authorize(query_id, self.identity.as_ref())
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Collector {
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(collectors) = req.extract::<Extension<Arc<ReportCollectors>>>().await?;
+        let identity = collectors.authenticate(
+            req.extensions().get::<ClientCertificate>(),
+            req.headers().get(AUTHORIZATION),
+        )?;
+        Ok(Self {
+            collectors,
+            identity,
+        })
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::{
+        config::TokenKey,
+        net::{sign_bearer_token, test::TEST_CERTS_DER},
+    };
+    use std::time::Duration;
+
+    fn collectors() -> ReportCollectors {
+        ReportCollectors::new(vec![
+            ReportCollectorConfig {
+                name: String::from("with-certificate"),
+                certificate: Some(Certificate(TEST_CERTS_DER[0].clone())),
+                token_key: None,
+            },
+            ReportCollectorConfig {
+                name: String::from("with-token"),
+                certificate: None,
+                token_key: Some(TokenKey::new(vec![7; 32])),
+            },
+        ])
+    }
+
+    fn bearer(name: &str, key: &TokenKey) -> HeaderValue {
+        let token = sign_bearer_token(name, key, SystemTime::now() + Duration::from_secs(60));
+        HeaderValue::try_from(format!("Bearer {token}")).unwrap()
+    }
+
+    fn identity(name: &str) -> CollectorIdentity {
+        CollectorIdentity(String::from(name))
+    }
+
+    #[test]
+    fn open_without_collectors() {
+        let collectors = ReportCollectors::default();
+        assert_eq!(None, collectors.authenticate(None, None).unwrap());
+        collectors.bind(QueryId, Some(identity("someone")));
+        collectors.authorize(QueryId, None).unwrap();
+    }
+
+    #[test]
+    fn authenticates_with_certificate() {
+        let collectors = collectors();
+        let cert = ClientCertificate(Certificate(TEST_CERTS_DER[0].clone()));
+        assert_eq!(
+            Some(identity("with-certificate")),
+            collectors.authenticate(Some(&cert), None).unwrap()
+        );
+
+        let unknown = ClientCertificate(Certificate(TEST_CERTS_DER[1].clone()));
+        assert!(matches!(
+            collectors.authenticate(Some(&unknown), None),
+            Err(Error::CollectorUnauthenticated(_))
+        ));
+    }
+
+    #[test]
+    fn authenticates_with_token() {
+        let collectors = collectors();
+        let header = bearer("with-token", &TokenKey::new(vec![7; 32]));
+        assert_eq!(
+            Some(identity("with-token")),
+            collectors.authenticate(None, Some(&header)).unwrap()
+        );
+
+        let forged = bearer("with-token", &TokenKey::new(vec![8; 32]));
+        assert!(matches!(
+            collectors.authenticate(None, Some(&forged)),
+            Err(Error::CollectorUnauthenticated(_))
+        ));
+        assert!(matches!(
+            collectors.authenticate(None, Some(&HeaderValue::from_static("Basic Zm9vOmJhcg=="))),
+            Err(Error::CollectorUnauthenticated(_))
+        ));
+    }
+
+    #[test]
+    fn requires_authentication() {
+        assert!(matches!(
+            collectors().authenticate(None, None),
+            Err(Error::CollectorUnauthenticated(_))
+        ));
+    }
+
+    #[test]
+    fn binds_queries_to_creator() {
+        let collectors = collectors();
+        collectors
+            .authorize(QueryId, Some(&identity("with-token")))
+            .unwrap();
+
+        collectors.bind(QueryId, Some(identity("with-certificate")));
+        collectors
+            .authorize(QueryId, Some(&identity("with-certificate")))
+            .unwrap();
+        assert!(matches!(
+            collectors.authorize(QueryId, Some(&identity("with-token"))),
+            Err(Error::QueryNotOwned(QueryId))
+        ));
+    }
+
+    #[tokio::test]
+    async fn tells_peers_about_creator() {
+        let collector = Collector {
+            collectors: Arc::new(collectors()),
+            identity: Some(identity("with-token")),
+        };
+        assert_eq!(None, creating_collector());
+        let query_id = collector
+            .create_query(async {
+                assert_eq!(Some(String::from("with-token")), creating_collector());
+                Ok::<_, ()>(QueryId)
+            })
+            .await
+            .unwrap();
+        assert!(matches!(
+            collector
+                .collectors
+                .authorize(query_id, Some(&identity("with-certificate"))),
+            Err(Error::QueryNotOwned(QueryId))
+        ));
+    }
+}
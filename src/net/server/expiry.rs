@@ -0,0 +1,118 @@
+use crate::{
+    error::BoxError,
+    telemetry::{labels::CERTIFICATE, metrics::TLS_CERTIFICATE_EXPIRY},
+};
+use metrics::gauge;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_rustls::rustls::Certificate;
+use tracing::warn;
+
+const SECS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+/// Returns the end of the validity period of `cert`.
+///
+/// # Errors
+/// If `cert` is not a valid X.509 certificate.
+fn not_after(cert: &Certificate) -> Result<SystemTime, BoxError> {
+    let (_, cert) = x509_parser::parse_x509_certificate(&cert.0)
+        .map_err(|e| format!("failed to parse certificate: {e}"))?;
+    let secs = u64::try_from(cert.validity().not_after.timestamp())?;
+    Ok(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Records the number of days left until each of the named `certificates` expires, and logs a
+/// warning for each that expires within `warning` from `now`.
+///
+/// Returns the names of the certificates that are about to expire, or expired already.
+pub(super) fn check_expiry<'a, I>(
+    certificates: I,
+    now: SystemTime,
+    warning: Duration,
+) -> Vec<String>
+where
+    I: IntoIterator<Item = (String, &'a Certificate)>,
+{
+    let mut expiring = Vec::new();
+    for (name, cert) in certificates {
+        let not_after = match not_after(cert) {
+            Ok(not_after) => not_after,
+            Err(e) => {
+                warn!("unable to check expiry of the {name} certificate: {e}");
+                continue;
+            }
+        };
+        let days_left = match not_after.duration_since(now) {
+            Ok(left) => left.as_secs_f64() / SECS_PER_DAY,
+            Err(e) => -e.duration().as_secs_f64() / SECS_PER_DAY,
+        };
+        gauge!(TLS_CERTIFICATE_EXPIRY, days_left, CERTIFICATE => name.clone());
+        if days_left * SECS_PER_DAY < warning.as_secs_f64() {
+            if days_left < 0.0 {
+                warn!("the {name} certificate expired {:.1} days ago", -days_left);
+            } else {
+                warn!("the {name} certificate expires in {days_left:.1} days");
+            }
+            expiring.push(name);
+        }
+    }
+    expiring
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::net::test::TEST_CERTS_DER;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// The test certificates are valid until 2123-04-20T20:44:42Z.
+    fn test_certs_expiry() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(4_837_697_082)
+    }
+
+    fn test_certs() -> Vec<(String, Certificate)> {
+        TEST_CERTS_DER
+            .iter()
+            .enumerate()
+            .map(|(i, der)| (format!("peer.{}", i + 1), Certificate(der.clone())))
+            .collect()
+    }
+
+    #[test]
+    fn reads_expiry() {
+        for (_, cert) in test_certs() {
+            assert_eq!(test_certs_expiry(), not_after(&cert).unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_certificate() {
+        assert!(not_after(&Certificate(b"not a certificate".to_vec())).is_err());
+    }
+
+    #[test]
+    fn warns_before_expiry() {
+        let certs = test_certs();
+        let named = || certs.iter().map(|(name, cert)| (name.clone(), cert));
+
+        let expiring = check_expiry(named(), test_certs_expiry() - 31 * DAY, 30 * DAY);
+        assert!(expiring.is_empty());
+
+        let expiring = check_expiry(named(), test_certs_expiry() - 29 * DAY, 30 * DAY);
+        assert_eq!(vec!["peer.1", "peer.2", "peer.3"], expiring);
+
+        let expiring = check_expiry(named(), test_certs_expiry() + DAY, 30 * DAY);
+        assert_eq!(3, expiring.len());
+    }
+
+    #[test]
+    fn skips_invalid_certificate() {
+        let invalid = Certificate(b"not a certificate".to_vec());
+        let expiring = check_expiry(
+            [(String::from("server"), &invalid)],
+            test_certs_expiry(),
+            30 * DAY,
+        );
+        assert!(expiring.is_empty());
+    }
+}
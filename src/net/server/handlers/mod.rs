@@ -2,16 +2,17 @@ mod echo;
 mod query;
 
 use crate::{
-    net::{http_serde, HttpTransport},
+    net::{http_serde, server::ReportCollectors, HttpTransport},
     sync::Arc,
 };
-use axum::Router;
+use axum::{Extension, Router};
 
-pub fn router(transport: Arc<HttpTransport>) -> Router {
+pub fn router(transport: Arc<HttpTransport>, collectors: Arc<ReportCollectors>) -> Router {
     echo::router().nest(
         http_serde::query::BASE_AXUM_PATH,
         Router::new()
             .merge(query::query_router(Arc::clone(&transport)))
-            .merge(query::h2h_router(transport)),
+            .merge(query::h2h_router(transport))
+            .layer(Extension(collectors)),
     )
 }
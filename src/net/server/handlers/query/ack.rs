@@ -0,0 +1,117 @@
+use crate::{
+    helpers::Transport,
+    net::{
+        http_serde,
+        server::{ClientIdentity, Error},
+        HttpTransport,
+    },
+    sync::Arc,
+};
+use axum::{routing::post, Extension, Router};
+
+#[allow(clippy::unused_async)] // axum doesn't like synchronous handler
+async fn handler(
+    transport: Extension<Arc<HttpTransport>>,
+    from: Extension<ClientIdentity>,
+    req: http_serde::query::ack::Request,
+) -> Result<(), Error> {
+    let transport = Transport::clone_ref(&*transport);
+    transport.receive_ack(req.query_id, req.gate, **from, req.received);
+    Ok(())
+}
+
+pub fn router(transport: Arc<HttpTransport>) -> Router {
+    Router::new()
+        .route(http_serde::query::ack::AXUM_PATH, post(handler))
+        .layer(Extension(transport))
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::{
+        helpers::HelperIdentity,
+        net::{
+            server::handlers::query::{
+                test_helpers::{assert_req_fails_with, IntoFailingReq},
+                MaybeExtensionExt,
+            },
+            test::TestServer,
+        },
+        protocol::{
+            step::{Gate, StepNarrow},
+            QueryId,
+        },
+        sync::atomic::Ordering,
+    };
+    use axum::http::Request;
+    use hyper::{Body, StatusCode};
+
+    #[tokio::test]
+    async fn ack() {
+        let TestServer { transport, .. } = TestServer::builder().build().await;
+
+        let step = Gate::default().narrow("test");
+        let acked = transport.acknowledged(HelperIdentity::TWO, (QueryId, step.clone()));
+        let req = http_serde::query::ack::Request::new(QueryId, step, 42);
+
+        handler(
+            Extension(Arc::clone(&transport)),
+            Extension(ClientIdentity(HelperIdentity::TWO)),
+            req,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(42, acked.load(Ordering::Acquire));
+    }
+
+    struct OverrideReq {
+        client_id: Option<ClientIdentity>,
+        received: String,
+    }
+
+    impl IntoFailingReq for OverrideReq {
+        fn into_req(self, port: u16) -> Request<Body> {
+            let uri = format!(
+                "http://localhost:{}{}/{}/ack/{}?received={}",
+                port,
+                http_serde::query::BASE_AXUM_PATH,
+                QueryId.as_ref(),
+                Gate::default().narrow("test").as_ref(),
+                self.received,
+            );
+            hyper::Request::post(uri)
+                .maybe_extension(self.client_id)
+                .body(Body::empty())
+                .unwrap()
+        }
+    }
+
+    impl Default for OverrideReq {
+        fn default() -> Self {
+            Self {
+                client_id: Some(ClientIdentity(HelperIdentity::ONE)),
+                received: "42".into(),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_offset_fails() {
+        let req = OverrideReq {
+            received: "-1".into(),
+            ..Default::default()
+        };
+        assert_req_fails_with(req, StatusCode::UNPROCESSABLE_ENTITY).await;
+    }
+
+    #[tokio::test]
+    async fn auth_required() {
+        let req = OverrideReq {
+            client_id: None,
+            ..Default::default()
+        };
+        assert_req_fails_with(req, StatusCode::UNAUTHORIZED).await;
+    }
+}
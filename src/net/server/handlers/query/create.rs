@@ -1,6 +1,6 @@
 use crate::{
     helpers::Transport,
-    net::{http_serde, Error, HttpTransport},
+    net::{http_serde, server::collectors::Collector, Error, HttpTransport},
     query::NewQueryError,
     sync::Arc,
 };
@@ -8,13 +8,17 @@ use axum::{routing::post, Extension, Json, Router};
 use hyper::StatusCode;
 
 /// Takes details from the HTTP request and creates a `[TransportCommand]::CreateQuery` that is sent
-/// to the [`HttpTransport`].
+/// to the [`HttpTransport`]. The query is bound to the report collector that created it.
 async fn handler(
     transport: Extension<Arc<HttpTransport>>,
+    collector: Collector,
     req: http_serde::query::create::Request,
 ) -> Result<Json<http_serde::query::create::ResponseBody>, Error> {
     let transport = Transport::clone_ref(&*transport);
-    match transport.receive_query(req.query_config).await {
+    match collector
+        .create_query(transport.receive_query(req.query_config))
+        .await
+    {
         Ok(query_id) => Ok(Json(http_serde::query::create::ResponseBody { query_id })),
         Err(err @ NewQueryError::State { .. }) => {
             Err(Error::application(StatusCode::CONFLICT, err))
@@ -35,7 +39,7 @@ mod tests {
     use crate::{
         ff::FieldType,
         helpers::{
-            query::{IpaQueryConfig, QueryConfig, QueryType},
+            query::{IpaQueryConfig, QueryConfig, QueryType, StepStreamConfig},
             TransportCallbacks,
         },
         net::{
@@ -114,6 +118,7 @@ mod tests {
                 num_multi_bits: 3,
                 plaintext_match_keys: true,
             }),
+            step_stream: StepStreamConfig::default(),
         })
         .await;
     }
@@ -1,6 +1,6 @@
 use crate::{
     helpers::Transport,
-    net::{http_serde, Error, HttpTransport},
+    net::{http_serde, server::collectors::Collector, Error, HttpTransport},
     sync::Arc,
 };
 use axum::{routing::post, Extension, Router};
@@ -8,8 +8,10 @@ use hyper::StatusCode;
 
 async fn handler(
     transport: Extension<Arc<HttpTransport>>,
+    collector: Collector,
     req: http_serde::query::input::Request,
 ) -> Result<(), Error> {
+    collector.authorize(req.query_input.query_id)?;
     let transport = Transport::clone_ref(&*transport);
     transport
         .query_input(req.query_input)
@@ -27,15 +29,29 @@ pub fn router(transport: Arc<HttpTransport>) -> Router {
 mod tests {
     use super::*;
     use crate::{
-        helpers::{query::QueryInput, BytesStream, TransportCallbacks},
+        config::{ReportCollectorConfig, TokenKey},
+        ff::FieldType,
+        helpers::{
+            query::{QueryConfig, QueryInput, QueryType},
+            BytesStream, TransportCallbacks,
+        },
         net::{
             server::handlers::query::test_helpers::{assert_req_fails_with, IntoFailingReq},
+            sign_bearer_token,
             test::TestServer,
         },
         protocol::QueryId,
     };
     use axum::http::Request;
-    use hyper::{Body, StatusCode};
+    use hyper::{
+        header::AUTHORIZATION,
+        http::uri::{Authority, Scheme},
+        Body, StatusCode,
+    };
+    use std::{
+        future::ready,
+        time::{Duration, SystemTime},
+    };
 
     #[tokio::test]
     async fn input_test() {
@@ -56,7 +72,62 @@ mod tests {
             query_id: expected_query_id,
             input_stream: expected_input.to_vec().into(),
         });
-        handler(Extension(transport), req).await.unwrap();
+        handler(Extension(transport), Collector::default(), req)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn input_is_bound_to_query_creator() {
+        let cb = TransportCallbacks {
+            receive_query: Box::new(|_transport, _query_config| Box::pin(ready(Ok(QueryId)))),
+            query_input: Box::new(|_transport, _query_input| Box::pin(ready(Ok(())))),
+            ..Default::default()
+        };
+        let key = TokenKey::new(vec![1; 32]);
+        let collectors = ["owner", "other"]
+            .map(|name| ReportCollectorConfig {
+                name: name.to_owned(),
+                certificate: None,
+                token_key: Some(key.clone()),
+            })
+            .to_vec();
+        let TestServer { server, .. } = TestServer::builder()
+            .disable_https()
+            .with_callbacks(cb)
+            .with_report_collectors(collectors)
+            .build()
+            .await;
+        let authenticated = |mut req: Request<Body>, name: &str| {
+            let expires = SystemTime::now() + Duration::from_secs(60);
+            let token = sign_bearer_token(name, &key, expires);
+            req.headers_mut()
+                .insert(AUTHORIZATION, format!("Bearer {token}").try_into().unwrap());
+            req
+        };
+
+        let create = http_serde::query::create::Request::new(
+            QueryConfig::new(QueryType::TestMultiply, FieldType::Fp31, 1).unwrap(),
+        )
+        .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
+        .unwrap();
+        let resp = server.handle_req(authenticated(create, "owner")).await;
+        assert_eq!(StatusCode::OK, resp.status());
+
+        let input = || {
+            http_serde::query::input::Request::new(QueryInput {
+                query_id: QueryId,
+                input_stream: vec![4u8; 4].into(),
+            })
+            .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
+            .unwrap()
+        };
+        let resp = server.handle_req(input()).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+        let resp = server.handle_req(authenticated(input(), "other")).await;
+        assert_eq!(StatusCode::FORBIDDEN, resp.status());
+        let resp = server.handle_req(authenticated(input(), "owner")).await;
+        assert_eq!(StatusCode::OK, resp.status());
     }
 
     struct OverrideReq {
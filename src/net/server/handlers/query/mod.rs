@@ -1,3 +1,4 @@
+mod ack;
 mod create;
 mod input;
 mod prepare;
@@ -44,7 +45,8 @@ pub fn query_router(transport: Arc<HttpTransport>) -> Router {
 pub fn h2h_router(transport: Arc<HttpTransport>) -> Router {
     Router::new()
         .merge(prepare::router(Arc::clone(&transport)))
-        .merge(step::router(transport))
+        .merge(step::router(Arc::clone(&transport)))
+        .merge(ack::router(transport))
         .layer(layer_fn(HelperAuthentication::new))
 }
 
@@ -1,20 +1,29 @@
 use std::sync::Arc;
 
 use crate::{
-    net::{http_serde, server::ClientIdentity, HttpTransport},
+    net::{
+        http_serde,
+        server::{collectors::CollectorIdentity, ClientIdentity, ReportCollectors},
+        HttpTransport,
+    },
     query::PrepareQueryError,
 };
 use axum::{response::IntoResponse, routing::post, Extension, Router};
 use hyper::StatusCode;
 
 /// Called by whichever peer helper is the leader for an individual query, to initiatialize
-/// processing of that query.
+/// processing of that query. The query is bound to the report collector that created it on the
+/// leader.
 async fn handler(
     transport: Extension<Arc<HttpTransport>>,
     _from: Extension<ClientIdentity>, // require that client is an authenticated helper
+    collectors: Extension<Arc<ReportCollectors>>,
     req: http_serde::query::prepare::Request,
 ) -> Result<(), PrepareQueryError> {
-    Arc::clone(&transport).prepare_query(req.data).await
+    let query_id = req.data.query_id;
+    Arc::clone(&transport).prepare_query(req.data).await?;
+    collectors.bind(query_id, req.owner.map(CollectorIdentity));
+    Ok(())
 }
 
 impl IntoResponse for PrepareQueryError {
@@ -75,6 +84,7 @@ mod tests {
         handler(
             Extension(transport),
             Extension(ClientIdentity(HelperIdentity::TWO)),
+            Extension(Arc::new(ReportCollectors::default())),
             req.clone(),
         )
         .await
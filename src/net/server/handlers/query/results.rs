@@ -2,7 +2,11 @@ use std::sync::Arc;
 
 use crate::{
     helpers::Transport,
-    net::{http_serde, server::Error, HttpTransport},
+    net::{
+        http_serde,
+        server::{collectors::Collector, Error},
+        HttpTransport,
+    },
 };
 use axum::{routing::get, Extension, Router};
 use hyper::StatusCode;
@@ -10,8 +14,10 @@ use hyper::StatusCode;
 /// Handles the completion of the query by blocking the sender until query is completed.
 async fn handler(
     transport: Extension<Arc<HttpTransport>>,
+    collector: Collector,
     req: http_serde::query::results::Request,
 ) -> Result<Vec<u8>, Error> {
+    collector.authorize(req.query_id)?;
     // TODO: we may be able to stream the response
     let transport = Transport::clone_ref(&*transport);
     match transport.complete_query(req.query_id).await {
@@ -63,7 +69,9 @@ mod tests {
         };
         let TestServer { transport, .. } = TestServer::builder().with_callbacks(cb).build().await;
         let req = http_serde::query::results::Request::new(QueryId);
-        let results = handler(Extension(transport), req.clone()).await.unwrap();
+        let results = handler(Extension(transport), Collector::default(), req.clone())
+            .await
+            .unwrap();
         assert_eq!(results, expected_results.into_bytes());
     }
 
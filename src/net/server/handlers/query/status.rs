@@ -2,20 +2,36 @@ use std::sync::Arc;
 
 use crate::{
     helpers::Transport,
-    net::{http_serde::query::status, server::Error, HttpTransport},
+    net::{
+        http_serde::query::status,
+        server::{collectors::Collector, Error},
+        HttpTransport,
+    },
+    query::QueryStatus,
 };
 use axum::{routing::get, Extension, Json, Router};
 use hyper::StatusCode;
 
 async fn handler(
     transport: Extension<Arc<HttpTransport>>,
+    collector: Collector,
     req: status::Request,
 ) -> Result<Json<status::ResponseBody>, Error> {
+    collector.authorize(req.query_id)?;
     let transport = Transport::clone_ref(&*transport);
-    match transport.query_status(req.query_id).await {
-        Ok(state) => Ok(Json(status::ResponseBody { status: state })),
-        Err(e) => Err(Error::application(StatusCode::INTERNAL_SERVER_ERROR, e)),
-    }
+    let status = Arc::clone(&transport)
+        .query_status(req.query_id)
+        .await
+        .map_err(|e| Error::application(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let progress = match status {
+        QueryStatus::Running => transport
+            .query_progress(req.query_id)
+            .await
+            .map_err(|e| Error::application(StatusCode::INTERNAL_SERVER_ERROR, e))?,
+        _ => None,
+    };
+
+    Ok(Json(status::ResponseBody { status, progress }))
 }
 
 pub fn router(transport: Arc<HttpTransport>) -> Router {
@@ -37,11 +53,20 @@ mod tests {
             test::TestServer,
         },
         protocol::QueryId,
-        query::QueryStatus,
+        query::QueryProgress,
     };
     use axum::http::Request;
     use hyper::StatusCode;
 
+    fn progress() -> QueryProgress {
+        QueryProgress {
+            stage: "attribution".into(),
+            gate: "protocol/attribution/credit".into(),
+            records_sent: 10,
+            total_records: Some(100),
+        }
+    }
+
     #[tokio::test]
     async fn status_test() {
         let expected_status = QueryStatus::Running;
@@ -51,14 +76,47 @@ mod tests {
                 assert_eq!(query_id, expected_query_id);
                 Box::pin(ready(Ok(expected_status)))
             }),
+            query_progress: Box::new(move |_transport, query_id| {
+                assert_eq!(query_id, expected_query_id);
+                Box::pin(ready(Ok(Some(progress()))))
+            }),
             ..Default::default()
         };
         let TestServer { transport, .. } = TestServer::builder().with_callbacks(cb).build().await;
         let req = http_serde::query::status::Request::new(QueryId);
-        let response = handler(Extension(transport), req.clone()).await.unwrap();
+        let response = handler(Extension(transport), Collector::default(), req.clone())
+            .await
+            .unwrap();
 
-        let Json(http_serde::query::status::ResponseBody { status }) = response;
+        let Json(http_serde::query::status::ResponseBody {
+            status,
+            progress: p,
+        }) = response;
         assert_eq!(status, expected_status);
+        assert_eq!(p, Some(progress()));
+    }
+
+    #[tokio::test]
+    async fn no_progress_unless_running() {
+        let cb = TransportCallbacks {
+            query_status: Box::new(move |_transport, _query_id| {
+                Box::pin(ready(Ok(QueryStatus::Completed)))
+            }),
+            ..Default::default()
+        };
+        let TestServer { transport, .. } = TestServer::builder().with_callbacks(cb).build().await;
+        let req = http_serde::query::status::Request::new(QueryId);
+        let Json(body) = handler(Extension(transport), Collector::default(), req)
+            .await
+            .unwrap();
+
+        assert_eq!(QueryStatus::Completed, body.status);
+        assert_eq!(None, body.progress);
+        // the body is the same as before progress was reported.
+        assert_eq!(
+            r#"{"status":"Completed"}"#,
+            serde_json::to_string(&body).unwrap()
+        );
     }
 
     struct OverrideReq {
@@ -7,7 +7,7 @@ use crate::{
     },
     sync::Arc,
 };
-use axum::{routing::post, Extension, Router};
+use axum::{http::StatusCode, routing::post, Extension, Router};
 
 #[allow(clippy::unused_async)] // axum doesn't like synchronous handler
 async fn handler(
@@ -16,8 +16,9 @@ async fn handler(
     req: http_serde::query::step::Request<BodyStream>,
 ) -> Result<(), Error> {
     let transport = Transport::clone_ref(&*transport);
-    transport.receive_stream(req.query_id, req.gate, **from, req.body);
-    Ok(())
+    transport
+        .receive_stream(req.query_id, req.gate, **from, req.offset, req.body)
+        .map_err(|e| Error::application(StatusCode::CONFLICT, e))
 }
 
 pub fn router(transport: Arc<HttpTransport>) -> Router {
@@ -58,7 +59,7 @@ mod tests {
         let step = Gate::default().narrow("test");
         let payload = vec![213; DATA_LEN * MESSAGE_PAYLOAD_SIZE_BYTES];
         let req =
-            http_serde::query::step::Request::new(QueryId, step.clone(), payload.clone().into());
+            http_serde::query::step::Request::new(QueryId, step.clone(), 0, payload.clone().into());
 
         handler(
             Extension(Arc::clone(&transport)),
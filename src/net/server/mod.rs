@@ -1,11 +1,17 @@
+mod collectors;
+mod expiry;
 mod handlers;
 
+pub(super) use collectors::{creating_collector, QUERY_OWNER_HEADER};
+
+use collectors::{ClientCertificate, ReportCollectors};
+
 use crate::{
-    config::{NetworkConfig, ServerConfig, TlsConfig},
+    config::{NetworkConfig, ServerConfig, StepTransport, TlsConfig, TlsReloadConfig},
     error::BoxError,
-    helpers::HelperIdentity,
-    net::{Error, HttpTransport},
-    sync::Arc,
+    helpers::{HelperIdentity, Transport},
+    net::{discovery::Signed, multiplex, Error, HttpTransport},
+    sync::{Arc, Mutex, RwLock},
     task::JoinHandle,
     telemetry::metrics::{web::RequestProtocolVersion, REQUESTS_RECEIVED},
 };
@@ -25,7 +31,12 @@ use futures::{
     future::{ready, BoxFuture, Either, Ready},
     Future, FutureExt,
 };
-use hyper::{header::HeaderName, server::conn::AddrStream, Request};
+use hyper::{
+    header::HeaderName,
+    http::uri::{Scheme, Uri},
+    server::conn::AddrStream,
+    Request,
+};
 use metrics::increment_counter;
 use rustls_pemfile::Item;
 use std::{
@@ -33,7 +44,9 @@ use std::{
     io,
     net::{Ipv4Addr, SocketAddr, TcpListener},
     ops::Deref,
+    path::Path,
     task::{Context, Poll},
+    time::{Duration, SystemTime},
 };
 use tokio_rustls::{
     rustls::{
@@ -44,16 +57,25 @@ use tokio_rustls::{
 };
 use tower::{layer::layer_fn, Service};
 use tower_http::trace::TraceLayer;
-use tracing::{error, Span};
+use tracing::{error, info, Span};
 
 use ::tokio::{
     fs,
     io::{AsyncRead, AsyncWrite},
+    time::sleep,
 };
 
 #[cfg(all(feature = "shuttle", test))]
 use shuttle::future as tokio;
 
+// This should probably come from the server config.
+// Note that listening on 0.0.0.0 requires accepting a MacOS security
+// warning on each test run.
+#[cfg(test)]
+const BIND_ADDRESS: Ipv4Addr = Ipv4Addr::LOCALHOST;
+#[cfg(not(test))]
+const BIND_ADDRESS: Ipv4Addr = Ipv4Addr::UNSPECIFIED;
+
 pub trait TracingSpanMaker: Send + Sync + Clone + 'static {
     fn make_span(&self) -> Span;
 }
@@ -74,13 +96,29 @@ impl TracingSpanMaker for () {
     }
 }
 
+/// Network configuration that can be replaced while the server is running, to change the
+/// certificates that identify peer helpers.
+pub(super) type SharedNetworkConfig = Arc<RwLock<NetworkConfig>>;
+
+const HTTP_ALPN: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// TLS configurations of the listeners that were started, kept to reload them.
+#[derive(Default)]
+struct TlsListeners {
+    http: Option<RustlsConfig>,
+    step: Option<RustlsConfig>,
+}
+
 /// IPA helper web service
 ///
 /// `MpcHelperServer` handles requests from both peer helpers and external clients.
+#[derive(Clone)]
 pub struct MpcHelperServer {
     transport: Arc<HttpTransport>,
     config: ServerConfig,
-    network_config: NetworkConfig,
+    network_config: SharedNetworkConfig,
+    collectors: Arc<ReportCollectors>,
+    tls: Arc<Mutex<TlsListeners>>,
 }
 
 impl MpcHelperServer {
@@ -89,15 +127,22 @@ impl MpcHelperServer {
         config: ServerConfig,
         network_config: NetworkConfig,
     ) -> Self {
+        let collectors = Arc::new(ReportCollectors::new(config.report_collectors.clone()));
         MpcHelperServer {
             transport,
             config,
-            network_config,
+            network_config: Arc::new(RwLock::new(network_config)),
+            collectors,
+            tls: Arc::new(Mutex::new(TlsListeners::default())),
         }
     }
 
+    fn network_config(&self) -> NetworkConfig {
+        self.network_config.read().unwrap().clone()
+    }
+
     fn router(&self) -> Router {
-        handlers::router(Arc::clone(&self.transport))
+        handlers::router(Arc::clone(&self.transport), Arc::clone(&self.collectors))
     }
 
     #[cfg(all(test, unit_test))]
@@ -125,14 +170,6 @@ impl MpcHelperServer {
         listener: Option<TcpListener>,
         tracing: T,
     ) -> (SocketAddr, JoinHandle<()>) {
-        // This should probably come from the server config.
-        // Note that listening on 0.0.0.0 requires accepting a MacOS security
-        // warning on each test run.
-        #[cfg(test)]
-        const BIND_ADDRESS: Ipv4Addr = Ipv4Addr::LOCALHOST;
-        #[cfg(not(test))]
-        const BIND_ADDRESS: Ipv4Addr = Ipv4Addr::UNSPECIFIED;
-
         let svc = self.router().layer(
             TraceLayer::new_for_http()
                 .make_span_with(move |_request: &hyper::Request<hyper::Body>| tracing.make_span())
@@ -158,12 +195,11 @@ impl MpcHelperServer {
                 spawn_server(axum_server::bind(addr), handle.clone(), svc).await
             }
             (false, Some(listener)) => {
-                let rustls_config = rustls_config(&self.config, &self.network_config)
-                    .await
-                    .expect("invalid TLS configuration");
+                let rustls_config = self.rustls_config(&HTTP_ALPN).await;
+                self.tls.lock().unwrap().http = Some(rustls_config.clone());
                 spawn_server(
                     axum_server::from_tcp_rustls(listener, rustls_config).map(|a| {
-                        ClientCertRecognizingAcceptor::new(a, self.network_config.clone())
+                        ClientCertRecognizingAcceptor::new(a, Arc::clone(&self.network_config))
                     }),
                     handle.clone(),
                     svc.into_make_service(),
@@ -172,12 +208,11 @@ impl MpcHelperServer {
             }
             (false, None) => {
                 let addr = SocketAddr::new(BIND_ADDRESS.into(), self.config.port.unwrap_or(0));
-                let rustls_config = rustls_config(&self.config, &self.network_config)
-                    .await
-                    .expect("invalid TLS configuration");
+                let rustls_config = self.rustls_config(&HTTP_ALPN).await;
+                self.tls.lock().unwrap().http = Some(rustls_config.clone());
                 spawn_server(
                     axum_server::bind_rustls(addr, rustls_config).map(|a| {
-                        ClientCertRecognizingAcceptor::new(a, self.network_config.clone())
+                        ClientCertRecognizingAcceptor::new(a, Arc::clone(&self.network_config))
                     }),
                     handle.clone(),
                     svc.into_make_service(),
@@ -209,6 +244,227 @@ impl MpcHelperServer {
     ) -> impl Future<Output = (SocketAddr, JoinHandle<()>)> + '_ {
         self.start_on(None, tracing)
     }
+
+    /// Starts accepting multiplexed step connections from peer helpers, if the network
+    /// configuration selects [`StepTransport::Stream`]. Returns `None` otherwise.
+    ///
+    /// If `listener` is provided, accepts connections on the supplied socket. Otherwise, listens on
+    /// the stream port this helper has in the network configuration.
+    ///
+    /// # Errors
+    /// If `listener` is not provided and this helper has no stream port in the network
+    /// configuration.
+    ///
+    /// # Panics
+    /// If the server TLS configuration is not valid, or the stream port can't be bound.
+    pub async fn start_streams_on(
+        &self,
+        listener: Option<TcpListener>,
+    ) -> Result<Option<(SocketAddr, JoinHandle<()>)>, Error> {
+        let network_config = self.network_config();
+        if network_config.client.step_transport != StepTransport::Stream {
+            return Ok(None);
+        }
+
+        let listener = if let Some(listener) = listener {
+            listener
+                .set_nonblocking(true)
+                .and_then(|()| ::tokio::net::TcpListener::from_std(listener))
+        } else {
+            let port = network_config.peers()[Transport::identity(&self.transport)]
+                .required_stream_port()?;
+            ::tokio::net::TcpListener::bind(SocketAddr::new(BIND_ADDRESS.into(), port)).await
+        }
+        .expect("Failed to bind step listener");
+        let tls = if self.config.disable_https {
+            None
+        } else {
+            let rustls_config = self.rustls_config(&[multiplex::ALPN]).await;
+            self.tls.lock().unwrap().step = Some(rustls_config.clone());
+            Some(rustls_config)
+        };
+
+        let bound_addr = listener.local_addr().expect("Failed to bind step listener");
+        #[cfg(not(test))] // reduce spam in test output
+        tracing::info!("accepting step connections on {bound_addr}");
+        let task_handle = tokio::spawn(multiplex::serve(
+            listener,
+            tls,
+            Arc::clone(&self.network_config),
+            Arc::clone(&self.transport),
+        ));
+        Ok(Some((bound_addr, task_handle)))
+    }
+
+    /// # Panics
+    /// If the server TLS configuration is not valid.
+    async fn rustls_config(&self, alpn_protocols: &[&[u8]]) -> RustlsConfig {
+        rustls_config(&self.config, &self.network_config(), alpn_protocols)
+            .await
+            .expect("invalid TLS configuration")
+    }
+
+    /// Reloads the server certificate and key from the server configuration, and makes
+    /// `network_config` the source of the peer URLs and certificates.
+    ///
+    /// Listeners that were started use the new certificates for connections accepted from now on,
+    /// and clients use them for new connections to the peers. Established connections are kept.
+    ///
+    /// # Errors
+    /// If the TLS configuration is not valid. The certificates in use are kept in that case.
+    ///
+    /// # Panics
+    /// If the internal locks are poisoned.
+    pub async fn reload_tls(&self, network_config: NetworkConfig) -> Result<(), BoxError> {
+        let identity = if self.config.disable_https {
+            crate::net::ClientIdentity::Helper(Transport::identity(&self.transport))
+        } else {
+            let http = rustls_server_config(&self.config, &network_config, &HTTP_ALPN).await?;
+            let step =
+                rustls_server_config(&self.config, &network_config, &[multiplex::ALPN]).await?;
+            let (http_tls, step_tls) = {
+                let listeners = self.tls.lock().unwrap();
+                (listeners.http.clone(), listeners.step.clone())
+            };
+            if let Some(http_tls) = http_tls {
+                http_tls.reload_from_config(Arc::new(http));
+            }
+            if let Some(step_tls) = step_tls {
+                step_tls.reload_from_config(Arc::new(step));
+            }
+            crate::net::ClientIdentity::Certificate(certificate_and_key(&self.config).await?)
+        };
+
+        self.transport.reload_clients(&network_config, &identity)?;
+        *self.network_config.write().unwrap() = network_config;
+        Ok(())
+    }
+
+    /// Periodically checks the server certificate and the peer certificates for changes, and
+    /// reloads them with [`Self::reload_tls`] when they change. Peer certificates are re-read from
+    /// the network configuration file, if `reload` names one.
+    ///
+    /// Every check also records the number of days left until each certificate expires, and logs a
+    /// warning if any of them expires within the configured warning period.
+    #[must_use]
+    pub fn watch_tls(&self, reload: TlsReloadConfig) -> JoinHandle<()> {
+        let server = self.clone();
+        tokio::spawn(async move {
+            let mut current = None;
+            loop {
+                match server
+                    .read_tls_material(reload.network_config_path.as_deref())
+                    .await
+                {
+                    Ok((network_config, material)) => {
+                        material.check_expiry(reload.expiry_warning);
+                        match &current {
+                            None => current = Some(material),
+                            Some(current_material) if *current_material == material => {}
+                            Some(_) => match server.reload_tls(network_config).await {
+                                Ok(()) => {
+                                    info!("reloaded TLS certificates");
+                                    current = Some(material);
+                                }
+                                Err(e) => error!("failed to reload TLS certificates: {e}"),
+                            },
+                        }
+                    }
+                    Err(e) => error!("failed to read TLS certificates: {e}"),
+                }
+                sleep(reload.interval).await;
+            }
+        })
+    }
+
+    /// Periodically reads the network configuration from `discovery` again, and applies it with
+    /// [`Self::reload_tls`] when it changes. Changes are applied between queries: if a query is in
+    /// progress, they wait for it to finish.
+    #[must_use]
+    pub fn watch_discovery(&self, discovery: Signed, interval: Duration) -> JoinHandle<()> {
+        let server = self.clone();
+        tokio::spawn(async move {
+            let mut pending = None;
+            loop {
+                sleep(interval).await;
+                match discovery.refresh().await {
+                    Ok(Some(network_config)) => {
+                        info!("discovered a new network configuration");
+                        pending = Some(network_config);
+                    }
+                    Ok(None) => {}
+                    Err(e) => error!("failed to refresh peer discovery: {e}"),
+                }
+                if pending.is_none() || !Arc::clone(&server.transport).is_idle().await {
+                    continue;
+                }
+                let Some(network_config) = pending.take() else {
+                    continue;
+                };
+                let network_config = network_config.override_scheme(&server.scheme());
+                match server.reload_tls(network_config).await {
+                    Ok(()) => info!("applied the discovered network configuration"),
+                    Err(e) => error!("failed to apply the discovered network configuration: {e}"),
+                }
+            }
+        })
+    }
+
+    fn scheme(&self) -> Scheme {
+        if self.config.disable_https {
+            Scheme::HTTP
+        } else {
+            Scheme::HTTPS
+        }
+    }
+
+    async fn read_tls_material(
+        &self,
+        network_config_path: Option<&Path>,
+    ) -> Result<(NetworkConfig, TlsMaterial), BoxError> {
+        let network_config = match network_config_path {
+            Some(path) => NetworkConfig::from_toml_str(&fs::read_to_string(path).await?)?
+                .override_scheme(&self.scheme()),
+            None => self.network_config(),
+        };
+        let identity = if self.config.disable_https {
+            None
+        } else {
+            Some(certificate_and_key(&self.config).await?)
+        };
+        let material = TlsMaterial {
+            identity,
+            peers: network_config
+                .peers()
+                .clone()
+                .map(|peer| (peer.url, peer.certificate)),
+        };
+        Ok((network_config, material))
+    }
+}
+
+/// Certificate chain and private key of this helper, and the URLs and certificates of its peers.
+/// The server is reconfigured when any of them changes.
+#[derive(PartialEq)]
+struct TlsMaterial {
+    identity: Option<(Vec<Certificate>, PrivateKey)>,
+    peers: [(Uri, Option<Certificate>); 3],
+}
+
+impl TlsMaterial {
+    fn check_expiry(&self, warning: std::time::Duration) {
+        let server = self
+            .identity
+            .iter()
+            .filter_map(|(chain, _)| chain.first())
+            .map(|cert| (String::from("server"), cert));
+        let peers = self
+            .peers
+            .iter()
+            .zip(HelperIdentity::make_three())
+            .filter_map(|((_, cert), id)| Some((format!("peer.{}", u8::from(id)), cert.as_ref()?)));
+        expiry::check_expiry(server.chain(peers), SystemTime::now(), warning);
+    }
 }
 
 async fn spawn_server<A>(
@@ -289,7 +545,23 @@ async fn certificate_and_key(
 async fn rustls_config(
     config: &ServerConfig,
     network: &NetworkConfig,
+    alpn_protocols: &[&[u8]],
 ) -> Result<RustlsConfig, BoxError> {
+    let config = rustls_server_config(config, network, alpn_protocols).await?;
+
+    Ok(RustlsConfig::from_config(Arc::new(config)))
+}
+
+/// Create the native rustls configuration for the `ServerConfig`, which accepts client
+/// certificates of the helpers in `network` and negotiates one of `alpn_protocols`.
+///
+/// # Errors
+/// If there is a problem with the TLS configuration.
+async fn rustls_server_config(
+    config: &ServerConfig,
+    network: &NetworkConfig,
+    alpn_protocols: &[&[u8]],
+) -> Result<RustlsServerConfig, BoxError> {
     let (cert, key) = certificate_and_key(config).await?;
 
     let mut trusted_certs = RootCertStore::empty();
@@ -303,16 +575,24 @@ async fn rustls_config(
         // configuration errors.
         trusted_certs.add(cert)?;
     }
+    // Report collectors may authenticate with client certificates as well. The query APIs tell
+    // them apart from helpers.
+    for cert in config
+        .report_collectors
+        .iter()
+        .filter_map(|collector| collector.certificate.as_ref())
+    {
+        trusted_certs.add(cert)?;
+    }
     let verifier = AllowAnyAnonymousOrAuthenticatedClient::new(trusted_certs);
 
     let mut config = RustlsServerConfig::builder()
         .with_safe_defaults()
         .with_client_cert_verifier(verifier.boxed())
         .with_single_cert(cert, key)?;
+    config.alpn_protocols = alpn_protocols.iter().map(|p| p.to_vec()).collect();
 
-    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
-
-    Ok(RustlsConfig::from_config(Arc::new(config)))
+    Ok(config)
 }
 
 /// Axum `Extension` indicating the authenticated remote helper identity, if any.
@@ -322,7 +602,7 @@ async fn rustls_config(
 // to avoid possible confusion about how many times the return from `req.extensions().get()` must be
 // unwrapped to ensure valid authentication.
 #[derive(Clone, Copy, Debug)]
-struct ClientIdentity(pub HelperIdentity);
+pub(super) struct ClientIdentity(pub HelperIdentity);
 
 impl Deref for ClientIdentity {
     type Target = HelperIdentity;
@@ -334,27 +614,42 @@ impl Deref for ClientIdentity {
 
 /// `Accept`or that sets an axum `Extension` indiciating the authenticated remote helper identity.
 #[derive(Clone)]
-struct ClientCertRecognizingAcceptor {
+pub(super) struct ClientCertRecognizingAcceptor {
     inner: RustlsAcceptor,
-    network_config: Arc<NetworkConfig>,
+    network_config: SharedNetworkConfig,
 }
 
 impl ClientCertRecognizingAcceptor {
-    fn new(inner: RustlsAcceptor, network_config: NetworkConfig) -> Self {
+    fn new(inner: RustlsAcceptor, network_config: SharedNetworkConfig) -> Self {
         Self {
             inner,
-            network_config: Arc::new(network_config),
+            network_config,
         }
     }
 
     // This can't be a method (at least not that takes `&self`) because it needs to go in a 'static future.
-    fn identify_client(
+    pub(super) fn identify_client(
         network_config: &NetworkConfig,
         cert_option: Option<&Certificate>,
     ) -> Option<ClientIdentity> {
         let Some(cert) = cert_option else {
             return None;
         };
+        let id = Self::identify_helper(network_config, cert);
+        if id.is_none() {
+            // It might be nice to log something here. We could log the certificate base64?
+            error!(
+                "A client certificate was presented that does not match a known helper. Certificate: {}",
+                BASE64.encode(cert),
+            );
+        }
+        id
+    }
+
+    fn identify_helper(
+        network_config: &NetworkConfig,
+        cert: &Certificate,
+    ) -> Option<ClientIdentity> {
         // We currently require an exact match with the peer cert (i.e. we don't support verifying
         // the certificate against a truststore and identifying the peer by the certificate
         // subject). This could be changed if the need arises.
@@ -363,11 +658,6 @@ impl ClientCertRecognizingAcceptor {
                 return Some(ClientIdentity(id));
             }
         }
-        // It might be nice to log something here. We could log the certificate base64?
-        error!(
-            "A client certificate was presented that does not match a known helper. Certificate: {}",
-            BASE64.encode(cert),
-        );
         None
     }
 }
@@ -391,31 +681,41 @@ where
                 err
             })?;
 
-            // The return from `identify_client` is an `Option<HelperIdentity>`.
+            // The return from `identify_helper` is an `Option<HelperIdentity>`.
             // No client identity will be associated with the connection if:
             //  * No certificate was supplied.
             //  * There was a problem interpreting the certificate. It is unlikely to see an invalid
             //    certificate here, because the certificate must have passed full verification at
             //    connection time. But it's possible the certificate subject is not something we
             //    recognize as a helper.
-            let id = Self::identify_client(
-                &network_config,
-                stream
-                    .get_ref()
-                    .1
-                    .peer_certificates()
-                    .and_then(<[_]>::first),
-            );
-            let service = SetClientIdentityFromCertificate { inner: service, id };
+            // A certificate that does not belong to a helper may belong to a report collector. The
+            // query APIs check that.
+            let cert = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(<[_]>::first);
+            let id =
+                cert.and_then(|cert| Self::identify_helper(&network_config.read().unwrap(), cert));
+            let certificate = cert
+                .filter(|_| id.is_none())
+                .cloned()
+                .map(ClientCertificate);
+            let service = SetClientIdentityFromCertificate {
+                inner: service,
+                id,
+                certificate,
+            };
             Ok((stream, service))
         })
     }
 }
 
 #[derive(Clone)]
-struct SetClientIdentityFromCertificate<S> {
+pub(super) struct SetClientIdentityFromCertificate<S> {
     inner: S,
     id: Option<ClientIdentity>,
+    certificate: Option<ClientCertificate>,
 }
 
 impl<B, S: Service<Request<B>>> Service<Request<B>> for SetClientIdentityFromCertificate<S> {
@@ -432,6 +732,9 @@ impl<B, S: Service<Request<B>>> Service<Request<B>> for SetClientIdentityFromCer
         if let Some(id) = self.id {
             req.extensions_mut().insert(id);
         }
+        if let Some(certificate) = self.certificate.clone() {
+            req.extensions_mut().insert(certificate);
+        }
         self.inner.call(req)
     }
 }
@@ -484,7 +787,11 @@ impl<B, S: Service<Request<B>, Response = Response>> Service<Request<B>>
 mod e2e_tests {
     use super::*;
     use crate::{
-        net::{http_serde, test::TestServer},
+        net::{
+            http_serde,
+            test::{get_test_identity, TestServer},
+            MpcHelperClient,
+        },
         test_fixture::metrics::MetricsHandle,
     };
     use hyper::{client::HttpConnector, http::uri, StatusCode, Version};
@@ -584,6 +891,30 @@ mod e2e_tests {
         assert_eq!(expected, resp_body);
     }
 
+    #[tokio::test]
+    async fn reload_tls_applies_to_new_connections() {
+        let TestServer { server, .. } = TestServer::builder().build().await;
+        let network_config = server.network_config();
+        let new_client = || {
+            MpcHelperClient::new(
+                &network_config.client,
+                network_config.peers[0].clone(),
+                get_test_identity(HelperIdentity::ONE),
+            )
+            .unwrap()
+        };
+        new_client().echo("trusted").await.unwrap();
+
+        // Helper 1 is no longer trusted, so it can't complete the TLS handshake.
+        let mut untrusted = network_config.clone();
+        untrusted.peers[0].certificate = None;
+        server.reload_tls(untrusted).await.unwrap();
+        assert!(new_client().echo("untrusted").await.is_err());
+
+        server.reload_tls(network_config.clone()).await.unwrap();
+        new_client().echo("trusted_again").await.unwrap();
+    }
+
     /// Ensures that server tracks number of requests it received and emits a corresponding metric.
     /// In order for this test not to be flaky, we rely on tokio::test macro to set up a
     /// new runtime per test (which it currently does) and set up metric recorders per thread (done
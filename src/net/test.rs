@@ -11,8 +11,8 @@
 
 use crate::{
     config::{
-        ClientConfig, HpkeClientConfig, HpkeServerConfig, NetworkConfig, PeerConfig, ServerConfig,
-        TlsConfig,
+        ClientConfig, HpkeClientConfig, HpkeServerConfig, NetworkConfig, PeerConfig,
+        ReportCollectorConfig, ServerConfig, StepTransport, TlsConfig,
     },
     helpers::{HelperIdentity, TransportCallbacks},
     hpke::{Deserializable as _, IpaPublicKey},
@@ -37,6 +37,9 @@ pub struct TestConfig {
     pub network: NetworkConfig,
     pub servers: [ServerConfig; 3],
     pub sockets: Option<[TcpListener; 3]>,
+    /// Sockets to accept multiplexed step connections on, if the stream step transport is
+    /// selected and ports are assigned dynamically.
+    pub stream_sockets: Option<[TcpListener; 3]>,
 }
 
 impl TestConfig {
@@ -71,6 +74,7 @@ fn server_config_insecure_http(port: u16, matchkey_encryption: bool) -> ServerCo
         disable_https: true,
         tls: None,
         hpke_config: get_dummy_matchkey_encryption_info(matchkey_encryption),
+        report_collectors: Vec::new(),
     }
 }
 
@@ -89,6 +93,7 @@ pub fn server_config_https(
             private_key: String::from_utf8(private_key.to_owned()).unwrap(),
         }),
         hpke_config: get_dummy_matchkey_encryption_info(matchkey_encryption),
+        report_collectors: Vec::new(),
     }
 }
 
@@ -98,6 +103,7 @@ pub struct TestConfigBuilder {
     disable_https: bool,
     use_http1: bool,
     disable_matchkey_encryption: bool,
+    step_transport: StepTransport,
 }
 
 impl TestConfigBuilder {
@@ -108,6 +114,7 @@ impl TestConfigBuilder {
             disable_https: true,
             use_http1: false,
             disable_matchkey_encryption: false,
+            step_transport: StepTransport::Http,
         }
     }
 
@@ -118,6 +125,7 @@ impl TestConfigBuilder {
             disable_https: false,
             use_http1: false,
             disable_matchkey_encryption: false,
+            step_transport: StepTransport::Http,
         }
     }
 
@@ -141,20 +149,27 @@ impl TestConfigBuilder {
         self
     }
 
+    #[must_use]
+    pub fn with_step_transport(mut self, step_transport: StepTransport) -> Self {
+        self.step_transport = step_transport;
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> TestConfig {
-        let mut sockets = None;
-        let ports = self.ports.unwrap_or_else(|| {
-            let socks = array::from_fn(|_| TcpListener::bind("localhost:0").unwrap());
-            let ports = socks
-                .iter()
-                .map(|sock| sock.local_addr().unwrap().port())
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap();
-            sockets = Some(socks);
-            ports
-        });
+        let (ports, sockets) = match self.ports {
+            Some(ports) => (ports, None),
+            None => open_ports(),
+        };
+        let (stream_ports, stream_sockets) = match (self.step_transport, self.ports) {
+            (StepTransport::Http, _) => ([None; 3], None),
+            // Stream ports follow the fixed HTTP ports, which are assigned sequentially.
+            (StepTransport::Stream, Some(ports)) => (ports.map(|port| Some(port + 3)), None),
+            (StepTransport::Stream, None) => {
+                let (ports, sockets) = open_ports();
+                (ports.map(Some), sockets)
+            }
+        };
         let (scheme, certs) = if self.disable_https {
             ("http", [None, None, None])
         } else {
@@ -164,6 +179,7 @@ impl TestConfigBuilder {
             .into_iter()
             .enumerate()
             .map(|(i, cert)| PeerConfig {
+                stream_port: stream_ports[i],
                 url: format!("{scheme}://localhost:{}", ports[i])
                     .parse()
                     .unwrap(),
@@ -187,7 +203,8 @@ impl TestConfigBuilder {
             client: self
                 .use_http1
                 .then(ClientConfig::use_http1)
-                .unwrap_or_default(),
+                .unwrap_or_default()
+                .with_step_transport(self.step_transport),
         };
         let servers = if self.disable_https {
             ports.map(|ports| server_config_insecure_http(ports, !self.disable_matchkey_encryption))
@@ -199,11 +216,23 @@ impl TestConfigBuilder {
             network,
             servers,
             sockets,
+            stream_sockets,
             disable_https: self.disable_https,
         }
     }
 }
 
+fn open_ports() -> ([u16; 3], Option<[TcpListener; 3]>) {
+    let sockets = array::from_fn(|_| TcpListener::bind("localhost:0").unwrap());
+    let ports = sockets
+        .iter()
+        .map(|sock| sock.local_addr().unwrap().port())
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap();
+    (ports, Some(sockets))
+}
+
 type HttpTransportCallbacks = TransportCallbacks<Arc<HttpTransport>>;
 
 pub struct TestServer {
@@ -237,6 +266,7 @@ pub struct TestServerBuilder {
     disable_https: bool,
     use_http1: bool,
     disable_matchkey_encryption: bool,
+    report_collectors: Vec<ReportCollectorConfig>,
 }
 
 impl TestServerBuilder {
@@ -259,6 +289,13 @@ impl TestServerBuilder {
         self
     }
 
+    /// Require report collectors to authenticate to the query APIs.
+    #[must_use]
+    pub fn with_report_collectors(mut self, collectors: Vec<ReportCollectorConfig>) -> Self {
+        self.report_collectors = collectors;
+        self
+    }
+
     #[allow(dead_code)]
     #[must_use]
     // TODO(richaj) Add tests for checking the handling of this. At present the code to decrypt does not exist.
@@ -286,13 +323,15 @@ impl TestServerBuilder {
             .build();
         let TestConfig {
             network: network_config,
-            servers: [server_config, _, _],
+            servers: [mut server_config, _, _],
             sockets: Some([server_socket, _, _]),
             ..
-        } = test_config else {
+        } = test_config
+        else {
             panic!("TestConfig should have allocated ports");
         };
-        let clients = MpcHelperClient::from_conf(&network_config, identity.clone());
+        server_config.report_collectors = self.report_collectors;
+        let clients = MpcHelperClient::from_conf(&network_config, identity.clone()).unwrap();
         let (transport, server) = HttpTransport::new(
             HelperIdentity::ONE,
             server_config,
@@ -306,7 +345,8 @@ impl TestServerBuilder {
         // At some point it might be appropriate to return two clients here -- the first being
         // another helper and the second being a report collector. For now we use the same client
         // for both types of calls.
-        let client = MpcHelperClient::new(&network_config.client, h1_peer_config, identity);
+        let client =
+            MpcHelperClient::new(&network_config.client, h1_peer_config, identity).unwrap();
         TestServer {
             addr,
             handle,
@@ -330,38 +370,38 @@ pub fn get_test_identity(id: HelperIdentity) -> ClientIdentity {
 pub const TEST_CERTS: [&[u8]; 3] = [
     b"\
 -----BEGIN CERTIFICATE-----
-MIIBZjCCAQugAwIBAgIIIw4wCKfWSPwwCgYIKoZIzj0EAwIwFDESMBAGA1UEAwwJ
-bG9jYWxob3N0MB4XDTIzMDUxNDIwNDQ0MloXDTIzMDgxMzIwNDQ0MlowFDESMBAG
-A1UEAwwJbG9jYWxob3N0MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEm1kSoFLr
-+NqpxsD9um7SHeMkOwN9nucVc+2kp38rBJdQXMn7Y24rSmGfle0cqFZGMr9yX7yi
-aPlI9he3bHGxUaNHMEUwFAYDVR0RBA0wC4IJbG9jYWxob3N0MA4GA1UdDwEB/wQE
-AwICpDAdBgNVHSUEFjAUBggrBgEFBQcDAQYIKwYBBQUHAwIwCgYIKoZIzj0EAwID
-SQAwRgIhAOyM9wLZFviaBJxofO1biI14hsfF83ZjmJ3ecfTt/HdfAiEAgzGzmJQC
-T0I681GCNIl5G+81QhtxZU+L/wTFEDvZab8=
+MIIBZjCCAQ2gAwIBAgIIIw4wCKfWSPwwCgYIKoZIzj0EAwIwFDESMBAGA1UEAwwJ
+bG9jYWxob3N0MCAXDTIzMDUxNDIwNDQ0MloYDzIxMjMwNDIwMjA0NDQyWjAUMRIw
+EAYDVQQDDAlsb2NhbGhvc3QwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAASbWRKg
+Uuv42qnGwP26btId4yQ7A32e5xVz7aSnfysEl1BcyftjbitKYZ+V7RyoVkYyv3Jf
+vKJo+Uj2F7dscbFRo0cwRTAUBgNVHREEDTALgglsb2NhbGhvc3QwDgYDVR0PAQH/
+BAQDAgKkMB0GA1UdJQQWMBQGCCsGAQUFBwMBBggrBgEFBQcDAjAKBggqhkjOPQQD
+AgNHADBEAiAw8fMBBLmkv9hAjiCEfyykXQJ0/md+qJ0vWQlsavxfzQIgY366APOO
+PIqSB2Z3a3YjG05Gi4ueOC8tZgRCZKV7Y78=
 -----END CERTIFICATE-----
 ",
     b"\
 -----BEGIN CERTIFICATE-----
-MIIBZTCCAQugAwIBAgIIALb+d1gYZ6wwCgYIKoZIzj0EAwIwFDESMBAGA1UEAwwJ
-bG9jYWxob3N0MB4XDTIzMDUxNDIwNDQ0MloXDTIzMDgxMzIwNDQ0MlowFDESMBAG
-A1UEAwwJbG9jYWxob3N0MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE4+qYlzJ9
-7HnR0l75c1SbfcEh264VxSm0jFaX2I77sT7snsB1UXa4z+DPctpTEsaCao8xf2vh
-rp/Zg+drYa2k66NHMEUwFAYDVR0RBA0wC4IJbG9jYWxob3N0MA4GA1UdDwEB/wQE
-AwICpDAdBgNVHSUEFjAUBggrBgEFBQcDAQYIKwYBBQUHAwIwCgYIKoZIzj0EAwID
-SAAwRQIgAxmYy2xWLuT7Tj4mPN4o2dN6hUUrLgDoaB3ANKGn6HUCIQDWCWDEFYz6
-axKi9RVXFKJRTl+2uDnvJDlByuu9eO7Zcw==
+MIIBZjCCAQ2gAwIBAgIIALb+d1gYZ6wwCgYIKoZIzj0EAwIwFDESMBAGA1UEAwwJ
+bG9jYWxob3N0MCAXDTIzMDUxNDIwNDQ0MloYDzIxMjMwNDIwMjA0NDQyWjAUMRIw
+EAYDVQQDDAlsb2NhbGhvc3QwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAATj6piX
+Mn3sedHSXvlzVJt9wSHbrhXFKbSMVpfYjvuxPuyewHVRdrjP4M9y2lMSxoJqjzF/
+a+Gun9mD52thraTro0cwRTAUBgNVHREEDTALgglsb2NhbGhvc3QwDgYDVR0PAQH/
+BAQDAgKkMB0GA1UdJQQWMBQGCCsGAQUFBwMBBggrBgEFBQcDAjAKBggqhkjOPQQD
+AgNHADBEAiBsZj+ivPhi3oD8yxrv29wvrbgJxKODPjui7o5bwSd2pQIged4g9lrk
++1Gyb6UcwCuJ2jEM9t1BXhxUBX9L+e1xD9M=
 -----END CERTIFICATE-----
 ",
     b"\
 -----BEGIN CERTIFICATE-----
-MIIBZjCCAQugAwIBAgIICNNqnceOGYowCgYIKoZIzj0EAwIwFDESMBAGA1UEAwwJ
-bG9jYWxob3N0MB4XDTIzMDUxNDIwNDQ0MloXDTIzMDgxMzIwNDQ0MlowFDESMBAG
-A1UEAwwJbG9jYWxob3N0MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEwTmF1UEn
-ifmQ242uQWzZgoZD0SHD+clfBBj8Lq10lbTt1dlhxhYnKDrkuZhFgECXYAR8ZUfp
-5/xBTjDdiSOx1aNHMEUwFAYDVR0RBA0wC4IJbG9jYWxob3N0MA4GA1UdDwEB/wQE
-AwICpDAdBgNVHSUEFjAUBggrBgEFBQcDAQYIKwYBBQUHAwIwCgYIKoZIzj0EAwID
-SQAwRgIhAI+C0EKy19WiQjm8WukTVDBQls48axbloGjtmGdCpQz2AiEAuZnRtQhq
-ap/vPUI/erdbh9kBXcOaHSDVR3gCfhuPhyI=
+MIIBZzCCAQ2gAwIBAgIICNNqnceOGYowCgYIKoZIzj0EAwIwFDESMBAGA1UEAwwJ
+bG9jYWxob3N0MCAXDTIzMDUxNDIwNDQ0MloYDzIxMjMwNDIwMjA0NDQyWjAUMRIw
+EAYDVQQDDAlsb2NhbGhvc3QwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAATBOYXV
+QSeJ+ZDbja5BbNmChkPRIcP5yV8EGPwurXSVtO3V2WHGFicoOuS5mEWAQJdgBHxl
+R+nn/EFOMN2JI7HVo0cwRTAUBgNVHREEDTALgglsb2NhbGhvc3QwDgYDVR0PAQH/
+BAQDAgKkMB0GA1UdJQQWMBQGCCsGAQUFBwMBBggrBgEFBQcDAjAKBggqhkjOPQQD
+AgNIADBFAiEAoI3GTC7INya7qLEaf+hTdirsyeqQYGE2BONUzVNsE9ACIAM8Q0ez
+q0ZC1sDpEH+xoSD2/jEMyIoZhU8lW0cfIBCt
 -----END CERTIFICATE-----
 ",
 ];
@@ -3,14 +3,19 @@ use crate::{
     error::BoxError,
     helpers::{
         query::{PrepareQuery, QueryConfig, QueryInput},
-        BodyStream, CompleteQueryResult, HelperIdentity, LogErrors, NoResourceIdentifier,
-        PrepareQueryResult, QueryIdBinding, QueryInputResult, QueryStatusResult,
-        ReceiveQueryResult, ReceiveRecords, RouteId, RouteParams, StepBinding, StreamCollection,
+        records_offset, send_acknowledgements, Acknowledgements, BodyStream, CompleteQueryResult,
+        HelperIdentity, LogErrors, NoResourceIdentifier, PrepareQueryResult, QueryIdBinding,
+        QueryInputResult, QueryProgressResult, QueryStatusResult, ReceiveQueryResult,
+        ReceiveRecords, ResumeError, RouteId, RouteParams, StepBinding, StreamCollection,
         Transport, TransportCallbacks,
     },
-    net::{client::MpcHelperClient, error::Error, MpcHelperServer},
+    net::{
+        client::MpcHelperClient, error::Error, server::creating_collector, ClientIdentity,
+        MpcHelperServer,
+    },
     protocol::{step::Gate, QueryId},
-    sync::Arc,
+    query::QueryStatus,
+    sync::{atomic::AtomicUsize, Arc, Weak},
 };
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -18,6 +23,7 @@ use futures::{Stream, TryFutureExt};
 use std::{
     borrow::Borrow,
     future::Future,
+    iter::zip,
     pin::Pin,
     task::{Context, Poll},
 };
@@ -32,6 +38,7 @@ pub struct HttpTransport {
     // TODO(615): supporting multiple queries likely require a hashmap here. It will be ok if we
     // only allow one query at a time.
     record_streams: StreamCollection<LogHttpErrors>,
+    acknowledgements: Acknowledgements,
 }
 
 impl HttpTransport {
@@ -53,11 +60,30 @@ impl HttpTransport {
         clients: [MpcHelperClient; 3],
         callbacks: TransportCallbacks<Arc<HttpTransport>>,
     ) -> Arc<Self> {
-        Arc::new(Self {
-            identity,
-            callbacks,
-            clients,
-            record_streams: StreamCollection::default(),
+        let (record_streams, acks) = StreamCollection::with_acks();
+        Arc::new_cyclic(|this: &Weak<Self>| {
+            let this = Weak::clone(this);
+            tokio::spawn(send_acknowledgements(
+                acks,
+                move |(query_id, peer, gate), received| {
+                    let this = this.upgrade();
+                    async move {
+                        match this {
+                            Some(this) => this.clients[peer].ack(query_id, &gate, received).await,
+                            // nothing to acknowledge if the transport is gone.
+                            None => Ok(()),
+                        }
+                    }
+                },
+            ));
+
+            Self {
+                identity,
+                callbacks,
+                clients,
+                record_streams,
+                acknowledgements: Acknowledgements::default(),
+            }
         })
     }
 
@@ -77,6 +103,10 @@ impl HttpTransport {
         (Arc::clone(&self).callbacks.query_status)(self, query_id)
     }
 
+    pub fn query_progress(self: Arc<Self>, query_id: QueryId) -> QueryProgressResult {
+        (Arc::clone(&self).callbacks.query_progress)(self, query_id)
+    }
+
     pub fn complete_query(self: Arc<Self>, query_id: QueryId) -> CompleteQueryResult {
         /// Cleans up the `records_stream` collection after drop to ensure this transport
         /// can process the next query even in case of a panic.
@@ -96,6 +126,7 @@ impl HttpTransport {
         impl Drop for ClearOnDrop {
             fn drop(&mut self) {
                 self.transport.record_streams.clear();
+                self.transport.acknowledgements.clear();
             }
         }
 
@@ -105,18 +136,64 @@ impl HttpTransport {
         })
     }
 
-    /// Connect an inbound stream of MPC record data.
+    /// Rebuild the TLS configuration of the clients used to talk to the peers, so that new
+    /// connections use the certificates in `network_config` and the client `identity`.
+    ///
+    /// # Errors
+    /// If a client can't be created from `network_config`. All clients are kept unchanged in
+    /// that case.
+    pub fn reload_clients(
+        &self,
+        network_config: &NetworkConfig,
+        identity: &ClientIdentity,
+    ) -> Result<(), Error> {
+        let reloaded = MpcHelperClient::from_conf(network_config, identity.clone())?;
+        for (client, reloaded) in zip(&self.clients, &reloaded) {
+            client.reload(reloaded);
+        }
+        Ok(())
+    }
+
+    /// Returns true if no query is in progress, so the peer configuration can be changed without
+    /// disrupting one.
+    pub async fn is_idle(self: Arc<Self>) -> bool {
+        // TODO(615): This needs to check all queries once there can be more than one.
+        !matches!(
+            self.query_status(QueryId).await,
+            Ok(status) if status != QueryStatus::Completed
+        )
+    }
+
+    /// Connect an inbound stream of MPC record data, starting at byte `offset` of the channel.
     ///
     /// This is called by peer helpers via the HTTP server.
+    ///
+    /// ## Errors
+    /// If the stream resumes a channel past the data that was received on it.
     pub fn receive_stream(
         self: Arc<Self>,
         query_id: QueryId,
         gate: Gate,
         from: HelperIdentity,
+        offset: usize,
         stream: BodyStream,
-    ) {
+    ) -> Result<(), ResumeError> {
         self.record_streams
-            .add_stream((query_id, from, gate), LogErrors::new(stream));
+            .add_stream((query_id, from, gate), offset, LogErrors::new(stream))
+    }
+
+    /// Records that the peer received the first `received` bytes of the records stream sent to it.
+    ///
+    /// This is called by peer helpers via the HTTP server.
+    pub fn receive_ack(
+        self: Arc<Self>,
+        query_id: QueryId,
+        gate: Gate,
+        from: HelperIdentity,
+        received: usize,
+    ) {
+        self.acknowledgements
+            .record((query_id, from, gate), received);
     }
 }
 
@@ -147,27 +224,45 @@ impl Transport for Arc<HttpTransport> {
         let route_id = route.resource_identifier();
         match route_id {
             RouteId::Records => {
-                // TODO(600): These fallible extractions aren't really necessary.
-                let query_id = <Option<QueryId>>::from(route.query_id())
-                    .expect("query_id required when sending records");
-                let step =
-                    <Option<Gate>>::from(route.gate()).expect("step required when sending records");
-                let resp_future = self.clients[dest].step(query_id, &step, data)?;
-                tokio::spawn(async move {
-                    resp_future
+                let query_id = <Option<QueryId>>::from(route.query_id()).ok_or_else(|| {
+                    Error::BadPathString("query_id required when sending records".into())
+                })?;
+                let step = <Option<Gate>>::from(route.gate()).ok_or_else(|| {
+                    Error::BadPathString("step required when sending records".into())
+                })?;
+                let offset = records_offset(route.extra().borrow())
+                    .map_err(|e| Error::BadQueryString(e.into()))?;
+                // The peer responds once it accepted the stream. If the connection is lost while
+                // the data is streamed, the gateway notices that the stream was dropped and
+                // resumes it.
+                if let Some(step_client) = self.clients[dest].step_client() {
+                    step_client.send(query_id, step, offset, data).await
+                } else {
+                    self.clients[dest]
+                        .step(query_id, &step, offset, data)?
                         .map_err(Into::into)
                         .and_then(MpcHelperClient::resp_ok)
                         .await
-                        .expect("failed to stream records");
-                });
-                // TODO(600): We need to do something better than panic if there is an error sending the
-                // data. Note, also, that the caller of this function (`GatewayBase::get_sender`)
-                // currently panics on errors.
-                Ok(())
+                }
+            }
+            RouteId::RecordsAck => {
+                let query_id = <Option<QueryId>>::from(route.query_id()).ok_or_else(|| {
+                    Error::BadPathString("query_id required when acknowledging records".into())
+                })?;
+                let step = <Option<Gate>>::from(route.gate()).ok_or_else(|| {
+                    Error::BadPathString("step required when acknowledging records".into())
+                })?;
+                let received = records_offset(route.extra().borrow())
+                    .map_err(|e| Error::BadQueryString(e.into()))?;
+                self.clients[dest].ack(query_id, &step, received).await
             }
             RouteId::PrepareQuery => {
                 let req = serde_json::from_str(route.extra().borrow()).unwrap();
-                self.clients[dest].prepare_query(req).await
+                // Tell the other helpers which report collector creates the query, so that they
+                // bind it to the same one.
+                self.clients[dest]
+                    .prepare_query(req, creating_collector())
+                    .await
             }
             RouteId::ReceiveQuery => {
                 unimplemented!("attempting to send ReceiveQuery to another helper")
@@ -185,13 +280,22 @@ impl Transport for Arc<HttpTransport> {
             self.record_streams.clone(),
         )
     }
+
+    fn acknowledged<R: RouteParams<NoResourceIdentifier, QueryId, Gate>>(
+        &self,
+        to: HelperIdentity,
+        route: R,
+    ) -> Arc<AtomicUsize> {
+        self.acknowledgements
+            .get((route.query_id(), to, route.gate()))
+    }
 }
 
 #[cfg(all(test, web_test))]
 mod tests {
     use super::*;
     use crate::{
-        config::{NetworkConfig, ServerConfig},
+        config::{NetworkConfig, ServerConfig, StepTransport},
         ff::{FieldType, Fp31, Serializable},
         helpers::query::QueryType::TestMultiply,
         net::{
@@ -226,7 +330,9 @@ mod tests {
         );
 
         // Register the stream with the transport (normally called by step data HTTP API handler)
-        Arc::clone(&transport).receive_stream(QueryId, STEP.clone(), HelperIdentity::TWO, body);
+        Arc::clone(&transport)
+            .receive_stream(QueryId, STEP.clone(), HelperIdentity::TWO, 0, body)
+            .unwrap();
 
         // Request step data reception (normally called by protocol)
         let mut stream =
@@ -255,24 +361,74 @@ mod tests {
         );
     }
 
-    // TODO(651): write a test for an error while reading the body (after error handling is finalized)
+    #[tokio::test]
+    async fn resume_stream() {
+        let (tx, rx) = channel::<Result<Bytes, Box<dyn std::error::Error + Send + Sync>>>(1);
+        let TestServer { transport, .. } = TestServer::default().await;
+
+        let body = BodyStream::from_body(
+            Box::new(ReceiverStream::new(rx)) as Box<dyn Stream<Item = _> + Send>
+        );
+        Arc::clone(&transport)
+            .receive_stream(QueryId, STEP.clone(), HelperIdentity::TWO, 0, body)
+            .unwrap();
+        let mut stream =
+            Arc::clone(&transport).receive(HelperIdentity::TWO, (QueryId, STEP.clone()));
+
+        tx.send(Ok(vec![0_u8, 1, 2, 3].into())).await.unwrap();
+        assert_eq!(Some(vec![0, 1, 2, 3]), stream.next().await);
+
+        // the connection fails, the stream waits for the sender to reconnect.
+        tx.send(Err("connection reset".into())).await.unwrap();
+        assert!(matches!(
+            poll_immediate(&mut stream).next().await,
+            Some(Poll::Pending)
+        ));
+
+        // the sender can't skip the data that was not received.
+        let (_, rx) = channel::<Result<Bytes, Box<dyn std::error::Error + Send + Sync>>>(1);
+        let body = BodyStream::from_body(
+            Box::new(ReceiverStream::new(rx)) as Box<dyn Stream<Item = _> + Send>
+        );
+        Arc::clone(&transport)
+            .receive_stream(QueryId, STEP.clone(), HelperIdentity::TWO, 5, body)
+            .unwrap_err();
+
+        // resuming from an earlier offset skips the data received already.
+        let (tx, rx) = channel::<Result<Bytes, Box<dyn std::error::Error + Send + Sync>>>(1);
+        let body = BodyStream::from_body(
+            Box::new(ReceiverStream::new(rx)) as Box<dyn Stream<Item = _> + Send>
+        );
+        Arc::clone(&transport)
+            .receive_stream(QueryId, STEP.clone(), HelperIdentity::TWO, 2, body)
+            .unwrap();
+        tx.send(Ok(vec![2_u8, 3, 4, 5].into())).await.unwrap();
+        drop(tx);
+        assert_eq!(vec![vec![4, 5]], stream.collect::<Vec<_>>().await);
+    }
 
     async fn make_helpers(
         sockets: [TcpListener; 3],
+        stream_sockets: Option<[TcpListener; 3]>,
         server_config: [ServerConfig; 3],
         network_config: &NetworkConfig,
         disable_https: bool,
     ) -> [HelperApp; 3] {
+        let stream_sockets = stream_sockets.map_or([None, None, None], |sockets| sockets.map(Some));
         join_all(
-            zip(HelperIdentity::make_three(), zip(sockets, server_config)).map(
-                |(id, (socket, server_config))| async move {
+            zip(
+                HelperIdentity::make_three(),
+                zip(zip(sockets, stream_sockets), server_config),
+            )
+            .map(
+                |(id, ((socket, stream_socket), server_config))| async move {
                     let identity = if disable_https {
                         ClientIdentity::Helper(id)
                     } else {
                         get_test_identity(id)
                     };
                     let (setup, callbacks) = AppSetup::new();
-                    let clients = MpcHelperClient::from_conf(network_config, identity);
+                    let clients = MpcHelperClient::from_conf(network_config, identity).unwrap();
                     let (transport, server) = HttpTransport::new(
                         id,
                         server_config,
@@ -281,6 +437,7 @@ mod tests {
                         callbacks,
                     );
                     server.start_on(Some(socket), ()).await;
+                    server.start_streams_on(stream_socket).await.unwrap();
                     let app = setup.connect(transport);
                     app
                 },
@@ -293,9 +450,10 @@ mod tests {
     }
 
     async fn test_three_helpers(mut conf: TestConfig) {
-        let clients = MpcHelperClient::from_conf(&conf.network, ClientIdentity::None);
+        let clients = MpcHelperClient::from_conf(&conf.network, ClientIdentity::None).unwrap();
         let _helpers = make_helpers(
             conf.sockets.take().unwrap(),
+            conf.stream_sockets.take(),
             conf.servers,
             &conf.network,
             conf.disable_https,
@@ -308,9 +466,10 @@ mod tests {
     #[tokio::test(flavor = "multi_thread")]
     async fn happy_case_twice() {
         let mut conf = TestConfigBuilder::with_open_ports().build();
-        let clients = MpcHelperClient::from_conf(&conf.network, ClientIdentity::None);
+        let clients = MpcHelperClient::from_conf(&conf.network, ClientIdentity::None).unwrap();
         let _helpers = make_helpers(
             conf.sockets.take().unwrap(),
+            conf.stream_sockets.take(),
             conf.servers,
             &conf.network,
             conf.disable_https,
@@ -376,4 +535,40 @@ mod tests {
         let conf = TestConfigBuilder::with_open_ports().build();
         test_three_helpers(conf).await;
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn three_helpers_stream() {
+        let conf = TestConfigBuilder::with_open_ports()
+            .with_disable_https_option(true)
+            .with_step_transport(StepTransport::Stream)
+            .build();
+        test_three_helpers(conf).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn three_helpers_stream_tls() {
+        let conf = TestConfigBuilder::with_open_ports()
+            .with_step_transport(StepTransport::Stream)
+            .build();
+        test_three_helpers(conf).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stream_happy_case_twice() {
+        let mut conf = TestConfigBuilder::with_open_ports()
+            .with_step_transport(StepTransport::Stream)
+            .build();
+        let clients = MpcHelperClient::from_conf(&conf.network, ClientIdentity::None).unwrap();
+        let _helpers = make_helpers(
+            conf.sockets.take().unwrap(),
+            conf.stream_sockets.take(),
+            conf.servers,
+            &conf.network,
+            conf.disable_https,
+        )
+        .await;
+
+        test_multiply(&clients).await;
+        test_multiply(&clients).await;
+    }
 }
@@ -498,13 +498,19 @@ mod tests {
                 credit_capping::credit_capping,
                 input::{CreditCappingInputRow, MCCreditCappingInputRow, MCCreditCappingOutputRow},
             },
+            boolean::RandomBits,
             context::Context,
             modulus_conversion::{convert_all_bits, convert_all_bits_local},
-            BreakdownKey, MatchKey,
+            BasicProtocols, BreakdownKey, MatchKey,
         },
-        secret_sharing::{replicated::semi_honest::AdditiveShare, SharedValue},
-        test_fixture::{input::GenericReportTestInput, Reconstruct, Runner, TestWorld},
+        secret_sharing::{
+            replicated::semi_honest::AdditiveShare, BitDecomposed, Linear as LinearSecretSharing,
+            SharedValue,
+        },
+        test_fixture::{fuzz, input::GenericReportTestInput, Reconstruct, Runner, TestWorld},
     };
+    use proptest::{collection::vec, prelude::any, proptest};
+    use std::iter::zip;
 
     async fn run_credit_capping_test(
         input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>>,
@@ -692,4 +698,121 @@ mod tests {
             assert_eq!(v.as_u128(), *expected);
         }
     }
+
+    /// Credit capping in the clear, for rows of `(is_trigger_report, helper_bit, credit)`. Each
+    /// user gets at most `cap`, which goes to their most recent source reports first.
+    fn credit_capping_in_the_clear(rows: &[(bool, bool, u32)], cap: u32) -> Vec<Fp32BitPrime> {
+        let mut credits = vec![Fp32BitPrime::ZERO; rows.len()];
+        let mut budget = cap;
+        for (i, &(is_trigger_report, helper_bit, credit)) in rows.iter().enumerate().rev() {
+            let credit = if is_trigger_report {
+                0
+            } else {
+                credit.min(cap)
+            }
+            .min(budget);
+            credits[i] = Fp32BitPrime::truncate_from(credit);
+            budget -= credit;
+            // the helper bit of the first row is never looked at
+            if !helper_bit || i == 0 {
+                budget = cap;
+            }
+        }
+        credits
+    }
+
+    /// Caps the credits of the rows under either security model.
+    async fn cap_credits<C, S>(
+        ctx: C,
+        (is_trigger_report, (helper_bit, credit)): (Vec<S>, (Vec<S>, Vec<S>)),
+        cap: u32,
+    ) -> Vec<S>
+    where
+        C: Context + RandomBits<Fp32BitPrime, Share = S>,
+        S: LinearSecretSharing<Fp32BitPrime> + BasicProtocols<C, Fp32BitPrime>,
+    {
+        let rows = zip(is_trigger_report, zip(helper_bit, credit))
+            .map(|(is_trigger_report, (helper_bit, credit))| {
+                MCCreditCappingInputRow::new(
+                    is_trigger_report,
+                    helper_bit,
+                    BitDecomposed::new(Vec::new()),
+                    credit,
+                )
+            })
+            .collect::<Vec<_>>();
+        credit_capping(ctx, &rows, cap)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| row.credit)
+            .collect()
+    }
+
+    /// When the cap is one, credits are zero or one and no two adjacent source reports both
+    /// have a credit, as the protocol expects.
+    fn valid_rows(rows: Vec<(bool, bool, u32)>, cap: u32) -> Vec<(bool, bool, u32)> {
+        rows.into_iter()
+            .scan(
+                false,
+                |previous_has_credit, (is_trigger_report, helper_bit, credit)| {
+                    let credit = if cap == 1 {
+                        u32::from(credit > 0 && !*previous_has_credit)
+                    } else {
+                        credit
+                    };
+                    *previous_has_credit = !is_trigger_report && credit > 0;
+                    Some((is_trigger_report, helper_bit, credit))
+                },
+            )
+            .collect()
+    }
+
+    proptest! {
+        #![proptest_config(fuzz::config())]
+
+        #[test]
+        fn credit_capping_fuzz(
+            cap in 1..8_u32,
+            rows in vec((any::<bool>(), any::<bool>(), 0..16_u32), 2..16),
+        ) {
+            let rows = valid_rows(rows, cap);
+            let column = |f: fn(&(bool, bool, u32)) -> u128| {
+                rows.iter()
+                    .map(|row| Fp32BitPrime::truncate_from(f(row)))
+                    .collect::<Vec<_>>()
+            };
+            let input = (
+                column(|row| u128::from(row.0)),
+                (column(|row| u128::from(row.1)), column(|row| u128::from(row.2))),
+            );
+            let (sh_input, m_input) = (input.clone(), input);
+
+            fuzz::check_protocol(
+                &credit_capping_in_the_clear(&rows, cap),
+                |world| async move {
+                    let (is_trigger_report, (helper_bit, credit)) = sh_input;
+                    let input = (
+                        is_trigger_report.into_iter(),
+                        (helper_bit.into_iter(), credit.into_iter()),
+                    );
+                    world
+                        .semi_honest(input, |ctx, input| cap_credits(ctx, input, cap))
+                        .await
+                        .reconstruct()
+                },
+                |world| async move {
+                    let (is_trigger_report, (helper_bit, credit)) = m_input;
+                    let input = (
+                        is_trigger_report.into_iter(),
+                        (helper_bit.into_iter(), credit.into_iter()),
+                    );
+                    world
+                        .upgraded_malicious(input, |ctx, input| cap_credits(ctx, input, cap))
+                        .await
+                        .reconstruct()
+                },
+            );
+        }
+    }
 }
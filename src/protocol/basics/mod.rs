@@ -5,6 +5,7 @@ mod reshare;
 mod reveal;
 mod share_known_value;
 pub mod sum_of_product;
+pub(crate) mod truncate;
 
 pub use check_zero::check_zero;
 pub use if_else::if_else;
@@ -13,6 +14,7 @@ pub use reshare::Reshare;
 pub use reveal::Reveal;
 pub use share_known_value::ShareKnownValue;
 pub use sum_of_product::SumOfProducts;
+pub use truncate::{Truncate, STATISTICAL_SECURITY};
 
 use crate::{
     ff::Field,
@@ -122,11 +122,17 @@ where
 #[cfg(all(test, unit_test))]
 mod test {
     use crate::{
-        ff::Fp31,
-        protocol::{basics::SecureMul, context::Context, RecordId},
+        ff::{Field, Fp31, Fp32BitPrime},
+        helpers::Role,
+        protocol::{
+            basics::SecureMul,
+            context::{Context, UpgradedContext},
+            RecordId,
+        },
         rand::{thread_rng, Rng},
-        test_fixture::{Reconstruct, Runner, TestWorld},
+        test_fixture::{assert_attacks_detected, MaliciousHelper, Reconstruct, Runner, TestWorld},
     };
+    use futures::FutureExt;
 
     #[tokio::test]
     pub async fn simple() {
@@ -146,4 +152,26 @@ mod test {
 
         assert_eq!(a * b, res.reconstruct());
     }
+
+    /// One helper adds an offset to what it sends to compute either `x · y` or `r · x · y`.
+    #[tokio::test]
+    pub async fn additive_attack() {
+        let mut rng = thread_rng();
+        let a = rng.gen::<Fp32BitPrime>();
+        let b = rng.gen::<Fp32BitPrime>();
+
+        let attacks = Role::all().iter().flat_map(|&role| {
+            ["attacked", "duplicate_multiply"]
+                .map(|gate| MaliciousHelper::new(role).add_offset(gate, Fp32BitPrime::ONE))
+        });
+        assert_attacks_detected(attacks, (a, b), |ctx, (a, b)| {
+            async move {
+                let (a, b) = ctx.upgrade((a, b)).await.unwrap();
+                let m_ctx = ctx.narrow("attacked").set_total_records(1);
+                a.multiply(&b, m_ctx, RecordId::from(0)).await.unwrap()
+            }
+            .boxed()
+        })
+        .await;
+    }
 }
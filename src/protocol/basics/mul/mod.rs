@@ -11,6 +11,7 @@ use crate::{
     },
 };
 use async_trait::async_trait;
+use std::iter::zip;
 
 pub(crate) mod malicious;
 mod semi_honest;
@@ -44,10 +45,29 @@ pub trait SecureMul<C: Context>: Send + Sync + Sized {
     ) -> Result<Self, Error>
     where
         C: 'fut;
+
+    /// Multiply `a[i]` by `b[i]` for every `i`, using `i` as the record id.
+    ///
+    /// ## Panics
+    /// If `a` and `b` have different lengths.
+    async fn multiply_all<'fut>(ctx: C, a: &[Self], b: &[Self]) -> Result<Vec<Self>, Error>
+    where
+        C: 'fut,
+    {
+        assert_eq!(a.len(), b.len());
+        ctx.try_join(zip(a, b).enumerate().map(|(i, (a, b))| {
+            let ctx = ctx.clone();
+            async move { a.multiply(b, ctx, RecordId::from(i)).await }
+        }))
+        .await
+    }
 }
 
 /// looks like clippy disagrees with itself on whether this attribute is useless or not.
-use {malicious::multiply as malicious_mul, semi_honest::multiply as semi_honest_mul};
+use {
+    malicious::multiply as malicious_mul,
+    semi_honest::{multiply as semi_honest_mul, multiply_all as semi_honest_mul_all},
+};
 
 /// Implement secure multiplication for semi-honest contexts with replicated secret sharing.
 #[async_trait]
@@ -64,6 +84,14 @@ impl<C: Context, F: Field> SecureMul<C> for Replicated<F> {
     {
         semi_honest_mul(ctx, record_id, self, rhs, zeros_at).await
     }
+
+    /// Draws the masks for all records with a single PRSS call.
+    async fn multiply_all<'fut>(ctx: C, a: &[Self], b: &[Self]) -> Result<Vec<Self>, Error>
+    where
+        C: 'fut,
+    {
+        semi_honest_mul_all(ctx, a, b).await
+    }
 }
 
 /// Implement secure multiplication for malicious contexts with replicated secret sharing.
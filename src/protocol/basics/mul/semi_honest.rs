@@ -3,7 +3,7 @@ use crate::{
     ff::Field,
     helpers::Direction,
     protocol::{
-        basics::{mul::sparse::MultiplyWork, MultiplyZeroPositions},
+        basics::{mul::sparse::MultiplyWork, MultiplyZeroPositions, ZeroPositions},
         context::Context,
         prss::SharedRandomness,
         RecordId,
@@ -12,6 +12,7 @@ use crate::{
         semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing,
     },
 };
+use std::iter::zip;
 
 /// IKHC multiplication protocol
 /// for use with replicated secret sharing over some field F.
@@ -33,6 +34,63 @@ pub async fn multiply<C, F>(
     b: &Replicated<F>,
     zeros: MultiplyZeroPositions,
 ) -> Result<Replicated<F>, Error>
+where
+    C: Context,
+    F: Field,
+{
+    // Shared randomness used to mask the values that are sent.
+    let randomness = ctx.prss().generate_fields(record_id);
+    multiply_with_randomness(ctx, record_id, a, b, zeros, randomness).await
+}
+
+/// Multiplies `a[i]` by `b[i]` for every `i`, using `i` as the record id. This is the same as
+/// calling [`multiply`] for every record, except that the shared randomness for all of them is
+/// generated in one go.
+///
+/// ## Errors
+/// Same as [`multiply`].
+///
+/// ## Panics
+/// If `a` and `b` have different lengths.
+pub async fn multiply_all<C, F>(
+    ctx: C,
+    a: &[Replicated<F>],
+    b: &[Replicated<F>],
+) -> Result<Vec<Replicated<F>>, Error>
+where
+    C: Context,
+    F: Field,
+{
+    assert_eq!(a.len(), b.len());
+    let mut randomness = vec![(F::ZERO, F::ZERO); a.len()];
+    ctx.prss()
+        .generate_fields_range(RecordId::FIRST..RecordId::from(a.len()), &mut randomness);
+
+    ctx.try_join(
+        zip(zip(a, b), randomness)
+            .enumerate()
+            .map(|(i, ((a, b), randomness))| {
+                multiply_with_randomness(
+                    ctx.clone(),
+                    RecordId::from(i),
+                    a,
+                    b,
+                    ZeroPositions::NONE,
+                    randomness,
+                )
+            }),
+    )
+    .await
+}
+
+async fn multiply_with_randomness<C, F>(
+    ctx: C,
+    record_id: RecordId,
+    a: &Replicated<F>,
+    b: &Replicated<F>,
+    zeros: MultiplyZeroPositions,
+    (s0, s1): (F, F),
+) -> Result<Replicated<F>, Error>
 where
     C: Context,
     F: Field,
@@ -42,9 +100,6 @@ where
     zeros.0.check(role, "a", a);
     zeros.1.check(role, "b", b);
 
-    // Shared randomness used to mask the values that are sent.
-    let (s0, s1) = ctx.prss().generate_fields(record_id);
-
     let mut rhs = a.right() * b.right();
     if need_to_send {
         // Compute the value (d_i) we want to send to the right helper (i+1).
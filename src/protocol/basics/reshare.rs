@@ -254,7 +254,7 @@ mod tests {
     }
 
     mod malicious {
-        use futures::future::try_join;
+        use futures::{future::try_join, FutureExt};
         use rand::{distributions::Standard, prelude::Distribution};
 
         use crate::{
@@ -280,7 +280,9 @@ mod tests {
                 },
                 SharedValue,
             },
-            test_fixture::{Reconstruct, Runner, TestWorld},
+            test_fixture::{
+                assert_attacks_detected, MaliciousHelper, Reconstruct, Runner, TestWorld,
+            },
         };
 
         /// Relies on semi-honest protocol tests that enforce reshare to communicate and produce
@@ -452,5 +454,28 @@ mod tests {
                 }
             }
         }
+
+        /// One of the helpers that send shares to `to_helper` adds an offset to either `x` or `rx`.
+        #[tokio::test]
+        async fn additive_attack() {
+            let a = thread_rng().gen::<Fp32BitPrime>();
+            let to_helper = Role::H1;
+
+            let attacks = [Role::H2, Role::H3].into_iter().flat_map(|role| {
+                ["attacked", "reshare_rx"]
+                    .map(|gate| MaliciousHelper::new(role).add_offset(gate, Fp32BitPrime::ONE))
+            });
+            assert_attacks_detected(attacks, a, |ctx, a| {
+                async move {
+                    let m_a = ctx.upgrade(a).await.unwrap();
+                    let m_ctx = ctx.narrow("attacked").set_total_records(1);
+                    m_a.reshare(m_ctx, RecordId::from(0), to_helper)
+                        .await
+                        .unwrap()
+                }
+                .boxed()
+            })
+            .await;
+        }
     }
 }
@@ -158,12 +158,17 @@ where
 mod test {
     use super::sum_of_products;
     use crate::{
-        ff::Fp31,
-        protocol::{context::Context, RecordId},
+        ff::{Field, Fp31, Fp32BitPrime},
+        helpers::Role,
+        protocol::{
+            context::{Context, UpgradedContext},
+            RecordId,
+        },
         rand::{thread_rng, Rng},
         secret_sharing::SharedValue,
-        test_fixture::{Reconstruct, Runner, TestWorld},
+        test_fixture::{assert_attacks_detected, MaliciousHelper, Reconstruct, Runner, TestWorld},
     };
+    use futures::FutureExt;
 
     #[tokio::test]
     pub async fn simple() {
@@ -197,4 +202,33 @@ mod test {
 
         assert_eq!(expected, res.reconstruct());
     }
+
+    /// One helper adds an offset to what it sends to compute either `Σx · y` or `Σr · x · y`.
+    #[tokio::test]
+    pub async fn additive_attack() {
+        const BATCHSIZE: usize = 10;
+        let mut rng = thread_rng();
+        let av = (0..BATCHSIZE)
+            .map(|_| rng.gen::<Fp32BitPrime>())
+            .collect::<Vec<_>>();
+        let bv = (0..BATCHSIZE)
+            .map(|_| rng.gen::<Fp32BitPrime>())
+            .collect::<Vec<_>>();
+
+        let attacks = Role::all().iter().flat_map(|&role| {
+            ["attacked", "duplicate_sop"]
+                .map(|gate| MaliciousHelper::new(role).add_offset(gate, Fp32BitPrime::ONE))
+        });
+        assert_attacks_detected(attacks, (av.into_iter(), bv.into_iter()), |ctx, (a, b)| {
+            async move {
+                let (a, b) = ctx.upgrade((a, b)).await.unwrap();
+                let m_ctx = ctx.narrow("attacked").set_total_records(1);
+                sum_of_products(m_ctx, RecordId::from(0), &a, &b)
+                    .await
+                    .unwrap()
+            }
+            .boxed()
+        })
+        .await;
+    }
 }
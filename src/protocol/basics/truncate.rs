@@ -0,0 +1,500 @@
+use crate::{
+    error::Error,
+    ff::PrimeField,
+    protocol::{
+        boolean::{comparison::bitwise_greater_than_constant, RandomBits},
+        context::{Context, UpgradedMaliciousContext},
+        BasicProtocols, RecordId,
+    },
+    secret_sharing::{
+        replicated::{
+            malicious::{AdditiveShare as MaliciousReplicated, ExtendableField},
+            semi_honest::AdditiveShare as Replicated,
+        },
+        Linear as LinearSecretSharing,
+    },
+};
+use async_trait::async_trait;
+use std::iter::zip;
+
+/// Statistical security parameter `κ` of the masks used by [`Truncate`].
+pub const STATISTICAL_SECURITY: u32 = 40;
+
+/// Truncation and division of secret-shared integers by public constants.
+///
+/// All operations expect the shared value `a` to be a non-negative integer that fits into `k`
+/// bits, i.e. `0 ≤ a < 2^k`. They mask `a` with a random value built from `l - 1` random bits,
+/// where `l` is the bit length of the prime, reveal the masked value and finish the computation
+/// locally. The mask hides `a` statistically with `l - 1 - k` bits of security, which must be
+/// at least [`STATISTICAL_SECURITY`], as Catrina and Saxena require.
+///
+/// This limits the input width:
+/// * [`Fp61BitPrime`] supports `k ≤ 20`.
+/// * [`Fp32BitPrime`] is too small for any `k`.
+/// * [`div_by_constant`] masks a product about twice as wide as `a`, so it supports `k ≤ 9` in
+///   [`Fp61BitPrime`].
+///
+/// Based on "Secure Computation With Fixed-Point Numbers" by O. Catrina and A. Saxena
+/// <https://doi.org/10.1007/978-3-642-14577-3_6>
+///
+/// [`Fp61BitPrime`]: crate::ff::Fp61BitPrime
+/// [`Fp32BitPrime`]: crate::ff::Fp32BitPrime
+/// [`div_by_constant`]: Self::div_by_constant
+///
+/// # Errors
+/// [`Error::Unsupported`] if `m > k` or if a `k`-bit value cannot be masked with
+/// [`STATISTICAL_SECURITY`] bits of statistical security in `F`. Other errors are propagated
+/// from the underlying protocols.
+#[async_trait]
+pub trait Truncate<C: Context, F: PrimeField>: Sized {
+    /// Computes `⌊a / 2^m⌋ + u`, where `u ∈ {0, 1}` is `1` with probability
+    /// `(a mod 2^m) / 2^m`. This costs a single reveal on top of the random bits.
+    async fn truncate_pr<'fut>(
+        &self,
+        ctx: C,
+        record_id: RecordId,
+        k: u32,
+        m: u32,
+    ) -> Result<Self, Error>
+    where
+        C: 'fut;
+
+    /// Computes `⌊a / 2^m⌋` exactly. Compared to [`truncate_pr`], this additionally runs a
+    /// comparison of `m` shared bits to correct the carry out of the low bits.
+    ///
+    /// [`truncate_pr`]: Self::truncate_pr
+    async fn truncate<'fut>(
+        &self,
+        ctx: C,
+        record_id: RecordId,
+        k: u32,
+        m: u32,
+    ) -> Result<Self, Error>
+    where
+        C: 'fut;
+
+    /// Computes `⌊a / d⌋` for a public divisor `d > 0`.
+    ///
+    /// Any `d ≥ 2^k` is larger than every `k`-bit value, so the quotient is a sharing of zero.
+    /// `a` is multiplied by `M = ⌈2^s / d⌉` with `s = k + ⌈log2 d⌉` and then truncated by `s`
+    /// bits, which gives the exact quotient for every `k`-bit `a`. The product needs roughly
+    /// `2k + 1` bits, which must be masked, so in `Fp61BitPrime` `k` is limited to 9.
+    ///
+    /// # Panics
+    /// If `d` is zero.
+    async fn div_by_constant<'fut>(
+        &self,
+        ctx: C,
+        record_id: RecordId,
+        k: u32,
+        d: u128,
+    ) -> Result<Self, Error>
+    where
+        C: 'fut;
+}
+
+#[async_trait]
+impl<C, F> Truncate<C, F> for Replicated<F>
+where
+    C: Context + RandomBits<F, Share = Replicated<F>>,
+    F: PrimeField,
+{
+    async fn truncate_pr<'fut>(
+        &self,
+        ctx: C,
+        record_id: RecordId,
+        k: u32,
+        m: u32,
+    ) -> Result<Self, Error>
+    where
+        C: 'fut,
+    {
+        truncate_pr(ctx, record_id, self, k, m).await
+    }
+
+    async fn truncate<'fut>(
+        &self,
+        ctx: C,
+        record_id: RecordId,
+        k: u32,
+        m: u32,
+    ) -> Result<Self, Error>
+    where
+        C: 'fut,
+    {
+        truncate(ctx, record_id, self, k, m).await
+    }
+
+    async fn div_by_constant<'fut>(
+        &self,
+        ctx: C,
+        record_id: RecordId,
+        k: u32,
+        d: u128,
+    ) -> Result<Self, Error>
+    where
+        C: 'fut,
+    {
+        div_by_constant(ctx, record_id, self, k, d).await
+    }
+}
+
+#[async_trait]
+impl<'a, F> Truncate<UpgradedMaliciousContext<'a, F>, F> for MaliciousReplicated<F>
+where
+    F: PrimeField + ExtendableField,
+{
+    async fn truncate_pr<'fut>(
+        &self,
+        ctx: UpgradedMaliciousContext<'a, F>,
+        record_id: RecordId,
+        k: u32,
+        m: u32,
+    ) -> Result<Self, Error>
+    where
+        UpgradedMaliciousContext<'a, F>: 'fut,
+    {
+        truncate_pr(ctx, record_id, self, k, m).await
+    }
+
+    async fn truncate<'fut>(
+        &self,
+        ctx: UpgradedMaliciousContext<'a, F>,
+        record_id: RecordId,
+        k: u32,
+        m: u32,
+    ) -> Result<Self, Error>
+    where
+        UpgradedMaliciousContext<'a, F>: 'fut,
+    {
+        truncate(ctx, record_id, self, k, m).await
+    }
+
+    async fn div_by_constant<'fut>(
+        &self,
+        ctx: UpgradedMaliciousContext<'a, F>,
+        record_id: RecordId,
+        k: u32,
+        d: u128,
+    ) -> Result<Self, Error>
+    where
+        UpgradedMaliciousContext<'a, F>: 'fut,
+    {
+        div_by_constant(ctx, record_id, self, k, d).await
+    }
+}
+
+/// Result of revealing `c = a + 2^m * r_high + r_low`.
+struct Masked<S> {
+    c: u128,
+    r_low_bits: Vec<S>,
+    r_high: S,
+}
+
+/// Checks that any `k`-bit value masked with `l - 1` random bits stays below the prime and is
+/// hidden with [`STATISTICAL_SECURITY`] bits of security, and returns `l - 1`, the number of
+/// mask bits.
+fn mask_bits<F: PrimeField>(k: u32, m: u32) -> Result<u32, Error> {
+    let prime: u128 = F::PRIME.into();
+    let l = u128::BITS - prime.leading_zeros();
+    if m > k {
+        return Err(Error::Unsupported(format!(
+            "cannot truncate {m} bits off a {k}-bit value"
+        )));
+    }
+    if k + STATISTICAL_SECURITY < l && (1_u128 << k) + (1_u128 << (l - 1)) - 2 < prime {
+        Ok(l - 1)
+    } else {
+        Err(Error::Unsupported(format!(
+            "{k}-bit values cannot be masked in a field with {l}-bit prime"
+        )))
+    }
+}
+
+fn pow2<F: PrimeField>(i: u32) -> F {
+    F::truncate_from(1_u128 << i)
+}
+
+/// Computes `Σ 2^i * bits[i]`.
+fn weighted_sum<F: PrimeField, S: LinearSecretSharing<F>>(bits: &[S]) -> S {
+    zip(0.., bits).fold(S::ZERO, |acc, (i, b)| acc + &(b.clone() * pow2(i)))
+}
+
+async fn mask_and_reveal<F, C, S>(
+    ctx: C,
+    record_id: RecordId,
+    a: &S,
+    k: u32,
+    m: u32,
+) -> Result<Masked<S>, Error>
+where
+    F: PrimeField,
+    C: Context + RandomBits<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+{
+    let mask_bits = mask_bits::<F>(k, m)?;
+    let mut bits = ctx
+        .narrow(&Step::RandomBits)
+        .generate_random_bits(record_id)
+        .await?;
+    bits.truncate(usize::try_from(mask_bits).unwrap());
+    let high_bits = bits.split_off(usize::try_from(m).unwrap());
+
+    let r_low = weighted_sum(&bits);
+    let r_high = weighted_sum(&high_bits);
+
+    let masked = a.clone() + &r_low + &(r_high.clone() * pow2(m));
+    let c = masked
+        .reveal(ctx.narrow(&Step::RevealMasked), record_id)
+        .await?
+        .as_u128();
+
+    Ok(Masked {
+        c,
+        r_low_bits: bits,
+        r_high,
+    })
+}
+
+async fn truncate_pr<F, C, S>(
+    ctx: C,
+    record_id: RecordId,
+    a: &S,
+    k: u32,
+    m: u32,
+) -> Result<S, Error>
+where
+    F: PrimeField,
+    C: Context + RandomBits<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+{
+    if m == 0 {
+        return Ok(a.clone());
+    }
+
+    // `c >> m` = `(a >> m) + r_high + carry`, where carry comes from adding the low bits
+    // of `a` and `r`.
+    let Masked { c, r_high, .. } = mask_and_reveal(ctx.clone(), record_id, a, k, m).await?;
+    Ok(S::share_known_value(&ctx, F::truncate_from(c >> m)) - &r_high)
+}
+
+async fn truncate<F, C, S>(ctx: C, record_id: RecordId, a: &S, k: u32, m: u32) -> Result<S, Error>
+where
+    F: PrimeField,
+    C: Context + RandomBits<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+{
+    if m == 0 {
+        return Ok(a.clone());
+    }
+
+    let Masked {
+        c,
+        r_low_bits,
+        r_high,
+    } = mask_and_reveal(ctx.clone(), record_id, a, k, m).await?;
+
+    // The low bits of `a` and `r` produced a carry iff `c mod 2^m < r_low`.
+    let c_low = c & ((1 << m) - 1);
+    let carry =
+        bitwise_greater_than_constant(ctx.narrow(&Step::Carry), record_id, &r_low_bits, c_low)
+            .await?;
+
+    Ok(S::share_known_value(&ctx, F::truncate_from(c >> m)) - &r_high - &carry)
+}
+
+async fn div_by_constant<F, C, S>(
+    ctx: C,
+    record_id: RecordId,
+    a: &S,
+    k: u32,
+    d: u128,
+) -> Result<S, Error>
+where
+    F: PrimeField,
+    C: Context + RandomBits<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+{
+    assert_ne!(d, 0, "division by zero");
+    if k < u128::BITS && d >= 1 << k {
+        return Ok(S::ZERO);
+    }
+    if d.is_power_of_two() {
+        return truncate(ctx, record_id, a, k, d.trailing_zeros()).await;
+    }
+
+    // Granlund and Montgomery, "Division by Invariant Integers using Multiplication", Thm 4.2:
+    // with `s = k + ⌈log2 d⌉` and `M = ⌈2^s / d⌉`, `⌊a * M / 2^s⌋ = ⌊a / d⌋` for all `a < 2^k`.
+    let s = k + (u128::BITS - (d - 1).leading_zeros());
+    let multiplier = 1_u128
+        .checked_shl(s)
+        .map(|p| (p + d - 1) / d)
+        .ok_or_else(|| Error::Unsupported(format!("cannot divide {k}-bit values by {d}")))?;
+    let product_bits = k + (u128::BITS - multiplier.leading_zeros());
+
+    truncate(
+        ctx,
+        record_id,
+        &(a.clone() * F::truncate_from(multiplier)),
+        product_bits,
+        s,
+    )
+    .await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Step {
+    RandomBits,
+    RevealMasked,
+    Carry,
+}
+
+impl crate::protocol::step::Step for Step {}
+
+impl AsRef<str> for Step {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::RandomBits => "random_bits",
+            Self::RevealMasked => "reveal_masked",
+            Self::Carry => "carry",
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::Truncate;
+    use crate::{
+        error::Error,
+        ff::{Field, Fp32BitPrime, Fp61BitPrime},
+        protocol::{context::Context, RecordId},
+        rand::{thread_rng, Rng},
+        test_fixture::{Reconstruct, Runner, TestWorld},
+    };
+
+    const K: u32 = 20;
+    /// Largest input width `div_by_constant` supports in `Fp61BitPrime`.
+    const DIV_K: u32 = 9;
+
+    #[tokio::test]
+    pub async fn truncate_semi_honest() {
+        let world = TestWorld::default();
+        let mut rng = thread_rng();
+
+        for m in [0, 1, 5, K] {
+            let a = rng.gen_range(0..1_u128 << K);
+            let result: Vec<Fp61BitPrime> = world
+                .semi_honest(Fp61BitPrime::truncate_from(a), |ctx, a| async move {
+                    let ctx = ctx.set_total_records(1);
+                    let exact = a.truncate(ctx.narrow("exact"), RecordId::FIRST, K, m);
+                    let pr = a.truncate_pr(ctx.narrow("pr"), RecordId::FIRST, K, m);
+                    vec![exact.await.unwrap(), pr.await.unwrap()]
+                })
+                .await
+                .reconstruct();
+
+            assert_eq!(a >> m, result[0].as_u128(), "{a} >> {m}");
+            let pr = result[1].as_u128();
+            assert!(pr == a >> m || pr == (a >> m) + 1, "{a} >> {m} ~ {pr}");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn truncate_malicious() {
+        let world = TestWorld::default();
+        let a = thread_rng().gen_range(0..1_u128 << K);
+
+        let result: Fp61BitPrime = world
+            .upgraded_malicious(Fp61BitPrime::truncate_from(a), |ctx, a| async move {
+                a.truncate(ctx.set_total_records(1), RecordId::FIRST, K, 4)
+                    .await
+                    .unwrap()
+            })
+            .await
+            .reconstruct();
+
+        assert_eq!(a >> 4, result.as_u128());
+    }
+
+    #[tokio::test]
+    pub async fn div_by_constant() {
+        let world = TestWorld::default();
+        let mut rng = thread_rng();
+
+        for d in [1, 3, 7, 8, 100] {
+            let a = rng.gen_range(0..1_u128 << DIV_K);
+            let result: Fp61BitPrime = world
+                .semi_honest(Fp61BitPrime::truncate_from(a), |ctx, a| async move {
+                    a.div_by_constant(ctx.set_total_records(1), RecordId::FIRST, DIV_K, d)
+                        .await
+                        .unwrap()
+                })
+                .await
+                .reconstruct();
+            assert_eq!(a / d, result.as_u128(), "{a} / {d}");
+        }
+
+        let a = rng.gen_range(0..1_u128 << DIV_K);
+        let result: Fp61BitPrime = world
+            .upgraded_malicious(Fp61BitPrime::truncate_from(a), |ctx, a| async move {
+                a.div_by_constant(ctx.set_total_records(1), RecordId::FIRST, DIV_K, 10)
+                    .await
+                    .unwrap()
+            })
+            .await
+            .reconstruct();
+        assert_eq!(a / 10, result.as_u128());
+    }
+
+    /// Divisors that exceed every `k`-bit value produce zero.
+    #[tokio::test]
+    pub async fn div_by_large_constant() {
+        let world = TestWorld::default();
+        let a = thread_rng().gen_range(0..1_u128 << 4);
+
+        for d in [16, 100] {
+            let result: Fp61BitPrime = world
+                .semi_honest(Fp61BitPrime::truncate_from(a), |ctx, a| async move {
+                    a.div_by_constant(ctx.set_total_records(1), RecordId::FIRST, 4, d)
+                        .await
+                        .unwrap()
+                })
+                .await
+                .reconstruct();
+            assert_eq!(0, result.as_u128(), "{a} / {d}");
+        }
+    }
+
+    /// Inputs that cannot be masked with enough statistical security are rejected. `Fp32BitPrime`
+    /// is too small for any input.
+    #[tokio::test]
+    pub async fn rejects_wide_inputs() {
+        let world = TestWorld::default();
+
+        let results = world
+            .semi_honest(Fp32BitPrime::truncate_from(1_u128), |ctx, a| async move {
+                a.truncate(ctx.set_total_records(1), RecordId::FIRST, 4, 1)
+                    .await
+            })
+            .await;
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(Error::Unsupported(_)))));
+
+        let results = world
+            .semi_honest(Fp61BitPrime::truncate_from(1_u128), |ctx, a| async move {
+                let ctx = ctx.set_total_records(1);
+                [
+                    a.truncate(ctx.narrow("truncate"), RecordId::FIRST, K + 1, 1)
+                        .await,
+                    a.div_by_constant(ctx.narrow("div"), RecordId::FIRST, DIV_K + 1, 3)
+                        .await,
+                ]
+            })
+            .await;
+        assert!(results
+            .iter()
+            .flatten()
+            .all(|r| matches!(r, Err(Error::Unsupported(_)))));
+    }
+}
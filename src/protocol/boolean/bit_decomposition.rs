@@ -102,11 +102,15 @@ mod tests {
     use crate::{
         ff::{Field, Fp31, Fp32BitPrime, PrimeField},
         protocol::{
-            boolean::random_bits_generator::RandomBitsGenerator, context::Context, RecordId,
+            basics::BasicProtocols,
+            boolean::{random_bits_generator::RandomBitsGenerator, RandomBits},
+            context::Context,
+            RecordId,
         },
-        secret_sharing::replicated::malicious::ExtendableField,
-        test_fixture::{bits_to_value, Reconstruct, Runner, TestWorld},
+        secret_sharing::{replicated::malicious::ExtendableField, Linear as LinearSecretSharing},
+        test_fixture::{bits_to_value, fuzz, into_bits, Reconstruct, Runner, TestWorld},
     };
+    use proptest::proptest;
     use rand::{distributions::Standard, prelude::Distribution};
 
     async fn bit_decomposition<F>(world: &TestWorld, a: F) -> Vec<F>
@@ -169,4 +173,31 @@ mod tests {
             bits_to_value(&bit_decomposition(&world, c(Fp32BitPrime::PRIME - 1)).await)
         );
     }
+
+    /// Decomposes `a` under either security model.
+    async fn decompose<F, C, S>(ctx: C, a: S) -> Vec<S>
+    where
+        F: PrimeField,
+        C: Context + RandomBits<F, Share = S>,
+        S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+    {
+        let ctx = ctx.set_total_records(1);
+        let rbg = RandomBitsGenerator::new(ctx.narrow("generate_random_bits"));
+        BitDecomposition::execute(ctx, RecordId::from(0), &rbg, &a)
+            .await
+            .unwrap()
+    }
+
+    proptest! {
+        #![proptest_config(fuzz::config())]
+
+        #[test]
+        fn fp31_fuzz(a in fuzz::field_value::<Fp31>()) {
+            fuzz::check_protocol(
+                &into_bits(a).to_vec(),
+                |world| async move { world.semi_honest(a, decompose).await.reconstruct() },
+                |world| async move { world.upgraded_malicious(a, decompose).await.reconstruct() },
+            );
+        }
+    }
 }
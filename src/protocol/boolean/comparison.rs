@@ -325,11 +325,16 @@ mod tests {
     use crate::{
         ff::{Field, Fp31, Fp32BitPrime, PrimeField},
         protocol::{
-            boolean::random_bits_generator::RandomBitsGenerator, context::Context, RecordId,
+            basics::BasicProtocols,
+            boolean::{random_bits_generator::RandomBitsGenerator, RandomBits},
+            context::Context,
+            RecordId,
         },
         rand::thread_rng,
-        secret_sharing::{replicated::malicious::ExtendableField, SharedValue},
-        test_fixture::{into_bits, Reconstruct, Runner, TestWorld},
+        secret_sharing::{
+            replicated::malicious::ExtendableField, Linear as LinearSecretSharing, SharedValue,
+        },
+        test_fixture::{fuzz, into_bits, Reconstruct, Runner, TestWorld},
     };
     use proptest::proptest;
     use rand::{distributions::Standard, prelude::Distribution, Rng};
@@ -588,6 +593,45 @@ mod tests {
         }
     }
 
+    /// Compares `a` with `c` under either security model.
+    async fn gt_constant<F, C, S>(ctx: C, a: S, c: u128) -> S
+    where
+        F: PrimeField,
+        C: Context + RandomBits<F, Share = S>,
+        S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+    {
+        let ctx = ctx.set_total_records(1);
+        let rbg = RandomBitsGenerator::new(ctx.clone());
+        greater_than_constant(ctx, RecordId::from(0), &rbg, &a, c)
+            .await
+            .unwrap()
+    }
+
+    proptest! {
+        #![proptest_config(fuzz::config())]
+
+        #[test]
+        fn gt_fuzz(a in fuzz::field_value::<Fp32BitPrime>(), c in 0..Fp32BitPrime::PRIME) {
+            let c = u128::from(c);
+            let expected = Fp32BitPrime::truncate_from(a.as_u128() > c);
+            fuzz::check_protocol(
+                &expected,
+                |world| async move {
+                    world
+                        .semi_honest(a, |ctx, a| gt_constant(ctx, a, c))
+                        .await
+                        .reconstruct()
+                },
+                |world| async move {
+                    world
+                        .upgraded_malicious(a, |ctx, a| gt_constant(ctx, a, c))
+                        .await
+                        .reconstruct()
+                },
+            );
+        }
+    }
+
     // this test is for manual execution only
     #[ignore]
     #[tokio::test]
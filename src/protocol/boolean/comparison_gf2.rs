@@ -0,0 +1,342 @@
+//! Comparison protocols for values that are bitwise shared in `Gf2`.
+//!
+//! Unlike the routines in [`comparison`], these work on two secret-shared values, so
+//! protocols that already hold both sides of a comparison as XOR-shared bits (match keys,
+//! timestamps, breakdown keys) can compare them without converting into a prime field first.
+//!
+//! [`comparison`]: crate::protocol::boolean::comparison
+use crate::{
+    error::Error,
+    ff::Gf2,
+    protocol::{
+        basics::SecureMul, boolean::bitwise_equal::bitwise_equal_gf2, context::Context,
+        step::BitOpStep, BasicProtocols, RecordId,
+    },
+    secret_sharing::{BitDecomposed, Linear as LinearSecretSharing},
+};
+use std::iter::zip;
+
+/// Compares `[a]` and `[b]` and returns `1` iff `a < b`. Both values are little-endian
+/// sequences of bits and must have the same length.
+///
+/// The comparison is a ripple comparator that walks from the least significant bit up,
+/// maintaining `[lt_i] = [a_0..i < b_0..i]`:
+///
+/// `lt_i = lt_{i-1} ⊕ ((a_i ⊕ b_i) ∧ (b_i ⊕ lt_{i-1}))`
+///
+/// When bits `a_i` and `b_i` differ, the result is `b_i`, otherwise it stays `lt_{i-1}`.
+/// It requires one multiplication per bit and `a.len()` rounds of communication.
+///
+/// # Errors
+/// Propagates errors from multiplications.
+///
+/// # Panics
+/// If `a` and `b` have different lengths.
+pub async fn bitwise_less_than_gf2<C, S>(
+    ctx: C,
+    record_id: RecordId,
+    a: &BitDecomposed<S>,
+    b: &BitDecomposed<S>,
+) -> Result<S, Error>
+where
+    C: Context,
+    S: LinearSecretSharing<Gf2> + SecureMul<C>,
+{
+    assert_eq!(
+        a.len(),
+        b.len(),
+        "compared values must have the same length"
+    );
+
+    let mut lt = S::ZERO;
+    for (i, (a_bit, b_bit)) in zip(a.iter(), b.iter()).enumerate() {
+        let differ = a_bit.clone() - b_bit;
+        let flip = differ
+            .multiply(
+                &(b_bit.clone() - &lt),
+                ctx.narrow(&BitOpStep::from(i)),
+                record_id,
+            )
+            .await?;
+        lt += &flip;
+    }
+
+    Ok(lt)
+}
+
+/// Compares `[a]` and `[b]` and returns `1` iff `a > b`.
+///
+/// # Errors
+/// Propagates errors from multiplications.
+///
+/// # Panics
+/// If `a` and `b` have different lengths.
+pub async fn bitwise_greater_than_gf2<C, S>(
+    ctx: C,
+    record_id: RecordId,
+    a: &BitDecomposed<S>,
+    b: &BitDecomposed<S>,
+) -> Result<S, Error>
+where
+    C: Context,
+    S: LinearSecretSharing<Gf2> + SecureMul<C>,
+{
+    bitwise_less_than_gf2(ctx, record_id, b, a).await
+}
+
+/// Compares `[a]` and `[b]` and returns `1` iff `a == b`.
+///
+/// # Errors
+/// Propagates errors from multiplications.
+///
+/// # Panics
+/// If `a` and `b` have different lengths.
+pub async fn bitwise_equal_to_gf2<C, S>(
+    ctx: C,
+    record_id: RecordId,
+    a: &BitDecomposed<S>,
+    b: &BitDecomposed<S>,
+) -> Result<S, Error>
+where
+    C: Context,
+    S: LinearSecretSharing<Gf2> + BasicProtocols<C, Gf2>,
+{
+    assert_eq!(
+        a.len(),
+        b.len(),
+        "compared values must have the same length"
+    );
+    bitwise_equal_gf2(ctx, record_id, a, b).await
+}
+
+/// Returns the bits of the smaller of `[a]` and `[b]`.
+///
+/// # Errors
+/// Propagates errors from multiplications.
+///
+/// # Panics
+/// If `a` and `b` have different lengths.
+pub async fn bitwise_min_gf2<C, S>(
+    ctx: C,
+    record_id: RecordId,
+    a: &BitDecomposed<S>,
+    b: &BitDecomposed<S>,
+) -> Result<BitDecomposed<S>, Error>
+where
+    C: Context,
+    S: LinearSecretSharing<Gf2> + SecureMul<C>,
+{
+    let a_lt_b = bitwise_less_than_gf2(ctx.narrow(&Step::Compare), record_id, a, b).await?;
+    select(ctx.narrow(&Step::Select), record_id, &a_lt_b, a, b).await
+}
+
+/// Returns the bits of the larger of `[a]` and `[b]`.
+///
+/// # Errors
+/// Propagates errors from multiplications.
+///
+/// # Panics
+/// If `a` and `b` have different lengths.
+pub async fn bitwise_max_gf2<C, S>(
+    ctx: C,
+    record_id: RecordId,
+    a: &BitDecomposed<S>,
+    b: &BitDecomposed<S>,
+) -> Result<BitDecomposed<S>, Error>
+where
+    C: Context,
+    S: LinearSecretSharing<Gf2> + SecureMul<C>,
+{
+    let a_lt_b = bitwise_less_than_gf2(ctx.narrow(&Step::Compare), record_id, a, b).await?;
+    select(ctx.narrow(&Step::Select), record_id, &a_lt_b, b, a).await
+}
+
+/// Returns `[x]` if `[condition]` is `1` and `[y]` otherwise, computed bitwise as
+/// `y_i ⊕ (condition ∧ (x_i ⊕ y_i))`.
+async fn select<C, S>(
+    ctx: C,
+    record_id: RecordId,
+    condition: &S,
+    x: &BitDecomposed<S>,
+    y: &BitDecomposed<S>,
+) -> Result<BitDecomposed<S>, Error>
+where
+    C: Context,
+    S: LinearSecretSharing<Gf2> + SecureMul<C>,
+{
+    let selected = ctx
+        .parallel_join(
+            zip(x.iter(), y.iter())
+                .enumerate()
+                .map(|(i, (x_bit, y_bit))| {
+                    let c = ctx.narrow(&BitOpStep::from(i));
+                    async move {
+                        let diff = condition
+                            .multiply(&(x_bit.clone() - y_bit), c, record_id)
+                            .await?;
+                        Ok::<_, Error>(diff + y_bit)
+                    }
+                }),
+        )
+        .await?;
+
+    BitDecomposed::try_from(selected)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Step {
+    Compare,
+    Select,
+}
+
+impl crate::protocol::step::Step for Step {}
+
+impl AsRef<str> for Step {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Compare => "compare",
+            Self::Select => "select",
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::{
+        bitwise_equal_to_gf2, bitwise_greater_than_gf2, bitwise_less_than_gf2, bitwise_max_gf2,
+        bitwise_min_gf2,
+    };
+    use crate::{
+        ff::{Field, Gf2},
+        protocol::{
+            context::{Context, UpgradedMaliciousContext},
+            BasicProtocols, RecordId,
+        },
+        rand::{thread_rng, Rng},
+        secret_sharing::{BitDecomposed, Linear as LinearSecretSharing},
+        test_fixture::{get_bits, Reconstruct, Runner, TestWorld},
+    };
+
+    const BITS: u32 = 12;
+
+    fn value(bits: &[Gf2]) -> u32 {
+        bits.iter()
+            .enumerate()
+            .map(|(i, b)| u32::try_from(b.as_u128()).unwrap() << i)
+            .sum()
+    }
+
+    /// Returns `[a < b]`, `[a > b]` and `[a == b]`.
+    async fn lt_gt_eq<C, S>(ctx: C, a: &BitDecomposed<S>, b: &BitDecomposed<S>) -> Vec<S>
+    where
+        C: Context,
+        S: LinearSecretSharing<Gf2> + BasicProtocols<C, Gf2>,
+    {
+        let ctx = ctx.set_total_records(1);
+        let lt = bitwise_less_than_gf2(ctx.narrow("lt"), RecordId::FIRST, a, b);
+        let gt = bitwise_greater_than_gf2(ctx.narrow("gt"), RecordId::FIRST, a, b);
+        let eq = bitwise_equal_to_gf2(ctx.narrow("eq"), RecordId::FIRST, a, b);
+        vec![lt.await.unwrap(), gt.await.unwrap(), eq.await.unwrap()]
+    }
+
+    /// Returns the bits of `min(a, b)` followed by the bits of `max(a, b)`.
+    async fn min_then_max<C, S>(ctx: C, a: &BitDecomposed<S>, b: &BitDecomposed<S>) -> Vec<S>
+    where
+        C: Context,
+        S: LinearSecretSharing<Gf2> + BasicProtocols<C, Gf2>,
+    {
+        let ctx = ctx.set_total_records(1);
+        let min = bitwise_min_gf2(ctx.narrow("min"), RecordId::FIRST, a, b);
+        let max = bitwise_max_gf2(ctx.narrow("max"), RecordId::FIRST, a, b);
+        let mut result = min.await.unwrap().to_vec();
+        result.extend(max.await.unwrap());
+        result
+    }
+
+    async fn compare(world: &TestWorld, a: u32, b: u32) -> [bool; 3] {
+        let input = (get_bits::<Gf2>(a, BITS), get_bits::<Gf2>(b, BITS));
+        let result: Vec<Gf2> = world
+            .semi_honest(
+                input.clone(),
+                |ctx, (a, b): (BitDecomposed<_>, BitDecomposed<_>)| async move {
+                    lt_gt_eq(ctx, &a, &b).await
+                },
+            )
+            .await
+            .reconstruct();
+        let [lt, gt, eq]: [Gf2; 3] = result.try_into().unwrap();
+
+        let m_result: Vec<Gf2> = world
+            .upgraded_malicious(
+                input,
+                |ctx: UpgradedMaliciousContext<Gf2>, (a, b): (Vec<_>, Vec<_>)| async move {
+                    lt_gt_eq(ctx, &BitDecomposed::new(a), &BitDecomposed::new(b)).await
+                },
+            )
+            .await
+            .reconstruct();
+        assert_eq!(vec![lt, gt, eq], m_result);
+
+        [lt == Gf2::ONE, gt == Gf2::ONE, eq == Gf2::ONE]
+    }
+
+    #[tokio::test]
+    pub async fn less_greater_equal() {
+        let world = TestWorld::default();
+        let mut rng = thread_rng();
+        let mut cases = vec![
+            (0, 0),
+            (0, 1),
+            (1, 0),
+            (4095, 4095),
+            (4094, 4095),
+            (2048, 2047),
+        ];
+        cases.extend((0..10).map(|_| (rng.gen_range(0..1 << BITS), rng.gen_range(0..1 << BITS))));
+
+        for (a, b) in cases {
+            assert_eq!(
+                [a < b, a > b, a == b],
+                compare(&world, a, b).await,
+                "{a} vs {b}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    pub async fn min_max() {
+        let world = TestWorld::default();
+        let mut rng = thread_rng();
+
+        for _ in 0..5 {
+            let (a, b) = (rng.gen_range(0..1 << BITS), rng.gen_range(0..1 << BITS));
+            let input = (get_bits::<Gf2>(a, BITS), get_bits::<Gf2>(b, BITS));
+
+            let result: Vec<Gf2> = world
+                .semi_honest(
+                    input.clone(),
+                    |ctx, (a, b): (BitDecomposed<_>, BitDecomposed<_>)| async move {
+                        min_then_max(ctx, &a, &b).await
+                    },
+                )
+                .await
+                .reconstruct();
+            let (min, max) = result.split_at(BITS as usize);
+            assert_eq!(a.min(b), value(min));
+            assert_eq!(a.max(b), value(max));
+
+            let m_result: Vec<Gf2> = world
+                .upgraded_malicious(
+                    input,
+                    |ctx: UpgradedMaliciousContext<Gf2>, (a, b): (Vec<_>, Vec<_>)| async move {
+                        min_then_max(ctx, &BitDecomposed::new(a), &BitDecomposed::new(b)).await
+                    },
+                )
+                .await
+                .reconstruct();
+            let (m_min, m_max) = m_result.split_at(BITS as usize);
+            assert_eq!(a.min(b), value(m_min));
+            assert_eq!(a.max(b), value(m_max));
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     error::Error,
-    ff::{Field, Gf40Bit, PrimeField},
+    ff::{Field, Gf64Bit, PrimeField},
     protocol::{
         basics::SecureMul,
         context::{
@@ -49,13 +49,13 @@ where
 
     // Same here. For now, 256-bit is enough for our F_p
     let xor_share = Replicated::new(
-        Gf40Bit::truncate_from(b_bits_left),
-        Gf40Bit::truncate_from(b_bits_right),
+        Gf64Bit::truncate_from(b_bits_left),
+        Gf64Bit::truncate_from(b_bits_right),
     );
 
     // Convert each bit to secret sharings of that bit in the target field
     (0..l)
-        .map(|i| convert_bit_local::<F, Gf40Bit>(ctx.role(), i, &xor_share))
+        .map(|i| convert_bit_local::<F, Gf64Bit>(ctx.role(), i, &xor_share))
         .collect::<Vec<_>>()
 }
 
@@ -16,6 +16,7 @@ pub mod bit_decomposition;
 pub mod bitwise_equal;
 pub mod bitwise_less_than_prime;
 pub mod comparison;
+pub mod comparison_gf2;
 pub mod generate_random_bits;
 pub mod or;
 pub mod random_bits_generator;
@@ -24,6 +25,10 @@ mod xor;
 
 pub use bit_decomposition::BitDecomposition;
 pub use comparison::greater_than_constant;
+pub use comparison_gf2::{
+    bitwise_equal_to_gf2, bitwise_greater_than_gf2, bitwise_less_than_gf2, bitwise_max_gf2,
+    bitwise_min_gf2,
+};
 pub use generate_random_bits::RandomBits;
 pub use solved_bits::RandomBitsShare;
 pub use xor::{xor, xor_sparse};
@@ -275,7 +275,8 @@ mod tests {
             ReplicatedSecretSharing,
         },
         telemetry::metrics::{
-            BYTES_SENT, INDEXED_PRSS_GENERATED, RECORDS_SENT, SEQUENTIAL_PRSS_GENERATED,
+            BYTES_SENT, INDEXED_PRSS_GENERATED, INDEXED_PRSS_MAX_INDEX, RECORDS_SENT,
+            SEQUENTIAL_PRSS_GENERATED,
         },
         test_fixture::{Reconstruct, Runner, TestWorld, TestWorldConfig},
     };
@@ -413,6 +414,14 @@ mod tests {
             .total(6 * 3 * input_size)
             .per_step(&metrics_step.narrow("seq-prss-0"), 6 * 3);
 
+        // record ids are used as PRSS indices, so the largest one is the last record id
+        #[allow(clippy::cast_precision_loss)]
+        let max_index = (input_size - 1) as f64;
+        assert_eq!(
+            Some(max_index),
+            snapshot.get_gauge_per_step(INDEXED_PRSS_MAX_INDEX, &metrics_step)
+        );
+
         for role in Role::all() {
             records_sent_assert.per_helper(role, input_size);
             bytes_sent_assert.per_helper(role, field_size * input_size);
@@ -497,6 +506,21 @@ mod tests {
         }
     }
 
+    /// `TestWorld` must detect protocols drawing the same PRSS index twice under the same gate.
+    #[tokio::test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "twice using the same key")]
+    async fn prss_index_reuse() {
+        let world = TestWorld::default();
+        world
+            .semi_honest((), |ctx, ()| async move {
+                let ctx = ctx.narrow("reuse");
+                let _: (Fp31, Fp31) = ctx.prss().generate_fields(RecordId::FIRST);
+                let _: (Fp31, Fp31) = ctx.prss().generate_fields(RecordId::FIRST);
+            })
+            .await;
+    }
+
     /// validates that malicious upgrade can be called more than once on contexts narrowed down
     /// to unique steps
     #[tokio::test]
@@ -1,24 +1,34 @@
 //! Metric-aware PRSS decorators
 
 use crate::{
+    ff::Field,
     helpers::Role,
     protocol::{
         prss::{IndexedSharedRandomness, SequentialSharedRandomness, SharedRandomness},
         step::Gate,
     },
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     telemetry::{
         labels::{ROLE, STEP},
-        metrics::{INDEXED_PRSS_GENERATED, SEQUENTIAL_PRSS_GENERATED},
+        metrics::{INDEXED_PRSS_GENERATED, INDEXED_PRSS_MAX_INDEX, SEQUENTIAL_PRSS_GENERATED},
     },
 };
 use rand_core::{Error, RngCore};
+use std::ops::Range;
 
-/// Wrapper around `IndexedSharedRandomness` that instrument calls to `generate_values`
+/// Wrapper around `IndexedSharedRandomness` that instrument calls to `generate_values`.
+///
+/// The number of generated values is accumulated locally and published, along with the
+/// maximum index used at this gate, when the wrapper is dropped. This keeps metric updates
+/// (and the allocation of the step label) off the path of every single call.
 pub struct InstrumentedIndexedSharedRandomness<'a> {
     inner: Arc<IndexedSharedRandomness>,
     step: &'a Gate,
     role: Role,
+    generated: AtomicUsize,
 }
 
 impl<'a> InstrumentedIndexedSharedRandomness<'a> {
@@ -28,19 +38,43 @@ impl<'a> InstrumentedIndexedSharedRandomness<'a> {
             inner: source,
             step,
             role,
+            generated: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Drop for InstrumentedIndexedSharedRandomness<'_> {
+    /// Reports the number of values generated through this instance and the maximum index consumed by this gate so far. Indices are expected to stay below the number
+    /// of records processed at this gate, a larger value points to a protocol that derives PRSS
+    /// indices incorrectly and may end up reusing them.
+    #[allow(clippy::cast_precision_loss)]
+    fn drop(&mut self) {
+        let generated = self.generated.load(Ordering::Relaxed);
+        if generated == 0 {
+            return;
         }
+        let step = self.step.as_ref().to_string();
+        if let Some(max) = self.inner.max_index() {
+            metrics::gauge!(INDEXED_PRSS_MAX_INDEX, max as f64, STEP => step.clone(), ROLE => self.role.as_static_str());
+        }
+        metrics::counter!(INDEXED_PRSS_GENERATED, generated as u64, STEP => step, ROLE => self.role.as_static_str());
     }
 }
 
 impl SharedRandomness for InstrumentedIndexedSharedRandomness<'_> {
     fn generate_values<I: Into<u128>>(&self, index: I) -> (u128, u128) {
-        let step = self.step.as_ref().to_string();
-        // TODO: what we really want here is a gauge indicating the maximum index used to generate
-        // PRSS. Gauge infrastructure is not supported yet, `Metrics` struct needs to be able to
-        // handle gauges
-        metrics::increment_counter!(INDEXED_PRSS_GENERATED, STEP => step, ROLE => self.role.as_static_str());
+        self.generated.fetch_add(1, Ordering::Relaxed);
         self.inner.generate_values(index)
     }
+
+    fn generate_fields_range<F: Field, I: Into<u128>>(
+        &self,
+        indices: Range<I>,
+        out: &mut [(F, F)],
+    ) {
+        self.generated.fetch_add(out.len(), Ordering::Relaxed);
+        self.inner.generate_fields_range(indices, out);
+    }
 }
 
 /// Wrapper for `SequentialSharedRandomness` that instrument calls to generate random values.
@@ -11,10 +11,15 @@ use crate::{
         prss::SharedRandomness,
         RecordId,
     },
-    secret_sharing::replicated::{
-        malicious::{AdditiveShare as MaliciousReplicated, DowngradeMalicious, ExtendableField},
-        semi_honest::AdditiveShare as Replicated,
-        ReplicatedSecretSharing,
+    secret_sharing::{
+        replicated::{
+            malicious::{
+                AdditiveShare as MaliciousReplicated, DowngradeMalicious, ExtendableField,
+            },
+            semi_honest::AdditiveShare as Replicated,
+            ReplicatedSecretSharing,
+        },
+        SharedValue,
     },
     sync::{Arc, Mutex, Weak},
 };
@@ -269,10 +274,13 @@ impl<'a, F: ExtendableField> Malicious<'a, F> {
     #[allow(clippy::needless_pass_by_value)]
     pub fn new(ctx: MaliciousContext<'a>) -> Self {
         // Use the current step in the context for initialization.
-        let r_share: Replicated<F::ExtendedField> = ctx.prss().generate_replicated(RecordId::FIRST);
-        let prss = ctx.prss();
-        let u: F::ExtendedField = prss.zero(RecordId::FIRST + 1);
-        let w: F::ExtendedField = prss.zero(RecordId::FIRST + 2);
+        // `r`, `u` and `w` are drawn from three consecutive PRSS indices, so generate them in one go.
+        let mut values = [(F::ExtendedField::ZERO, F::ExtendedField::ZERO); 3];
+        ctx.prss()
+            .generate_fields_range(RecordId::FIRST..RecordId::FIRST + 3, &mut values);
+        let [(r_left, r_right), (u_left, u_right), (w_left, w_right)] = values;
+        let r_share = Replicated::new(r_left, r_right);
+        let (u, w) = (u_left - u_right, w_left - w_right);
         let state = AccumulatorState::new(u, w);
 
         let u_and_w = Arc::new(Mutex::new(state));
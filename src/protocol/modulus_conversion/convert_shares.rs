@@ -223,8 +223,9 @@ mod tests {
         secret_sharing::replicated::{
             semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing,
         },
-        test_fixture::{Reconstruct, Runner, TestWorld},
+        test_fixture::{assert_attacks_detected, MaliciousHelper, Reconstruct, Runner, TestWorld},
     };
+    use futures::FutureExt;
 
     #[tokio::test]
     pub async fn one_bit() {
@@ -330,4 +331,27 @@ mod tests {
                 .await;
         }
     }
+
+    #[tokio::test]
+    pub async fn one_bit_malicious_additive_attack() {
+        const BITNUM: u32 = 4;
+
+        let attacks = Role::all().iter().map(|&malicious_actor| {
+            MaliciousHelper::new(malicious_actor)
+                .add_offset("duplicate_multiply", Fp32BitPrime::ONE)
+        });
+        assert_attacks_detected(attacks, thread_rng().gen::<MatchKey>(), |ctx, mk_share| {
+            async move {
+                let triple =
+                    convert_bit_local::<Fp32BitPrime, MatchKey>(ctx.role(), BITNUM, &mk_share);
+                let m_triples = ctx.upgrade([triple]).await.unwrap();
+                let m_ctx = ctx.set_total_records(1);
+                convert_bit(m_ctx, RecordId::from(0), &m_triples[0])
+                    .await
+                    .unwrap()
+            }
+            .boxed()
+        })
+        .await;
+    }
 }
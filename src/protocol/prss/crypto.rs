@@ -11,6 +11,7 @@ use aes::{
 use hkdf::Hkdf;
 use rand::{CryptoRng, RngCore};
 use sha2::Sha256;
+use std::{iter::zip, ops::Range};
 use x25519_dalek::{EphemeralSecret, PublicKey};
 
 pub trait SharedRandomness {
@@ -27,6 +28,29 @@ pub trait SharedRandomness {
         (F::truncate_from(l), F::truncate_from(r))
     }
 
+    /// Generate random field values for every index in the contiguous range `indices`, writing
+    /// the pair generated for `indices.start + i` into `out[i]`. The result is the same as
+    /// calling [`Self::generate_fields`] for each index, implementations are free to produce
+    /// the values in bulk.
+    ///
+    /// ## Panics
+    /// If the length of `out` does not match the number of indices in the range.
+    fn generate_fields_range<F: Field, I: Into<u128>>(
+        &self,
+        indices: Range<I>,
+        out: &mut [(F, F)],
+    ) {
+        let (start, end) = (indices.start.into(), indices.end.into());
+        assert_eq!(
+            end.saturating_sub(start),
+            out.len() as u128,
+            "output does not match the index range {start}..{end}"
+        );
+        for (index, v) in zip(start.., out) {
+            *v = self.generate_fields(index);
+        }
+    }
+
     /// Generate two sequences of random Fp2 bits.
     #[must_use]
     fn generate_bit_arrays<B: GaloisField, I: Into<u128>>(&self, index: I) -> (B, B) {
@@ -144,6 +168,9 @@ pub struct Generator {
 }
 
 impl Generator {
+    /// Number of blocks encrypted together by [`Self::generate_batch`].
+    pub const BATCH: usize = 8;
+
     /// Generate the value at the given index.
     /// This uses the MMO^{\pi} function described in <https://eprint.iacr.org/2019/074>.
     #[must_use]
@@ -154,4 +181,25 @@ impl Generator {
 
         u128::from_le_bytes(buf) ^ index
     }
+
+    /// Generate values for the contiguous range of indices `start..start + out.len()`, writing
+    /// them into `out`. The output is the same as calling `generate` for every index, but blocks
+    /// are handed to the cipher [`Self::BATCH`] at a time, so it can pipeline them (AES-NI
+    /// processes several blocks in parallel).
+    pub fn generate_batch(&self, start: u128, out: &mut [u128]) {
+        let mut blocks = [GenericArray::default(); Self::BATCH];
+        let mut index = start;
+        for chunk in out.chunks_mut(Self::BATCH) {
+            let blocks = &mut blocks[..chunk.len()];
+            for (i, block) in blocks.iter_mut().enumerate() {
+                *block = GenericArray::from((index + i as u128).to_le_bytes());
+            }
+            self.cipher.encrypt_blocks(blocks);
+
+            for (dst, block) in zip(chunk.iter_mut(), blocks.iter()) {
+                *dst = u128::from_le_bytes((*block).into()) ^ index;
+                index += 1;
+            }
+        }
+    }
 }
@@ -10,23 +10,30 @@ pub use no_op::{Generator, GeneratorFactory, KeyExchange, SharedRandomness};
 
 use super::step::Gate;
 use crate::{
+    ff::Field,
     rand::{CryptoRng, RngCore},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
-use std::{collections::HashMap, fmt::Debug};
+use std::{collections::HashMap, fmt::Debug, iter::zip, ops::Range};
 use x25519_dalek::PublicKey;
 
+#[cfg(any(test, debug_assertions))]
+use crate::helpers::Direction;
 #[cfg(debug_assertions)]
 use std::{collections::HashSet, fmt::Formatter};
 
 /// Keeps track of all indices used to generate shared randomness inside `IndexedSharedRandomness`.
 /// Any two indices provided to `IndexesSharedRandomness::generate_values` must be unique.
 /// As PRSS instance is unique per step, this only constrains randomness generated within
-/// a given step.
+/// a given step. Left and right randomness are tracked separately, so it is possible
+/// to draw randomness shared with only one peer without consuming the index for the other one.
 #[cfg(debug_assertions)]
 struct UsedSet {
     key: Gate,
-    used: Arc<Mutex<HashSet<usize>>>,
+    used: Arc<Mutex<HashSet<(usize, Direction)>>>,
 }
 
 #[cfg(debug_assertions)]
@@ -38,11 +45,11 @@ impl UsedSet {
         }
     }
 
-    /// Adds a given index to the list of used indices.
+    /// Adds a given index to the list of used indices for the given direction.
     ///
     /// ## Panics
     /// Panic if this index has been used before.
-    fn insert(&self, index: u128) {
+    fn insert(&self, index: u128, direction: Direction) {
         if index > usize::MAX as u128 {
             tracing::warn!(
                 "PRSS verification can validate values not exceeding {}, index {index} is greater.",
@@ -50,12 +57,21 @@ impl UsedSet {
             );
         } else {
             assert!(
-                self.used.lock().unwrap().insert(index as usize),
-                "Generated randomness for index '{index}' twice using the same key '{}'",
+                self.used
+                    .lock()
+                    .unwrap()
+                    .insert((index as usize, direction)),
+                "Generated {direction:?} randomness for index '{index}' twice using the same key '{}'",
                 self.key
             );
         }
     }
+
+    /// Marks the index as used in both directions.
+    fn insert_both(&self, index: u128) {
+        self.insert(index, Direction::Left);
+        self.insert(index, Direction::Right);
+    }
 }
 
 #[cfg(debug_assertions)]
@@ -75,20 +91,94 @@ impl Debug for UsedSet {
 pub struct IndexedSharedRandomness {
     left: Generator,
     right: Generator,
+    /// One past the largest index used to generate randomness so far, zero if none were used.
+    max_index: AtomicUsize,
+    /// Index reuse tracker, only present in debug builds if `Endpoint` was set up to track reuse.
     #[cfg(debug_assertions)]
-    used: UsedSet,
+    used: Option<UsedSet>,
+}
+
+impl IndexedSharedRandomness {
+    /// Returns the largest index used to generate randomness with this instance, or `None`
+    /// if it has not been used yet. Indices that do not fit into `usize` are reported as
+    /// `usize::MAX - 1`.
+    #[must_use]
+    pub fn max_index(&self) -> Option<usize> {
+        self.max_index.load(Ordering::Relaxed).checked_sub(1)
+    }
+
+    /// Generate the random value shared with the helper in the given `direction`. Only tests
+    /// draw one direction at a time, protocols use [`SharedRandomness::generate_values`].
+    #[cfg(test)]
+    #[must_use]
+    fn generate_value<I: Into<u128>>(&self, index: I, direction: Direction) -> u128 {
+        let index = index.into();
+        #[cfg(debug_assertions)]
+        if let Some(used) = &self.used {
+            used.insert(index, direction);
+        }
+        self.observe_index(index);
+
+        match direction {
+            Direction::Left => self.left.generate(index),
+            Direction::Right => self.right.generate(index),
+        }
+    }
+
+    fn observe_index(&self, index: u128) {
+        let bound = usize::try_from(index).map_or(usize::MAX, |v| v.saturating_add(1));
+        self.max_index.fetch_max(bound, Ordering::Relaxed);
+    }
 }
 
 impl SharedRandomness for IndexedSharedRandomness {
     fn generate_values<I: Into<u128>>(&self, index: I) -> (u128, u128) {
         let index = index.into();
         #[cfg(debug_assertions)]
-        {
-            self.used.insert(index);
+        if let Some(used) = &self.used {
+            used.insert_both(index);
         }
+        self.observe_index(index);
 
         (self.left.generate(index), self.right.generate(index))
     }
+
+    /// Generates the values [`Generator::BATCH`] indices at a time, so AES can pipeline them.
+    fn generate_fields_range<F: Field, I: Into<u128>>(
+        &self,
+        indices: Range<I>,
+        out: &mut [(F, F)],
+    ) {
+        let (start, end) = (indices.start.into(), indices.end.into());
+        assert_eq!(
+            end.saturating_sub(start),
+            out.len() as u128,
+            "output does not match the index range {start}..{end}"
+        );
+        if start == end {
+            return;
+        }
+        #[cfg(debug_assertions)]
+        if let Some(used) = &self.used {
+            for index in start..end {
+                used.insert_both(index);
+            }
+        }
+        self.observe_index(end - 1);
+
+        let mut left = [0_u128; Generator::BATCH];
+        let mut right = [0_u128; Generator::BATCH];
+        let mut index = start;
+        for chunk in out.chunks_mut(Generator::BATCH) {
+            let (left, right) = (&mut left[..chunk.len()], &mut right[..chunk.len()]);
+            self.left.generate_batch(index, left);
+            self.right.generate_batch(index, right);
+            for (v, (&l, &r)) in zip(chunk.iter_mut(), zip(left.iter(), right.iter())) {
+                *v = (F::truncate_from(l), F::truncate_from(r));
+            }
+            index += chunk.len() as u128;
+        }
+    }
 }
 
 /// An implementation of `RngCore` that uses the same underlying `Generator`.
@@ -124,12 +214,24 @@ impl RngCore for SequentialSharedRandomness {
         v as u64
     }
 
+    // Large requests are served in batches of generated values, using the same
+    // `u64` per index layout as `fill_bytes_via_next` would.
+    #[allow(clippy::cast_possible_truncation)]
     fn fill_bytes(&mut self, dest: &mut [u8]) {
-        rand_core::impls::fill_bytes_via_next(self, dest);
+        let mut values = [0_u128; Generator::BATCH];
+        let mut chunks = dest.chunks_exact_mut(8 * Generator::BATCH);
+        for chunk in &mut chunks {
+            self.generator.generate_batch(self.counter, &mut values);
+            self.counter += values.len() as u128;
+            for (bytes, v) in zip(chunk.chunks_exact_mut(8), values) {
+                bytes.copy_from_slice(&(v as u64).to_le_bytes());
+            }
+        }
+        rand_core::impls::fill_bytes_via_next(self, chunks.into_remainder());
     }
 
     fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
-        rand_core::impls::fill_bytes_via_next(self, dest);
+        self.fill_bytes(dest);
         Ok(())
     }
 }
@@ -145,10 +247,12 @@ pub struct Endpoint {
 impl Endpoint {
     /// Construct a new, unconfigured participant.  This can be configured by
     /// providing public keys for the left and right participants to `setup()`.
+    /// Index reuse detection is enabled in debug builds, see [`EndpointSetup::track_index_reuse`].
     pub fn prepare<R: RngCore + CryptoRng>(r: &mut R) -> EndpointSetup {
         EndpointSetup {
             left: KeyExchange::new(r),
             right: KeyExchange::new(r),
+            track_reuse: cfg!(debug_assertions),
         }
     }
 
@@ -188,6 +292,10 @@ struct EndpointInner {
     left: GeneratorFactory,
     right: GeneratorFactory,
     items: HashMap<Gate, EndpointItem>,
+    /// Whether `IndexedSharedRandomness` instances created by this endpoint must detect index
+    /// reuse. Has no effect in release builds.
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    track_reuse: bool,
 }
 
 impl EndpointInner {
@@ -202,8 +310,9 @@ impl EndpointInner {
                 EndpointItem::Indexed(Arc::new(IndexedSharedRandomness {
                     left: self.left.generator(k.as_ref().as_bytes()),
                     right: self.right.generator(k.as_ref().as_bytes()),
+                    max_index: AtomicUsize::new(0),
                     #[cfg(debug_assertions)]
-                    used: UsedSet::new(key.clone()),
+                    used: self.track_reuse.then(|| UsedSet::new(key.clone())),
                 }))
             })
         };
@@ -234,6 +343,7 @@ impl EndpointInner {
 pub struct EndpointSetup {
     left: KeyExchange,
     right: KeyExchange,
+    track_reuse: bool,
 }
 
 impl EndpointSetup {
@@ -245,6 +355,17 @@ impl EndpointSetup {
         (self.left.public_key(), self.right.public_key())
     }
 
+    /// Makes the endpoint keep track of every (gate, index, direction) triple used to generate
+    /// indexed randomness and panic if the same one is used twice. Generating randomness with
+    /// the same index under the same gate silently breaks security, so this is useful to catch
+    /// protocol bugs. Tracking requires memory proportional to the number of indices
+    /// used, so it is only done in debug builds, where it is enabled by default.
+    #[must_use]
+    pub fn track_index_reuse(mut self, enabled: bool) -> Self {
+        self.track_reuse = enabled;
+        self
+    }
+
     /// Provide the left and right public keys to construct a functioning
     /// participant instance.
     #[must_use]
@@ -256,6 +377,7 @@ impl EndpointSetup {
                 left: fl,
                 right: fr,
                 items: HashMap::new(),
+                track_reuse: self.track_reuse,
             }),
         }
     }
@@ -266,11 +388,12 @@ pub mod test {
     use super::{Generator, KeyExchange, SequentialSharedRandomness};
     use crate::{
         ff::{Field, Fp31},
+        helpers::Direction,
         protocol::{
             prss::{Endpoint, SharedRandomness},
             step::{Gate, StepNarrow},
         },
-        rand::{thread_rng, Rng},
+        rand::{thread_rng, Rng, RngCore},
         secret_sharing::SharedValue,
         test_fixture::make_participants,
     };
@@ -330,6 +453,128 @@ pub mod test {
         assert_ne!(g1.generate(1), g2.generate(1));
     }
 
+    /// Batched generation produces the same values as generating them one by one.
+    #[test]
+    fn generate_batch_matches() {
+        let (g1, _) = make();
+        let mut batch = [0_u128; 19];
+        g1.generate_batch(1000, &mut batch);
+        for (i, v) in batch.iter().enumerate() {
+            assert_eq!(g1.generate(1000 + i as u128), *v);
+        }
+    }
+
+    /// Sequential randomness served in batches is the same as the one served value by value.
+    #[test]
+    fn sequential_fill_bytes() {
+        let [p1, p2, _p3] = participants();
+        let step = Gate::default().narrow("fill");
+        let (_, mut batched) = p1.sequential(&step);
+        let (mut one_by_one, _) = p2.sequential(&step);
+
+        let mut bytes = [0_u8; 150];
+        batched.fill_bytes(&mut bytes);
+        for chunk in bytes.chunks(8) {
+            let expected = one_by_one.next_u64().to_le_bytes();
+            assert_eq!(&expected[..chunk.len()], chunk);
+        }
+        assert_eq!(batched.next_u64(), one_by_one.next_u64());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Right randomness for index '3' twice")]
+    fn indexed_rejects_the_same_direction() {
+        let [p1, _p2, _p3] = participants();
+        let step = Gate::default().narrow("test");
+
+        let _: u128 = p1.indexed(&step).generate_value(3_u128, Direction::Right);
+        let _: u128 = p1.indexed(&step).generate_value(3_u128, Direction::Right);
+    }
+
+    #[test]
+    fn indexed_accepts_different_directions() {
+        let [p1, p2, _p3] = participants();
+        let step = Gate::default().narrow("test");
+
+        let r = p1.indexed(&step).generate_value(3_u128, Direction::Right);
+        let l = p1.indexed(&step).generate_value(3_u128, Direction::Left);
+        assert_eq!(r, p2.indexed(&step).generate_value(3_u128, Direction::Left));
+        assert_ne!(l, r);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "twice using the same key")]
+    fn endpoint_tracks_reuse_by_default() {
+        let mut r = thread_rng();
+        let (s1, s2) = (Endpoint::prepare(&mut r), Endpoint::prepare(&mut r));
+        let (pk1_l, pk1_r) = s1.public_keys();
+        let (pk2_l, pk2_r) = s2.public_keys();
+        let p1 = s1.setup(&pk2_r, &pk2_l);
+        let _p2 = s2.setup(&pk1_r, &pk1_l);
+        let step = Gate::default().narrow("test");
+
+        let _: u128 = p1.indexed(&step).random_u128(1_u128);
+        let _: u128 = p1.indexed(&step).random_u128(1_u128);
+    }
+
+    #[test]
+    fn untracked_endpoint_allows_reuse() {
+        let mut r = thread_rng();
+        let (s1, s2) = (Endpoint::prepare(&mut r), Endpoint::prepare(&mut r));
+        let (pk1_l, pk1_r) = s1.public_keys();
+        let (pk2_l, pk2_r) = s2.public_keys();
+        let p1 = s1.track_index_reuse(false).setup(&pk2_r, &pk2_l);
+        let _p2 = s2.setup(&pk1_r, &pk1_l);
+        let step = Gate::default().narrow("test");
+
+        assert_eq!(
+            p1.indexed(&step).random_u128(1_u128),
+            p1.indexed(&step).random_u128(1_u128)
+        );
+    }
+
+    #[test]
+    fn indexed_max_index() {
+        let [p1, _p2, _p3] = participants();
+        let step = Gate::default().narrow("max_index");
+        let prss = p1.indexed(&step);
+        assert_eq!(None, prss.max_index());
+
+        let _: (Fp31, Fp31) = prss.generate_fields(14_u128);
+        let _: (Fp31, Fp31) = prss.generate_fields(5_u128);
+        assert_eq!(Some(14), prss.max_index());
+    }
+
+    #[test]
+    fn indexed_fields_range() {
+        let [p1, p2, p3] = participants();
+        let step = Gate::default().narrow("range");
+        let prss = p1.indexed(&step);
+
+        let mut batch = [(Fp31::ZERO, Fp31::ZERO); 19];
+        prss.generate_fields_range(5_u128..24, &mut batch);
+        for (index, (l, r)) in (5_u128..).zip(batch) {
+            let (_, p3_r): (Fp31, Fp31) = p3.indexed(&step).generate_fields(index);
+            let (p2_l, _): (Fp31, Fp31) = p2.indexed(&step).generate_fields(index);
+            assert_eq!((p3_r, p2_l), (l, r));
+        }
+        assert_eq!(Some(23), prss.max_index());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "randomness for index '7' twice")]
+    fn indexed_fields_range_rejects_reuse() {
+        let [p1, _p2, _p3] = participants();
+        let step = Gate::default().narrow("range");
+        let prss = p1.indexed(&step);
+
+        let _: (Fp31, Fp31) = prss.generate_fields(7_u128);
+        prss.generate_fields_range(0_u128..8, &mut [(Fp31::ZERO, Fp31::ZERO); 8]);
+    }
+
     #[test]
     fn three_party_values() {
         const IDX: u128 = 7;
@@ -4,7 +4,7 @@ use crate::{
     secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
 };
 
-use std::fmt::Debug;
+use std::{fmt::Debug, ops::Range};
 
 use x25519_dalek::PublicKey;
 
@@ -18,6 +18,14 @@ pub trait SharedRandomness {
         (F::ZERO, F::ZERO)
     }
 
+    fn generate_fields_range<F: Field, I: Into<u128>>(
+        &self,
+        _indices: Range<I>,
+        out: &mut [(F, F)],
+    ) {
+        out.fill((F::ZERO, F::ZERO));
+    }
+
     #[must_use]
     fn generate_replicated<F: Field, I: Into<u128>>(&self, index: I) -> Replicated<F> {
         let (l, r) = self.generate_fields(index);
@@ -81,8 +89,14 @@ impl GeneratorFactory {
 #[derive(Debug, Clone)]
 pub struct Generator {}
 impl Generator {
+    pub const BATCH: usize = 8;
+
     #[must_use]
     pub fn generate(&self, _index: u128) -> u128 {
         0
     }
+
+    pub fn generate_batch(&self, _start: u128, out: &mut [u128]) {
+        out.fill(0);
+    }
 }
@@ -1,7 +1,7 @@
 use crate::{
     error::Error,
     ff::Field,
-    protocol::{context::Context, BasicProtocols, RecordId},
+    protocol::{context::Context, BasicProtocols},
     secret_sharing::Linear as LinearSecretSharing,
 };
 use embed_doc_image::embed_doc_image;
@@ -38,26 +38,18 @@ pub async fn bit_permutation<
     ctx: C,
     input: &[S],
 ) -> Result<Vec<S>, Error> {
-    let ctx_ref = &ctx;
     let ctx = ctx.set_total_records(2 * input.len());
     let share_of_one = S::share_known_value(&ctx, F::ONE);
 
-    let mult_input = zip(repeat(share_of_one.clone()), input)
+    let (x, sum): (Vec<_>, Vec<_>) = zip(repeat(share_of_one.clone()), input)
         .map(|(one, x)| one - x)
         .chain(input.iter().cloned())
         .scan(S::ZERO, |sum, x| {
             *sum += &x;
             Some((x, sum.clone()))
-        });
-
-    let async_multiply =
-        zip(repeat(ctx), mult_input)
-            .enumerate()
-            .map(|(i, (ctx, (x, sum)))| async move {
-                let record_id = RecordId::from(i);
-                x.multiply(&sum, ctx, record_id).await
-            });
-    let mut mult_output = ctx_ref.try_join(async_multiply).await?;
+        })
+        .unzip();
+    let mut mult_output = S::multiply_all(ctx, &x, &sum).await?;
 
     debug_assert!(mult_output.len() == input.len() * 2);
     // Generate permutation location
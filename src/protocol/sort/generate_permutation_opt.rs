@@ -159,15 +159,23 @@ mod tests {
     use crate::{
         ff::{Field, Fp31, GaloisField, Gf40Bit},
         protocol::{
-            context::{Context, Validator},
+            basics::SecureMul,
+            context::{Context, UpgradableContext, UpgradedContext, Validator},
             modulus_conversion::{convert_all_bits, convert_all_bits_local},
-            sort::generate_permutation_opt::generate_permutation_opt,
-            MatchKey,
+            sort::{
+                generate_permutation::ShuffledPermutationWrapper,
+                generate_permutation_opt::generate_permutation_opt,
+            },
+            BasicProtocols, MatchKey,
         },
         rand::{thread_rng, Rng},
-        secret_sharing::SharedValue,
-        test_fixture::{join3, Reconstruct, Runner, TestWorld},
+        secret_sharing::{
+            replicated::{malicious::DowngradeMalicious, semi_honest::AdditiveShare as Replicated},
+            Linear as LinearSecretSharing, SharedValue,
+        },
+        test_fixture::{fuzz, join3, Reconstruct, Runner, TestWorld},
     };
+    use proptest::{collection::vec, proptest};
     use std::iter::zip;
 
     #[tokio::test]
@@ -300,4 +308,67 @@ mod tests {
         sortn(1).await;
         sortn(0).await;
     }
+
+    /// The permutation that sorts `keys`: the sorted position of each key. Keys that are equal
+    /// keep their order.
+    fn sort_permutation(keys: &[MatchKey]) -> Vec<Fp31> {
+        let mut order = (0..keys.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| keys[i].as_u128());
+
+        let mut permutation = vec![Fp31::ZERO; keys.len()];
+        for (position, i) in order.into_iter().enumerate() {
+            permutation[i] = Fp31::truncate_from(u128::try_from(position).unwrap());
+        }
+        permutation
+    }
+
+    /// Sorts `match_keys` and validates the permutation, under either security model.
+    async fn sort<C, S>(ctx: C, match_keys: Vec<Replicated<MatchKey>>) -> Vec<Replicated<Fp31>>
+    where
+        C: UpgradableContext,
+        C::UpgradedContext<Fp31>: UpgradedContext<Fp31, Share = S>,
+        S: LinearSecretSharing<Fp31> + BasicProtocols<C::UpgradedContext<Fp31>, Fp31> + 'static,
+        Vec<S>: DowngradeMalicious<Target = Vec<Replicated<Fp31>>>,
+        ShuffledPermutationWrapper<S, C::UpgradedContext<Fp31>>:
+            DowngradeMalicious<Target = Vec<u32>>,
+        Replicated<Fp31>: SecureMul<C>,
+    {
+        const NUM_MULTI_BITS: u32 = 3;
+
+        let local_lists = convert_all_bits_local::<Fp31, _>(ctx.role(), match_keys.into_iter());
+        let converted_shares = convert_all_bits(&ctx, &local_lists, Gf40Bit::BITS, NUM_MULTI_BITS)
+            .await
+            .unwrap();
+        let (validator, result) =
+            generate_permutation_opt(ctx.narrow("sort"), converted_shares.iter())
+                .await
+                .unwrap();
+        validator.validate(result).await.unwrap()
+    }
+
+    proptest! {
+        #![proptest_config(fuzz::config())]
+
+        /// Keys are drawn from a small range, so that many of them are equal.
+        #[test]
+        fn sort_fuzz(keys in vec(0..16_u64, 2..20)) {
+            let match_keys = keys.into_iter().map(MatchKey::truncate_from).collect::<Vec<_>>();
+            let (sh_keys, m_keys) = (match_keys.clone(), match_keys.clone());
+            fuzz::check_protocol(
+                &sort_permutation(&match_keys),
+                |world| async move {
+                    world
+                        .semi_honest(sh_keys.into_iter(), sort)
+                        .await
+                        .reconstruct()
+                },
+                |world| async move {
+                    world
+                        .malicious(m_keys.into_iter(), sort)
+                        .await
+                        .reconstruct()
+                },
+            );
+        }
+    }
 }
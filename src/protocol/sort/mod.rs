@@ -5,7 +5,7 @@ pub mod generate_permutation_opt;
 
 mod apply;
 mod compose;
-mod multi_bit_permutation;
+pub mod multi_bit_permutation;
 mod secureapplyinv;
 mod shuffle;
 
@@ -168,10 +168,21 @@ pub async fn unshuffle_shares<F: Field, S: SecretSharing<F> + Reshare<C, RecordI
 #[cfg(all(test, unit_test))]
 mod tests {
     use crate::{
-        protocol::{sort::shuffle::get_two_of_three_random_permutations, step::Gate},
+        ff::{Field, Fp31},
+        protocol::{
+            basics::Reshare,
+            context::Context,
+            sort::shuffle::{
+                get_two_of_three_random_permutations, shuffle_shares, unshuffle_shares,
+            },
+            step::Gate,
+            RecordId,
+        },
         rand::thread_rng,
-        test_fixture::{make_participants, permutation_valid},
+        secret_sharing::SecretSharing,
+        test_fixture::{fuzz, make_participants, permutation_valid, Reconstruct, Runner},
     };
+    use proptest::{collection::vec, proptest};
 
     #[test]
     fn random_sequence_generated() {
@@ -198,6 +209,62 @@ mod tests {
         assert!(permutation_valid(&perm3.0));
     }
 
+    /// Sorts shuffled values, which should then be the input values, sorted.
+    fn sorted((shuffled, unshuffled): (Vec<Fp31>, Vec<Fp31>)) -> (Vec<u128>, Vec<Fp31>) {
+        let mut shuffled = shuffled.iter().map(Field::as_u128).collect::<Vec<_>>();
+        shuffled.sort_unstable();
+        (shuffled, unshuffled)
+    }
+
+    /// Shuffles `input` and unshuffles the result under either security model.
+    async fn shuffle_unshuffle<F, S, C>(ctx: C, input: Vec<S>) -> (Vec<S>, Vec<S>)
+    where
+        F: Field,
+        S: SecretSharing<F> + Reshare<C, RecordId>,
+        C: Context,
+    {
+        let batch_size = u32::try_from(input.len()).unwrap();
+        let perms = get_two_of_three_random_permutations(batch_size, ctx.prss_rng());
+        let perms = (perms.0.as_slice(), perms.1.as_slice());
+        let shuffled = shuffle_shares(input, perms, ctx.clone()).await.unwrap();
+        let unshuffled = unshuffle_shares(shuffled.clone(), perms, ctx.narrow("unshuffle"))
+            .await
+            .unwrap();
+        (shuffled, unshuffled)
+    }
+
+    proptest! {
+        #![proptest_config(fuzz::config())]
+
+        /// Shuffling keeps the input values, and unshuffling gives them back in their order.
+        #[test]
+        fn shuffle_unshuffle_fuzz(input in vec(fuzz::field_value::<Fp31>(), 1..32)) {
+            let mut expected = input.iter().map(Field::as_u128).collect::<Vec<_>>();
+            expected.sort_unstable();
+            let (sh_input, m_input) = (input.clone(), input.clone());
+
+            fuzz::check_protocol(
+                &(expected, input),
+                |world| async move {
+                    sorted(
+                        world
+                            .semi_honest(sh_input.into_iter(), shuffle_unshuffle)
+                            .await
+                            .reconstruct(),
+                    )
+                },
+                |world| async move {
+                    sorted(
+                        world
+                            .upgraded_malicious(m_input.into_iter(), shuffle_unshuffle)
+                            .await
+                            .reconstruct(),
+                    )
+                },
+            );
+        }
+    }
+
     mod semi_honest {
         use crate::{
             ff::{Field, Fp31},
@@ -294,15 +361,19 @@ mod tests {
 
     mod malicious {
         use crate::{
-            ff::{Field, Fp31},
+            ff::{Field, Fp31, Fp32BitPrime},
+            helpers::Role,
             protocol::{
-                context::Context,
+                context::{Context, UpgradedContext},
                 sort::shuffle::{
                     get_two_of_three_random_permutations, shuffle_shares, unshuffle_shares,
                 },
             },
-            test_fixture::{Reconstruct, Runner, TestWorld},
+            test_fixture::{
+                assert_attacks_detected, MaliciousHelper, Reconstruct, Runner, TestWorld,
+            },
         };
+        use futures::FutureExt;
         use std::collections::HashSet;
 
         #[tokio::test]
@@ -382,5 +453,43 @@ mod tests {
 
             assert_eq!(&input[..], &result.reconstruct());
         }
+
+        /// Every helper reshares in two of the three shuffle steps, so each of them adds an offset
+        /// to either `x` or `rx` in all three.
+        #[tokio::test]
+        async fn additive_attack() {
+            const BATCHSIZE: u32 = 5;
+            let input: Vec<u128> = (0..u128::from(BATCHSIZE)).collect();
+
+            let attacks = Role::all().iter().flat_map(|&malicious_actor| {
+                [
+                    ["shuffle1", "shuffle2", "shuffle3"]
+                        .into_iter()
+                        .fold(MaliciousHelper::new(malicious_actor), |helper, gate| {
+                            helper.add_offset(gate, Fp32BitPrime::ONE)
+                        }),
+                    MaliciousHelper::new(malicious_actor)
+                        .add_offset("reshare_rx", Fp32BitPrime::ONE),
+                ]
+            });
+            assert_attacks_detected(
+                attacks,
+                input.into_iter().map(Fp32BitPrime::truncate_from),
+                |ctx, shares| {
+                    async move {
+                        let perms = get_two_of_three_random_permutations(
+                            BATCHSIZE,
+                            ctx.narrow("permutations").prss_rng(),
+                        );
+                        let m_shares = ctx.upgrade(shares).await.unwrap();
+                        shuffle_shares(m_shares, (perms.0.as_slice(), perms.1.as_slice()), ctx)
+                            .await
+                            .unwrap()
+                    }
+                    .boxed()
+                },
+            )
+            .await;
+        }
     }
 }
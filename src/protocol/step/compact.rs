@@ -160,6 +160,23 @@ impl StepNarrow<crate::protocol::boolean::bitwise_equal::Step> for Compact {
     }
 }
 
+impl StepNarrow<crate::protocol::boolean::comparison_gf2::Step> for Compact {
+    // Not used by IPA yet, so `steps.txt` has no states for it
+    fn narrow(&self, step: &crate::protocol::boolean::comparison_gf2::Step) -> Self {
+        panic!(
+            "Cannot narrow a boolean::comparison_gf2::Step::{}",
+            step.as_ref()
+        )
+    }
+}
+
+impl StepNarrow<crate::protocol::basics::truncate::Step> for Compact {
+    // Not used by IPA yet, so `steps.txt` has no states for it
+    fn narrow(&self, step: &crate::protocol::basics::truncate::Step) -> Self {
+        panic!("Cannot narrow a basics::truncate::Step::{}", step.as_ref())
+    }
+}
+
 impl StepNarrow<crate::protocol::attribution::input::AttributionResharableStep> for Compact {
     // This is used in unit tests only
     fn narrow(
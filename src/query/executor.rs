@@ -1,5 +1,5 @@
 use crate::{
-    ff::{Field, FieldType, Fp32BitPrime, GaloisField, Serializable},
+    ff::{Field, FieldType, Fp32BitPrime, Fp61BitPrime, GaloisField, Serializable},
     helpers::{
         negotiate_prss,
         query::{QueryConfig, QueryType},
@@ -94,6 +94,12 @@ pub fn execute(
                 Box::pin(execute_test_multiply::<Fp32BitPrime>(prss, gateway, input))
             })
         }
+        #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
+        (QueryType::TestMultiply, FieldType::Fp61BitPrime) => {
+            do_query(config, gateway, input, |prss, gateway, _config, input| {
+                Box::pin(execute_test_multiply::<Fp61BitPrime>(prss, gateway, input))
+            })
+        }
         #[cfg(any(test, feature = "weak-field"))]
         (QueryType::SemiHonestIpa(ipa_config), FieldType::Fp31) => do_query(
             config,
@@ -121,6 +127,19 @@ pub fn execute(
                 )
             },
         ),
+        (QueryType::SemiHonestIpa(ipa_config), FieldType::Fp61BitPrime) => do_query(
+            config,
+            gateway,
+            input,
+            move |prss, gateway, config, input| {
+                let ctx = SemiHonestContext::new(prss, gateway);
+                Box::pin(
+                    IpaQuery::<Fp61BitPrime, _, _>::new(ipa_config, key_registry)
+                        .execute(ctx, config.size, input)
+                        .then(|res| ready(res.map(|out| Box::new(out) as Box<dyn Result>))),
+                )
+            },
+        ),
         #[cfg(any(test, feature = "weak-field"))]
         (QueryType::MaliciousIpa(ipa_config), FieldType::Fp31) => do_query(
             config,
@@ -148,6 +167,19 @@ pub fn execute(
                 )
             },
         ),
+        (QueryType::MaliciousIpa(ipa_config), FieldType::Fp61BitPrime) => do_query(
+            config,
+            gateway,
+            input,
+            move |prss, gateway, config, input| {
+                let ctx = MaliciousContext::new(prss, gateway);
+                Box::pin(
+                    IpaQuery::<Fp61BitPrime, _, _>::new(ipa_config, key_registry)
+                        .execute(ctx, config.size, input)
+                        .then(|res| ready(res.map(|out| Box::new(out) as Box<dyn Result>))),
+                )
+            },
+        ),
     }
 }
 
@@ -168,6 +200,7 @@ where
         + 'static,
 {
     let (tx, rx) = oneshot::channel();
+    let progress = gateway.progress();
 
     let join_handle = tokio::spawn(async move {
         // TODO: make it a generic argument for this function
@@ -183,6 +216,7 @@ where
     RunningQuery {
         result: rx,
         join_handle,
+        progress,
     }
 }
 
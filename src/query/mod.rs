@@ -11,6 +11,6 @@ pub use processor::{
     QueryInputError, QueryStatusError,
 };
 
-pub use state::QueryStatus;
+pub use state::{QueryProgress, QueryStatus};
 
 use completion::Handle as CompletionHandle;
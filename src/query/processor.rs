@@ -8,7 +8,7 @@ use crate::{
     protocol::QueryId,
     query::{
         executor,
-        state::{QueryState, QueryStatus, RemoveQuery, RunningQueries, StateError},
+        state::{QueryProgress, QueryState, QueryStatus, RemoveQuery, RunningQueries, StateError},
         CompletionHandle, ProtocolResult,
     },
 };
@@ -16,6 +16,7 @@ use futures::{future::try_join, stream};
 use std::{
     collections::hash_map::Entry,
     fmt::{Debug, Formatter},
+    num::NonZeroU32,
     sync::Arc,
 };
 
@@ -39,6 +40,8 @@ use std::{
 pub struct Processor {
     queries: RunningQueries,
     key_registry: Arc<KeyRegistry<KeyPair>>,
+    /// If set, the maximum number of bytes per second every query sends to each peer.
+    bandwidth_limit: Option<NonZeroU32>,
 }
 
 impl Default for Processor {
@@ -46,6 +49,7 @@ impl Default for Processor {
         Self {
             queries: RunningQueries::default(),
             key_registry: Arc::new(KeyRegistry::<KeyPair>::empty()),
+            bandwidth_limit: None,
         }
     }
 }
@@ -113,6 +117,16 @@ impl Processor {
         Self {
             queries: RunningQueries::default(),
             key_registry: Arc::new(key_registry),
+            bandwidth_limit: None,
+        }
+    }
+
+    /// Limits the rate at which every query sends data to each peer to `bytes_per_second`.
+    #[must_use]
+    pub fn with_bandwidth_limit(self, bytes_per_second: NonZeroU32) -> Self {
+        Self {
+            bandwidth_limit: Some(bytes_per_second),
+            ..self
         }
     }
 
@@ -217,12 +231,12 @@ impl Processor {
                         input.query_id, query_id,
                         "received inputs for a different query"
                     );
-                    let gateway = Gateway::new(
-                        query_id,
-                        GatewayConfig::from(&config),
-                        role_assignment,
-                        transport,
-                    );
+                    let mut gateway_config = GatewayConfig::from(&config);
+                    if let Some(limit) = self.bandwidth_limit {
+                        gateway_config = gateway_config.with_bandwidth_limit(limit);
+                    }
+                    let gateway =
+                        Gateway::new(query_id, gateway_config, role_assignment, transport);
                     queries.insert(
                         input.query_id,
                         QueryState::Running(executor::execute(
@@ -270,6 +284,25 @@ impl Processor {
         Ok(status)
     }
 
+    /// Returns the progress of the query, if it is running and has sent some records already.
+    ///
+    /// ## Errors
+    /// If query is not registered on this helper.
+    ///
+    /// ## Panics
+    /// If the query collection mutex is poisoned.
+    pub fn query_progress(
+        &self,
+        query_id: QueryId,
+    ) -> Result<Option<QueryProgress>, QueryStatusError> {
+        let queries = self.queries.inner.lock().unwrap();
+        match queries.get(&query_id) {
+            Some(QueryState::Running(running)) => Ok(running.progress()),
+            Some(_) => Ok(None),
+            None => Err(QueryStatusError::NoSuchQuery(query_id)),
+        }
+    }
+
     /// Awaits the query completion
     ///
     /// ## Errors
@@ -315,7 +348,8 @@ mod tests {
         ff::FieldType,
         helpers::{
             query::{QueryType, QueryType::TestMultiply},
-            HelperIdentity, InMemoryNetwork, PrepareQueryCallback, TransportCallbacks,
+            FaultSchedule, HelperIdentity, InMemoryNetwork, PrepareQueryCallback, RouteId,
+            TransportCallbacks,
         },
     };
     use futures::pin_mut;
@@ -460,6 +494,25 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn prepare_rejected_by_network() {
+        let network = InMemoryNetwork::with_faults(
+            array::from_fn(|_| TransportCallbacks {
+                prepare_query: prepare_query_callback(|_, _| async { Ok(()) }),
+                ..Default::default()
+            }),
+            &FaultSchedule::new(0).reject(RouteId::PrepareQuery),
+        );
+        let [t0, _, _] = network.transports();
+        let p0 = Processor::default();
+
+        assert!(matches!(
+            p0.new_query(t0, test_multiply_config()).await.unwrap_err(),
+            NewQueryError::Transport(_)
+        ));
+        assert!(p0.query_status(QueryId).is_err());
+    }
+
     mod prepare {
         use super::*;
 
@@ -524,7 +577,7 @@ mod tests {
         use crate::{
             error::BoxError,
             ff::{Field, Fp31},
-            helpers::query::IpaQueryConfig,
+            helpers::query::{IpaQueryConfig, StepStreamConfig},
             ipa_test_input,
             protocol::{ipa::IPAInputRow, BreakdownKey, MatchKey},
             secret_sharing::replicated::semi_honest,
@@ -618,6 +671,7 @@ mod tests {
                             num_multi_bits: 3,
                             plaintext_match_keys: true,
                         }),
+                        step_stream: StepStreamConfig::default(),
                     },
                 )
                 .await?;
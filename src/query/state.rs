@@ -1,6 +1,6 @@
 use crate::{
-    helpers::{query::QueryConfig, RoleAssignment},
-    protocol::QueryId,
+    helpers::{query::QueryConfig, ChannelProgress, GatewayProgress, RoleAssignment},
+    protocol::{step::Gate, QueryId},
     query::runner::QueryResult,
     sync::Mutex,
     task::JoinHandle,
@@ -10,7 +10,7 @@ use futures::{ready, FutureExt};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{hash_map::Entry, HashMap},
-    fmt::{Debug, Formatter},
+    fmt::{Debug, Display, Formatter},
     future::Future,
     task::Poll,
 };
@@ -34,6 +34,50 @@ pub enum QueryStatus {
     Completed,
 }
 
+/// How far along a running query is. The query is at the step of the protocol that sends
+/// records on `gate`, and has sent `records_sent` of them so far. This only tells the progress
+/// of one step, a query makes progress as long as these numbers change.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QueryProgress {
+    /// The top-level stage of the protocol, e.g. `attribution`.
+    pub stage: String,
+    pub gate: String,
+    pub records_sent: usize,
+    /// Number of records sent on `gate` in total, if it is known upfront.
+    pub total_records: Option<usize>,
+}
+
+impl QueryProgress {
+    /// Progress of a protocol whose contexts were created at `root`. Its stage is the step that
+    /// `root` was narrowed to first on the way to the gate of the channel, or empty if the
+    /// channel is not under `root`.
+    fn new(root: &Gate, source: &ChannelProgress) -> Self {
+        let gate = source.channel_id.gate.as_ref().to_string();
+        let stage = gate
+            .strip_prefix(root.as_ref())
+            .and_then(|steps| steps.strip_prefix('/'))
+            .and_then(|steps| steps.split('/').next())
+            .unwrap_or_default()
+            .to_string();
+        Self {
+            stage,
+            gate,
+            records_sent: source.records_sent,
+            total_records: source.total_records,
+        }
+    }
+}
+
+impl Display for QueryProgress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.stage, self.records_sent)?;
+        if let Some(total) = self.total_records {
+            write!(f, "/{total}")?;
+        }
+        write!(f, " records sent on {}", self.gate)
+    }
+}
+
 impl From<&QueryState> for QueryStatus {
     fn from(source: &QueryState) -> Self {
         match source {
@@ -86,9 +130,20 @@ pub struct RunningQuery {
     /// We could return the result via the JoinHandle, except that we want to check the status
     /// of the task, and shuttle doesn't implement `JoinHandle::is_finished`.
     pub join_handle: JoinHandle<()>,
+
+    /// Records sent by the gateway of the query.
+    pub progress: GatewayProgress,
 }
 
 impl RunningQuery {
+    pub fn progress(&self) -> Option<QueryProgress> {
+        // `executor::execute` creates the contexts of every query at the default gate
+        let root = Gate::default();
+        self.progress
+            .current()
+            .map(|progress| QueryProgress::new(&root, &progress))
+    }
+
     pub fn try_complete(&mut self) -> Option<QueryResult> {
         match self.result.try_recv() {
             Ok(result) => Some(result),
@@ -225,3 +280,54 @@ impl Drop for RemoveQuery<'_> {
         }
     }
 }
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::{
+        helpers::{ChannelId, Role},
+        protocol::{ipa::Step as IpaStep, step::StepNarrow},
+    };
+
+    fn progress(root: &Gate, gate: Gate) -> QueryProgress {
+        QueryProgress::new(
+            root,
+            &ChannelProgress {
+                channel_id: ChannelId::new(Role::H2, gate),
+                records_sent: 10,
+                total_records: Some(100),
+            },
+        )
+    }
+
+    #[test]
+    fn stage_is_first_step_under_root() {
+        let stage = Gate::default().narrow(&IpaStep::GenSortPermutationFromMatchKeys);
+        let sort = progress(&Gate::default(), stage.narrow("bit0").narrow("mul"));
+        assert_eq!(
+            IpaStep::GenSortPermutationFromMatchKeys.as_ref(),
+            sort.stage
+        );
+        assert_eq!(stage.narrow("bit0").narrow("mul").as_ref(), sort.gate);
+
+        let root = Gate::default().narrow("run-0");
+        assert_eq!(
+            "attribution",
+            progress(&root, root.narrow("attribution").narrow("credit")).stage
+        );
+    }
+
+    #[test]
+    fn no_stage_outside_root() {
+        let root = Gate::default().narrow("run-0");
+        assert_eq!("", progress(&root, root.clone()).stage);
+        assert_eq!(
+            "",
+            progress(
+                &root,
+                Gate::default().narrow("run-01").narrow("attribution")
+            )
+            .stage
+        );
+    }
+}
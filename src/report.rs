@@ -123,6 +123,8 @@ pub enum InvalidReportError {
     NonAsciiString(#[from] NonAsciiStringError),
     #[error("timestamp {0} out of range")]
     Timestamp(Timestamp),
+    #[error("report is {0} bytes long, it must be at least {1} bytes")]
+    Length(usize, usize),
     #[error("en/decryption failure: {0}")]
     Crypt(#[from] CryptError),
 }
@@ -223,6 +225,12 @@ where
     /// ## Errors
     /// If the report contents are invalid.
     pub fn from_bytes(bytes: B) -> Result<Self, InvalidReportError> {
+        if bytes.len() < Self::SITE_DOMAIN_OFFSET {
+            return Err(InvalidReportError::Length(
+                bytes.len(),
+                Self::SITE_DOMAIN_OFFSET,
+            ));
+        }
         EventType::try_from(bytes[Self::EVENT_TYPE_OFFSET])?;
         let site_domain = &bytes[Self::SITE_DOMAIN_OFFSET..];
         if !site_domain.is_ascii() {
@@ -489,4 +497,14 @@ mod test {
             .unwrap();
         assert!(matches!(err, InvalidReportError::NonAsciiString(_)));
     }
+
+    #[test]
+    fn too_short() {
+        let bytes = hex::decode("3301e8d7528e0867").unwrap();
+
+        let err = EncryptedReport::<Fp32BitPrime, Gf40Bit, Gf8Bit, _>::from_bytes(bytes.as_slice())
+            .err()
+            .unwrap();
+        assert!(matches!(err, InvalidReportError::Length(8, _)));
+    }
 }
@@ -6,16 +6,20 @@ pub use step_stats::CsvExporter as StepStatsCsvExporter;
 pub mod labels {
     pub const STEP: &str = "step";
     pub const ROLE: &str = "role";
+    pub const CERTIFICATE: &str = "certificate";
 }
 
 pub mod metrics {
-    use metrics::{describe_counter, Unit};
+    use metrics::{describe_counter, describe_gauge, Unit};
 
     pub const REQUESTS_RECEIVED: &str = "requests.received";
     pub const RECORDS_SENT: &str = "records.sent";
     pub const BYTES_SENT: &str = "bytes.sent";
+    pub const WIRE_BYTES_SENT: &str = "bytes.sent.wire";
     pub const INDEXED_PRSS_GENERATED: &str = "i.prss.gen";
     pub const SEQUENTIAL_PRSS_GENERATED: &str = "s.prss.gen";
+    pub const INDEXED_PRSS_MAX_INDEX: &str = "i.prss.max_idx";
+    pub const TLS_CERTIFICATE_EXPIRY: &str = "tls.certificate.expiry_days";
 
     #[cfg(feature = "web-app")]
     pub mod web {
@@ -87,6 +91,12 @@ pub mod metrics {
             "Bytes sent from the infrastructure layer to the network"
         );
 
+        describe_counter!(
+            WIRE_BYTES_SENT,
+            Unit::Count,
+            "Bytes sent over the network after compression"
+        );
+
         describe_counter!(
             INDEXED_PRSS_GENERATED,
             Unit::Count,
@@ -98,5 +108,17 @@ pub mod metrics {
             Unit::Count,
             "Number of times PRSS is used as CPRNG to generate a random value"
         );
+
+        describe_gauge!(
+            INDEXED_PRSS_MAX_INDEX,
+            Unit::Count,
+            "Maximum index used to generate shared randomness"
+        );
+
+        describe_gauge!(
+            TLS_CERTIFICATE_EXPIRY,
+            Unit::Count,
+            "Number of days until a configured TLS certificate expires"
+        );
     }
 }
@@ -19,7 +19,7 @@ pub struct CounterDetails {
 }
 
 /// Container for metrics, their descriptions and values they've accumulated.
-/// Currently only support `Counter`, however `Gauge` and `Histogram` can be easily added later.
+/// Currently supports `Counter` and `Gauge`, however `Histogram` can be easily added later.
 ///
 /// An example of a counter layout inside this struct
 /// `counter_name` -> (`total_value`: X, `dimensions`: (Y -> X1, Y -> X2))
@@ -34,10 +34,37 @@ pub struct CounterDetails {
 /// X1 and X2 cannot be greater than X, but these values may overlap, i.e. X1 + X2 >= X
 pub struct Metrics {
     pub counters: HashMap<KeyName, CounterDetails>,
+    pub gauges: HashMap<KeyName, GaugeDetails>,
     pub metric_description: HashMap<KeyName, SharedString>,
     pub print_header: bool,
 }
 
+/// Gauge stats. Protocols use gauges to report maximum values, so values are aggregated
+/// by taking the maximum across all dimensions.
+#[derive(Debug, Default)]
+pub struct GaugeDetails {
+    pub max_value: f64,
+    pub dimensions: HashMap<SharedString, HashMap<SharedString, f64>>,
+}
+
+impl GaugeDetails {
+    pub fn add(&mut self, key: &CompositeKey, val: &DebugValue) {
+        let val = match val {
+            DebugValue::Gauge(v) => v.into_inner(),
+            _ => unreachable!(),
+        };
+        for label in key.key().labels() {
+            let (label_key, label_val) = label.clone().into_parts();
+            let dimension_values = self.dimensions.entry(label_key).or_default();
+
+            let v = dimension_values.entry(label_val).or_insert(val);
+            *v = v.max(val);
+        }
+
+        self.max_value = self.max_value.max(val);
+    }
+}
+
 impl CounterDetails {
     pub fn add(&mut self, key: &CompositeKey, val: &DebugValue) {
         let val = match val {
@@ -46,10 +73,7 @@ impl CounterDetails {
         };
         for label in key.key().labels() {
             let (label_key, label_val) = label.clone().into_parts();
-            let dimension_values = self
-                .dimensions
-                .entry(label_key)
-                .or_insert_with(HashMap::new);
+            let dimension_values = self.dimensions.entry(label_key).or_default();
 
             *dimension_values.entry(label_val).or_insert(0) += val;
         }
@@ -75,6 +99,7 @@ impl Metrics {
     pub fn with_filter<F: Fn(&[Label]) -> bool>(snapshot: Snapshot, filter_fn: F) -> Self {
         let mut this = Metrics {
             counters: HashMap::new(),
+            gauges: HashMap::new(),
             metric_description: HashMap::new(),
             print_header: !cfg!(feature = "step-trace"),
         };
@@ -85,18 +110,14 @@ impl Metrics {
             if !filter_fn(labels.as_slice()) {
                 continue;
             }
-            let entry = this
-                .counters
-                .entry(key_name.clone())
-                .or_insert_with(CounterDetails::default);
-
             if let Some(descr) = descr {
-                this.metric_description.insert(key_name, descr);
+                this.metric_description.insert(key_name.clone(), descr);
             }
 
             match ckey.kind() {
-                MetricKind::Counter => entry.add(&ckey, &val),
-                MetricKind::Gauge | MetricKind::Histogram => unimplemented!(),
+                MetricKind::Counter => this.counters.entry(key_name).or_default().add(&ckey, &val),
+                MetricKind::Gauge => this.gauges.entry(key_name).or_default().add(&ckey, &val),
+                MetricKind::Histogram => unimplemented!(),
             }
         }
 
@@ -110,6 +131,24 @@ impl Metrics {
             .map_or(0, |details| details.total_value)
     }
 
+    /// Returns the maximum value recorded by the given gauge, if it has been set.
+    #[must_use]
+    pub fn get_gauge(&self, name: &'static str) -> Option<f64> {
+        self.gauges
+            .get::<KeyName>(&name.into())
+            .map(|details| details.max_value)
+    }
+
+    /// Returns the maximum value the given gauge reported at the given step.
+    #[must_use]
+    pub fn get_gauge_per_step(&self, name: &'static str, gate: &Gate) -> Option<f64> {
+        self.gauges
+            .get::<KeyName>(&name.into())
+            .and_then(|details| details.dimensions.get(labels::STEP))
+            .and_then(|steps| steps.get(gate.as_ref()))
+            .copied()
+    }
+
     /// Creates a new assertion object that later can be used to validate assumptions about the
     /// given metric
     ///
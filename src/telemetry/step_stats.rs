@@ -3,7 +3,10 @@
 
 use crate::telemetry::{
     labels,
-    metrics::{BYTES_SENT, INDEXED_PRSS_GENERATED, RECORDS_SENT, SEQUENTIAL_PRSS_GENERATED},
+    metrics::{
+        BYTES_SENT, INDEXED_PRSS_GENERATED, INDEXED_PRSS_MAX_INDEX, RECORDS_SENT,
+        SEQUENTIAL_PRSS_GENERATED, WIRE_BYTES_SENT,
+    },
     stats::Metrics,
 };
 use std::{
@@ -31,6 +34,14 @@ impl CsvExporter for Metrics {
                 }
             }
         }
+        for (gauge_name, details) in &self.gauges {
+            if let Some(steps) = details.dimensions.get(labels::STEP) {
+                for (step, val) in steps {
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    steps_stats.offer(step, gauge_name.as_str(), *val as u64);
+                }
+            }
+        }
 
         // then dump them to the provided Write interface
         // TODO: include role dimension. That requires rethinking `Metrics` implementation
@@ -38,18 +49,20 @@ impl CsvExporter for Metrics {
         if self.print_header {
             writeln!(
                 w,
-                "Step,Records sent,Bytes sent,Indexed PRSS,Sequential PRSS"
+                "Step,Records sent,Bytes sent,Wire bytes sent,Indexed PRSS,Sequential PRSS,Max PRSS index"
             )?;
         }
         for (step, stats) in steps_stats.all_steps() {
             writeln!(
                 w,
-                "{},{},{},{},{}",
+                "{},{},{},{},{},{},{}",
                 step,
                 stats.get(RECORDS_SENT),
                 stats.get(BYTES_SENT),
+                stats.get(WIRE_BYTES_SENT),
                 stats.get(INDEXED_PRSS_GENERATED),
-                stats.get(SEQUENTIAL_PRSS_GENERATED)
+                stats.get(SEQUENTIAL_PRSS_GENERATED),
+                stats.get(INDEXED_PRSS_MAX_INDEX)
             )?;
         }
 
@@ -10,7 +10,7 @@ use crate::{
 };
 use std::iter::zip;
 
-use crate::helpers::{InMemoryNetwork, InMemoryTransport};
+use crate::helpers::{FaultSchedule, InMemoryNetwork, InMemoryTransport, TransportCallbacks};
 use generic_array::GenericArray;
 use typenum::Unsigned;
 
@@ -57,10 +57,26 @@ fn unzip_tuple_array<T, U>(input: [(T, U); 3]) -> ([T; 3], [U; 3]) {
 
 impl Default for TestApp {
     fn default() -> Self {
+        Self::new(InMemoryNetwork::new)
+    }
+}
+
+impl TestApp {
+    /// Creates an app whose helpers disrupt the requests they send to each other according
+    /// to `faults`.
+    #[must_use]
+    pub fn with_faults(faults: &FaultSchedule) -> Self {
+        Self::new(|callbacks| InMemoryNetwork::with_faults(callbacks, faults))
+    }
+
+    fn new<F>(network: F) -> Self
+    where
+        F: FnOnce([TransportCallbacks<InMemoryTransport>; 3]) -> InMemoryNetwork,
+    {
         let (setup, callbacks) =
             unzip_tuple_array([AppSetup::new(), AppSetup::new(), AppSetup::new()]);
 
-        let network = InMemoryNetwork::new(callbacks);
+        let network = network(callbacks);
         let drivers = network
             .transports()
             .iter()
@@ -73,9 +89,7 @@ impl Default for TestApp {
 
         Self { drivers, network }
     }
-}
 
-impl TestApp {
     /// Initiates a new query on all helpers and drives it to completion.
     ///
     /// ## Errors
@@ -0,0 +1,407 @@
+//! Communication cost of protocols, per step, as a function of the input size.
+//!
+//! The cost is measured by running any protocol in [`TestWorld`] at a few small input sizes, and
+//! fitting a linear model to the number of records and bytes sent at every step. That is enough
+//! to tell the cost at sizes that are too large to run. The number of rounds usually grows with
+//! the logarithm of the input size, which a few small sizes can't pin down, so it is reported as
+//! measured.
+
+use crate::{
+    telemetry::{
+        labels,
+        metrics::{BYTES_SENT, RECORDS_SENT},
+        StepStatsCsvExporter,
+    },
+    test_fixture::{TestWorld, TestWorldConfig},
+};
+use futures::future::LocalBoxFuture;
+use metrics::KeyName;
+#[cfg(feature = "enable-serde")]
+use serde::Serialize;
+use std::{collections::BTreeMap, io};
+
+/// Communication at one step of a protocol, summed over all helpers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize))]
+pub struct StepCost {
+    pub records_sent: u64,
+    pub bytes_sent: u64,
+    /// Sequential communication rounds. Only measured if the world has link latency.
+    pub rounds: usize,
+}
+
+impl StepCost {
+    fn add(&mut self, other: &Self) {
+        self.records_sent += other.records_sent;
+        self.bytes_sent += other.bytes_sent;
+        // steps under the same parent may run in parallel, so this is an upper bound.
+        self.rounds += other.rounds;
+    }
+}
+
+/// Communication cost of every step of a protocol run at one input size.
+#[derive(Debug, Default)]
+pub struct CostSample {
+    pub size: usize,
+    pub steps: BTreeMap<String, StepCost>,
+}
+
+impl CostSample {
+    /// Runs `protocol` on `size` records in a new world, and collects its cost. Metrics are
+    /// enabled on `config`. For the rounds to be counted, it must have link latency.
+    pub async fn measure<P>(config: TestWorldConfig, size: usize, protocol: P) -> Self
+    where
+        P: for<'w> FnOnce(&'w TestWorld) -> LocalBoxFuture<'w, ()>,
+    {
+        let world = TestWorld::new_with(config.enable_metrics());
+        protocol(&world).await;
+        Self::collect(&world, size)
+    }
+
+    /// Collects the cost of the protocols run in `world` so far. `world` must have metrics enabled
+    /// and, for the rounds to be counted, link latency.
+    #[must_use]
+    pub fn collect(world: &TestWorld, size: usize) -> Self {
+        let metrics = world.metrics_snapshot();
+        let mut steps = BTreeMap::<String, StepCost>::new();
+        for (name, field) in [
+            (RECORDS_SENT, StepCostField::Records),
+            (BYTES_SENT, StepCostField::Bytes),
+        ] {
+            let Some(per_step) = metrics
+                .counters
+                .get::<KeyName>(&name.into())
+                .and_then(|details| details.dimensions.get(labels::STEP))
+            else {
+                continue;
+            };
+            for (step, value) in per_step {
+                let cost = steps.entry(step.to_string()).or_default();
+                match field {
+                    StepCostField::Records => cost.records_sent += value,
+                    StepCostField::Bytes => cost.bytes_sent += value,
+                }
+            }
+        }
+        for (step, rounds) in world.rounds().iter() {
+            steps.entry(step.to_string()).or_default().rounds = rounds;
+        }
+
+        Self { size, steps }
+    }
+
+    /// Sums up the cost of the steps below `depth` levels of the gate tree into their ancestor at
+    /// that depth.
+    #[must_use]
+    pub fn truncate(self, depth: usize) -> Self {
+        let mut steps = BTreeMap::<String, StepCost>::new();
+        for (step, cost) in self.steps {
+            let ancestor = step.split('/').take(depth).collect::<Vec<_>>().join("/");
+            steps.entry(ancestor).or_default().add(&cost);
+        }
+
+        Self {
+            size: self.size,
+            steps,
+        }
+    }
+}
+
+enum StepCostField {
+    Records,
+    Bytes,
+}
+
+/// Least squares fit of `value = per_row * size + fixed`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize))]
+pub struct LinearFit {
+    pub per_row: f64,
+    pub fixed: f64,
+}
+
+impl LinearFit {
+    #[allow(clippy::cast_precision_loss)] // sizes and costs are much smaller than 2^52
+    fn new<I: IntoIterator<Item = (usize, u64)>>(points: I) -> Self {
+        let points = points.into_iter().collect::<Vec<_>>();
+        let single_size = points.windows(2).all(|pair| pair[0].0 == pair[1].0);
+        let points = points
+            .into_iter()
+            .map(|(x, y)| (x as f64, y as f64))
+            .collect::<Vec<_>>();
+        if points.is_empty() {
+            return Self::default();
+        }
+
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        if single_size {
+            return Self {
+                per_row: 0.0,
+                fixed: mean_y,
+            };
+        }
+        let var_x = points
+            .iter()
+            .map(|(x, _)| (x - mean_x).powi(2))
+            .sum::<f64>();
+        let cov = points
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum::<f64>();
+        let per_row = cov / var_x;
+
+        Self {
+            per_row,
+            fixed: mean_y - per_row * mean_x,
+        }
+    }
+
+    /// The value predicted for `size`.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn at(&self, size: usize) -> f64 {
+        self.per_row * size as f64 + self.fixed
+    }
+}
+
+/// Cost of one step, at every measured size, and the cost model fit to it.
+#[derive(Debug)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize))]
+pub struct StepReport {
+    pub step: String,
+    /// Cost at each of the sizes of the report, in the same order.
+    pub samples: Vec<StepCost>,
+    pub records_sent: LinearFit,
+    pub bytes_sent: LinearFit,
+}
+
+/// Cost of every step of a protocol as a function of the input size.
+#[derive(Debug)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize))]
+pub struct CostReport {
+    pub sizes: Vec<usize>,
+    pub steps: Vec<StepReport>,
+}
+
+impl CostReport {
+    /// Fits the cost model of every step to `samples`. Steps that are missing from a sample cost
+    /// nothing at its size.
+    #[must_use]
+    pub fn new(mut samples: Vec<CostSample>) -> Self {
+        samples.sort_by_key(|sample| sample.size);
+        let sizes = samples.iter().map(|sample| sample.size).collect::<Vec<_>>();
+        let mut steps = BTreeMap::<&str, Vec<StepCost>>::new();
+        for (i, sample) in samples.iter().enumerate() {
+            for (step, cost) in &sample.steps {
+                steps
+                    .entry(step.as_str())
+                    .or_insert_with(|| vec![StepCost::default(); samples.len()])[i] = *cost;
+            }
+        }
+
+        let steps = steps
+            .into_iter()
+            .map(|(step, costs)| {
+                let fit = |value: fn(&StepCost) -> u64| {
+                    LinearFit::new(sizes.iter().copied().zip(costs.iter().map(value)))
+                };
+                StepReport {
+                    step: step.to_string(),
+                    records_sent: fit(|cost| cost.records_sent),
+                    bytes_sent: fit(|cost| cost.bytes_sent),
+                    samples: costs,
+                }
+            })
+            .collect();
+
+        Self { sizes, steps }
+    }
+
+    /// Serializes this report to JSON.
+    ///
+    /// ## Errors
+    /// If writing to `w` fails.
+    #[cfg(feature = "enable-serde")]
+    pub fn export_json<W: io::Write>(&self, w: &mut W) -> Result<(), io::Error> {
+        serde_json::to_writer_pretty(&mut *w, self)?;
+        writeln!(w)
+    }
+}
+
+impl StepStatsCsvExporter for CostReport {
+    fn export<W: io::Write>(&self, w: &mut W) -> Result<(), io::Error> {
+        write!(w, "Step")?;
+        for size in &self.sizes {
+            write!(
+                w,
+                ",Records sent (n={size}),Bytes sent (n={size}),Rounds (n={size})"
+            )?;
+        }
+        writeln!(
+            w,
+            ",Records per row,Records fixed,Bytes per row,Bytes fixed"
+        )?;
+
+        for step in &self.steps {
+            write!(w, "{}", step.step)?;
+            for cost in &step.samples {
+                write!(
+                    w,
+                    ",{},{},{}",
+                    cost.records_sent, cost.bytes_sent, cost.rounds
+                )?;
+            }
+            writeln!(
+                w,
+                ",{:.3},{:.3},{:.3},{:.3}",
+                step.records_sent.per_row,
+                step.records_sent.fixed,
+                step.bytes_sent.per_row,
+                step.bytes_sent.fixed,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::{
+        ff::{Field, Fp31},
+        protocol::{basics::SecureMul, context::Context, RecordId},
+        test_fixture::Runner,
+    };
+    use futures::FutureExt;
+
+    fn sample(size: usize, steps: &[(&str, u64, usize)]) -> CostSample {
+        CostSample {
+            size,
+            steps: steps
+                .iter()
+                .map(|&(step, records_sent, rounds)| {
+                    (
+                        step.to_string(),
+                        StepCost {
+                            records_sent,
+                            bytes_sent: 4 * records_sent,
+                            rounds,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn measures_protocol() {
+        let (a, b) = (Fp31::truncate_from(6_u128), Fp31::truncate_from(5_u128));
+        let sample = CostSample::measure(TestWorldConfig::default(), 1, |world| {
+            async move {
+                world
+                    .semi_honest((a, b), |ctx, (a, b)| async move {
+                        a.multiply(&b, ctx.set_total_records(1), RecordId::from(0))
+                            .await
+                            .unwrap()
+                    })
+                    .await;
+            }
+            .boxed_local()
+        })
+        .await;
+
+        // every helper sends one share of the product to a peer.
+        assert_eq!(1, sample.size);
+        assert_eq!(
+            StepCost {
+                records_sent: 3,
+                bytes_sent: 3,
+                rounds: 0,
+            },
+            sample
+                .steps
+                .values()
+                .fold(StepCost::default(), |mut total, cost| {
+                    total.add(cost);
+                    total
+                })
+        );
+    }
+
+    #[test]
+    fn fits_lines() {
+        let fit = LinearFit::new([(10, 25), (20, 45), (40, 85)]);
+        assert!((fit.per_row - 2.0).abs() < 1e-9);
+        assert!((fit.fixed - 5.0).abs() < 1e-9);
+        assert!((fit.at(1000) - 2005.0).abs() < 1e-6);
+
+        assert_eq!(
+            LinearFit {
+                per_row: 0.0,
+                fixed: 7.0
+            },
+            LinearFit::new([(10, 7)])
+        );
+    }
+
+    #[test]
+    fn fits_every_step() {
+        let report = CostReport::new(vec![
+            sample(20, &[("a", 40, 1), ("a/b", 60, 2)]),
+            sample(10, &[("a", 20, 1)]),
+        ]);
+        assert_eq!(vec![10, 20], report.sizes);
+        assert_eq!(2, report.steps.len());
+
+        let a = &report.steps[0];
+        assert_eq!("a", a.step);
+        assert!((a.records_sent.per_row - 2.0).abs() < 1e-9);
+        assert!((a.bytes_sent.per_row - 8.0).abs() < 1e-9);
+        assert_eq!(
+            vec![1, 1],
+            a.samples.iter().map(|cost| cost.rounds).collect::<Vec<_>>()
+        );
+
+        // the step that did not show up at size 10 cost nothing there.
+        let b = &report.steps[1];
+        assert_eq!(StepCost::default(), b.samples[0]);
+        assert!((b.records_sent.per_row - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn truncates_gate_tree() {
+        let sample = sample(10, &[("a/b/c", 1, 1), ("a/b/d", 2, 3), ("a/e", 4, 1)]).truncate(2);
+        assert_eq!(
+            vec!["a/b", "a/e"],
+            sample.steps.keys().map(String::as_str).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            StepCost {
+                records_sent: 3,
+                bytes_sent: 12,
+                rounds: 4
+            },
+            sample.steps["a/b"]
+        );
+    }
+
+    #[test]
+    fn exports_csv() {
+        let report = CostReport::new(vec![
+            sample(10, &[("a", 20, 1)]),
+            sample(20, &[("a", 40, 1)]),
+        ]);
+        let mut csv = Vec::new();
+        report.export(&mut csv).unwrap();
+        assert_eq!(
+            "Step,Records sent (n=10),Bytes sent (n=10),Rounds (n=10),Records sent (n=20),\
+             Bytes sent (n=20),Rounds (n=20),Records per row,Records fixed,Bytes per row,\
+             Bytes fixed\n\
+             a,20,80,1,40,160,1,2.000,0.000,8.000,0.000\n",
+            String::from_utf8(csv).unwrap()
+        );
+    }
+}
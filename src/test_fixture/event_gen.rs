@@ -34,6 +34,16 @@ pub enum ReportFilter {
     SourceOnly,
 }
 
+impl ReportFilter {
+    pub(super) fn keeps(self, record: &TestRawDataRecord) -> bool {
+        match self {
+            ReportFilter::All => true,
+            ReportFilter::TriggerOnly => record.is_trigger_report,
+            ReportFilter::SourceOnly => !record.is_trigger_report,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "clap", derive(clap::Args))]
 pub struct Config {
@@ -53,7 +63,7 @@ pub struct Config {
     pub conversion_probability: Option<f32>,
 }
 
-fn validate_probability(value: &str) -> Result<f32, String> {
+pub(super) fn validate_probability(value: &str) -> Result<f32, String> {
     let v = value
         .parse::<f32>()
         .map_err(|e| format!("{e} not a float number"))?;
@@ -97,7 +107,14 @@ impl Config {
     }
 }
 
-use crate::{rand::Rng, test_fixture::ipa::TestRawDataRecord};
+use crate::{
+    rand::Rng,
+    report::Epoch,
+    test_fixture::{
+        event_sim::{Simulation, SimulationConfig, ADVERTISER_DOMAIN, EPOCH_DURATION_SECS},
+        ipa::TestRawDataRecord,
+    },
+};
 use std::{
     collections::HashSet,
     num::{NonZeroU32, NonZeroU64},
@@ -126,13 +143,27 @@ impl UserStats {
     }
 }
 
+/// A generated event, with the details that IPA inputs don't have.
+#[derive(Debug, Clone)]
+pub struct GeneratedEvent {
+    pub record: TestRawDataRecord,
+    pub epoch: Epoch,
+    /// Site the impression was shown on, or [`ADVERTISER_DOMAIN`] for conversions.
+    pub site_domain: String,
+    /// Device of the user that the event happened on.
+    pub device: u32,
+}
+
 /// Generates random source and trigger events with guarantee that every next event
 /// occurs at the same time or after the previous event.
 ///
-/// Number of events generated depends on the configured number of unique users per set
-/// and maximum number of events per user. See [`Config`] for more details
+/// By default, events of a few users at a time are sampled uniformly, and the number of events
+/// generated depends on the configured number of unique users per set and maximum number of
+/// events per user. See [`Config`] for more details. [`with_simulation`] instead simulates how
+/// users behave over several epochs, see [`SimulationConfig`].
 ///
 /// [`Config`]: Config
+/// [`with_simulation`]: Self::with_simulation
 pub struct EventGenerator<R: Rng> {
     config: Config,
     rng: R,
@@ -141,6 +172,7 @@ pub struct EventGenerator<R: Rng> {
     // here
     used: HashSet<UserId>,
     current_ts: u64,
+    simulation: Option<Simulation>,
 }
 
 impl<R: Rng> EventGenerator<R> {
@@ -155,7 +187,66 @@ impl<R: Rng> EventGenerator<R> {
             users: vec![],
             used: HashSet::new(),
             current_ts: 0,
+            simulation: None,
+        }
+    }
+
+    /// Creates a generator that simulates the behavior of users over several epochs, and picks
+    /// the match key and devices of every user.
+    ///
+    /// ## Panics
+    /// If there are more users than match keys.
+    pub fn with_simulation(mut rng: R, config: Config, simulation: SimulationConfig) -> Self {
+        let simulation = Simulation::new(&mut rng, &config, simulation);
+        Self {
+            simulation: Some(simulation),
+            ..Self::with_config(rng, config)
+        }
+    }
+
+    /// Generates events along with their epoch, site domain and device. Events sampled
+    /// uniformly all happen on the only device of their user, and every breakdown key has its
+    /// own site.
+    pub fn events(mut self) -> impl Iterator<Item = GeneratedEvent> {
+        std::iter::from_fn(move || {
+            if let Some(simulation) = &mut self.simulation {
+                return simulation.next_event(&mut self.rng, &self.config);
+            }
+            self.next_uniform().map(|record| GeneratedEvent {
+                epoch: Epoch::try_from(record.timestamp / EPOCH_DURATION_SECS)
+                    .unwrap_or(Epoch::MAX),
+                site_domain: if record.is_trigger_report {
+                    ADVERTISER_DOMAIN.to_string()
+                } else {
+                    format!("site{}.example", record.breakdown_key)
+                },
+                device: 0,
+                record,
+            })
+        })
+    }
+
+    fn next_uniform(&mut self) -> Option<TestRawDataRecord> {
+        const USERS_IN_FLIGHT: usize = 10;
+        while self.users.len() < USERS_IN_FLIGHT {
+            if let Some(next_user) = self.sample_user() {
+                self.users.push(next_user);
+            } else {
+                break;
+            }
+        }
+
+        if self.users.is_empty() {
+            return None;
         }
+
+        let idx = self.rng.gen_range(0..self.users.len());
+        let user_id = self.users[idx].user_id;
+        if self.users[idx].add_one() {
+            self.users.swap_remove(idx);
+        }
+
+        Some(self.gen_event(user_id))
     }
 
     fn gen_event(&mut self, user_id: UserId) -> TestRawDataRecord {
@@ -240,26 +331,12 @@ impl<R: Rng> Iterator for EventGenerator<R> {
     type Item = TestRawDataRecord;
 
     fn next(&mut self) -> Option<Self::Item> {
-        const USERS_IN_FLIGHT: usize = 10;
-        while self.users.len() < USERS_IN_FLIGHT {
-            if let Some(next_user) = self.sample_user() {
-                self.users.push(next_user);
-            } else {
-                break;
-            }
-        }
-
-        if self.users.is_empty() {
-            return None;
+        if let Some(simulation) = &mut self.simulation {
+            return simulation
+                .next_event(&mut self.rng, &self.config)
+                .map(|event| event.record);
         }
-
-        let idx = self.rng.gen_range(0..self.users.len());
-        let user_id = self.users[idx].user_id;
-        if self.users[idx].add_one() {
-            self.users.swap_remove(idx);
-        }
-
-        Some(self.gen_event(user_id))
+        self.next_uniform()
     }
 }
 
@@ -0,0 +1,459 @@
+use crate::{
+    report::Epoch,
+    test_fixture::{
+        event_gen::{Config, GeneratedEvent},
+        ipa::TestRawDataRecord,
+    },
+};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    Rng,
+};
+use std::{
+    collections::{HashSet, VecDeque},
+    f64::consts::PI,
+    num::{NonZeroU16, NonZeroU32, NonZeroU64},
+};
+
+/// Site of the advertiser, where conversions happen.
+pub const ADVERTISER_DOMAIN: &str = "advertiser.example";
+
+/// Default length of an epoch: one week.
+pub(super) const EPOCH_DURATION_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum DelayDistribution {
+    /// Every conversion happens exactly the mean delay after its impression
+    Fixed,
+    /// Delays are uniform between zero and twice the mean
+    Uniform,
+    /// Most conversions happen soon after their impression, and few much later
+    Exponential,
+    /// Log-normal delays, which have a heavier tail than exponential ones
+    LogNormal,
+}
+
+impl DelayDistribution {
+    fn sample<R: Rng>(self, mean: f64, rng: &mut R) -> f64 {
+        match self {
+            Self::Fixed => mean,
+            Self::Uniform => rng.gen_range(0.0..=2.0 * mean),
+            Self::Exponential => -mean * f64::ln(1.0 - rng.gen::<f64>()),
+            Self::LogNormal => {
+                // with sigma = 1, the mean of a log-normal distribution is exp(mu + 1/2)
+                let mu = f64::ln(mean) - 0.5;
+                let normal = f64::sqrt(-2.0 * f64::ln(1.0 - rng.gen::<f64>()))
+                    * f64::cos(2.0 * PI * rng.gen::<f64>());
+                f64::exp(mu + normal)
+            }
+        }
+    }
+}
+
+/// How the users of [`EventGenerator::with_simulation`] behave. Limits on match keys, breakdown
+/// keys, trigger values and events per user come from the generator [`Config`].
+///
+/// [`EventGenerator::with_simulation`]: super::EventGenerator::with_simulation
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct SimulationConfig {
+    /// Number of users. Each one has its own match key
+    #[cfg_attr(feature = "clap", arg(long, default_value = "1000"))]
+    pub users: NonZeroU32,
+    /// Number of epochs the events span
+    #[cfg_attr(feature = "clap", arg(long, default_value = "4"))]
+    pub epochs: NonZeroU16,
+    /// Length of an epoch, in seconds
+    #[cfg_attr(feature = "clap", arg(long, default_value = "604800"))]
+    pub epoch_duration_secs: NonZeroU64,
+    /// Maximum number of devices per user. All devices of a user share its match key, and
+    /// conversions may happen on a different device than their impression
+    #[cfg_attr(feature = "clap", arg(long, default_value = "3"))]
+    pub max_devices_per_user: NonZeroU32,
+    /// How much more active some users are than others. The activity of the user at rank `r`
+    /// is proportional to `1 / r^activity_skew`, so 0 makes all users equally active
+    #[cfg_attr(feature = "clap", arg(long, default_value = "1.0", value_parser = validate_non_negative))]
+    pub activity_skew: f64,
+    /// Average number of impressions per user in every epoch
+    #[cfg_attr(feature = "clap", arg(long, default_value = "5.0", value_parser = validate_non_negative))]
+    pub impressions_per_user: f64,
+    /// Probability that an impression leads to a conversion
+    #[cfg_attr(feature = "clap", arg(long, default_value = "0.05", value_parser = super::event_gen::validate_probability))]
+    pub conversion_rate: f32,
+    /// Distribution of the time from an impression to its conversion
+    #[cfg_attr(feature = "clap", arg(long, value_enum, default_value_t = DelayDistribution::Exponential))]
+    pub conversion_delay: DelayDistribution,
+    /// Average time from an impression to its conversion, in seconds
+    #[cfg_attr(feature = "clap", arg(long, default_value = "86400", value_parser = validate_non_negative))]
+    pub mean_conversion_delay_secs: f64,
+    /// Number of sites that show impressions. Some sites show more of them than others, with
+    /// the same skew as user activity
+    #[cfg_attr(feature = "clap", arg(long, default_value = "20"))]
+    pub site_domains: NonZeroU32,
+}
+
+#[cfg(feature = "clap")]
+fn validate_non_negative(value: &str) -> Result<f64, String> {
+    let v = value
+        .parse::<f64>()
+        .map_err(|e| format!("{e} not a float number"))?;
+    if v >= 0.0 && v.is_finite() {
+        Ok(v)
+    } else {
+        Err(format!("expected a non-negative number, got {v}"))
+    }
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            users: NonZeroU32::new(1000).unwrap(),
+            epochs: NonZeroU16::new(4).unwrap(),
+            epoch_duration_secs: NonZeroU64::new(EPOCH_DURATION_SECS).unwrap(),
+            max_devices_per_user: NonZeroU32::new(3).unwrap(),
+            activity_skew: 1.0,
+            impressions_per_user: 5.0,
+            conversion_rate: 0.05,
+            conversion_delay: DelayDistribution::Exponential,
+            mean_conversion_delay_secs: 86400.0,
+            site_domains: NonZeroU32::new(20).unwrap(),
+        }
+    }
+}
+
+struct SimulatedUser {
+    match_key: u64,
+    devices: u32,
+    events: u32,
+}
+
+/// Users that see impressions and convert, over several epochs. Every user has a match key,
+/// shared by all of their devices, and some users are much more active than others.
+/// Conversions follow their impression after a random delay, which may carry them into the
+/// next epoch.
+///
+/// Events come out ordered by timestamp. Conversions that would happen after the last epoch
+/// are dropped.
+pub(super) struct Simulation {
+    config: SimulationConfig,
+    users: Vec<SimulatedUser>,
+    activity: WeightedIndex<f64>,
+    sites: WeightedIndex<f64>,
+    epoch: u16,
+    ready: VecDeque<GeneratedEvent>,
+    // conversions of past epochs that happen in a later one
+    carried: Vec<GeneratedEvent>,
+}
+
+impl Simulation {
+    /// Picks the match key and devices of every user.
+    ///
+    /// ## Panics
+    /// If there are more users than match keys.
+    pub(super) fn new<R: Rng>(rng: &mut R, limits: &Config, config: SimulationConfig) -> Self {
+        assert!(
+            u64::from(config.users.get()) <= limits.max_user_id.get(),
+            "{} users don't fit into {} match keys",
+            config.users,
+            limits.max_user_id
+        );
+
+        let mut used = HashSet::new();
+        let users = (0..config.users.get())
+            .map(|_| {
+                let match_key = loop {
+                    let match_key = rng.gen_range(1..=limits.max_user_id.get());
+                    if used.insert(match_key) {
+                        break match_key;
+                    }
+                };
+                SimulatedUser {
+                    match_key,
+                    devices: rng.gen_range(1..=config.max_devices_per_user.get()),
+                    events: 0,
+                }
+            })
+            .collect();
+        let activity = zipf(config.users, config.activity_skew);
+        let sites = zipf(config.site_domains, config.activity_skew);
+
+        Self {
+            config,
+            users,
+            activity,
+            sites,
+            epoch: 0,
+            ready: VecDeque::new(),
+            carried: Vec::new(),
+        }
+    }
+
+    pub(super) fn next_event<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        limits: &Config,
+    ) -> Option<GeneratedEvent> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Some(event);
+            }
+            if self.epoch == self.config.epochs.get() {
+                return None;
+            }
+            self.simulate_epoch(rng, limits);
+        }
+    }
+
+    fn simulate_epoch<R: Rng>(&mut self, rng: &mut R, limits: &Config) {
+        let duration = self.config.epoch_duration_secs.get();
+        let start = u64::from(self.epoch) * duration;
+        let end = start + duration;
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let impressions = (f64::from(self.config.users.get()) * self.config.impressions_per_user)
+            .round() as usize;
+        let mut events = std::mem::take(&mut self.carried);
+        for _ in 0..impressions {
+            let user = self.activity.sample(rng);
+            let timestamp = rng.gen_range(start..end);
+            self.impression(rng, limits, user, timestamp, &mut events);
+        }
+
+        events.sort_by_key(|event| event.record.timestamp);
+        let next_epoch = events.partition_point(|event| event.record.timestamp < end);
+        self.carried = events.split_off(next_epoch);
+        self.ready = events.into();
+        self.epoch += 1;
+    }
+
+    /// Shows an impression to `user`, which may lead to a conversion later.
+    fn impression<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        limits: &Config,
+        user: usize,
+        timestamp: u64,
+        events: &mut Vec<GeneratedEvent>,
+    ) {
+        let max_events = limits.max_events_per_user.get();
+        if self.users[user].events >= max_events {
+            return;
+        }
+
+        let breakdown_key = rng.gen_range(0..limits.max_breakdown_key.get());
+        let site = self.sites.sample(rng);
+        self.push(
+            rng,
+            limits,
+            events,
+            user,
+            TestRawDataRecord {
+                user_id: 0,
+                timestamp,
+                is_trigger_report: false,
+                breakdown_key,
+                trigger_value: 0,
+            },
+            format!("site{site}.example"),
+        );
+
+        if self.users[user].events < max_events && rng.gen::<f32>() < self.config.conversion_rate {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let delay = self
+                .config
+                .conversion_delay
+                .sample(self.config.mean_conversion_delay_secs, rng) as u64;
+            let trigger_value = rng.gen_range(1..=limits.max_trigger_value.get());
+            self.push(
+                rng,
+                limits,
+                events,
+                user,
+                TestRawDataRecord {
+                    user_id: 0,
+                    timestamp: timestamp.saturating_add(delay),
+                    is_trigger_report: true,
+                    breakdown_key: 0,
+                    trigger_value,
+                },
+                ADVERTISER_DOMAIN.to_string(),
+            );
+        }
+    }
+
+    /// Counts `record` towards the events of `user`, and keeps it unless the report filter
+    /// leaves it out.
+    fn push<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        limits: &Config,
+        events: &mut Vec<GeneratedEvent>,
+        user: usize,
+        mut record: TestRawDataRecord,
+        site_domain: String,
+    ) {
+        let user = &mut self.users[user];
+        user.events += 1;
+        if !limits.report_filter.keeps(&record) {
+            return;
+        }
+
+        record.user_id = user.match_key;
+        events.push(GeneratedEvent {
+            epoch: Epoch::try_from(record.timestamp / self.config.epoch_duration_secs)
+                .unwrap_or(Epoch::MAX),
+            record,
+            site_domain,
+            device: rng.gen_range(0..user.devices),
+        });
+    }
+}
+
+/// Weights `n` ranks so that the weight of rank `r` is proportional to `1 / r^skew`.
+fn zipf(n: NonZeroU32, skew: f64) -> WeightedIndex<f64> {
+    WeightedIndex::new((1..=n.get()).map(|rank| 1.0 / f64::from(rank).powf(skew))).unwrap()
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::*;
+    use crate::test_fixture::{event_gen::ReportFilter, EventGenerator};
+    use rand::rngs::StdRng;
+    use rand_core::SeedableRng;
+    use std::collections::HashMap;
+
+    fn simulate(config: Config, simulation: SimulationConfig) -> Vec<GeneratedEvent> {
+        EventGenerator::with_simulation(StdRng::seed_from_u64(42), config, simulation)
+            .events()
+            .collect()
+    }
+
+    #[test]
+    fn events_are_ordered_within_epochs() {
+        let config = Config::new(10_000, 5, 16, 20);
+        let simulation = SimulationConfig {
+            users: NonZeroU32::new(100).unwrap(),
+            epochs: NonZeroU16::new(3).unwrap(),
+            epoch_duration_secs: NonZeroU64::new(1000).unwrap(),
+            conversion_rate: 0.5,
+            mean_conversion_delay_secs: 300.0,
+            ..SimulationConfig::default()
+        };
+        let events = simulate(config.clone(), simulation);
+
+        let mut last_ts = 0;
+        let mut per_user = HashMap::<_, u32>::new();
+        for event in &events {
+            let record = &event.record;
+            assert!(record.timestamp >= last_ts);
+            assert!(record.timestamp < 3000);
+            assert_eq!(u64::from(event.epoch), record.timestamp / 1000);
+            if record.is_trigger_report {
+                assert_eq!(0, record.breakdown_key);
+                assert!((1..=5).contains(&record.trigger_value));
+                assert_eq!(ADVERTISER_DOMAIN, event.site_domain);
+            } else {
+                assert_eq!(0, record.trigger_value);
+                assert!(record.breakdown_key < 16);
+                assert!(event.site_domain.starts_with("site"));
+            }
+            assert!(event.device < 3);
+            *per_user.entry(record.user_id).or_default() += 1;
+            last_ts = record.timestamp;
+        }
+
+        assert!(events.iter().any(|event| event.record.is_trigger_report));
+        assert!(events.iter().any(|event| event.epoch == 2));
+        assert!(per_user
+            .values()
+            .all(|&count| count <= config.max_events_per_user.get()));
+    }
+
+    #[test]
+    fn devices_share_match_keys() {
+        let events = simulate(
+            Config::new(10_000, 5, 16, 100),
+            SimulationConfig {
+                users: NonZeroU32::new(10).unwrap(),
+                epochs: NonZeroU16::new(1).unwrap(),
+                ..SimulationConfig::default()
+            },
+        );
+
+        let mut devices = HashMap::<_, HashSet<_>>::new();
+        for event in events {
+            devices
+                .entry(event.record.user_id)
+                .or_default()
+                .insert(event.device);
+        }
+        assert!(devices.len() <= 10);
+        assert!(devices.values().any(|devices| devices.len() > 1));
+    }
+
+    #[test]
+    fn activity_is_skewed() {
+        let events = simulate(
+            Config::new(10_000, 5, 16, u32::MAX),
+            SimulationConfig {
+                users: NonZeroU32::new(100).unwrap(),
+                epochs: NonZeroU16::new(1).unwrap(),
+                activity_skew: 2.0,
+                conversion_rate: 0.0,
+                ..SimulationConfig::default()
+            },
+        );
+
+        let mut per_user = HashMap::<_, usize>::new();
+        for event in &events {
+            *per_user.entry(event.record.user_id).or_default() += 1;
+        }
+        // the most active user sees about 60% of the impressions
+        let most_active = per_user.values().copied().max().unwrap();
+        assert!(
+            most_active * 2 > events.len(),
+            "{most_active} of {}",
+            events.len()
+        );
+    }
+
+    #[test]
+    fn report_filter() {
+        let config = Config {
+            report_filter: ReportFilter::TriggerOnly,
+            ..Config::new(10_000, 5, 16, 20)
+        };
+        let events = simulate(
+            config,
+            SimulationConfig {
+                users: NonZeroU32::new(100).unwrap(),
+                conversion_rate: 0.5,
+                ..SimulationConfig::default()
+            },
+        );
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|event| event.record.is_trigger_report));
+    }
+
+    #[test]
+    fn delay_means() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for distribution in [
+            DelayDistribution::Fixed,
+            DelayDistribution::Uniform,
+            DelayDistribution::Exponential,
+            DelayDistribution::LogNormal,
+        ] {
+            let n = 100_000;
+            let mean = (0..n)
+                .map(|_| distribution.sample(100.0, &mut rng))
+                .sum::<f64>()
+                / f64::from(n);
+            assert!(
+                (mean - 100.0).abs() < 5.0,
+                "mean of {distribution:?} delays is {mean}"
+            );
+        }
+    }
+}
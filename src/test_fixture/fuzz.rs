@@ -0,0 +1,68 @@
+//! Property tests of MPC protocols. Protocols run on inputs that `proptest` generates, under both
+//! security models, and their reconstructed outputs are checked against reference functions that
+//! compute the same thing in the clear. When a check fails, `proptest` shrinks the input to a
+//! minimal failing case.
+//!
+//! A protocol needs a different context type under each security model, so it is given to
+//! [`check_protocol`] twice: once run with [`Runner::semi_honest`], and once with
+//! [`Runner::malicious`] or [`Runner::upgraded_malicious`].
+//!
+//! [`Runner::semi_honest`]: crate::test_fixture::Runner::semi_honest
+//! [`Runner::malicious`]: crate::test_fixture::Runner::malicious
+//! [`Runner::upgraded_malicious`]: crate::test_fixture::Runner::upgraded_malicious
+
+use crate::{ff::PrimeField, test_fixture::TestWorld};
+use proptest::{strategy::Strategy, test_runner::Config};
+use std::{fmt::Debug, future::Future};
+
+/// Number of inputs that every protocol runs on. MPC protocols are slow, so this is a lot less
+/// than the `proptest` default.
+pub const CASES: u32 = 16;
+
+/// `proptest` config for protocols.
+#[must_use]
+pub fn config() -> Config {
+    Config {
+        cases: CASES,
+        max_shrink_iters: 256,
+        ..Config::default()
+    }
+}
+
+/// Generates values of `F`, shrinking towards zero.
+pub fn field_value<F: PrimeField>() -> impl Strategy<Value = F> {
+    let prime: u128 = F::PRIME.into();
+    (0..prime).prop_map(F::truncate_from::<u128>)
+}
+
+/// Runs a protocol under both security models and checks its output against `expected`, the
+/// output of the reference function. `semi_honest` and `malicious` run the protocol in the given
+/// [`TestWorld`] and return the reconstructed output.
+///
+/// ## Panics
+/// If the output under either security model is not `expected`, which makes `proptest` shrink
+/// the input.
+pub fn check_protocol<T, SH, SF, MH, MF>(expected: &T, semi_honest: SH, malicious: MH)
+where
+    T: Debug + PartialEq,
+    SH: FnOnce(TestWorld) -> SF,
+    SF: Future<Output = T>,
+    MH: FnOnce(TestWorld) -> MF,
+    MF: Future<Output = T>,
+{
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let actual = runtime.block_on(async { semi_honest(TestWorld::default()).await });
+    assert_eq!(
+        expected, &actual,
+        "semi-honest output does not match the reference"
+    );
+    let actual = runtime.block_on(async { malicious(TestWorld::default()).await });
+    assert_eq!(
+        expected, &actual,
+        "malicious output does not match the reference"
+    );
+}
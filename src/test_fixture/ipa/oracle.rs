@@ -0,0 +1,350 @@
+//! IPA in the clear, as a reference for the MPC protocol. It computes the breakdowns that MPC IPA
+//! outputs for every [`IpaQueryConfig`], edge cases included:
+//!
+//! * Reports are grouped by match key with a stable sort, so reports of a user that have the same
+//!   timestamp keep the order they have in the input.
+//! * Trigger reports are attributed to the closest source report before them. Trigger reports
+//!   that come before any source report of their user are not attributed.
+//! * Trigger reports that are more than the attribution window after their source report are
+//!   not attributed.
+//! * With a per user cap of one, trigger values are ignored: a source report gets a credit of one
+//!   if a trigger report is attributed to it, even one with a trigger value of zero.
+//! * Otherwise, a source report gets the sum of the trigger values attributed to it, plus its own
+//!   trigger value, capped at the per user cap. Credits of a user are then capped at the per user
+//!   cap together, from the most recent source report backwards.
+//! * Breakdown keys are compared on the bits that keys below `max_breakdown_key` have, so the
+//!   higher bits of larger keys are dropped.
+
+use crate::{helpers::query::IpaQueryConfig, test_fixture::ipa::TestRawDataRecord};
+use std::{collections::HashMap, num::NonZeroU32};
+
+/// Computes the breakdowns that MPC IPA outputs for `input`, indexed by breakdown key. There are
+/// `max_breakdown_key` of them.
+///
+/// ## Panics
+/// If `input` is not sorted by timestamp.
+#[must_use]
+pub fn ipa_oracle(input: &[TestRawDataRecord], config: &IpaQueryConfig) -> Vec<u32> {
+    let mut user_events = HashMap::<_, Vec<_>>::new();
+    let mut last_ts = 0;
+    for row in input {
+        assert!(
+            last_ts <= row.timestamp,
+            "Input is not sorted: last row had timestamp {last_ts} that is greater than \
+             {this_ts} timestamp of the current row",
+            this_ts = row.timestamp
+        );
+        last_ts = row.timestamp;
+        user_events.entry(row.user_id).or_default().push(row);
+    }
+
+    let mut breakdowns = vec![0; usize::try_from(config.max_breakdown_key).unwrap()];
+    for records in user_events.values() {
+        let credits = source_credits(
+            records,
+            config.per_user_credit_cap,
+            config.attribution_window_seconds,
+        );
+        let credits = cap_user_credits(credits, config.per_user_credit_cap);
+        for (record, credit) in records.iter().zip(credits) {
+            if let Some(key) = breakdown_key(record.breakdown_key, config.max_breakdown_key) {
+                breakdowns[key] += credit;
+            }
+        }
+    }
+
+    breakdowns
+}
+
+/// Credits of the records of a single user, in chronological order. Trigger reports get none.
+fn source_credits(
+    records: &[&TestRawDataRecord],
+    per_user_cap: u32,
+    attribution_window_seconds: Option<NonZeroU32>,
+) -> Vec<u32> {
+    let within_window = |trigger: &TestRawDataRecord, source: &TestRawDataRecord| {
+        attribution_window_seconds.map_or(true, |window| {
+            trigger.timestamp - source.timestamp <= u64::from(window.get())
+        })
+    };
+
+    let mut credits = vec![0; records.len()];
+    let mut last_source = None;
+    for (i, record) in records.iter().enumerate() {
+        if !record.is_trigger_report {
+            last_source = Some(i);
+            if per_user_cap != 1 {
+                credits[i] = record.trigger_value;
+            }
+        } else if let Some(source) = last_source {
+            if !within_window(record, records[source]) {
+                continue;
+            }
+            if per_user_cap == 1 {
+                credits[source] = 1;
+            } else {
+                credits[source] += record.trigger_value;
+            }
+        }
+    }
+
+    credits
+        .into_iter()
+        .map(|credit| credit.min(per_user_cap))
+        .collect()
+}
+
+/// Caps the total credit of a user, giving what is left of the cap to the most recent source
+/// reports first.
+fn cap_user_credits(mut credits: Vec<u32>, per_user_cap: u32) -> Vec<u32> {
+    let mut budget = per_user_cap;
+    for credit in credits.iter_mut().rev() {
+        *credit = (*credit).min(budget);
+        budget -= *credit;
+    }
+
+    credits
+}
+
+/// The breakdown that MPC IPA counts a source report with `key` in, if any.
+fn breakdown_key(key: u32, max_breakdown_key: u32) -> Option<usize> {
+    let bits = u32::BITS - max_breakdown_key.checked_sub(1)?.leading_zeros();
+    let key = key & u32::MAX.checked_shr(u32::BITS - bits).unwrap_or(0);
+
+    (key < max_breakdown_key).then(|| usize::try_from(key).unwrap())
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::ipa_oracle;
+    use crate::{
+        ff::Fp32BitPrime,
+        helpers::{query::IpaQueryConfig, GatewayConfig},
+        test_fixture::{
+            ipa::{test_ipa, IpaSecurityModel, TestRawDataRecord},
+            EventGenerator, EventGeneratorConfig, TestWorld, TestWorldConfig,
+        },
+    };
+    use proptest::{prelude::*, test_runner::Config as ProptestConfig};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::num::NonZeroU32;
+
+    fn source(timestamp: u64, user_id: u64, breakdown_key: u32) -> TestRawDataRecord {
+        TestRawDataRecord {
+            timestamp,
+            user_id,
+            is_trigger_report: false,
+            breakdown_key,
+            trigger_value: 0,
+        }
+    }
+
+    fn trigger(timestamp: u64, user_id: u64, trigger_value: u32) -> TestRawDataRecord {
+        TestRawDataRecord {
+            timestamp,
+            user_id,
+            is_trigger_report: true,
+            breakdown_key: 0,
+            trigger_value,
+        }
+    }
+
+    fn config(per_user_cap: u32, window: Option<u32>) -> IpaQueryConfig {
+        IpaQueryConfig {
+            per_user_credit_cap: per_user_cap,
+            max_breakdown_key: 8,
+            attribution_window_seconds: window.map(|w| NonZeroU32::new(w).unwrap()),
+            num_multi_bits: 3,
+            plaintext_match_keys: true,
+        }
+    }
+
+    #[test]
+    fn ties_keep_input_order() {
+        let input = [
+            source(10, 1, 1),
+            trigger(10, 1, 3),
+            trigger(10, 2, 3),
+            source(10, 2, 2),
+        ];
+        assert_eq!(
+            vec![0, 3, 0, 0, 0, 0, 0, 0],
+            ipa_oracle(&input, &config(5, None))
+        );
+    }
+
+    #[test]
+    fn trigger_before_source() {
+        let input = [trigger(0, 1, 4), source(5, 1, 1), trigger(6, 1, 2)];
+        assert_eq!(
+            vec![0, 2, 0, 0, 0, 0, 0, 0],
+            ipa_oracle(&input, &config(5, None))
+        );
+    }
+
+    #[test]
+    fn zero_value_triggers() {
+        let input = [
+            source(0, 1, 1),
+            trigger(1, 1, 0),
+            source(2, 2, 2),
+            trigger(3, 2, 2),
+            source(4, 2, 3),
+            trigger(5, 2, 0),
+        ];
+        // trigger values don't matter with a cap of one, so user 2 gets one for its last source
+        assert_eq!(
+            vec![0, 1, 0, 1, 0, 0, 0, 0],
+            ipa_oracle(&input, &config(1, None))
+        );
+        assert_eq!(
+            vec![0, 0, 2, 0, 0, 0, 0, 0],
+            ipa_oracle(&input, &config(3, None))
+        );
+    }
+
+    #[test]
+    fn attribution_window() {
+        let input = [source(0, 1, 1), trigger(10, 1, 2), trigger(20, 1, 2)];
+        assert_eq!(
+            vec![0, 2, 0, 0, 0, 0, 0, 0],
+            ipa_oracle(&input, &config(5, Some(15)))
+        );
+        assert_eq!(
+            vec![0, 4, 0, 0, 0, 0, 0, 0],
+            ipa_oracle(&input, &config(5, Some(20)))
+        );
+        assert_eq!(vec![0; 8], ipa_oracle(&input, &config(1, Some(5))));
+    }
+
+    #[test]
+    fn per_user_cap() {
+        // the most recent source gets its credit first
+        let input = [
+            source(0, 1, 1),
+            trigger(1, 1, 3),
+            source(2, 1, 2),
+            trigger(3, 1, 3),
+        ];
+        assert_eq!(
+            vec![0, 1, 3, 0, 0, 0, 0, 0],
+            ipa_oracle(&input, &config(4, None))
+        );
+
+        let input = [source(0, 1, 1), trigger(1, 1, 5), trigger(2, 1, 5)];
+        assert_eq!(
+            vec![0, 4, 0, 0, 0, 0, 0, 0],
+            ipa_oracle(&input, &config(4, None))
+        );
+    }
+
+    #[test]
+    fn breakdown_keys_out_of_range() {
+        // keys below 5 have 3 bits: 9 is counted as 1, and 6 is not counted at all
+        let input = [
+            source(0, 1, 9),
+            trigger(1, 1, 2),
+            source(2, 2, 6),
+            trigger(3, 2, 2),
+        ];
+        let config = IpaQueryConfig {
+            max_breakdown_key: 5,
+            ..config(5, None)
+        };
+        assert_eq!(vec![0, 2, 0, 0, 0], ipa_oracle(&input, &config));
+        assert_eq!(
+            Vec::<u32>::new(),
+            ipa_oracle(
+                &input,
+                &IpaQueryConfig {
+                    max_breakdown_key: 0,
+                    ..config
+                }
+            )
+        );
+    }
+
+    fn check_mpc(records: &[TestRawDataRecord], config: IpaQueryConfig, mode: IpaSecurityModel) {
+        let expected = ipa_oracle(records, &config);
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let world = TestWorld::new_with(TestWorldConfig {
+                    gateway_config: GatewayConfig::new(records.len().clamp(4, 1024)),
+                    ..Default::default()
+                });
+                test_ipa::<Fp32BitPrime>(&world, records, &expected, config, mode).await;
+            });
+    }
+
+    #[test]
+    fn mpc_edge_cases() {
+        let input = [
+            trigger(0, 1, 4),
+            source(1, 1, 1),
+            trigger(1, 1, 0),
+            source(2, 2, 2),
+            trigger(2, 3, 3),
+            source(2, 3, 3),
+            trigger(3, 2, 5),
+            trigger(30, 2, 1),
+        ];
+        for mode in [IpaSecurityModel::SemiHonest, IpaSecurityModel::Malicious] {
+            for per_user_cap in [1, 3] {
+                for window in [None, Some(10)] {
+                    check_mpc(&input, config(per_user_cap, window), mode);
+                }
+            }
+        }
+    }
+
+    fn security_model() -> impl Strategy<Value = IpaSecurityModel> {
+        prop_oneof![
+            Just(IpaSecurityModel::SemiHonest),
+            Just(IpaSecurityModel::Malicious)
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn mpc_matches_oracle(
+            seed: u64,
+            mode in security_model(),
+            per_user_cap in prop_oneof![Just(1_u32), 2..8_u32],
+            max_breakdown_key in 1..=8_u32,
+            window in proptest::option::of(1..120_u32),
+            zero_value_triggers: bool,
+        ) {
+            const NUM_USERS: u64 = 8;
+            const MAX_EVENTS_PER_USER: u32 = 8;
+            const MAX_EVENTS: usize = 32;
+
+            let mut rng = StdRng::seed_from_u64(seed);
+            // breakdown keys go past `max_breakdown_key`, to cover keys that don't fit in it
+            let mut records = EventGenerator::with_config(
+                &mut rng,
+                EventGeneratorConfig::new(NUM_USERS, 8, 2 * max_breakdown_key, MAX_EVENTS_PER_USER),
+            )
+            .take(MAX_EVENTS)
+            .collect::<Vec<_>>();
+            // generated trigger values are never zero
+            if zero_value_triggers {
+                for record in records.iter_mut().filter(|r| r.is_trigger_report) {
+                    if rng.gen() {
+                        record.trigger_value = 0;
+                    }
+                }
+            }
+
+            let config = IpaQueryConfig {
+                max_breakdown_key,
+                ..config(per_user_cap, window)
+            };
+            check_mpc(&records, config, mode);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! A helper that deviates from the protocol, to test that malicious protocols detect it.
+
+use crate::{
+    error::Error,
+    ff::{Field, Serializable},
+    helpers::{ChannelId, MessageInterceptor, Role},
+    protocol::context::{UpgradableContext, UpgradedMaliciousContext, Validator},
+    secret_sharing::{
+        replicated::malicious::{DowngradeMalicious, ExtendableField},
+        IntoShares,
+    },
+    sync::Arc,
+    test_fixture::{Runner, TestWorld, TestWorldConfig},
+};
+use futures::future::BoxFuture;
+use generic_array::GenericArray;
+use std::fmt::Debug;
+use typenum::Unsigned;
+
+/// Adds an offset to a serialized message, if it has the right size.
+type Tamper = dyn Fn(&mut [u8]) -> bool + Send + Sync;
+
+/// Makes the helper that plays `role` in a [`TestWorld`] add offsets to the messages it sends at
+/// selected gates. This is an additive attack on whatever protocol runs at these gates, which
+/// malicious protocols must detect. All the other messages are sent unchanged.
+///
+/// A gate is selected by its full name or by its last step, so `"duplicate_multiply"` selects
+/// `protocol/attacked/duplicate_multiply`, but not `protocol/duplicate_multiply/row0`. Offsets are
+/// tried in the order they were added, and only the first one that matches both the gate and the
+/// size of a message is added to it.
+///
+/// ```ignore
+/// let world = TestWorld::new_with(TestWorldConfig::default().with_malicious_helper(
+///     MaliciousHelper::new(Role::H2).add_offset("duplicate_multiply", Fp32BitPrime::ONE),
+/// ));
+/// ```
+///
+/// [`TestWorld`]: crate::test_fixture::TestWorld
+#[derive(Clone)]
+pub struct MaliciousHelper {
+    role: Role,
+    offsets: Vec<(String, Arc<Tamper>)>,
+}
+
+impl MaliciousHelper {
+    #[must_use]
+    pub fn new(role: Role) -> Self {
+        Self {
+            role,
+            offsets: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Adds `offset` to the values sent at `gate`. Messages that are not the size of `F` are sent
+    /// unchanged.
+    #[must_use]
+    pub fn add_offset<F: Field>(mut self, gate: &str, offset: F) -> Self {
+        let tamper = move |data: &mut [u8]| {
+            if data.len() != <F as Serializable>::Size::USIZE {
+                return false;
+            }
+            let buf = GenericArray::from_mut_slice(data);
+            let value = F::deserialize(buf) + offset;
+            value.serialize(buf);
+            true
+        };
+        self.offsets.push((gate.to_string(), Arc::new(tamper)));
+        self
+    }
+}
+
+impl MessageInterceptor for MaliciousHelper {
+    fn intercept(&self, channel_id: &ChannelId, data: &mut [u8]) {
+        let gate = channel_id.gate.as_ref();
+        let last_step = gate.rsplit('/').next();
+        // `any` stops at the first offset that is added.
+        self.offsets
+            .iter()
+            .any(|(g, tamper)| (gate == g || last_step == Some(g.as_str())) && tamper(data));
+    }
+}
+
+/// Runs `protocol` on `input` once for every one of `attacks`, with the helper that makes the
+/// attack, and checks that validation catches it. `protocol` gets the context of the validator
+/// and returns the values to validate.
+///
+/// ## Panics
+/// If the values of one of the runs pass validation.
+pub async fn assert_attacks_detected<F, I, A, D, P>(
+    attacks: impl IntoIterator<Item = MaliciousHelper>,
+    input: I,
+    protocol: P,
+) where
+    F: ExtendableField,
+    I: IntoShares<A> + Clone + Send + 'static,
+    A: Send,
+    D: DowngradeMalicious,
+    D::Target: Debug,
+    P: for<'a> Fn(UpgradedMaliciousContext<'a, F>, A) -> BoxFuture<'a, D> + Send + Sync,
+{
+    let protocol = &protocol;
+    for attack in attacks {
+        let world = TestWorld::new_with(TestWorldConfig::default().with_malicious_helper(attack));
+        world
+            .malicious(input.clone(), |ctx, input| async move {
+                let v = ctx.validator::<F>();
+                let values = protocol(v.context(), input).await;
+                let err = v
+                    .validate(values)
+                    .await
+                    .expect_err("This should fail validation");
+                assert!(matches!(err, Error::MaliciousSecurityCheckFailed));
+            })
+            .await;
+    }
+}
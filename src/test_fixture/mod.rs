@@ -10,9 +10,16 @@ mod app;
 
 #[cfg(feature = "in-memory-infra")]
 pub mod circuit;
+#[cfg(feature = "in-memory-infra")]
+pub mod cost;
 mod event_gen;
+mod event_sim;
+#[cfg(all(test, unit_test))]
+pub mod fuzz;
 pub mod ipa;
 pub mod logging;
+#[cfg(feature = "in-memory-infra")]
+mod malicious_helper;
 pub mod metrics;
 
 use crate::{
@@ -26,8 +33,11 @@ use crate::{
 };
 #[cfg(feature = "in-memory-infra")]
 pub use app::TestApp;
-pub use event_gen::{Config as EventGeneratorConfig, EventGenerator};
+pub use event_gen::{Config as EventGeneratorConfig, EventGenerator, GeneratedEvent};
+pub use event_sim::{DelayDistribution, SimulationConfig, ADVERTISER_DOMAIN};
 use futures::TryFuture;
+#[cfg(feature = "in-memory-infra")]
+pub use malicious_helper::{assert_attacks_detected, MaliciousHelper};
 use rand::{distributions::Standard, prelude::Distribution, rngs::mock::StepRng};
 use rand_core::{CryptoRng, RngCore};
 pub use sharing::{get_bits, into_bits, Reconstruct};
@@ -57,11 +67,22 @@ where
 
 /// Generate three participants.
 /// p1 is left of p2, p2 is left of p3, p3 is left of p1...
+/// Participants detect PRSS index reuse in debug builds.
 #[must_use]
 pub fn make_participants<R: RngCore + CryptoRng>(r: &mut R) -> [PrssEndpoint; 3] {
-    let setup1 = PrssEndpoint::prepare(r);
-    let setup2 = PrssEndpoint::prepare(r);
-    let setup3 = PrssEndpoint::prepare(r);
+    make_participants_with_reuse_check(r, true)
+}
+
+/// Generate three participants, enabling or disabling PRSS index reuse detection.
+/// See [`make_participants`].
+#[must_use]
+pub fn make_participants_with_reuse_check<R: RngCore + CryptoRng>(
+    r: &mut R,
+    track_reuse: bool,
+) -> [PrssEndpoint; 3] {
+    let setup1 = PrssEndpoint::prepare(r).track_index_reuse(track_reuse);
+    let setup2 = PrssEndpoint::prepare(r).track_index_reuse(track_reuse);
+    let setup3 = PrssEndpoint::prepare(r).track_index_reuse(track_reuse);
     let (pk1_l, pk1_r) = setup1.public_keys();
     let (pk2_l, pk2_r) = setup2.public_keys();
     let (pk3_l, pk3_r) = setup3.public_keys();
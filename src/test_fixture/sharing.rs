@@ -184,3 +184,15 @@ impl<F: ExtendableField> ValidateMalicious<F>
         [v0.clone(), v1.clone(), v2.clone()].validate(r);
     }
 }
+
+impl<F: ExtendableField> ValidateMalicious<F>
+    for [(Vec<MaliciousReplicated<F>>, Vec<MaliciousReplicated<F>>); 3]
+{
+    fn validate(&self, r: F::ExtendedField) {
+        let [t0, t1, t2] = self;
+        let ((l0, r0), (l1, r1), (l2, r2)) = (t0, t1, t2);
+
+        [l0.clone(), l1.clone(), l2.clone()].validate(r);
+        [r0.clone(), r1.clone(), r2.clone()].validate(r);
+    }
+}
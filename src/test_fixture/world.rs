@@ -1,5 +1,8 @@
 use crate::{
-    helpers::{Gateway, GatewayConfig, InMemoryNetwork, Role, RoleAssignment},
+    helpers::{
+        CommunicationRounds, FaultSchedule, Gateway, GatewayConfig, InMemoryNetwork, LinkConfig,
+        Role, RoleAssignment, TransportCallbacks,
+    },
     protocol::{
         context::{
             Context, MaliciousContext, SemiHonestContext, UpgradableContext, UpgradeContext,
@@ -19,7 +22,8 @@ use crate::{
     },
     telemetry::{stats::Metrics, StepStatsCsvExporter},
     test_fixture::{
-        logging, make_participants, metrics::MetricsHandle, sharing::ValidateMalicious, Reconstruct,
+        logging, make_participants_with_reuse_check, metrics::MetricsHandle,
+        sharing::ValidateMalicious, MaliciousHelper, Reconstruct,
     },
 };
 use async_trait::async_trait;
@@ -38,7 +42,7 @@ pub struct TestWorld {
     participants: [PrssEndpoint; 3],
     executions: AtomicUsize,
     metrics_handle: MetricsHandle,
-    _network: InMemoryNetwork,
+    network: InMemoryNetwork,
 }
 
 #[derive(Clone)]
@@ -52,6 +56,15 @@ pub struct TestWorldConfig {
     pub role_assignment: Option<RoleAssignment>,
     /// Seed for random generators used in PRSS
     pub seed: u64,
+    /// Panic if the same PRSS index is used twice under the same gate. Only has effect in
+    /// debug builds. Enabled by default.
+    pub prss_reuse_check: bool,
+    /// Faults injected into the streams exchanged between helpers. None by default.
+    pub faults: Option<FaultSchedule>,
+    /// Latency and bandwidth of the links between helpers. Instant by default.
+    pub link: LinkConfig,
+    /// Helper that tampers with the messages it sends. All helpers are honest by default.
+    pub malicious_helper: Option<MaliciousHelper>,
 }
 
 impl Default for TestWorldConfig {
@@ -64,6 +77,10 @@ impl Default for TestWorldConfig {
             metrics_level: Level::DEBUG,
             role_assignment: None,
             seed: thread_rng().next_u64(),
+            prss_reuse_check: true,
+            faults: None,
+            link: LinkConfig::default(),
+            malicious_helper: None,
         }
     }
 }
@@ -80,6 +97,35 @@ impl TestWorldConfig {
         self.seed = seed;
         self
     }
+
+    /// Disables PRSS index reuse detection. Useful for large inputs, as tracking
+    /// every index used requires a lot of memory.
+    #[must_use]
+    pub fn without_prss_reuse_check(mut self) -> Self {
+        self.prss_reuse_check = false;
+        self
+    }
+
+    /// Makes helpers disrupt the streams they send to each other according to `faults`.
+    #[must_use]
+    pub fn with_faults(mut self, faults: FaultSchedule) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    /// Makes helpers send data to each other over links with the given latency and bandwidth.
+    #[must_use]
+    pub fn with_link(mut self, link: LinkConfig) -> Self {
+        self.link = link;
+        self
+    }
+
+    /// Makes one helper deviate from the protocol, as `helper` describes.
+    #[must_use]
+    pub fn with_malicious_helper(mut self, helper: MaliciousHelper) -> Self {
+        self.malicious_helper = Some(helper);
+        self
+    }
 }
 
 impl Default for TestWorld {
@@ -97,8 +143,19 @@ impl TestWorld {
         logging::setup();
 
         let metrics_handle = MetricsHandle::new(config.metrics_level);
-        let participants = make_participants(&mut StdRng::seed_from_u64(config.seed));
-        let network = InMemoryNetwork::default();
+        let participants = make_participants_with_reuse_check(
+            &mut StdRng::seed_from_u64(config.seed),
+            config.prss_reuse_check,
+        );
+        let callbacks = [
+            TransportCallbacks::default(),
+            TransportCallbacks::default(),
+            TransportCallbacks::default(),
+        ];
+        let network = match &config.faults {
+            Some(faults) => InMemoryNetwork::with_faults_and_links(callbacks, faults, config.link),
+            None => InMemoryNetwork::with_links(callbacks, config.link),
+        };
         let role_assignment = config
             .role_assignment
             .unwrap_or_else(|| RoleAssignment::new(network.helper_identities()));
@@ -107,13 +164,18 @@ impl TestWorld {
         for i in 0..3 {
             let transport = &network.transports[i];
             let role_assignment = role_assignment.clone();
-            let gateway = Gateway::new(
+            let mut gateway = Gateway::new(
                 QueryId,
                 config.gateway_config,
                 role_assignment,
                 Arc::downgrade(transport),
             );
             let role = gateway.role();
+            if let Some(helper) = config.malicious_helper.as_ref() {
+                if helper.role() == role {
+                    gateway = gateway.with_interceptor(Arc::new(helper.clone()));
+                }
+            }
             gateways[role] = Some(gateway);
         }
         let gateways = gateways.map(Option::unwrap);
@@ -123,7 +185,7 @@ impl TestWorld {
             participants,
             executions: AtomicUsize::new(0),
             metrics_handle,
-            _network: network,
+            network,
         }
     }
 
@@ -165,6 +227,13 @@ impl TestWorld {
         self.metrics_handle.snapshot()
     }
 
+    /// Returns the number of communication rounds the helpers needed on each gate so far. Rounds
+    /// are only counted if the world was configured with a link latency.
+    #[must_use]
+    pub fn rounds(&self) -> CommunicationRounds {
+        self.network.rounds()
+    }
+
     #[must_use]
     pub fn execution_step(execution: usize) -> String {
         format!("run-{execution}")